@@ -1,11 +1,51 @@
-use std::sync::Arc;
-use std::sync::Mutex;
 #[cfg(feature = "cookie")]
 use cookie::{Cookie, CookieJar};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
+use url::Url;
+
+use crate::background::{Workers, DEFAULT_BACKGROUND_THREADS};
+use crate::concurrency::{ConcurrencyConfig, ConcurrencyLimit};
+use crate::config::AgentConfig;
+#[cfg(feature = "cookie")]
+use crate::cookie_policy::CookiePolicy;
+use crate::download::Download;
+use crate::error::{Error, ErrorHook};
+use crate::events::EventListener;
+use crate::faults::FaultConfig;
+#[cfg(feature = "har")]
+use crate::har::HarRecorder;
 use crate::header::{self, Header};
-use crate::pool::ConnectionPool;
-use crate::request::Request;
+use crate::hsts::HstsStore;
+#[cfg(feature = "http3")]
+use crate::http3::Http3;
+use crate::link::Pages;
+use crate::metrics::{AgentCounters, AgentStats, MetricsObserver};
+#[cfg(feature = "ntlm")]
+use crate::ntlm::NtlmCredentials;
+#[cfg(feature = "oauth2")]
+use crate::oauth2::{OAuth2, OAuth2Config};
+#[cfg(feature = "pac")]
+use crate::pac::Pac;
+use crate::pool::{ConnectionPool, DEFAULT_DRAIN_LIMIT};
+use crate::proxy::Proxy;
+use crate::ratelimit::{RateLimitConfig, RateLimiter};
+use crate::redirect::RedirectPolicy;
+use crate::request::{self, Request};
+use crate::request_id::{self, RequestIdConfig};
+use crate::response::{EarlyHintsHook, Response};
+use crate::retry::{RetryBudget, RetryBudgetConfig};
+#[cfg(feature = "tls")]
+use crate::revocation::Crl;
+#[cfg(all(feature = "sockopt", target_os = "linux"))]
+use crate::sockopt::SocketOptions;
+use crate::sse::Subscription;
+use crate::stream::{Direction, WireHook};
+#[cfg(feature = "tls")]
+use crate::tls::{CertificateVerifier, TlsConfig, TlsSettings};
+use crate::unit;
+use crate::webdav::{self, Depth};
 
 /// Agents keep state between requests.
 ///
@@ -37,14 +77,107 @@ use crate::request::Request;
 /// ```
 #[derive(Debug, Default, Clone)]
 pub struct Agent {
-    /// Copied into each request of this agent.
-    pub(crate) headers: Vec<Header>,
+    /// Shared with each request of this agent, copied once one sets a header.
+    pub(crate) headers: Arc<Vec<Header>>,
+    /// Header names written first, in this order.
+    pub(crate) header_order: Vec<String>,
     /// Reused agent state for repeated requests from this agent.
-    pub(crate) state: Arc<Mutex<Option<AgentState>>>,
+    pub(crate) state: Arc<Option<AgentState>>,
+    /// Receives the bytes of every connection.
+    pub(crate) wire_hook: Option<WireHook>,
+    /// Failures injected into every connection.
+    pub(crate) faults: Option<FaultConfig>,
+    /// Told about the outcome of every request.
+    pub(crate) metrics: Option<Arc<dyn MetricsObserver>>,
+    /// Told about each step of every request.
+    pub(crate) events: Option<Arc<dyn EventListener>>,
+    /// Header and generator of a unique id per request.
+    pub(crate) request_id: Option<RequestIdConfig>,
+    /// Header of the key sent with non-idempotent requests.
+    pub(crate) idempotency_key: Option<String>,
+    /// Whether 4xx and 5xx responses are errors for `Response::into_result()`.
+    pub(crate) error_on_status: bool,
+    /// Print the requests and responses to stderr.
+    pub(crate) verbose: bool,
+    /// Whether response heads are parsed leniently.
+    pub(crate) lenient_parsing: bool,
+    /// Max body bytes read by `Response::into_json()`.
+    #[cfg(feature = "json")]
+    pub(crate) json_limit: Option<u64>,
+    /// Whether urls without a scheme and host are errors.
+    pub(crate) absolute_urls: bool,
+    /// Where redirects may go.
+    pub(crate) redirect_policy: RedirectPolicy,
+    /// Redirects followed by requests, `5` when unset.
+    pub(crate) redirects: Option<u32>,
+    /// Timeouts of requests in milliseconds, `0` for none.
+    pub(crate) timeout_resolve: u64,
+    pub(crate) timeout_connect: u64,
+    pub(crate) timeout_read: u64,
+    pub(crate) timeout_write: u64,
+    /// SOCKS5 proxies for all connections, tried in order.
+    pub(crate) proxies: Vec<Proxy>,
+    /// Location of the proxy auto-config, used when no proxy is set.
+    #[cfg(feature = "pac")]
+    pub(crate) pac: Option<String>,
+    /// Told about every failed request.
+    pub(crate) error_hook: Option<ErrorHook>,
+    /// Given the headers of `103 Early Hints` responses.
+    pub(crate) early_hints: Option<EarlyHintsHook>,
+    /// Requests per second allowed per host.
+    pub(crate) rate_limit: Option<RateLimitConfig>,
+    /// Requests in flight allowed at once.
+    pub(crate) concurrency: Option<ConcurrencyConfig>,
+    /// Share of requests that may be retries.
+    pub(crate) retry_budget: Option<RetryBudgetConfig>,
+    /// Threads for background requests.
+    pub(crate) background_threads: Option<usize>,
+    /// Whether pooled connections are probed before reuse.
+    pub(crate) pool_health_check: bool,
+    /// Most bytes of an unread body read off when the response is dropped.
+    pub(crate) drain_limit: Option<u64>,
+    /// Whether `Strict-Transport-Security` headers are ignored.
+    pub(crate) ignore_hsts: bool,
+    /// Whether `WWW-Authenticate: Negotiate` challenges are answered.
+    #[cfg(feature = "negotiate")]
+    pub(crate) negotiate: bool,
+    /// `SO_MARK` and DSCP set on the sockets.
+    #[cfg(all(feature = "sockopt", target_os = "linux"))]
+    pub(crate) socket_options: SocketOptions,
+    /// Credentials to answer `WWW-Authenticate: NTLM` challenges with.
+    #[cfg(feature = "ntlm")]
+    pub(crate) ntlm: Option<NtlmCredentials>,
+    /// Token endpoint and client of the bearer tokens sent.
+    #[cfg(feature = "oauth2")]
+    pub(crate) oauth2: Option<OAuth2Config>,
+    /// Which cookies of responses are kept.
+    #[cfg(feature = "cookie")]
+    pub(crate) cookie_policy: CookiePolicy,
+    /// Max body bytes kept per entry when recording a HAR.
+    #[cfg(feature = "har")]
+    pub(crate) har_max_body: Option<usize>,
+    /// Whether credentials are kept in the recorded HAR.
+    #[cfg(feature = "har")]
+    pub(crate) har_keep_secrets: bool,
+    /// Settings for the TLS config of the built agent.
+    #[cfg(feature = "tls")]
+    pub(crate) tls: TlsSettings,
+    /// Label of the keychain identity to present to servers.
+    #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+    pub(crate) keychain_identity: Option<String>,
+    /// Use HTTP/3 for origins that advertise it.
+    #[cfg(feature = "http3")]
+    pub(crate) http3: bool,
+    /// Use HTTP/3 for all `https` urls.
+    #[cfg(feature = "http3")]
+    pub(crate) http3_only: bool,
 }
 
 /// Container of the state
 ///
+/// Each part is locked on its own, so that requests of threads sharing an
+/// agent only wait for each other when they use the same part.
+///
 /// *Internal API*.
 #[derive(Debug)]
 pub(crate) struct AgentState {
@@ -52,20 +185,99 @@ pub(crate) struct AgentState {
     pub(crate) pool: ConnectionPool,
     /// Cookies saved between requests.
     #[cfg(feature = "cookie")]
-    pub(crate) jar: CookieJar,
+    pub(crate) jar: RwLock<CookieJar>,
+    /// Which cookies of responses go in the jar.
+    #[cfg(feature = "cookie")]
+    pub(crate) cookie_policy: CookiePolicy,
+    /// Token buckets per host, if rate limited.
+    pub(crate) rate_limiter: Option<Mutex<RateLimiter>>,
+    /// Places for requests in flight, if limited.
+    pub(crate) concurrency: Option<Arc<ConcurrencyLimit>>,
+    /// Recent requests and retries, if retries are budgeted.
+    pub(crate) retry_budget: Option<RetryBudget>,
+    /// Threads making background requests.
+    pub(crate) workers: Workers,
+    /// Totals for `Agent::stats()`.
+    pub(crate) counters: Arc<AgentCounters>,
+    /// Hosts that must be requested over https, unless ignoring HSTS.
+    pub(crate) hsts: Option<RwLock<HstsStore>>,
+    /// Recorded exchanges, if recording.
+    #[cfg(feature = "har")]
+    pub(crate) har: Option<Mutex<HarRecorder>>,
+    /// The bearer token, fetched when needed.
+    #[cfg(feature = "oauth2")]
+    pub(crate) oauth2: Option<OAuth2>,
+    /// The proxy auto-config, loaded on first use.
+    #[cfg(feature = "pac")]
+    pub(crate) pac: Option<Arc<Pac>>,
+    /// TLS config, with the sessions cached for resumption.
+    #[cfg(feature = "tls")]
+    pub(crate) tls_config: TlsConfig,
+    /// The settings of the config, for requests changing some of them.
+    #[cfg(feature = "tls")]
+    pub(crate) tls_settings: TlsSettings,
+    #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+    pub(crate) keychain_identity: Option<String>,
+    /// HTTP/3 connections and the origins that advertised it.
+    #[cfg(feature = "http3")]
+    pub(crate) http3: Http3,
 }
 
 impl AgentState {
-    fn new() -> Self {
+    #[allow(unused_variables)]
+    fn new(agent: &Agent) -> Self {
         AgentState {
-            pool: ConnectionPool::new(),
+            pool: ConnectionPool::new(
+                agent.pool_health_check,
+                agent.drain_limit.unwrap_or(DEFAULT_DRAIN_LIMIT),
+            ),
+            #[cfg(feature = "cookie")]
+            jar: RwLock::new(CookieJar::new()),
             #[cfg(feature = "cookie")]
-            jar: CookieJar::new(),
+            cookie_policy: agent.cookie_policy.clone(),
+            rate_limiter: agent
+                .rate_limit
+                .clone()
+                .map(|config| Mutex::new(RateLimiter::new(config))),
+            concurrency: agent
+                .concurrency
+                .map(|config| Arc::new(ConcurrencyLimit::new(config))),
+            retry_budget: agent.retry_budget.map(RetryBudget::new),
+            workers: Workers::new(
+                agent
+                    .background_threads
+                    .unwrap_or(DEFAULT_BACKGROUND_THREADS),
+            ),
+            counters: Arc::new(AgentCounters::default()),
+            hsts: if agent.ignore_hsts {
+                None
+            } else {
+                Some(RwLock::new(HstsStore::default()))
+            },
+            #[cfg(feature = "har")]
+            har: agent
+                .har_max_body
+                .map(|max| Mutex::new(HarRecorder::new(max, !agent.har_keep_secrets))),
+            #[cfg(feature = "oauth2")]
+            oauth2: agent
+                .oauth2
+                .clone()
+                .map(|config| OAuth2::new(config, agent.private())),
+            #[cfg(feature = "pac")]
+            pac: agent
+                .pac
+                .as_ref()
+                .map(|location| Arc::new(Pac::new(location, agent.private()))),
+            #[cfg(feature = "tls")]
+            tls_config: TlsConfig::new(&agent.tls),
+            #[cfg(feature = "tls")]
+            tls_settings: agent.tls.clone(),
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            keychain_identity: agent.keychain_identity.clone(),
+            #[cfg(feature = "http3")]
+            http3: Http3::default(),
         }
     }
-    pub fn pool(&mut self) -> &mut ConnectionPool {
-        &mut self.pool
-    }
 }
 
 impl Agent {
@@ -83,14 +295,50 @@ impl Agent {
         Default::default()
     }
 
+    /// Creates a new agent set up from an [`AgentConfig`](struct.AgentConfig.html),
+    /// to be further configured and then built like one from [`Agent::new()`].
+    ///
+    /// Fails for proxies that can't be parsed and settings that don't go
+    /// together, or need a feature that isn't enabled.
+    ///
+    /// ```
+    /// let mut config = ureq::AgentConfig::default();
+    /// config.timeout_read_ms = Some(10_000);
+    /// config.headers.insert("X-My-Header".into(), "Foo".into());
+    ///
+    /// let agent = ureq::Agent::from_config(&config).unwrap().build();
+    /// ```
+    pub fn from_config(config: &AgentConfig) -> Result<Agent, Error> {
+        let mut agent = Agent::new();
+        config.apply(&mut agent)?;
+        Ok(agent)
+    }
+
     /// Create a new agent after treating it as a builder.
     /// This actually clones the internal state to a new one and instantiates
     /// a new connection pool that is reused between connects.
     pub fn build(&self) -> Self {
         Agent {
-            headers: self.headers.clone(),
-            state: Arc::new(Mutex::new(Some(AgentState::new()))),
+            state: Arc::new(Some(AgentState::new(self))),
+            ..self.clone()
+        }
+    }
+
+    /// A built agent of the same config, for the requests the agent makes of
+    /// itself, like fetching the PAC file or an OAuth2 token. It has no PAC
+    /// file or OAuth2 token.
+    #[cfg(any(feature = "pac", feature = "oauth2"))]
+    pub(crate) fn private(&self) -> Agent {
+        let mut agent = self.clone();
+        #[cfg(feature = "pac")]
+        {
+            agent.pac = None;
         }
+        #[cfg(feature = "oauth2")]
+        {
+            agent.oauth2 = None;
+        }
+        agent.build()
     }
 
     /// Set a header field that will be present in all requests using the agent.
@@ -112,7 +360,33 @@ impl Agent {
     ///  }
     /// ```
     pub fn set(&mut self, header: &str, value: &str) -> &mut Agent {
-        header::add_header(&mut self.headers, Header::new(header, value));
+        header::add_header(Arc::make_mut(&mut self.headers), Header::new(header, value));
+        self
+    }
+
+    /// Set the `Accept-Language` header of all requests using the agent, see
+    /// [`Request::accept_language()`](struct.Request.html#method.accept_language).
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .accept_language(&["de-CH", "de", "en"])
+    ///     .build();
+    /// ```
+    pub fn accept_language(&mut self, languages: &[&str]) -> &mut Agent {
+        self.set("Accept-Language", &header::quality_list(languages))
+    }
+
+    /// Write these headers first, in this order, for all requests of this agent.
+    /// Other headers follow in the order they were first set, see
+    /// [`Request::header_order()`](struct.Request.html#method.header_order).
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .header_order(&["Host", "User-Agent"])
+    ///     .build();
+    /// ```
+    pub fn header_order(&mut self, names: &[&str]) -> &mut Agent {
+        self.header_order = names.iter().map(|n| n.to_string()).collect();
         self
     }
 
@@ -152,107 +426,1369 @@ impl Agent {
         self
     }
 
-    /// Request by providing the HTTP verb such as `GET`, `POST`...
+    /// The `User-Agent` header that will be present in all requests using the agent.
+    ///
+    /// Without this, requests are sent with `User-Agent: ureq/<version>`.
     ///
     /// ```
-    /// let agent = ureq::agent();
+    /// let agent = ureq::agent()
+    ///     .user_agent("my-crawler/1.0")
+    ///     .build();
     ///
     /// let r = agent
-    ///     .request("GET", "/my_page")
+    ///     .get("/my_page")
     ///     .call();
-    /// println!("{:?}", r);
     /// ```
-    pub fn request(&self, method: &str, path: &str) -> Request {
-        Request::new(&self, method.into(), path.into())
+    pub fn user_agent(&mut self, user_agent: &str) -> &mut Agent {
+        self.set("User-Agent", user_agent)
     }
 
-    /// Gets a cookie in this agent by name. Cookies are available
-    /// either by setting it in the agent, or by making requests
-    /// that `Set-Cookie` in the agent.
+    /// Send a random UUID as request id in the given header, such as
+    /// `X-Request-Id`. The id is generated once per call, kept across
+    /// redirects, and available as [`Response::request_id()`](struct.Response.html#method.request_id).
+    ///
+    /// A request that sets the header itself keeps its own value.
     ///
     /// ```
-    /// let agent = ureq::agent();
+    /// let agent = ureq::agent()
+    ///     .request_id("X-Request-Id")
+    ///     .build();
     ///
-    /// agent.get("http://www.google.com").call();
+    /// let resp = agent.get("/my_page").call();
+    /// println!("{:?}", resp.request_id());
+    /// ```
+    pub fn request_id(&mut self, header: &str) -> &mut Agent {
+        self.request_id_with(header, request_id::uuid_v4)
+    }
+
+    /// Like [`request_id()`](#method.request_id), but with ids from a
+    /// generator, such as a snowflake id service.
     ///
-    /// assert!(agent.cookie("NID").is_some());
     /// ```
-    #[cfg(feature = "cookie")]
-    pub fn cookie(&self, name: &str) -> Option<Cookie<'static>> {
-        let state = self.state.lock().unwrap();
-        state
-            .as_ref()
-            .and_then(|state| state.jar.get(name))
-            .cloned()
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// static NEXT: AtomicU64 = AtomicU64::new(1);
+    ///
+    /// let agent = ureq::agent()
+    ///     .request_id_with("X-Request-Id", || {
+    ///         format!("client-{}", NEXT.fetch_add(1, Ordering::SeqCst))
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn request_id_with<F>(&mut self, header: &str, generator: F) -> &mut Agent
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.request_id = Some(RequestIdConfig {
+            header: header.to_string(),
+            generate: Arc::new(generator),
+        });
+        self
     }
 
-    /// Set a cookie in this agent.
+    /// Send a random UUID in the given header, such as `Idempotency-Key`, with
+    /// requests whose method is not idempotent, like `POST` and `PATCH`. The key
+    /// is generated once per call and sent with each attempt, when the request
+    /// is sent again on a new connection, to a fallback url, or after a `307`
+    /// or `308` redirect, so the server can tell an attempt it already handled.
+    ///
+    /// A request that sets the header itself keeps its own value.
     ///
     /// ```
-    /// let agent = ureq::agent();
+    /// let agent = ureq::agent()
+    ///     .idempotency_key("Idempotency-Key")
+    ///     .build();
     ///
-    /// let cookie = ureq::Cookie::new("name", "value");
-    /// agent.set_cookie(cookie);
+    /// let resp = agent.post("/charges").send_string("amount=2000");
     /// ```
-    #[cfg(feature = "cookie")]
-    pub fn set_cookie(&self, cookie: Cookie<'static>) {
-        let mut state = self.state.lock().unwrap();
-        match state.as_mut() {
-            None => (),
-            Some(state) => {
-                state.jar.add_original(cookie);
+    pub fn idempotency_key(&mut self, header: &str) -> &mut Agent {
+        self.idempotency_key = Some(header.to_string());
+        self
+    }
+
+    /// Limit the requests to each host to `per_second`, with bursts of up to
+    /// that many. Requests over the limit wait their turn, or fail when
+    /// [`rate_limit_reject()`](#method.rate_limit_reject) is set.
+    ///
+    /// Redirects count as requests to the host redirected to. `0` means no limit.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .rate_limit(10)
+    ///     .rate_limit_host("api.example.com", 2)
+    ///     .build();
+    /// ```
+    pub fn rate_limit(&mut self, per_second: u32) -> &mut Agent {
+        self.rate_limit.get_or_insert_with(Default::default).default = per_second;
+        self
+    }
+
+    /// Limit the requests to one host, overriding [`rate_limit()`](#method.rate_limit).
+    /// `0` means no limit for the host.
+    pub fn rate_limit_host(&mut self, host: &str, per_second: u32) -> &mut Agent {
+        self.rate_limit
+            .get_or_insert_with(Default::default)
+            .hosts
+            .insert(unit::ascii_host(host), per_second);
+        self
+    }
+
+    /// Fail requests over the rate limit with
+    /// [`Error::RateLimited`](enum.Error.html#variant.RateLimited), a synthetic
+    /// `429`, instead of waiting.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .rate_limit(1)
+    ///     .rate_limit_reject(true)
+    ///     .build();
+    /// ```
+    pub fn rate_limit_reject(&mut self, reject: bool) -> &mut Agent {
+        self.rate_limit.get_or_insert_with(Default::default).reject = reject;
+        self
+    }
+
+    /// Allow at most `max` requests in flight at once, across all threads using
+    /// the agent. Further requests wait in line for one to finish, which is when
+    /// its body is read to the end or its response dropped. Redirects keep the
+    /// place of the request.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let agent = ureq::agent()
+    ///     .max_concurrent_requests(8)
+    ///     .queue_timeout(Duration::from_secs(5))
+    ///     .build();
+    /// ```
+    pub fn max_concurrent_requests(&mut self, max: usize) -> &mut Agent {
+        let queue_timeout = self.concurrency.and_then(|c| c.queue_timeout);
+        self.concurrency = Some(ConcurrencyConfig { max, queue_timeout });
+        self
+    }
+
+    /// How long a request waits in line for
+    /// [`max_concurrent_requests()`](#method.max_concurrent_requests), after which
+    /// it fails with [`Error::QueueTimeout`](enum.Error.html#variant.QueueTimeout),
+    /// a synthetic `503`. Requests wait as long as it takes by default.
+    pub fn queue_timeout(&mut self, timeout: Duration) -> &mut Agent {
+        match &mut self.concurrency {
+            Some(config) => config.queue_timeout = Some(timeout),
+            None => {
+                self.concurrency = Some(ConcurrencyConfig {
+                    max: usize::MAX,
+                    queue_timeout: Some(timeout),
+                })
             }
         }
+        self
     }
 
-    /// Make a GET request from this agent.
-    pub fn get(&self, path: &str) -> Request {
-        self.request("GET", path)
+    /// Allow retries to be at most `ratio` of the requests made in the last
+    /// `window`, so that they can't pile on load while a server is down. Over the
+    /// budget, the error of the failed attempt is returned instead. One retry is
+    /// allowed in the window however few requests were made.
+    ///
+    /// Retries are the requests sent again on a new connection when a pooled one
+    /// turns out to be closed. Redirects count as requests.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let agent = ureq::agent()
+    ///     .retry_budget(0.2, Duration::from_secs(10))
+    ///     .build();
+    /// ```
+    pub fn retry_budget(&mut self, ratio: f64, window: Duration) -> &mut Agent {
+        self.retry_budget = Some(RetryBudgetConfig { ratio, window });
+        self
     }
 
-    /// Make a HEAD request from this agent.
-    pub fn head(&self, path: &str) -> Request {
-        self.request("HEAD", path)
+    /// How many threads make the requests of
+    /// [`Request::call_background()`](struct.Request.html#method.call_background),
+    /// 4 by default. Threads are started as requests need them, and more requests
+    /// than threads wait for one to finish.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .background_threads(16)
+    ///     .build();
+    /// ```
+    pub fn background_threads(&mut self, threads: usize) -> &mut Agent {
+        self.background_threads = Some(threads);
+        self
     }
 
-    /// Make a POST request from this agent.
-    pub fn post(&self, path: &str) -> Request {
-        self.request("POST", path)
+    /// Check that a pooled connection is still open before reusing it, with a
+    /// read probe that doesn't block. A connection the server closed while idle
+    /// is then replaced by a new one before sending, instead of failing once the
+    /// request is written, which can't be retried for bodies read from a reader.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .pool_health_check(true)
+    ///     .build();
+    /// ```
+    pub fn pool_health_check(&mut self, check: bool) -> &mut Agent {
+        self.pool_health_check = check;
+        self
     }
 
-    /// Make a PUT request from this agent.
-    pub fn put(&self, path: &str) -> Request {
-        self.request("PUT", path)
+    /// Most bytes left of a body that are read off when a response, or its
+    /// reader, is dropped before the body is read to the end. The connection
+    /// then goes back to the pool instead of being closed. Bigger bodies still
+    /// close it. `0` never reads any. Defaults to 16 KiB.
+    ///
+    /// The reading is done on drop. Only a body with a `Content-Length` is waited
+    /// for, at most the read timeout of the request. Of a chunked one only what
+    /// already arrived is read, so dropping a stream of events doesn't wait for
+    /// the next one.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .drain_limit(64 * 1024)
+    ///     .build();
+    /// ```
+    pub fn drain_limit(&mut self, bytes: u64) -> &mut Agent {
+        self.drain_limit = Some(bytes);
+        self
     }
 
-    /// Make a DELETE request from this agent.
-    pub fn delete(&self, path: &str) -> Request {
-        self.request("DELETE", path)
+    /// Whether to follow `Strict-Transport-Security` headers, like browsers do.
+    /// Defaults to `true`.
+    ///
+    /// A host that sent the header over https is requested over https for the
+    /// `max-age` it gave, also when asked for an `http://` url. With
+    /// `includeSubDomains` the same goes for its subdomains.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .hsts(false)
+    ///     .build();
+    /// ```
+    pub fn hsts(&mut self, enabled: bool) -> &mut Agent {
+        self.ignore_hsts = !enabled;
+        self
     }
 
-    /// Make a TRACE request from this agent.
-    pub fn trace(&self, path: &str) -> Request {
-        self.request("TRACE", path)
+    /// Answer `401` responses with a `WWW-Authenticate: Negotiate` challenge by
+    /// sending the request again with a SPNEGO token for `HTTP@host`, like
+    /// browsers do for intranet services behind Windows Integrated Auth.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["negotiate"] }`
+    ///
+    /// The token comes from the system GSSAPI library, using the Kerberos ticket
+    /// of the user, such as from `kinit`. Without the library or a ticket, and on
+    /// platforms other than unix, the `401` response is given back as it is.
+    /// Requests with a body read from a reader aren't sent again either.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .negotiate_auth(true)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "negotiate")]
+    pub fn negotiate_auth(&mut self, enabled: bool) -> &mut Agent {
+        self.negotiate = enabled;
+        self
     }
 
-    /// Make a OPTIONS request from this agent.
-    pub fn options(&self, path: &str) -> Request {
-        self.request("OPTIONS", path)
+    /// Set `SO_MARK` on the sockets of this agent, the fwmark that policy
+    /// routing and firewall rules can match on. Needs `CAP_NET_ADMIN`, without
+    /// it connecting fails.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["sockopt"] }`, on Linux.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .socket_mark(0x100)
+    ///     .dscp(46) // expedited forwarding
+    ///     .build();
+    /// ```
+    #[cfg(all(feature = "sockopt", target_os = "linux"))]
+    pub fn socket_mark(&mut self, mark: u32) -> &mut Agent {
+        self.socket_options.mark = Some(mark);
+        self
     }
 
-    /// Make a CONNECT request from this agent.
-    pub fn connect(&self, path: &str) -> Request {
-        self.request("CONNECT", path)
+    /// Mark the packets of this agent's connections with a DSCP class, `0` to
+    /// `63`, for QoS. It's set as the IP TOS, or the traffic class for IPv6.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["sockopt"] }`, on Linux.
+    #[cfg(all(feature = "sockopt", target_os = "linux"))]
+    pub fn dscp(&mut self, dscp: u8) -> &mut Agent {
+        self.socket_options.dscp = Some(dscp.min(63));
+        self
     }
 
-    /// Make a PATCH request from this agent.
-    pub fn patch(&self, path: &str) -> Request {
-        self.request("PATCH", path)
+    /// Send the first bytes of new connections, the request head or the TLS
+    /// ClientHello, in the SYN with TCP Fast Open, saving a round trip once the
+    /// server has given a Fast Open cookie. Servers must enable it too, and
+    /// kernels without it connect as usual. Off by default.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["sockopt"] }`, on Linux.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .tcp_fast_open(true)
+    ///     .build();
+    /// ```
+    #[cfg(all(feature = "sockopt", target_os = "linux"))]
+    pub fn tcp_fast_open(&mut self, enabled: bool) -> &mut Agent {
+        self.socket_options.fast_open = enabled;
+        self
+    }
+
+    /// Answer `401` responses with a `WWW-Authenticate: NTLM` challenge by doing
+    /// the NTLMv2 handshake, for servers and proxies that still require it. The
+    /// user is given as `DOMAIN\user`, or just `user`.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["ntlm"] }`
+    ///
+    /// NTLM authenticates the connection rather than the request, so the
+    /// handshake and the request sent with it stay on the connection the
+    /// challenge came on. Requests with a body read from a reader can't be
+    /// sent again and get the challenge as the response.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .ntlm_auth("CORP\\martin", "rubbermashgum")
+    ///     .build();
+    /// ```
+    #[cfg(feature = "ntlm")]
+    pub fn ntlm_auth(&mut self, user: &str, password: &str) -> &mut Agent {
+        self.ntlm = Some(NtlmCredentials::new(user, password));
+        self
+    }
+
+    /// Send `Authorization: Bearer` with a token of the OAuth2 client credentials
+    /// grant, from the token endpoint at `token_url`. The agent caches the token,
+    /// and gets a new one before it expires, or when a response is `401`, sending
+    /// the request again if its body can be.
+    ///
+    /// One request at a time gets a token, the others wait for it. Requests that
+    /// set their own `Authorization`, and redirects to other hosts, don't get one.
+    /// The token requests are sent with the headers, TLS settings, proxies and
+    /// timeouts of the agent.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["oauth2"] }`
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .oauth2_client_credentials("https://auth.example/token", "my-client", "secret")
+    ///     .oauth2_scope("read write")
+    ///     .build();
+    /// ```
+    #[cfg(feature = "oauth2")]
+    pub fn oauth2_client_credentials(
+        &mut self,
+        token_url: &str,
+        client_id: &str,
+        client_secret: &str,
+    ) -> &mut Agent {
+        self.oauth2 = Some(OAuth2Config {
+            token_url: token_url.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            refresh_token: None,
+            scope: self.oauth2.as_ref().and_then(|c| c.scope.clone()),
+        });
+        self
+    }
+
+    /// Like [`oauth2_client_credentials()`](#method.oauth2_client_credentials),
+    /// with tokens of the refresh token grant. A new refresh token from the
+    /// token endpoint replaces this one.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["oauth2"] }`
+    #[cfg(feature = "oauth2")]
+    pub fn oauth2_refresh_token(
+        &mut self,
+        token_url: &str,
+        client_id: &str,
+        client_secret: &str,
+        refresh_token: &str,
+    ) -> &mut Agent {
+        self.oauth2_client_credentials(token_url, client_id, client_secret);
+        if let Some(config) = self.oauth2.as_mut() {
+            config.refresh_token = Some(refresh_token.to_string());
+        }
+        self
+    }
+
+    /// The `scope` asked for with OAuth2 tokens, like `"read write"`.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["oauth2"] }`
+    #[cfg(feature = "oauth2")]
+    pub fn oauth2_scope(&mut self, scope: &str) -> &mut Agent {
+        if let Some(config) = self.oauth2.as_mut() {
+            config.scope = Some(scope.to_string());
+        }
+        self
+    }
+
+    /// Totals of the requests made by this agent, such as the connections
+    /// opened and the bytes transferred. Counting is cheap and always on.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    /// agent.get("/my_page").call();
+    ///
+    /// let stats = agent.stats();
+    /// println!("{} requests, {} failed", stats.requests(), stats.failed());
+    /// ```
+    pub fn stats(&self) -> AgentStats {
+        match (*self.state).as_ref() {
+            Some(state) => state.counters.snapshot(),
+            None => AgentStats::default(),
+        }
+    }
+
+    /// The hosts this agent knows to require https, as text that can be saved
+    /// and given to [`import_hsts()`](#method.import_hsts) of a later agent.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    /// agent.get("/my_page").call();
+    ///
+    /// let saved = agent.export_hsts();
+    /// println!("{}", saved);
+    /// ```
+    pub fn export_hsts(&self) -> String {
+        match (*self.state).as_ref().and_then(|state| state.hsts.as_ref()) {
+            Some(store) => store.read().unwrap().export(),
+            None => String::new(),
+        }
+    }
+
+    /// Add the hosts from [`export_hsts()`](#method.export_hsts) of an earlier
+    /// agent. Lines that can't be understood are skipped.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    /// if let Ok(saved) = std::fs::read_to_string("hsts.txt") {
+    ///     agent.import_hsts(&saved);
+    /// }
+    /// ```
+    pub fn import_hsts(&self, data: &str) {
+        if let Some(store) = (*self.state).as_ref().and_then(|state| state.hsts.as_ref()) {
+            store.write().unwrap().import(data);
+        }
+    }
+
+    /// Make [`Response::into_result()`](struct.Response.html#method.into_result)
+    /// and [`Request::try_call()`](struct.Request.html#method.try_call) give
+    /// `4xx` and `5xx` responses as
+    /// [`Error::Status`](enum.Error.html#variant.Status) for all requests of this agent.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .error_on_status(true)
+    ///     .build();
+    /// ```
+    pub fn error_on_status(&mut self, enabled: bool) -> &mut Agent {
+        self.error_on_status = enabled;
+        self
+    }
+
+    /// Print the request line and headers sent, and the status line and headers
+    /// received, to stderr for all requests of this agent, like `curl -v`. See
+    /// [`Request::verbose()`](struct.Request.html#method.verbose).
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .verbose(true)
+    ///     .build();
+    /// ```
+    pub fn verbose(&mut self, enabled: bool) -> &mut Agent {
+        self.verbose = enabled;
+        self
+    }
+
+    /// Parse the responses of all requests of this agent leniently, see
+    /// [`Request::lenient_parsing()`](struct.Request.html#method.lenient_parsing).
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .lenient_parsing(true)
+    ///     .build();
+    /// ```
+    pub fn lenient_parsing(&mut self, enabled: bool) -> &mut Agent {
+        self.lenient_parsing = enabled;
+        self
+    }
+
+    /// Max size of the bodies read by [`Response::into_json()`](struct.Response.html#method.into_json)
+    /// of this agent's responses, like with
+    /// [`into_json_limited()`](struct.Response.html#method.into_json_limited).
+    /// There is no limit by default.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .json_limit(10 * 1024 * 1024)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json_limit(&mut self, max_bytes: u64) -> &mut Agent {
+        self.json_limit = Some(max_bytes);
+        self
+    }
+
+    /// Fail requests of this agent to urls that are not absolute, like `/page`
+    /// or `example.com/page`, with [`Error::BadUrl`](enum.Error.html#variant.BadUrl),
+    /// instead of making them to `http://localhost/`.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .require_absolute_urls(true)
+    ///     .build();
+    ///
+    /// let resp = agent.get("/my_page").call();
+    /// assert!(matches!(resp.synthetic_error(), Some(ureq::Error::BadUrl(_))));
+    /// ```
+    pub fn require_absolute_urls(&mut self, enabled: bool) -> &mut Agent {
+        self.absolute_urls = enabled;
+        self
+    }
+
+    /// How many redirects requests of this agent follow, `5` by default. See
+    /// [`Request::redirects()`](struct.Request.html#method.redirects).
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .redirects(0)
+    ///     .build();
+    /// ```
+    pub fn redirects(&mut self, n: u32) -> &mut Agent {
+        self.redirects = Some(n);
+        self
+    }
+
+    /// Timeout for resolving the host of requests of this agent. None by
+    /// default. See [`Request::timeout_resolve()`](struct.Request.html#method.timeout_resolve).
+    pub fn timeout_resolve(&mut self, timeout: Duration) -> &mut Agent {
+        self.timeout_resolve = request::duration_millis(timeout);
+        self
+    }
+
+    /// Timeout for connecting the socket of requests of this agent. None by
+    /// default. See [`Request::timeout_connect()`](struct.Request.html#method.timeout_connect).
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let agent = ureq::agent()
+    ///     .timeout_connect(Duration::from_secs(5))
+    ///     .timeout_read(Duration::from_secs(30))
+    ///     .build();
+    /// ```
+    pub fn timeout_connect(&mut self, timeout: Duration) -> &mut Agent {
+        self.timeout_connect = request::duration_millis(timeout);
+        self
+    }
+
+    /// Timeout for each read of the socket of requests of this agent. None by
+    /// default. See [`Request::timeout_read()`](struct.Request.html#method.timeout_read).
+    pub fn timeout_read(&mut self, timeout: Duration) -> &mut Agent {
+        self.timeout_read = request::duration_millis(timeout);
+        self
+    }
+
+    /// Timeout for each write to the socket of requests of this agent. None by
+    /// default. See [`Request::timeout_write()`](struct.Request.html#method.timeout_write).
+    pub fn timeout_write(&mut self, timeout: Duration) -> &mut Agent {
+        self.timeout_write = request::duration_millis(timeout);
+        self
+    }
+
+    /// Only follow redirects the policy allows, for all requests of this agent.
+    /// See [`RedirectPolicy`](struct.RedirectPolicy.html).
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .redirect_policy(ureq::RedirectPolicy::same_origin())
+    ///     .build();
+    /// ```
+    pub fn redirect_policy(&mut self, policy: RedirectPolicy) -> &mut Agent {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Make all connections of this agent through a SOCKS5 proxy.
+    ///
+    /// ```
+    /// let proxy = ureq::Proxy::new("socks5h://localhost:9050").unwrap();
+    ///
+    /// let agent = ureq::agent()
+    ///     .set_proxy(proxy)
+    ///     .build();
+    /// ```
+    pub fn set_proxy(&mut self, proxy: Proxy) -> &mut Agent {
+        self.proxies = vec![proxy];
+        self
+    }
+
+    /// Make all connections of this agent through the first of the SOCKS5
+    /// proxies that works. A proxy that can't be connected to, or that fails
+    /// to connect to the host, is followed by the next one, and the error of
+    /// the last one is given when all fail. An empty list connects directly.
+    ///
+    /// Pooled connections are kept apart by the proxies they were made for.
+    ///
+    /// ```
+    /// let proxies = vec![
+    ///     ureq::Proxy::new("socks5h://proxy-a.example.com:1080").unwrap(),
+    ///     ureq::Proxy::new("socks5h://proxy-b.example.com:1080").unwrap(),
+    /// ];
+    ///
+    /// let agent = ureq::agent()
+    ///     .set_proxies(proxies)
+    ///     .build();
+    /// ```
+    pub fn set_proxies(&mut self, proxies: Vec<Proxy>) -> &mut Agent {
+        self.proxies = proxies;
+        self
+    }
+
+    /// Choose the proxy for each connection of this agent with a proxy auto-config
+    /// (PAC) file, by a path or an `http(s)` url. The file is loaded on first use,
+    /// and its `FindProxyForURL(url, host)` is run for every new connection.
+    ///
+    /// The entries of the result are tried in turn until one connects, like
+    /// browsers do. `DIRECT` connects directly, `SOCKS5` and `SOCKS` entries use
+    /// a SOCKS5 proxy, and `PROXY` entries an http proxy that tunnels the
+    /// connection with `CONNECT`, for `http` urls too. `HTTPS` entries are
+    /// skipped, TLS to the proxy is not supported. An `http(s)` location is
+    /// fetched with the TLS settings, timeouts and proxies of the agent.
+    ///
+    /// A proxy from [`set_proxy()`](#method.set_proxy) or
+    /// [`set_proxies()`](#method.set_proxies) wins over the PAC file. The PAC
+    /// file is only used by requests of the built agent.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["pac"] }`
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .proxy_pac("http://wpad.example.com/wpad.dat")
+    ///     .build();
+    /// ```
+    #[cfg(feature = "pac")]
+    pub fn proxy_pac(&mut self, location: &str) -> &mut Agent {
+        self.pac = Some(location.to_string());
+        self
+    }
+
+    /// Max number of TLS sessions this agent keeps to resume, so reconnecting
+    /// to a host takes an abbreviated handshake. Defaults to `32`, and `0`
+    /// turns resumption off. Each built agent has its own cache.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .tls_session_cache(256)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn tls_session_cache(&mut self, size: usize) -> &mut Agent {
+        self.tls.session_cache = size;
+        self
+    }
+
+    /// Protocols to offer by ALPN in the TLS handshake, in order of preference,
+    /// like `&["http/1.1"]`. By default none are offered. The one the server
+    /// picked is in [`Response::alpn_protocol()`](struct.Response.html#method.alpn_protocol).
+    ///
+    /// ureq only speaks HTTP/1.1, offering `h2` is only useful to find out
+    /// whether a server supports it.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .alpn_protocols(&["http/1.1"])
+    ///     .build();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn alpn_protocols(&mut self, protocols: &[&str]) -> &mut Agent {
+        self.tls.alpn_protocols = protocols.iter().map(|p| p.as_bytes().to_vec()).collect();
+        self
+    }
+
+    /// Require servers to staple an OCSP response to the TLS handshake that
+    /// says their certificate is good, and fail the connection otherwise. The
+    /// response must be signed by the issuer of the certificate, or a responder
+    /// it delegated to, and not be expired.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .require_ocsp_stapling(true)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn require_ocsp_stapling(&mut self, required: bool) -> &mut Agent {
+        self.tls.require_ocsp = required;
+        self
+    }
+
+    /// Fail TLS connections to servers with a certificate, or intermediate
+    /// certificate, that is revoked by the list. Can be called several times,
+    /// for the lists of different certificate authorities.
+    ///
+    /// ```no_run
+    /// let pem = std::fs::read_to_string("intermediate.crl.pem").unwrap();
+    /// let crl = ureq::Crl::from_pem(&pem).unwrap();
+    ///
+    /// let agent = ureq::agent()
+    ///     .add_crl(crl)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn add_crl(&mut self, crl: Crl) -> &mut Agent {
+        self.tls.crls.push(crl);
+        self
+    }
+
+    /// Accept any certificate from the host, such as a self-signed one of a
+    /// development or staging server. Connections to other hosts are verified
+    /// as usual. Can be called several times.
+    ///
+    /// This makes connections to the host open to anyone who can intercept them,
+    /// don't use it for hosts on the internet.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .accept_invalid_certs_for_host("dev.local")
+    ///     .build();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn accept_invalid_certs_for_host(&mut self, host: &str) -> &mut Agent {
+        let host = unit::ascii_host(host);
+        self.tls.insecure_hosts.push(host.to_ascii_lowercase());
+        self
+    }
+
+    /// Present the identity of the keychains with this label, a certificate and
+    /// its private key, to servers asking for a client certificate. The key is
+    /// used where it is, so one that can't be exported, such as one installed by
+    /// device management, works too. The search fails the requests to `https`
+    /// urls when there is no such identity.
+    ///
+    /// With feature `security-framework` on macOS and iOS, `https` urls are
+    /// connected with the Security framework instead of rustls, which verifies
+    /// servers with the roots the system and its keychains trust. The TLS
+    /// settings of rustls, such as [`tls_config()`](#method.tls_config), are
+    /// then not used.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["security-framework"] }`
+    ///
+    /// ```no_run
+    /// let agent = ureq::agent()
+    ///     .keychain_identity("VPN Client Certificate")
+    ///     .build();
+    /// ```
+    #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+    pub fn keychain_identity(&mut self, label: &str) -> &mut Agent {
+        self.keychain_identity = Some(label.to_string());
+        self
+    }
+
+    /// Ask this verifier whether to trust the certificates of servers, such as
+    /// to trust on first use or to allow only some certificates. See
+    /// [`CertificateVerifier`](trait.CertificateVerifier.html) for an example.
+    #[cfg(feature = "tls")]
+    pub fn certificate_verifier(
+        &mut self,
+        verifier: impl CertificateVerifier + 'static,
+    ) -> &mut Agent {
+        self.tls.verifier = Some(Arc::new(verifier));
+        self
+    }
+
+    /// Use this rustls (0.16) config for the connections of this agent, such
+    /// as one with a verifier or session storage of its own. The other TLS
+    /// settings of the agent are then ignored, only roots and client identities
+    /// set on a request are added to a copy of it.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// let mut config = rustls::ClientConfig::new();
+    /// config
+    ///     .root_store
+    ///     .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    ///
+    /// let agent = ureq::agent()
+    ///     .tls_config(Arc::new(config))
+    ///     .build();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn tls_config(&mut self, config: Arc<rustls::ClientConfig>) -> &mut Agent {
+        self.tls.custom = Some(TlsConfig(config));
+        self
+    }
+
+    /// Send requests to `https` origins that advertise HTTP/3 with an `Alt-Svc`
+    /// header over HTTP/3, once a response over TCP told. Should connecting
+    /// over QUIC fail, the request goes over TCP and the advertisement is
+    /// forgotten. Connections are kept open and shared by the requests of
+    /// the agent.
+    ///
+    /// HTTP/3 trusts the webpki roots, apart from hosts of
+    /// [`accept_invalid_certs_for_host()`](#method.accept_invalid_certs_for_host),
+    /// and doesn't apply the other TLS settings or proxies, which keep a
+    /// request on TCP. Request bodies are sent once written in full.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .http3(true)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "http3")]
+    pub fn http3(&mut self, enabled: bool) -> &mut Agent {
+        self.http3 = enabled;
+        self
+    }
+
+    /// Send all requests to `https` urls over HTTP/3, without waiting for the
+    /// server to advertise it and without falling back to TCP. See
+    /// [`http3()`](#method.http3).
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .http3_only(true)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "http3")]
+    pub fn http3_only(&mut self, enabled: bool) -> &mut Agent {
+        self.http3_only = enabled;
+        self
+    }
+
+    /// Set a hook that is called for every request of this agent that fails,
+    /// with a transport error such as a refused connection, or with
+    /// [`Error::Status`](enum.Error.html#variant.Status) for a `4xx` or `5xx`
+    /// response when [`error_on_status()`](#method.error_on_status) is set.
+    ///
+    /// The hook only looks, the caller still gets the response as usual.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .on_error(|req, err| {
+    ///         eprintln!("{} {} failed: {}", req.get_method(), req.get_url(), err);
+    ///     })
+    ///     .build();
+    ///
+    /// agent.get("/my_page").call();
+    /// ```
+    pub fn on_error<F>(&mut self, hook: F) -> &mut Agent
+    where
+        F: Fn(&Request, &Error) + Send + Sync + 'static,
+    {
+        self.error_hook = Some(ErrorHook(Arc::new(hook)));
+        self
+    }
+
+    /// Set a hook that is given the headers of each `103 Early Hints` response
+    /// the server sends before the final response, such as `Link` headers of
+    /// resources to preload.
+    ///
+    /// Interim `1xx` responses are never the response of a request, they are
+    /// skipped with or without a hook.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .on_early_hints(|headers| {
+    ///         for header in headers {
+    ///             println!("hint {}: {}", header.name(), header.value());
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn on_early_hints<F>(&mut self, hook: F) -> &mut Agent
+    where
+        F: Fn(&[Header]) + Send + Sync + 'static,
+    {
+        self.early_hints = Some(EarlyHintsHook(Arc::new(hook)));
+        self
+    }
+
+    /// Set a hook that receives the exact bytes written to and read from the
+    /// connections of this agent. For https this is the plaintext inside TLS.
+    ///
+    /// ```
+    /// use ureq::Direction;
+    ///
+    /// let agent = ureq::agent()
+    ///     .on_wire(|direction, bytes| {
+    ///         let prefix = if direction == Direction::Sent { ">" } else { "<" };
+    ///         eprintln!("{} {}", prefix, String::from_utf8_lossy(bytes));
+    ///     })
+    ///     .build();
+    ///
+    /// agent.get("/my_page").call();
+    /// ```
+    pub fn on_wire<F>(&mut self, hook: F) -> &mut Agent
+    where
+        F: Fn(Direction, &[u8]) + Send + Sync + 'static,
+    {
+        self.wire_hook = Some(WireHook(Arc::new(hook)));
+        self
+    }
+
+    /// Wait `latency` before the first byte of every response, to test
+    /// timeouts. Like the other `fault_` settings, it's for testing how an
+    /// application copes with a misbehaving network or server.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let agent = ureq::agent()
+    ///     .fault_latency(Duration::from_millis(500))
+    ///     .fault_truncate_body(100)
+    ///     .build();
+    /// ```
+    pub fn fault_latency(&mut self, latency: Duration) -> &mut Agent {
+        self.faults.get_or_insert_with(Default::default).latency = Some(latency);
+        self
+    }
+
+    /// Reset the connection after `bytes` of every response, counting the head,
+    /// so reading fails with `ErrorKind::ConnectionReset`.
+    pub fn fault_reset_after(&mut self, bytes: u64) -> &mut Agent {
+        self.faults.get_or_insert_with(Default::default).reset_after = Some(bytes);
+        self
+    }
+
+    /// End the connection after `bytes` of the body of every response, as when
+    /// a server goes away mid-response.
+    pub fn fault_truncate_body(&mut self, bytes: u64) -> &mut Agent {
+        self.faults
+            .get_or_insert_with(Default::default)
+            .truncate_body = Some(bytes);
+        self
+    }
+
+    /// Break the first header of every response by taking out its colon.
+    pub fn fault_malformed_headers(&mut self, malformed: bool) -> &mut Agent {
+        self.faults
+            .get_or_insert_with(Default::default)
+            .malformed_headers = malformed;
+        self
+    }
+
+    /// Set an observer that is told the outcome, status, byte counts and phase
+    /// durations of every request made by this agent.
+    ///
+    /// See [`MetricsObserver`](trait.MetricsObserver.html) for an example.
+    pub fn metrics_observer(&mut self, observer: impl MetricsObserver + 'static) -> &mut Agent {
+        self.metrics = Some(Arc::new(observer));
+        self
+    }
+
+    /// Set a listener that is told about each step of every request made by this
+    /// agent, such as resolving, connecting, redirects and the response head.
+    ///
+    /// See [`EventListener`](trait.EventListener.html) for an example.
+    pub fn event_listener(&mut self, listener: impl EventListener + 'static) -> &mut Agent {
+        self.events = Some(Arc::new(listener));
+        self
+    }
+
+    /// Request by providing the HTTP verb such as `GET`, `POST`...
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// let r = agent
+    ///     .request("GET", "/my_page")
+    ///     .call();
+    /// println!("{:?}", r);
+    /// ```
+    pub fn request(&self, method: &str, path: &str) -> Request {
+        Request::new(self, method.into(), path.into())
+    }
+
+    /// Make a request with an already parsed url, which is used as is instead
+    /// of being turned into a string and parsed again.
+    ///
+    /// ```
+    /// let url = url::Url::parse("https://cool.server/innit").unwrap();
+    ///
+    /// let r = ureq::agent()
+    ///     .request_url("GET", &url)
+    ///     .call();
+    /// ```
+    pub fn request_url(&self, method: &str, url: &Url) -> Request {
+        Request::with_url(self, method.into(), url)
+    }
+
+    /// Subscribe to the [server-sent events](https://html.spec.whatwg.org/multipage/server-sent-events.html)
+    /// of a url. When the stream ends or fails, it is reconnected to after the
+    /// `retry:` time the server sent (3 seconds if none), with the id of the last
+    /// event as `Last-Event-ID`.
+    ///
+    /// Failed connections and reads are given as errors, and the next call of the
+    /// iterator reconnects. The iteration ends after a `204 No Content` response,
+    /// and after the error of a `4xx` response. `5xx` responses are retried.
+    ///
+    /// ```no_run
+    /// let agent = ureq::agent();
+    ///
+    /// for event in agent.subscribe("https://example.com/events") {
+    ///     match event {
+    ///         Ok(event) => println!("{}: {}", event.event(), event.data()),
+    ///         Err(e) => println!("reconnecting after {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe(&self, path: &str) -> Subscription {
+        Subscription::new(self, path)
+    }
+
+    /// Download a url into a file in ranges fetched in parallel, over the
+    /// pooled connections of the agent. A `HEAD` request first checks for
+    /// `Accept-Ranges: bytes` and the `Content-Length`, and the ranges are asked
+    /// for with `If-Range` so they are all of the same version. A range that fails
+    /// is fetched again from where it stopped.
+    ///
+    /// ```no_run
+    /// let agent = ureq::agent();
+    ///
+    /// let bytes = agent
+    ///     .download("https://example.com/big.iso")
+    ///     .segments(8)
+    ///     .to_file("big.iso")
+    ///     .unwrap();
+    /// println!("{} bytes", bytes);
+    /// ```
+    pub fn download(&self, path: &str) -> Download {
+        Download::new(self, path)
+    }
+
+    /// Make GET requests to the same host by HTTP/1.1 pipelining: all requests
+    /// are written on one connection before the responses are read, saving a
+    /// round trip per request. *Experimental*, many servers and proxies handle
+    /// pipelining badly.
+    ///
+    /// The responses are in the order of the paths, with their bodies read into
+    /// memory. Paths to another host than the first give
+    /// [`Error::BadUrl`](enum.Error.html#variant.BadUrl). Requests without a
+    /// response on the shared connection, such as when the server closes it early,
+    /// are made again one by one.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// let pages = agent.pipeline(&[
+    ///     "http://localhost/page/1",
+    ///     "http://localhost/page/2",
+    ///     "http://localhost/page/3",
+    /// ]);
+    /// for page in pages {
+    ///     println!("{}", page.status());
+    /// }
+    /// ```
+    pub fn pipeline(&self, paths: &[&str]) -> Vec<Response> {
+        let mut requests: Vec<Request> = paths.iter().map(|path| self.get(path)).collect();
+        unit::pipeline(&mut requests)
+    }
+
+    /// Gets a cookie in this agent by name. Cookies are available
+    /// either by setting it in the agent, or by making requests
+    /// that `Set-Cookie` in the agent.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// agent.get("http://www.google.com").call();
+    ///
+    /// assert!(agent.cookie("NID").is_some());
+    /// ```
+    #[cfg(feature = "cookie")]
+    pub fn cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        let state = (*self.state).as_ref()?;
+        state.jar.read().unwrap().get(name).cloned()
+    }
+
+    /// Set a cookie in this agent.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// let cookie = ureq::Cookie::new("name", "value");
+    /// agent.set_cookie(cookie);
+    /// ```
+    #[cfg(feature = "cookie")]
+    pub fn set_cookie(&self, cookie: Cookie<'static>) {
+        if let Some(state) = (*self.state).as_ref() {
+            state.jar.write().unwrap().add_original(cookie);
+        }
+    }
+
+    /// Don't keep cookies set by a response after a redirect to another site,
+    /// one that isn't the host first requested or a subdomain or parent of it.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .cookies_reject_third_party(true)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "cookie")]
+    pub fn cookies_reject_third_party(&mut self, reject: bool) -> &mut Agent {
+        self.cookie_policy.reject_third_party = reject;
+        self
+    }
+
+    /// Only keep cookies received over https that have the `Secure` attribute,
+    /// so they are never sent over plain http.
+    #[cfg(feature = "cookie")]
+    pub fn cookies_secure_only(&mut self, secure_only: bool) -> &mut Agent {
+        self.cookie_policy.secure_only = secure_only;
+        self
+    }
+
+    /// Keep at most `max` cookies per domain. Cookies over the limit are dropped,
+    /// unless they replace a cookie of the same name.
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .cookies_max_per_domain(20)
+    ///     .cookies_max_size(4096)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "cookie")]
+    pub fn cookies_max_per_domain(&mut self, max: usize) -> &mut Agent {
+        self.cookie_policy.max_per_domain = Some(max);
+        self
+    }
+
+    /// Drop cookies whose name and value are together longer than `max` bytes.
+    #[cfg(feature = "cookie")]
+    pub fn cookies_max_size(&mut self, max: usize) -> &mut Agent {
+        self.cookie_policy.max_size = Some(max);
+        self
+    }
+
+    /// Record the requests made by this agent so they can be exported as a
+    /// [HTTP Archive](http://www.softwareishard.com/blog/har-12-spec/) with
+    /// [`har()`](#method.har).
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["har"] }`
+    ///
+    /// Each exchange keeps headers, timings and the first `max_body_size` bytes
+    /// of the request and response bodies. Response bodies are recorded as they are read.
+    /// The values of `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie`
+    /// headers, and credentials in urls, are left out, see [`har_redact()`](#method.har_redact).
+    /// Entries are kept until taken with [`take_har()`](#method.take_har).
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .record_har(64 * 1024)
+    ///     .build();
+    ///
+    /// agent.get("/my_page").call();
+    ///
+    /// println!("{}", agent.har());
+    /// ```
+    #[cfg(feature = "har")]
+    pub fn record_har(&mut self, max_body_size: usize) -> &mut Agent {
+        self.har_max_body = Some(max_body_size);
+        self
+    }
+
+    /// Whether to mask credentials in the recorded HAR, see
+    /// [`record_har()`](#method.record_har). Defaults to `true`.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["har"] }`
+    ///
+    /// ```
+    /// let agent = ureq::agent()
+    ///     .record_har(64 * 1024)
+    ///     .har_redact(false)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "har")]
+    pub fn har_redact(&mut self, enabled: bool) -> &mut Agent {
+        self.har_keep_secrets = !enabled;
+        self
+    }
+
+    /// The requests recorded so far as a HAR 1.2 JSON document, see
+    /// [`record_har()`](#method.record_har).
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["har"] }`
+    #[cfg(feature = "har")]
+    pub fn har(&self) -> crate::SerdeValue {
+        match (*self.state).as_ref().and_then(|state| state.har.as_ref()) {
+            Some(recorder) => recorder.lock().unwrap().to_json(),
+            None => HarRecorder::new(0, true).to_json(),
+        }
+    }
+
+    /// Like [`har()`](#method.har), but the returned requests are removed from
+    /// the recording, so that a long-lived agent doesn't keep them all.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["har"] }`
+    ///
+    /// A response body that is still being read when its request is taken is
+    /// not recorded.
+    #[cfg(feature = "har")]
+    pub fn take_har(&self) -> crate::SerdeValue {
+        match (*self.state).as_ref().and_then(|state| state.har.as_ref()) {
+            Some(recorder) => recorder.lock().unwrap().take(),
+            None => HarRecorder::new(0, true).to_json(),
+        }
+    }
+
+    /// Iterate the pages of a listing by making GET requests that follow the
+    /// `rel="next"` [`Link`](struct.Response.html#method.links) of each page.
+    ///
+    /// The iterator ends after a page without a next link, or after a response
+    /// that is not `2xx`, which is returned as the last item.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// for page in agent.paginate("/repos/algesten/ureq/issues") {
+    ///     if !page.ok() {
+    ///         break;
+    ///     }
+    ///     println!("{}", page.into_string().unwrap());
+    /// }
+    /// ```
+    pub fn paginate(&self, url: &str) -> Pages<'_> {
+        Pages::new(self, url)
+    }
+
+    /// Make a GET request from this agent.
+    pub fn get(&self, path: &str) -> Request {
+        self.request("GET", path)
+    }
+
+    /// Make a HEAD request from this agent.
+    pub fn head(&self, path: &str) -> Request {
+        self.request("HEAD", path)
+    }
+
+    /// Make a POST request from this agent.
+    pub fn post(&self, path: &str) -> Request {
+        self.request("POST", path)
+    }
+
+    /// Make a PUT request from this agent.
+    pub fn put(&self, path: &str) -> Request {
+        self.request("PUT", path)
+    }
+
+    /// Make a DELETE request from this agent.
+    pub fn delete(&self, path: &str) -> Request {
+        self.request("DELETE", path)
+    }
+
+    /// Make a TRACE request from this agent.
+    pub fn trace(&self, path: &str) -> Request {
+        self.request("TRACE", path)
+    }
+
+    /// Make a OPTIONS request from this agent.
+    pub fn options(&self, path: &str) -> Request {
+        self.request("OPTIONS", path)
+    }
+
+    /// Make an `OPTIONS *` request from this agent, which asks about the server
+    /// rather than a resource. Only the scheme, host and port of the url are
+    /// used, the request target is `*`.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// let resp = agent.options_asterisk("http://example.com").call();
+    /// let allow = resp.header("Allow");
+    /// ```
+    pub fn options_asterisk(&self, url: &str) -> Request {
+        let mut req = self.request("OPTIONS", url);
+        req.asterisk_form = true;
+        req
+    }
+
+    /// Make a CONNECT request from this agent.
+    pub fn connect(&self, path: &str) -> Request {
+        self.request("CONNECT", path)
+    }
+
+    /// Make a PATCH request from this agent.
+    pub fn patch(&self, path: &str) -> Request {
+        self.request("PATCH", path)
+    }
+
+    /// Make a WebDAV PROPFIND request from this agent. It is sent with `Depth: 1`
+    /// unless changed with [`Request::depth()`](struct.Request.html#method.depth),
+    /// since servers often refuse the default of infinity.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// let resp = agent
+    ///     .propfind("https://cloud.example.com/remote.php/dav/files/martin/")
+    ///     .set("Content-Type", "application/xml")
+    ///     .send_string(r#"<?xml version="1.0"?><d:propfind xmlns:d="DAV:"><d:allprop/></d:propfind>"#);
+    /// ```
+    pub fn propfind(&self, path: &str) -> Request {
+        let mut req = self.request("PROPFIND", path);
+        req.depth(Depth::One);
+        req
+    }
+
+    /// Make a WebDAV PROPPATCH request from this agent.
+    pub fn proppatch(&self, path: &str) -> Request {
+        self.request("PROPPATCH", path)
+    }
+
+    /// Make a WebDAV MKCOL request from this agent, to create a collection.
+    pub fn mkcol(&self, path: &str) -> Request {
+        self.request("MKCOL", path)
+    }
+
+    /// Make a WebDAV COPY request from this agent. The `Destination` is resolved
+    /// against the url, so it can be a path on the same server.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// let resp = agent
+    ///     .copy("https://cloud.example.com/dav/notes.txt", "/dav/backup/notes.txt")
+    ///     .overwrite(false)
+    ///     .call();
+    /// ```
+    pub fn copy(&self, path: &str, destination: &str) -> Request {
+        let mut req = self.request("COPY", path);
+        webdav::set_destination(&mut req, destination);
+        req
+    }
+
+    /// Make a WebDAV MOVE request from this agent. The `Destination` is resolved
+    /// against the url, so it can be a path on the same server.
+    pub fn move_(&self, path: &str, destination: &str) -> Request {
+        let mut req = self.request("MOVE", path);
+        webdav::set_destination(&mut req, destination);
+        req
+    }
+
+    /// Make a WebDAV LOCK request from this agent.
+    pub fn lock(&self, path: &str) -> Request {
+        self.request("LOCK", path)
+    }
+
+    /// Make a WebDAV UNLOCK request from this agent, with the token given by the
+    /// LOCK as `Lock-Token`. Angle brackets around the token are added if missing.
+    pub fn unlock(&self, path: &str, token: &str) -> Request {
+        let mut req = self.request("UNLOCK", path);
+        webdav::set_lock_token(&mut req, token);
+        req
     }
 
     #[cfg(test)]
-    pub(crate) fn state(&self) -> &Arc<Mutex<Option<AgentState>>> {
-        &self.state
+    pub(crate) fn state(&self) -> Option<&AgentState> {
+        (*self.state).as_ref()
     }
 }
 
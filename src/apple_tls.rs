@@ -0,0 +1,70 @@
+use std::io::{Error as IoError, ErrorKind};
+use std::net::TcpStream;
+
+use security_framework::identity::SecIdentity;
+use security_framework::item::{ItemClass, ItemSearchOptions, Reference, SearchResult};
+use security_framework::secure_transport::{ClientBuilder, ClientHandshakeError, SslStream};
+
+use crate::error::Error;
+use crate::stream::{self, Stream};
+use crate::unit::Unit;
+
+/// Connect with TLS by the Security framework, which trusts the roots of the
+/// system and its keychains, including those installed by device management.
+pub(crate) fn connect(unit: &Unit) -> Result<Stream, Error> {
+    let hostname = unit.url.host_str().unwrap();
+    let port = unit.url.port().unwrap_or(443);
+
+    let mut builder = ClientBuilder::new();
+    let label = (*unit.agent)
+        .as_ref()
+        .and_then(|agent| agent.keychain_identity.as_deref());
+    if let Some(label) = label {
+        builder.identity(&find_identity(label)?, &[]);
+    }
+
+    let sock = stream::connect_host(unit, hostname, port)?;
+    let stream = handshake(&builder, hostname, sock)?;
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(sni = hostname, "security framework tls session started");
+
+    Ok(Stream::Apple(Box::new(stream)))
+}
+
+fn handshake(
+    builder: &ClientBuilder,
+    hostname: &str,
+    sock: TcpStream,
+) -> Result<SslStream<TcpStream>, Error> {
+    match builder.handshake(hostname, sock) {
+        Ok(stream) => Ok(stream),
+        Err(ClientHandshakeError::Failure(err)) => Err(Error::Tls(IoError::new(
+            ErrorKind::InvalidData,
+            format!("TLS handshake with {} failed: {}", hostname, err),
+        ))),
+        // the socket blocks, so this is a timeout.
+        Err(ClientHandshakeError::Interrupted(_)) => {
+            Err(IoError::new(ErrorKind::TimedOut, "TLS handshake timed out").into())
+        }
+    }
+}
+
+/// The identity, a certificate with its private key, of that label in the
+/// keychains. The key stays in the keychain, so it may not be exportable.
+fn find_identity(label: &str) -> Result<SecIdentity, Error> {
+    let results = ItemSearchOptions::new()
+        .class(ItemClass::identity())
+        .label(label)
+        .load_refs(true)
+        .limit(1)
+        .search()
+        .map_err(|e| Error::BadCertificate(format!("Keychain search failed: {}", e)))?;
+    results
+        .into_iter()
+        .find_map(|result| match result {
+            SearchResult::Ref(Reference::Identity(identity)) => Some(identity),
+            _ => None,
+        })
+        .ok_or_else(|| Error::BadCertificate(format!("No identity in keychain: {}", label)))
+}
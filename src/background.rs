@@ -0,0 +1,234 @@
+use std::io::Error as IoError;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::thread;
+
+use crate::agent::AgentState;
+use crate::error::Error;
+use crate::request::Request;
+use crate::response::Response;
+
+/// Threads started for background requests when the agent doesn't say.
+pub(crate) const DEFAULT_BACKGROUND_THREADS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The threads making the background requests of an agent, started as they
+/// are needed. They end when the agent is dropped.
+///
+/// *Internal API*
+pub(crate) struct Workers {
+    max: usize,
+    sender: Mutex<Sender<Job>>,
+    receiver: Arc<Mutex<Receiver<Job>>>,
+    threads: AtomicUsize,
+    idle: Arc<AtomicUsize>,
+}
+
+impl ::std::fmt::Debug for Workers {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "Workers")
+    }
+}
+
+impl Workers {
+    pub fn new(max: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Workers {
+            max: max.max(1),
+            sender: Mutex::new(sender),
+            receiver: Arc::new(Mutex::new(receiver)),
+            threads: AtomicUsize::new(0),
+            idle: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn submit(&self, job: Job) {
+        self.sender.lock().unwrap().send(job).ok();
+        if self.idle.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+        let started = self
+            .threads
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n < self.max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            });
+        if started.is_err() {
+            // all threads are busy, the job waits for one.
+            return;
+        }
+        let receiver = self.receiver.clone();
+        let idle = self.idle.clone();
+        thread::spawn(move || loop {
+            idle.fetch_add(1, Ordering::SeqCst);
+            let job = receiver.lock().unwrap().recv();
+            idle.fetch_sub(1, Ordering::SeqCst);
+            match job {
+                Ok(job) => job(),
+                // the agent is gone.
+                Err(_) => return,
+            }
+        });
+    }
+}
+
+/// A request made in the background, see
+/// [`Request::call_background()`](struct.Request.html#method.call_background).
+///
+/// Dropping the handle doesn't stop the request, its response is then dropped.
+/// With the `async` feature, the handle is also a `Future` of the response.
+pub struct ResponseHandle {
+    receiver: Receiver<Response>,
+    cancelled: Arc<AtomicBool>,
+    /// Woken when the response is there, if awaited.
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl ::std::fmt::Debug for ResponseHandle {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "ResponseHandle")
+    }
+}
+
+impl ResponseHandle {
+    /// Wait for the response.
+    pub fn join(self) -> Response {
+        self.receiver.recv().unwrap_or_else(|_| failed())
+    }
+
+    /// The response if the request is done, otherwise the handle back.
+    pub fn try_join(self) -> Result<Response, ResponseHandle> {
+        match self.receiver.try_recv() {
+            Ok(resp) => Ok(resp),
+            Err(TryRecvError::Empty) => Err(self),
+            Err(TryRecvError::Disconnected) => Ok(failed()),
+        }
+    }
+
+    /// Give up on the request. One that didn't start yet is never sent, one
+    /// under way goes on but its response is dropped.
+    pub fn cancel(self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "async")]
+impl std::future::Future for ResponseHandle {
+    type Output = Response;
+
+    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Response> {
+        // the waker goes in before looking, so a response sent in between wakes it.
+        *self.waker.lock().unwrap() = Some(cx.waker().clone());
+        match self.receiver.try_recv() {
+            Ok(resp) => Poll::Ready(resp),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => Poll::Ready(failed()),
+        }
+    }
+}
+
+struct WakeOnDrop(Arc<Mutex<Option<Waker>>>);
+
+impl Drop for WakeOnDrop {
+    fn drop(&mut self) {
+        let waker = self.0.lock().map(|mut w| w.take()).unwrap_or(None);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// The thread of the request panicked.
+fn failed() -> Response {
+    let err = IoError::other("Background request failed");
+    Error::Io(err).into()
+}
+
+/// Make the request on a thread of its agent.
+pub(crate) fn spawn(state: &Option<AgentState>, mut req: Request) -> ResponseHandle {
+    let (sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let waker: Arc<Mutex<Option<Waker>>> = Arc::new(Mutex::new(None));
+    let flag = cancelled.clone();
+    let wake = waker.clone();
+    let job: Job = Box::new(move || {
+        // wakes after the sender is dropped, even by a panic.
+        let _wake = WakeOnDrop(wake);
+        let sender = sender;
+        if flag.load(Ordering::SeqCst) {
+            return;
+        }
+        let resp = req.call();
+        if !flag.load(Ordering::SeqCst) {
+            sender.send(resp).ok();
+        }
+    });
+    match state {
+        Some(state) => state.workers.submit(job),
+        // no agent to own the threads.
+        None => {
+            thread::spawn(job);
+        }
+    }
+    ResponseHandle {
+        receiver,
+        cancelled,
+        waker,
+    }
+}
+
+/// A request made with `.await`, see [`ureq::async_get()`](fn.async_get.html).
+///
+/// It derefs to the [`Request`](struct.Request.html) for setting it up, while
+/// its own [`call()`](#method.call) gives a future of the response.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone)]
+pub struct AsyncRequest(Request);
+
+#[cfg(feature = "async")]
+impl AsyncRequest {
+    /// Executes the request on a thread of the agent, see
+    /// [`Request::call_background()`](struct.Request.html#method.call_background).
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let resp = ureq::async_get("/my_page").call().await;
+    /// println!("{}", resp.status());
+    /// # }
+    /// ```
+    pub fn call(&mut self) -> ResponseHandle {
+        self.0.call_background()
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<Request> for AsyncRequest {
+    fn from(req: Request) -> Self {
+        AsyncRequest(req)
+    }
+}
+
+#[cfg(feature = "async")]
+impl std::ops::Deref for AsyncRequest {
+    type Target = Request;
+
+    fn deref(&self) -> &Request {
+        &self.0
+    }
+}
+
+#[cfg(feature = "async")]
+impl std::ops::DerefMut for AsyncRequest {
+    fn deref_mut(&mut self) -> &mut Request {
+        &mut self.0
+    }
+}
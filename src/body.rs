@@ -1,6 +1,9 @@
 use crate::stream::Stream;
-use chunked_transfer;
-use std::io::{copy, empty, Cursor, Read, Result as IoResult};
+use std::fmt;
+use std::fs::File;
+use std::io::{empty, Cursor, Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::path::Path;
+use std::sync::Arc;
 
 #[cfg(feature = "charset")]
 use crate::response::DEFAULT_CHARACTER_SET;
@@ -11,19 +14,24 @@ use encoding::EncoderTrap;
 
 #[cfg(feature = "json")]
 use super::SerdeValue;
-#[cfg(feature = "json")]
-use serde_json;
 
 /// The different kinds of bodies to send.
 ///
 /// *Internal API*
+#[derive(Default)]
 pub(crate) enum Payload {
+    #[default]
     Empty,
     Text(String, String),
     #[cfg(feature = "json")]
-    JSON(SerdeValue),
+    Json(SerdeValue),
     Reader(Box<dyn Read + 'static>),
     Bytes(Vec<u8>),
+    /// A reader of a known length.
+    Sized(Box<dyn Read + 'static>, usize),
+    Chunks(Box<dyn Iterator<Item = Vec<u8>> + 'static>),
+    /// A body that can be sent again.
+    Body(Body),
 }
 
 impl ::std::fmt::Debug for Payload {
@@ -32,16 +40,152 @@ impl ::std::fmt::Debug for Payload {
             Payload::Empty => write!(f, "Empty"),
             Payload::Text(t, _) => write!(f, "{}", t),
             #[cfg(feature = "json")]
-            Payload::JSON(_) => write!(f, "JSON"),
+            Payload::Json(_) => write!(f, "JSON"),
             Payload::Reader(_) => write!(f, "Reader"),
             Payload::Bytes(v) => write!(f, "{:?}", v),
+            Payload::Sized(_, len) => write!(f, "Sized[{}]", len),
+            Payload::Chunks(_) => write!(f, "Chunks"),
+            Payload::Body(body) => write!(f, "{:?}", body),
         }
     }
 }
 
-impl Default for Payload {
-    fn default() -> Payload {
-        Payload::Empty
+/// A request body that can be sent more than once, so it is sent again when
+/// following a `307` or `308` redirect, or when retrying on a new connection.
+/// See [`Request::send_body()`](struct.Request.html#method.send_body).
+///
+/// The bodies of [`send_string()`](struct.Request.html#method.send_string),
+/// [`send_bytes()`](struct.Request.html#method.send_bytes),
+/// [`send_json()`](struct.Request.html#method.send_json) and
+/// [`send_file()`](struct.Request.html#method.send_file) are sent again too,
+/// but not those read from a reader with [`send()`](struct.Request.html#method.send).
+///
+/// ```
+/// let body = ureq::Body::text("Hello world!");
+///
+/// let r = ureq::post("/my_page")
+///     .send_body(body.clone());
+/// ```
+#[derive(Clone)]
+pub struct Body(BodyKind);
+
+#[derive(Clone)]
+enum BodyKind {
+    Empty,
+    Bytes(Arc<[u8]>),
+    /// The path of the file and its size, opened again for each reader.
+    File(Arc<Path>, u64),
+}
+
+impl ::std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        match &self.0 {
+            BodyKind::Empty => write!(f, "Body[empty]"),
+            BodyKind::Bytes(bytes) => write!(f, "Body[{} bytes]", bytes.len()),
+            BodyKind::File(_, len) => write!(f, "Body[file, {} bytes]", len),
+        }
+    }
+}
+
+impl Body {
+    /// A body of bytes.
+    pub fn bytes(data: impl Into<Vec<u8>>) -> Body {
+        Body(BodyKind::Bytes(data.into().into()))
+    }
+
+    /// A body of text, sent as utf-8.
+    pub fn text(text: &str) -> Body {
+        Body::bytes(text.as_bytes())
+    }
+
+    /// A body of the contents of a file, opened and read again each time it is
+    /// sent. The size is taken when opening the file, and must not change.
+    pub fn file(path: impl AsRef<Path>) -> IoResult<Body> {
+        let path = path.as_ref();
+        let len = File::open(path)?.metadata()?.len();
+        Ok(Body(BodyKind::File(path.into(), len)))
+    }
+
+    pub(crate) fn empty() -> Body {
+        Body(BodyKind::Empty)
+    }
+
+    /// A reader from the start of the body.
+    pub(crate) fn to_read(&self) -> SizedReader {
+        let mut reader = match &self.0 {
+            BodyKind::Empty => SizedReader::new(None, Box::new(empty())),
+            BodyKind::Bytes(bytes) => {
+                let cursor = Cursor::new(Arc::clone(bytes));
+                SizedReader::new(Some(bytes.len()), Box::new(cursor))
+            }
+            BodyKind::File(path, len) => {
+                let file = FileRead {
+                    path: Arc::clone(path),
+                    file: None,
+                };
+                let reader = ExactRead {
+                    reader: Box::new(file),
+                    left: *len,
+                };
+                SizedReader::new(Some(*len as usize), Box::new(reader))
+            }
+        };
+        if let BodyKind::Empty = self.0 {
+            reader.preview = None;
+        }
+        reader.replay = Some(self.clone());
+        reader
+    }
+}
+
+/// Reads a file from its start, opening it on the first read so that each
+/// reader of a body has its own offset.
+struct FileRead {
+    path: Arc<Path>,
+    file: Option<File>,
+}
+
+impl Read for FileRead {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.file.is_none() {
+            self.file = Some(File::open(&self.path)?);
+        }
+        self.file.as_mut().unwrap().read(buf)
+    }
+}
+
+/// Chars of a text body kept to show in the `Debug` of a request.
+const PREVIEW_CHARS: usize = 100;
+
+/// What a request sent as body, shown by its `Debug`.
+///
+/// *Internal API*
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BodyPreview {
+    pub size: Option<usize>,
+    /// The start of a string or JSON body.
+    pub text: Option<String>,
+}
+
+/// The start of a text, to preview.
+fn truncate(text: &str) -> String {
+    let mut preview: String = text.chars().take(PREVIEW_CHARS).collect();
+    if preview.len() < text.len() {
+        preview.push_str("...");
+    }
+    preview
+}
+
+impl fmt::Display for BodyPreview {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.size {
+            Some(size) => write!(f, "{} bytes", size)?,
+            None => write!(f, "unknown size")?,
+        }
+        match &self.text {
+            Some(text) => write!(f, ", {:?}", text),
+            None => Ok(()),
+        }
     }
 }
 
@@ -51,6 +195,10 @@ impl Default for Payload {
 pub(crate) struct SizedReader {
     pub size: Option<usize>,
     pub reader: Box<dyn Read + 'static>,
+    /// `None` for no body.
+    pub preview: Option<BodyPreview>,
+    /// To send the body again, if it can be.
+    pub replay: Option<Body>,
 }
 
 impl ::std::fmt::Debug for SizedReader {
@@ -61,15 +209,38 @@ impl ::std::fmt::Debug for SizedReader {
 
 impl SizedReader {
     fn new(size: Option<usize>, reader: Box<dyn Read + 'static>) -> Self {
-        SizedReader { size, reader }
+        SizedReader {
+            size,
+            reader,
+            preview: Some(BodyPreview { size, text: None }),
+            replay: None,
+        }
+    }
+
+    /// Show the start of a text body.
+    fn with_text(mut self, text: String) -> Self {
+        if let Some(preview) = self.preview.as_mut() {
+            preview.text = Some(text);
+        }
+        self
+    }
+
+    /// The body from its start again, if it can be sent again.
+    pub fn rewind(&self) -> Option<SizedReader> {
+        let replay = self.replay.as_ref()?;
+        let mut reader = replay.to_read();
+        // keep the text preview.
+        reader.preview = self.preview.clone();
+        Some(reader)
     }
 }
 
 impl Payload {
     pub fn into_read(self) -> SizedReader {
         match self {
-            Payload::Empty => SizedReader::new(None, Box::new(empty())),
+            Payload::Empty => Body::empty().to_read(),
             Payload::Text(text, _charset) => {
+                let preview = truncate(&text);
                 #[cfg(feature = "charset")]
                 let bytes = {
                     let encoding = encoding_from_whatwg_label(&_charset)
@@ -79,39 +250,165 @@ impl Payload {
                 };
                 #[cfg(not(feature = "charset"))]
                 let bytes = text.into_bytes();
-                let len = bytes.len();
-                let cursor = Cursor::new(bytes);
-                SizedReader::new(Some(len), Box::new(cursor))
+                Body::bytes(bytes).to_read().with_text(preview)
             }
             #[cfg(feature = "json")]
-            Payload::JSON(v) => {
-                let bytes = serde_json::to_vec(&v).expect("Bad JSON in payload");
-                let len = bytes.len();
-                let cursor = Cursor::new(bytes);
-                SizedReader::new(Some(len), Box::new(cursor))
+            Payload::Json(v) => {
+                let bytes = crate::json::to_vec(&v);
+                let text = truncate(&String::from_utf8_lossy(&bytes));
+                Body::bytes(bytes).to_read().with_text(text)
             }
             Payload::Reader(read) => SizedReader::new(None, read),
-            Payload::Bytes(bytes) => {
-                let len = bytes.len();
-                let cursor = Cursor::new(bytes);
-                SizedReader::new(Some(len), Box::new(cursor))
+            Payload::Bytes(bytes) => Body::bytes(bytes).to_read(),
+            Payload::Sized(reader, len) => SizedReader::new(
+                Some(len),
+                Box::new(ExactRead {
+                    reader,
+                    left: len as u64,
+                }),
+            ),
+            Payload::Chunks(chunks) => SizedReader::new(
+                None,
+                Box::new(ChunksRead {
+                    chunks,
+                    current: Cursor::new(vec![]),
+                }),
+            ),
+            Payload::Body(body) => body.to_read(),
+        }
+    }
+}
+
+/// Fails unless the reader gives exactly as many bytes as declared, so
+/// that a `Content-Length` is never wrong.
+struct ExactRead {
+    reader: Box<dyn Read>,
+    left: u64,
+}
+
+impl Read for ExactRead {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.left == 0 {
+            // must be at the end of the reader.
+            return match self.reader.read(&mut [0_u8])? {
+                0 => Ok(0),
+                _ => Err(IoError::new(
+                    ErrorKind::InvalidInput,
+                    "Body is longer than its declared length",
+                )),
+            };
+        }
+        let max = buf.len().min(self.left.min(usize::MAX as u64) as usize);
+        let amount = self.reader.read(&mut buf[..max])?;
+        if amount == 0 {
+            return Err(IoError::new(
+                ErrorKind::UnexpectedEof,
+                "Body is shorter than its declared length",
+            ));
+        }
+        self.left -= amount as u64;
+        Ok(amount)
+    }
+}
+
+/// Reads the items of an iterator, never more than one item per read, so that
+/// each item becomes its own chunk when sent chunked.
+struct ChunksRead {
+    chunks: Box<dyn Iterator<Item = Vec<u8>>>,
+    current: Cursor<Vec<u8>>,
+}
+
+impl Read for ChunksRead {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        loop {
+            let amount = self.current.read(buf)?;
+            if amount > 0 || buf.is_empty() {
+                return Ok(amount);
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.current = Cursor::new(chunk),
+                None => return Ok(0),
             }
         }
     }
 }
 
-/// Helper to send a body, either as chunked or not.
+/// Largest chunk sent for a chunked body, unless the request sets another.
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 8192;
+
+/// Size of the reads of a body that isn't sent chunked.
+const COPY_SIZE: usize = 8192;
+
+/// Asked before each write of the body whether to stop sending it.
+pub(crate) type StopFn<'a> = dyn FnMut(&mut Stream) -> bool + 'a;
+
+/// Send each read of the reader as a chunk, returning the body bytes sent.
+#[allow(clippy::write_with_newline)]
+fn send_chunked(
+    reader: &mut dyn Read,
+    chunk_size: usize,
+    stream: &mut Stream,
+    stop: &mut StopFn,
+) -> IoResult<u64> {
+    let mut buf = vec![0_u8; chunk_size.max(1)];
+    let mut frame = Vec::with_capacity(buf.len() + 12);
+    let mut total = 0;
+    loop {
+        let amount = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(amount) => amount,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if stop(stream) {
+            // no last chunk, the connection isn't reused.
+            return Ok(total);
+        }
+        frame.clear();
+        write!(frame, "{:x}\r\n", amount)?;
+        frame.extend_from_slice(&buf[..amount]);
+        frame.extend_from_slice(b"\r\n");
+        stream.write_all(&frame)?;
+        total += amount as u64;
+    }
+    stream.write_all(b"0\r\n\r\n")?;
+    Ok(total)
+}
+
+/// Send the reads of the reader as they are, returning the bytes sent.
+fn send_plain(reader: &mut dyn Read, stream: &mut Stream, stop: &mut StopFn) -> IoResult<u64> {
+    let mut buf = vec![0_u8; COPY_SIZE];
+    let mut total = 0;
+    loop {
+        let amount = match reader.read(&mut buf) {
+            Ok(0) => return Ok(total),
+            Ok(amount) => amount,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if stop(stream) {
+            return Ok(total);
+        }
+        stream.write_all(&buf[..amount])?;
+        total += amount as u64;
+    }
+}
+
+/// Helper to send a body, either as chunked or not. `stop` can end it early,
+/// leaving the body incomplete.
 pub(crate) fn send_body(
     mut body: SizedReader,
     do_chunk: bool,
+    chunk_size: usize,
     stream: &mut Stream,
+    stop: &mut StopFn,
 ) -> IoResult<u64> {
-    let n = if do_chunk {
-        let mut chunker = chunked_transfer::Encoder::new(stream);
-        copy(&mut body.reader, &mut chunker)?
+    if do_chunk {
+        send_chunked(&mut body.reader, chunk_size, stream, stop)
     } else {
-        copy(&mut body.reader, stream)?
-    };
-
-    Ok(n)
+        send_plain(&mut body.reader, stream, stop)
+    }
 }
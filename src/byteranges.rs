@@ -0,0 +1,296 @@
+use std::io::{self, Error as IoError, ErrorKind, Read, Result as IoResult};
+
+use crate::header::{self, Header};
+
+/// The parts of a `206 Partial Content` response, see
+/// [`Response::into_byte_ranges()`](struct.Response.html#method.into_byte_ranges).
+///
+/// ```
+/// use std::io::Read;
+///
+/// let resp = "HTTP/1.1 206 Partial Content\r\n\
+///     Content-Type: multipart/byteranges; boundary=SEP\r\n\
+///     \r\n\
+///     --SEP\r\n\
+///     Content-Range: bytes 0-4/20\r\n\
+///     \r\n\
+///     Hello\r\n\
+///     --SEP\r\n\
+///     Content-Range: bytes 15-19/20\r\n\
+///     \r\n\
+///     World\r\n\
+///     --SEP--\r\n"
+///     .parse::<ureq::Response>()
+///     .unwrap();
+///
+/// let mut ranges = resp.into_byte_ranges().unwrap();
+/// while let Some(mut part) = ranges.next_part().unwrap() {
+///     let (first, last) = part.range().unwrap();
+///     let mut body = String::new();
+///     part.read_to_string(&mut body).unwrap();
+///     println!("{}-{}: {}", first, last, body);
+/// }
+/// ```
+pub struct ByteRanges {
+    scanner: Scanner,
+    /// `\r\n--boundary`, empty for a body that is a single range.
+    delimiter: Vec<u8>,
+    /// The headers of the single range, until it is given.
+    single: Option<Vec<Header>>,
+    /// Whether the body of the current part was read up to the delimiter.
+    part_done: bool,
+    done: bool,
+}
+
+impl ::std::fmt::Debug for ByteRanges {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "ByteRanges")
+    }
+}
+
+/// A part of [`ByteRanges`](struct.ByteRanges.html), reading its body.
+pub struct RangePart<'a> {
+    headers: Vec<Header>,
+    ranges: &'a mut ByteRanges,
+}
+
+impl ::std::fmt::Debug for RangePart<'_> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "RangePart{:?}", self.headers)
+    }
+}
+
+impl ByteRanges {
+    pub(crate) fn multipart(reader: Box<dyn Read>, boundary: &str) -> Self {
+        let mut scanner = Scanner::new(reader);
+        // the first delimiter needs no line break before it.
+        scanner.buf.extend_from_slice(b"\r\n");
+        ByteRanges {
+            scanner,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            single: None,
+            part_done: false,
+            done: false,
+        }
+    }
+
+    pub(crate) fn single(reader: Box<dyn Read>, headers: Vec<Header>) -> Self {
+        ByteRanges {
+            scanner: Scanner::new(reader),
+            delimiter: vec![],
+            single: Some(headers),
+            part_done: true,
+            done: false,
+        }
+    }
+
+    /// The next part, `None` after the last one. What is left of the body of
+    /// the previous part is skipped.
+    pub fn next_part(&mut self) -> IoResult<Option<RangePart<'_>>> {
+        if self.done {
+            return Ok(None);
+        }
+        if let Some(headers) = self.single.take() {
+            self.done = true;
+            return Ok(Some(RangePart {
+                headers,
+                ranges: self,
+            }));
+        }
+        // the preamble, or the rest of the previous part.
+        while !self.part_done {
+            io::copy(&mut PartRead(self), &mut io::sink())?;
+        }
+        self.scanner.consume(self.delimiter.len());
+        let after = self.scanner.read_line()?;
+        if after.starts_with("--") {
+            self.done = true;
+            // read the epilogue so the connection can be reused.
+            io::copy(&mut self.scanner, &mut io::sink())?;
+            return Ok(None);
+        }
+        let mut headers = vec![];
+        loop {
+            let line = self.scanner.read_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Ok(header) = line.parse::<Header>() {
+                headers.push(header);
+            }
+        }
+        self.part_done = false;
+        Ok(Some(RangePart {
+            headers,
+            ranges: self,
+        }))
+    }
+
+    /// Read the body of the current part, up to the delimiter.
+    fn read_part(&mut self, out: &mut [u8]) -> IoResult<usize> {
+        if self.delimiter.is_empty() {
+            return self.scanner.read(out);
+        }
+        if self.part_done || out.is_empty() {
+            return Ok(0);
+        }
+        let len = self.delimiter.len();
+        self.scanner.fill(len)?;
+        let avail = self.scanner.available();
+        let found = avail.windows(len).position(|w| w == &self.delimiter[..]);
+        let amount = match found {
+            Some(0) => {
+                self.part_done = true;
+                return Ok(0);
+            }
+            Some(at) => at,
+            None if avail.len() < len => {
+                return Err(IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    "Multipart body cut short",
+                ));
+            }
+            // keep what could be the start of the delimiter.
+            None => avail.len() - (len - 1),
+        };
+        let amount = amount.min(out.len());
+        out[..amount].copy_from_slice(&avail[..amount]);
+        self.scanner.consume(amount);
+        Ok(amount)
+    }
+}
+
+impl RangePart<'_> {
+    /// The headers of the part, usually `Content-Range` and `Content-Type`.
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    /// The value of a header of the part.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header::get_header(&self.headers, name)
+    }
+
+    /// The first and last byte of the range, both included, from the
+    /// `Content-Range` header like `bytes 0-499/1234`.
+    pub fn range(&self) -> Option<(u64, u64)> {
+        let (range, _) = self.content_range()?;
+        let (first, last) = range.split_once('-')?;
+        Some((first.trim().parse().ok()?, last.trim().parse().ok()?))
+    }
+
+    /// The length of the whole resource, unless the server doesn't know it.
+    pub fn total_len(&self) -> Option<u64> {
+        let (_, total) = self.content_range()?;
+        total.trim().parse().ok()
+    }
+
+    fn content_range(&self) -> Option<(&str, &str)> {
+        let value = self.header("content-range")?.trim();
+        let (unit, rest) = value.split_once(' ')?;
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+        rest.split_once('/')
+    }
+}
+
+impl Read for RangePart<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.ranges.read_part(buf)
+    }
+}
+
+struct PartRead<'a>(&'a mut ByteRanges);
+
+impl Read for PartRead<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.0.read_part(buf)
+    }
+}
+
+/// A buffer over the body that can be searched ahead of reading.
+struct Scanner {
+    reader: Box<dyn Read>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Scanner {
+    fn new(reader: Box<dyn Read>) -> Self {
+        Scanner {
+            reader,
+            buf: vec![],
+            pos: 0,
+        }
+    }
+
+    fn available(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.buf.len());
+    }
+
+    /// Buffer at least `min` bytes, fewer only at the end of the body.
+    fn fill(&mut self, min: usize) -> IoResult<()> {
+        if self.available().len() >= min {
+            return Ok(());
+        }
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        let mut chunk = [0_u8; 8192];
+        while self.buf.len() < min {
+            let amount = self.reader.read(&mut chunk)?;
+            if amount == 0 {
+                break;
+            }
+            self.buf.extend_from_slice(&chunk[..amount]);
+        }
+        Ok(())
+    }
+
+    /// A line without its line break.
+    fn read_line(&mut self) -> IoResult<String> {
+        let mut line = vec![];
+        loop {
+            self.fill(1)?;
+            let avail = self.available();
+            if avail.is_empty() {
+                return Err(IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    "Multipart body cut short",
+                ));
+            }
+            match avail.iter().position(|b| *b == b'\n') {
+                Some(at) => {
+                    line.extend_from_slice(&avail[..at]);
+                    self.consume(at + 1);
+                    break;
+                }
+                None => {
+                    let amount = avail.len();
+                    line.extend_from_slice(avail);
+                    self.consume(amount);
+                }
+            }
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+impl Read for Scanner {
+    fn read(&mut self, out: &mut [u8]) -> IoResult<usize> {
+        if self.available().is_empty() {
+            return self.reader.read(out);
+        }
+        let amount = self.available().len().min(out.len());
+        out[..amount].copy_from_slice(&self.available()[..amount]);
+        self.consume(amount);
+        Ok(amount)
+    }
+}
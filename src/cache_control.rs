@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use crate::header;
+
+/// Delta seconds too large to parse are taken as this, per RFC 9111.
+const MAX_DELTA_SECONDS: u64 = 1 << 31;
+
+/// The directives of `Cache-Control` headers, see
+/// [`Response::cache_control()`](struct.Response.html#method.cache_control).
+///
+/// Directive names are case insensitive. When a directive is given more than
+/// once, the first one counts. A duration that isn't a number is taken as `0`,
+/// so the response is stale, as RFC 9111 asks of caches.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// let cc = ureq::CacheControl::parse("public, max-age=60, stale-while-revalidate=30");
+/// assert!(cc.public());
+/// assert_eq!(cc.max_age(), Some(Duration::from_secs(60)));
+/// assert_eq!(cc.stale_while_revalidate(), Some(Duration::from_secs(30)));
+/// assert!(!cc.no_store());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    directives: Vec<(String, Option<String>)>,
+}
+
+impl CacheControl {
+    /// Parse a header value like `no-cache="Set-Cookie", max-age=0`. Malformed
+    /// parts are skipped.
+    pub fn parse(value: &str) -> CacheControl {
+        let mut cc = CacheControl::default();
+        cc.add(value);
+        cc
+    }
+
+    /// Add the directives of another value of the header.
+    pub(crate) fn add(&mut self, value: &str) {
+        let mut chars = value.chars().peekable();
+        loop {
+            header::skip(&mut chars, |c| c.is_whitespace() || c == ',');
+            if chars.peek().is_none() {
+                break;
+            }
+            let name = header::take_token(&mut chars).to_ascii_lowercase();
+            header::skip(&mut chars, char::is_whitespace);
+            let arg = if chars.peek() == Some(&'=') {
+                chars.next();
+                header::skip(&mut chars, char::is_whitespace);
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    Some(header::take_quoted(&mut chars))
+                } else {
+                    Some(header::take_token(&mut chars))
+                }
+            } else {
+                None
+            };
+            // anything up to the next directive is garbage.
+            header::skip(&mut chars, |c| c != ',');
+            if !name.is_empty() {
+                self.directives.push((name, arg));
+            }
+        }
+    }
+
+    /// Whether the directive is present, with or without an argument.
+    pub fn has(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    /// The argument of a directive, unquoted, such as of an extension.
+    /// `None` when absent or without an argument.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.find(name)?.as_deref()
+    }
+
+    /// All directives in order, with lowercased names.
+    pub fn directives(&self) -> &[(String, Option<String>)] {
+        &self.directives
+    }
+
+    /// `max-age`, how long the response is fresh.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.seconds("max-age")
+    }
+
+    /// `s-maxage`, how long the response is fresh in shared caches.
+    pub fn s_maxage(&self) -> Option<Duration> {
+        self.seconds("s-maxage")
+    }
+
+    /// `stale-while-revalidate`, how long a stale response may be used while
+    /// it is revalidated in the background.
+    pub fn stale_while_revalidate(&self) -> Option<Duration> {
+        self.seconds("stale-while-revalidate")
+    }
+
+    /// `stale-if-error`, how long a stale response may be used when
+    /// revalidating it fails.
+    pub fn stale_if_error(&self) -> Option<Duration> {
+        self.seconds("stale-if-error")
+    }
+
+    /// `no-cache`, the response must be revalidated before each use. Can list
+    /// header names, see [`get()`](#method.get).
+    pub fn no_cache(&self) -> bool {
+        self.has("no-cache")
+    }
+
+    /// `no-store`, the response must not be stored at all.
+    pub fn no_store(&self) -> bool {
+        self.has("no-store")
+    }
+
+    /// `no-transform`.
+    pub fn no_transform(&self) -> bool {
+        self.has("no-transform")
+    }
+
+    /// `must-revalidate`, a stale response must not be used without revalidating.
+    pub fn must_revalidate(&self) -> bool {
+        self.has("must-revalidate")
+    }
+
+    /// `proxy-revalidate`, like `must-revalidate` for shared caches.
+    pub fn proxy_revalidate(&self) -> bool {
+        self.has("proxy-revalidate")
+    }
+
+    /// `public`.
+    pub fn public(&self) -> bool {
+        self.has("public")
+    }
+
+    /// `private`, only a private cache may store the response.
+    pub fn private(&self) -> bool {
+        self.has("private")
+    }
+
+    /// `immutable`, the response won't change while it is fresh.
+    pub fn immutable(&self) -> bool {
+        self.has("immutable")
+    }
+
+    fn find(&self, name: &str) -> Option<&Option<String>> {
+        self.directives
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, arg)| arg)
+    }
+
+    fn seconds(&self, name: &str) -> Option<Duration> {
+        let arg = self.find(name)?.as_deref().unwrap_or("");
+        let secs = if !arg.is_empty() && arg.bytes().all(|b| b.is_ascii_digit()) {
+            arg.parse()
+                .unwrap_or(MAX_DELTA_SECONDS)
+                .min(MAX_DELTA_SECONDS)
+        } else {
+            0
+        };
+        Some(Duration::from_secs(secs))
+    }
+}
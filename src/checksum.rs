@@ -0,0 +1,81 @@
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult};
+
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::error::Error;
+
+/// Digest algorithms for
+/// [`Request::verify_checksum()`](struct.Request.html#method.verify_checksum).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// SHA-256, 64 hex digits.
+    Sha256,
+    /// SHA-512, 128 hex digits.
+    Sha512,
+}
+
+/// The digest a response body must have.
+///
+/// *Internal API*
+#[derive(Clone, Debug)]
+pub(crate) struct Checksum {
+    pub algorithm: Algorithm,
+    /// Lowercased hex.
+    pub expected: String,
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+/// Hashes everything read, and fails the read at the end of the body if the
+/// digest is not the expected one.
+pub(crate) struct VerifyRead<R> {
+    reader: R,
+    hasher: Option<Hasher>,
+    expected: String,
+}
+
+impl<R: Read> VerifyRead<R> {
+    pub fn new(reader: R, checksum: Checksum) -> Self {
+        let hasher = match checksum.algorithm {
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        };
+        VerifyRead {
+            reader,
+            hasher: Some(hasher),
+            expected: checksum.expected,
+        }
+    }
+}
+
+impl<R: Read> Read for VerifyRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let amount = self.reader.read(buf)?;
+        if amount > 0 {
+            match self.hasher.as_mut() {
+                Some(Hasher::Sha256(h)) => h.update(&buf[..amount]),
+                Some(Hasher::Sha512(h)) => h.update(&buf[..amount]),
+                None => (),
+            }
+        } else if !buf.is_empty() {
+            // the end of the body, verify once.
+            let actual = match self.hasher.take() {
+                Some(Hasher::Sha256(h)) => hex(&h.finalize()),
+                Some(Hasher::Sha512(h)) => hex(&h.finalize()),
+                None => return Ok(0),
+            };
+            if actual != self.expected {
+                let err = Error::ChecksumMismatch(self.expected.clone(), actual);
+                return Err(IoError::new(ErrorKind::InvalidData, err));
+            }
+        }
+        Ok(amount)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
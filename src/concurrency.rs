@@ -0,0 +1,79 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::agent::AgentState;
+use crate::error::Error;
+use crate::time::Instant;
+
+/// How many requests may be in flight at once, see
+/// [`Agent::max_concurrent_requests()`](struct.Agent.html#method.max_concurrent_requests).
+///
+/// *Internal API*
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ConcurrencyConfig {
+    pub max: usize,
+    /// How long a request waits for a place, `None` for as long as it takes.
+    pub queue_timeout: Option<Duration>,
+}
+
+/// A semaphore of the places for requests.
+///
+/// *Internal API*
+#[derive(Debug)]
+pub(crate) struct ConcurrencyLimit {
+    config: ConcurrencyConfig,
+    in_flight: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(config: ConcurrencyConfig) -> Self {
+        ConcurrencyLimit {
+            config,
+            in_flight: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+}
+
+/// A place taken by a request, given back when dropped.
+///
+/// *Internal API*
+#[derive(Debug)]
+pub(crate) struct Permit(Arc<ConcurrencyLimit>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        *self.0.in_flight.lock().unwrap() -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+/// Wait for a place for a request of the agent, failing with
+/// `Error::QueueTimeout` after the queue timeout. `None` without a limit.
+pub(crate) fn acquire(state: &Option<AgentState>) -> Result<Option<Arc<Permit>>, Error> {
+    let limit = match state.as_ref().and_then(|s| s.concurrency.as_ref()) {
+        Some(limit) => limit,
+        None => return Ok(None),
+    };
+    let deadline = limit.config.queue_timeout.map(|t| Instant::now() + t);
+    let mut in_flight = limit.in_flight.lock().unwrap();
+    while *in_flight >= limit.config.max {
+        in_flight = match deadline {
+            None => limit.freed.wait(in_flight).unwrap(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(Error::QueueTimeout);
+                }
+                limit
+                    .freed
+                    .wait_timeout(in_flight, deadline - now)
+                    .unwrap()
+                    .0
+            }
+        };
+    }
+    *in_flight += 1;
+    Ok(Some(Arc::new(Permit(limit.clone()))))
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::agent::Agent;
+use crate::error::Error;
+use crate::proxy::Proxy;
+use crate::redirect::RedirectPolicy;
+
+/// Settings of an agent as data, to load from a config file, see
+/// [`Agent::from_config()`](struct.Agent.html#method.from_config).
+///
+/// Unset fields leave the agent's defaults. With feature `config` it can be
+/// deserialized with serde from TOML, JSON or any other format, where missing
+/// fields are unset and unknown ones are errors. Durations are milliseconds.
+///
+/// ```
+/// let config = ureq::AgentConfig {
+///     timeout_connect_ms: Some(5_000),
+///     proxies: vec!["socks5h://proxy.internal:1080".into()],
+///     ..Default::default()
+/// };
+/// let agent = ureq::Agent::from_config(&config).unwrap().build();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "config", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct AgentConfig {
+    /// Headers of every request, see [`Agent::set()`](struct.Agent.html#method.set).
+    pub headers: BTreeMap<String, String>,
+    pub user_agent: Option<String>,
+    pub timeout_resolve_ms: Option<u64>,
+    pub timeout_connect_ms: Option<u64>,
+    pub timeout_read_ms: Option<u64>,
+    pub timeout_write_ms: Option<u64>,
+    /// Redirects to follow, `0` for none.
+    pub redirects: Option<u32>,
+    /// Only follow redirects to the same origin, see [`RedirectPolicy`](struct.RedirectPolicy.html).
+    pub redirect_same_origin: bool,
+    /// Schemes redirects may go to, any when empty.
+    pub redirect_schemes: Vec<String>,
+    /// Hosts redirects may go to, like `*.example.com`, any when empty.
+    pub redirect_hosts: Vec<String>,
+    /// SOCKS5 proxies tried in order, like `socks5h://host:1080`.
+    pub proxies: Vec<String>,
+    pub pool_health_check: Option<bool>,
+    pub drain_limit: Option<u64>,
+    pub max_concurrent_requests: Option<usize>,
+    pub queue_timeout_ms: Option<u64>,
+    /// Requests per second.
+    pub rate_limit: Option<u32>,
+    /// Share of requests that may be retries, needs `retry_budget_window_ms`.
+    pub retry_budget_ratio: Option<f64>,
+    pub retry_budget_window_ms: Option<u64>,
+    pub error_on_status: Option<bool>,
+    pub hsts: Option<bool>,
+    /// Hosts whose certificates aren't verified. Needs feature `tls`.
+    pub accept_invalid_certs_for_hosts: Vec<String>,
+    /// Needs feature `tls`.
+    pub tls_session_cache: Option<usize>,
+    /// Needs feature `tls`.
+    pub alpn_protocols: Vec<String>,
+    /// Needs feature `tls`.
+    pub require_ocsp_stapling: Option<bool>,
+}
+
+impl AgentConfig {
+    /// Set up the agent as configured.
+    pub(crate) fn apply(&self, agent: &mut Agent) -> Result<(), Error> {
+        for (name, value) in &self.headers {
+            agent.set(name, value);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            agent.user_agent(user_agent);
+        }
+        let millis = Duration::from_millis;
+        if let Some(ms) = self.timeout_resolve_ms {
+            agent.timeout_resolve(millis(ms));
+        }
+        if let Some(ms) = self.timeout_connect_ms {
+            agent.timeout_connect(millis(ms));
+        }
+        if let Some(ms) = self.timeout_read_ms {
+            agent.timeout_read(millis(ms));
+        }
+        if let Some(ms) = self.timeout_write_ms {
+            agent.timeout_write(millis(ms));
+        }
+        if let Some(n) = self.redirects {
+            agent.redirects(n);
+        }
+
+        let mut policy = if self.redirect_same_origin {
+            RedirectPolicy::same_origin()
+        } else {
+            RedirectPolicy::new()
+        };
+        for scheme in &self.redirect_schemes {
+            policy = policy.allow_scheme(scheme);
+        }
+        for host in &self.redirect_hosts {
+            policy = policy.allow_host(host);
+        }
+        agent.redirect_policy(policy);
+
+        if !self.proxies.is_empty() {
+            let proxies = self
+                .proxies
+                .iter()
+                .map(|p| Proxy::new(p))
+                .collect::<Result<Vec<_>, _>>()?;
+            agent.set_proxies(proxies);
+        }
+
+        if let Some(check) = self.pool_health_check {
+            agent.pool_health_check(check);
+        }
+        if let Some(bytes) = self.drain_limit {
+            agent.drain_limit(bytes);
+        }
+        if let Some(max) = self.max_concurrent_requests {
+            agent.max_concurrent_requests(max);
+        }
+        if let Some(ms) = self.queue_timeout_ms {
+            agent.queue_timeout(millis(ms));
+        }
+        if let Some(per_second) = self.rate_limit {
+            agent.rate_limit(per_second);
+        }
+        match (self.retry_budget_ratio, self.retry_budget_window_ms) {
+            (Some(ratio), Some(ms)) => {
+                agent.retry_budget(ratio, millis(ms));
+            }
+            (None, None) => (),
+            _ => {
+                return Err(Error::BadConfig(
+                    "retry_budget_ratio and retry_budget_window_ms go together".to_string(),
+                ))
+            }
+        }
+        if let Some(enabled) = self.error_on_status {
+            agent.error_on_status(enabled);
+        }
+        if let Some(enabled) = self.hsts {
+            agent.hsts(enabled);
+        }
+        self.apply_tls(agent)
+    }
+
+    #[cfg(feature = "tls")]
+    fn apply_tls(&self, agent: &mut Agent) -> Result<(), Error> {
+        for host in &self.accept_invalid_certs_for_hosts {
+            agent.accept_invalid_certs_for_host(host);
+        }
+        if let Some(size) = self.tls_session_cache {
+            agent.tls_session_cache(size);
+        }
+        if !self.alpn_protocols.is_empty() {
+            let protocols: Vec<&str> = self.alpn_protocols.iter().map(|p| p.as_str()).collect();
+            agent.alpn_protocols(&protocols);
+        }
+        if let Some(required) = self.require_ocsp_stapling {
+            agent.require_ocsp_stapling(required);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn apply_tls(&self, _agent: &mut Agent) -> Result<(), Error> {
+        let tls = !self.accept_invalid_certs_for_hosts.is_empty()
+            || self.tls_session_cache.is_some()
+            || !self.alpn_protocols.is_empty()
+            || self.require_ocsp_stapling.is_some();
+        if tls {
+            return Err(Error::BadConfig(
+                "TLS settings need feature tls".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::header;
+
+/// A `Content-Type` value, such as `text/html; charset=utf-8`, see
+/// [`Response::content_type_parsed()`](struct.Response.html#method.content_type_parsed)
+/// and [`Request::content_type()`](struct.Request.html#method.content_type).
+///
+/// The type and subtype are lowercased, as are parameter names.
+///
+/// ```
+/// let json = ureq::ContentType::new("application", "json").param("charset", "utf-8");
+/// assert_eq!(json.to_string(), "application/json; charset=utf-8");
+///
+/// let form: ureq::ContentType = "multipart/form-data; boundary=\"a b\"".parse().unwrap();
+/// assert_eq!(form.essence(), "multipart/form-data");
+/// assert_eq!(form.boundary(), Some("a b"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentType {
+    kind: String,
+    subtype: String,
+    params: Vec<(String, String)>,
+}
+
+impl ContentType {
+    /// A media type without parameters, like `("text", "plain")`.
+    pub fn new(kind: &str, subtype: &str) -> Self {
+        ContentType {
+            kind: kind.trim().to_ascii_lowercase(),
+            subtype: subtype.trim().to_ascii_lowercase(),
+            params: vec![],
+        }
+    }
+
+    /// Add a parameter, replacing one of the same name.
+    pub fn param(mut self, name: &str, value: &str) -> Self {
+        let name = name.trim().to_ascii_lowercase();
+        self.params.retain(|(n, _)| *n != name);
+        self.params.push((name, value.to_string()));
+        self
+    }
+
+    /// The type, like `text`.
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// The subtype, like `html`.
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// The type and subtype without parameters, like `text/html`.
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.kind, self.subtype)
+    }
+
+    /// A parameter by its (case insensitive) name, unquoted.
+    pub fn get_param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All parameters in order, with lowercased names.
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// The `charset` parameter.
+    pub fn charset(&self) -> Option<&str> {
+        self.get_param("charset")
+    }
+
+    /// The `boundary` parameter of a multipart type.
+    pub fn boundary(&self) -> Option<&str> {
+        self.get_param("boundary")
+    }
+}
+
+impl FromStr for ContentType {
+    type Err = Error;
+
+    /// Fails with [`Error::BadHeader`](enum.Error.html#variant.BadHeader)
+    /// unless there is a `type/subtype`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars().peekable();
+        header::skip(&mut chars, char::is_whitespace);
+        let media_type = header::take_token(&mut chars);
+        let (kind, subtype) = match media_type.find('/') {
+            Some(i) => (&media_type[..i], &media_type[i + 1..]),
+            None => return Err(Error::BadHeader),
+        };
+        if kind.is_empty() || subtype.is_empty() {
+            return Err(Error::BadHeader);
+        }
+        let mut content_type = ContentType::new(kind, subtype);
+        content_type.params = header::parse_params(&mut chars);
+        Ok(content_type)
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.kind, self.subtype)?;
+        for (name, value) in &self.params {
+            if !value.is_empty() && value.chars().all(is_token_char) {
+                write!(f, "; {}={}", name, value)?;
+            } else {
+                let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "; {}=\"{}\"", name, escaped)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A character of an RFC 7230 token, which needs no quotes.
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
@@ -0,0 +1,59 @@
+use cookie::{Cookie, CookieJar};
+
+/// Which cookies of responses an agent keeps, see
+/// [`Agent::cookies_reject_third_party()`](struct.Agent.html#method.cookies_reject_third_party)
+/// and friends. The default keeps all of them.
+///
+/// *Internal API*
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CookiePolicy {
+    pub reject_third_party: bool,
+    pub secure_only: bool,
+    pub max_per_domain: Option<usize>,
+    pub max_size: Option<usize>,
+}
+
+impl CookiePolicy {
+    /// Whether to keep a cookie set by a response from `host`, after following
+    /// redirects from `first_host`. The cookie has its `Domain` set.
+    pub fn accepts(
+        &self,
+        cookie: &Cookie,
+        jar: &CookieJar,
+        host: &str,
+        first_host: &str,
+        is_https: bool,
+    ) -> bool {
+        if self.reject_third_party && !same_site(host, first_host) {
+            return false;
+        }
+        if self.secure_only && !(is_https && cookie.secure() == Some(true)) {
+            return false;
+        }
+        if let Some(max) = self.max_size {
+            if cookie.name().len() + cookie.value().len() > max {
+                return false;
+            }
+        }
+        if let Some(max) = self.max_per_domain {
+            let domain = cookie.domain().unwrap_or(host);
+            let in_domain: Vec<_> = jar.iter().filter(|c| c.domain() == Some(domain)).collect();
+            // replacing a cookie doesn't add one.
+            let replaces = in_domain.iter().any(|c| c.name() == cookie.name());
+            if !replaces && in_domain.len() >= max {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Hosts are the same site when one is the other or a subdomain of it.
+fn same_site(a: &str, b: &str) -> bool {
+    let sub_of = |sub: &str, parent: &str| {
+        sub.len() > parent.len()
+            && sub.ends_with(parent)
+            && sub.as_bytes()[sub.len() - parent.len() - 1] == b'.'
+    };
+    a.eq_ignore_ascii_case(b) || sub_of(a, b) || sub_of(b, a)
+}
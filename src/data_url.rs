@@ -0,0 +1,82 @@
+use std::io::Cursor;
+
+use url::Url;
+
+use crate::error::Error;
+use crate::response::{self, Response};
+use crate::stream::Stream;
+
+/// The media type of a `data:` url that doesn't say.
+const DEFAULT_MEDIA_TYPE: &str = "text/plain;charset=US-ASCII";
+
+/// A `200 OK` response with the contents of a `data:` url, like
+/// `data:text/plain;base64,SGVsbG8=`, made without any network traffic.
+pub(crate) fn response(url: &Url) -> Result<Response, Error> {
+    let bad_url = || Error::BadUrl(format!("Bad data url: {}", url));
+
+    let contents = &url.as_str()["data:".len()..];
+    let contents = contents.split('#').next().unwrap_or("");
+    let (media_type, data) = contents.split_once(',').ok_or_else(bad_url)?;
+
+    let mut media_type = media_type.trim();
+    let is_base64 = match media_type.rsplit_once(';') {
+        Some((rest, param)) if param.trim().eq_ignore_ascii_case("base64") => {
+            media_type = rest.trim();
+            true
+        }
+        _ => false,
+    };
+    let media_type = if media_type.is_empty() {
+        DEFAULT_MEDIA_TYPE.to_string()
+    } else if media_type.starts_with(';') {
+        format!("text/plain{}", media_type)
+    } else {
+        media_type.to_string()
+    };
+
+    let mut body = percent_decode(data);
+    if is_base64 {
+        body.retain(|b| !b.is_ascii_whitespace());
+        while body.last() == Some(&b'=') {
+            body.pop();
+        }
+        body = base64::decode_config(&body, base64::STANDARD_NO_PAD).map_err(|_| bad_url())?;
+    }
+
+    let mut bytes = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        media_type,
+        body.len()
+    )
+    .into_bytes();
+    bytes.extend_from_slice(&body);
+    let mut cursor = Cursor::new(bytes);
+    let mut resp = Response::do_from_read(&mut cursor)?;
+    response::set_stream(&mut resp, url.to_string(), None, Stream::Cursor(cursor));
+    Ok(resp)
+}
+
+/// Decode `%xx` escapes, keeping those that aren't valid as they are.
+fn percent_decode(data: &str) -> Vec<u8> {
+    let data = data.as_bytes();
+    let hex = |b: u8| (b as char).to_digit(16);
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let escaped = match data.get(i..i + 3) {
+            Some([b'%', hi, lo]) => hex(*hi).and_then(|hi| Some(hi * 16 + hex(*lo)?)),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte as u8);
+                i += 3;
+            }
+            None => {
+                bytes.push(data[i]);
+                i += 1;
+            }
+        }
+    }
+    bytes
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Format a time as an HTTP date (IMF-fixdate), like `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(784111777);
+/// assert_eq!(ureq::format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+/// ```
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[((days + 4) % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parse an HTTP date in any of the formats of RFC 7231: IMF-fixdate, and
+/// the obsolete RFC 850 and asctime formats.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(784111777);
+/// assert_eq!(ureq::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+/// assert_eq!(ureq::parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(time));
+/// assert_eq!(ureq::parse_http_date("Sun Nov  6 08:49:37 1994"), Some(time));
+/// assert_eq!(ureq::parse_http_date("yesterday"), None);
+/// ```
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let (year, month, day, time) = match parts.as_slice() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [wday, day, month, year, time, "GMT"] if wday.ends_with(',') => {
+            (year.parse().ok()?, *month, day.parse().ok()?, *time)
+        }
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [wday, date, time, "GMT"] if wday.ends_with(',') => {
+            let mut date = date.split('-');
+            let day = date.next()?.parse().ok()?;
+            let month = date.next()?;
+            let year: i64 = date.next()?.parse().ok()?;
+            // two digit years are taken to be within 1970-2069.
+            let year = match year {
+                0..=69 => year + 2000,
+                70..=99 => year + 1900,
+                _ => year,
+            };
+            (year, month, day, *time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => (year.parse().ok()?, *month, day.parse().ok()?, *time),
+        _ => return None,
+    };
+
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as u32 + 1;
+    if year < 1970 || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut time = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (hour, min, sec) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some() || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = days as u64 * 86_400 + hour * 3600 + min * 60 + sec;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Days since 1970-01-01 to (year, month, day). From Howard Hinnant's date algorithms.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// (year, month, day) to days since 1970-01-01, the inverse of `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
@@ -0,0 +1,101 @@
+use std::io::Read;
+
+type DecoderFn = fn(Box<dyn Read + Send>) -> Box<dyn Read + Send>;
+
+/// The `Content-Encoding`s decoded by [`Response::into_reader()`], one for each
+/// enabled feature.
+///
+/// [`Response::into_reader()`]: ../struct.Response.html#method.into_reader
+const DECODERS: &[(&str, DecoderFn)] = &[
+    #[cfg(feature = "gzip")]
+    ("gzip", gzip_decoder),
+    #[cfg(feature = "gzip")]
+    ("x-gzip", gzip_decoder),
+    #[cfg(feature = "gzip")]
+    ("deflate", deflate_decoder),
+    #[cfg(feature = "zstd")]
+    ("zstd", zstd_decoder),
+];
+
+/// The `Accept-Encoding` sent by requests that don't set one: the encodings
+/// with an enabled decoder, or `None` without any.
+pub(crate) fn accept_encoding() -> Option<String> {
+    let names: Vec<&str> = DECODERS
+        .iter()
+        .map(|(name, _)| *name)
+        // an alias of gzip only old servers send.
+        .filter(|name| *name != "x-gzip")
+        .collect();
+    if names.is_empty() {
+        None
+    } else {
+        Some(names.join(", "))
+    }
+}
+
+/// Wrap the body in a decoder of its `Content-Encoding`. Encodings without an
+/// enabled decoder, such as `identity`, are read as they are.
+pub(crate) fn decoder(encoding: &str, reader: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+    let encoding = encoding.trim();
+    match DECODERS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(encoding))
+    {
+        Some((_, decoder)) => decoder(reader),
+        None => reader,
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn gzip_decoder(reader: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+    Box::new(flate2::read::MultiGzDecoder::new(reader))
+}
+
+/// `deflate` is meant to be zlib wrapped, but some servers send it raw, so
+/// the first two bytes are checked for a zlib header.
+#[cfg(feature = "gzip")]
+fn deflate_decoder(mut reader: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+    let mut head = [0; 2];
+    let mut len = 0;
+    while len < head.len() {
+        match reader.read(&mut head[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Box::new(FailedRead(Some(e))),
+        }
+    }
+    if len == 0 {
+        return reader;
+    }
+    // compression method 8 in the low bits, and a check making it a multiple of 31.
+    let is_zlib = len == 2 && head[0] & 0x0f == 8 && u16::from_be_bytes(head) % 31 == 0;
+    let reader = std::io::Cursor::new(head[..len].to_vec()).chain(reader);
+    if is_zlib {
+        Box::new(flate2::read::ZlibDecoder::new(reader))
+    } else {
+        Box::new(flate2::read::DeflateDecoder::new(reader))
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decoder(reader: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+    match zstd::stream::read::Decoder::new(reader) {
+        Ok(decoder) => Box::new(decoder),
+        Err(e) => Box::new(FailedRead(Some(e))),
+    }
+}
+
+/// A body whose decoder couldn't be made, failing the first read.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+struct FailedRead(Option<std::io::Error>);
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl Read for FailedRead {
+    fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.take() {
+            Some(e) => Err(e),
+            None => Ok(0),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error as IoError, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+
+use crate::agent::Agent;
+use crate::error::Error;
+use crate::response::{self, Response};
+
+const DEFAULT_SEGMENTS: usize = 4;
+const DEFAULT_RETRIES: u32 = 3;
+
+/// A download of a url in ranges fetched in parallel, see
+/// [`Agent::download()`](struct.Agent.html#method.download).
+#[derive(Debug)]
+pub struct Download {
+    agent: Agent,
+    path: String,
+    segments: usize,
+    retries: u32,
+}
+
+/// What the probe found out about the resource.
+struct Probe {
+    len: u64,
+    /// `ETag` or `Last-Modified`, sent as `If-Range` so that all ranges are of
+    /// the same version.
+    validator: Option<String>,
+}
+
+impl Download {
+    pub(crate) fn new(agent: &Agent, path: &str) -> Self {
+        Download {
+            agent: agent.clone(),
+            path: path.to_string(),
+            segments: DEFAULT_SEGMENTS,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// How many ranges are fetched at once, 4 by default.
+    pub fn segments(&mut self, segments: usize) -> &mut Download {
+        self.segments = segments.max(1);
+        self
+    }
+
+    /// How many times a failed range is fetched again, from where it stopped.
+    /// 3 by default.
+    pub fn retries(&mut self, retries: u32) -> &mut Download {
+        self.retries = retries;
+        self
+    }
+
+    /// Download into a file, returning the number of bytes. Without
+    /// `Accept-Ranges: bytes` and a `Content-Length`, the body is read in one go.
+    ///
+    /// Like [`Response::save_atomic()`](struct.Response.html#method.save_atomic),
+    /// the ranges are written to a temporary file next to it, which replaces
+    /// the file once all are in. A failed download leaves the file as it was.
+    ///
+    /// Fails without retrying if the resource changes while it is downloaded.
+    pub fn to_file(&self, file: impl AsRef<Path>) -> Result<u64, Error> {
+        let file = file.as_ref();
+        let tmp = response::tmp_path(file)?;
+        let written = self.write(&tmp).and_then(|amount| {
+            OpenOptions::new().write(true).open(&tmp)?.sync_all()?;
+            fs::rename(&tmp, file)?;
+            Ok(amount)
+        });
+        if written.is_err() {
+            fs::remove_file(&tmp).ok();
+        }
+        written
+    }
+
+    fn write(&self, file: &Path) -> Result<u64, Error> {
+        let probe = match self.probe()? {
+            Some(probe) => probe,
+            None => {
+                let resp = self.agent.get(&self.path).call().into_result()?;
+                let mut out = File::create(file)?;
+                return Ok(io::copy(&mut resp.into_reader(), &mut out)?);
+            }
+        };
+        File::create(file)?.set_len(probe.len)?;
+        if probe.len == 0 {
+            return Ok(0);
+        }
+
+        let count = (self.segments as u64).min(probe.len);
+        let size = probe.len.div_ceil(count);
+        let ranges: Vec<(u64, u64)> = (0..count)
+            .map(|i| (i * size, ((i + 1) * size).min(probe.len)))
+            .filter(|(start, end)| start < end)
+            .collect();
+        let probe = &probe;
+        let results: Vec<Result<(), Error>> = thread::scope(|scope| {
+            let handles: Vec<_> = ranges
+                .iter()
+                .map(|&(start, end)| scope.spawn(move || self.segment(file, probe, start, end)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| {
+                    h.join()
+                        .unwrap_or_else(|_| Err(Error::Io(IoError::other("Segment panicked"))))
+                })
+                .collect()
+        });
+        for result in results {
+            result?;
+        }
+        Ok(probe.len)
+    }
+
+    /// `None` when the server can't serve ranges of the url.
+    fn probe(&self) -> Result<Option<Probe>, Error> {
+        let resp = self.agent.head(&self.path).call().into_result()?;
+        let ranges = resp
+            .header("accept-ranges")
+            .map(|v| v.trim().eq_ignore_ascii_case("bytes"))
+            .unwrap_or(false);
+        let len = resp
+            .header("content-length")
+            .and_then(|v| v.trim().parse::<u64>().ok());
+        Ok(match (ranges, len) {
+            (true, Some(len)) => Some(Probe {
+                len,
+                validator: resp
+                    .header("etag")
+                    .or_else(|| resp.header("last-modified"))
+                    .map(|v| v.to_string()),
+            }),
+            _ => None,
+        })
+    }
+
+    /// Fetch the bytes `start..end` into the file, retrying from where a
+    /// failed attempt stopped.
+    fn segment(&self, file: &Path, probe: &Probe, start: u64, end: u64) -> Result<(), Error> {
+        let mut out = OpenOptions::new().write(true).open(file)?;
+        let mut pos = start;
+        let mut attempts = 0;
+        loop {
+            let err = match self.fetch(&mut out, probe, &mut pos, end) {
+                Ok(()) if pos == end => return Ok(()),
+                Ok(()) => failed(ErrorKind::UnexpectedEof, "Segment cut short"),
+                Err(Failure::Changed) => {
+                    return Err(failed(
+                        ErrorKind::InvalidData,
+                        "Resource changed during download",
+                    ))
+                }
+                Err(Failure::Retry(e)) => e,
+            };
+            if attempts >= self.retries {
+                return Err(err);
+            }
+            attempts += 1;
+        }
+    }
+
+    fn fetch(&self, out: &mut File, probe: &Probe, pos: &mut u64, end: u64) -> Result<(), Failure> {
+        let mut req = self.agent.get(&self.path);
+        req.set("Range", &format!("bytes={}-{}", pos, end - 1));
+        if let Some(validator) = &probe.validator {
+            req.set("If-Range", validator);
+        }
+        let resp = req.call().into_result()?;
+        if probe.validator.is_some() && resp.status() == 200 {
+            // a 200 after If-Range means the resource changed.
+            return Err(Failure::Changed);
+        }
+        if resp.status() != 206 || content_range_start(&resp) != Some(*pos) {
+            return Err(Failure::Retry(failed(
+                ErrorKind::InvalidData,
+                "Server didn't send the range",
+            )));
+        }
+        out.seek(SeekFrom::Start(*pos))?;
+        let mut reader = resp.into_reader().take(end - *pos);
+        let mut buf = [0_u8; 8192];
+        loop {
+            let amount = reader.read(&mut buf)?;
+            if amount == 0 {
+                return Ok(());
+            }
+            out.write_all(&buf[..amount])?;
+            *pos += amount as u64;
+        }
+    }
+}
+
+/// Why fetching a range failed.
+enum Failure {
+    /// Worth fetching again from where it stopped.
+    Retry(Error),
+    /// The server answered `If-Range` with the whole, changed resource.
+    Changed,
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure::Retry(e)
+    }
+}
+
+impl From<IoError> for Failure {
+    fn from(e: IoError) -> Self {
+        Failure::Retry(e.into())
+    }
+}
+
+/// The first byte of `Content-Range: bytes 0-499/1234`.
+fn content_range_start(resp: &Response) -> Option<u64> {
+    let value = resp.header("content-range")?.trim();
+    let range = value.strip_prefix("bytes ")?;
+    range.split('-').next()?.trim().parse().ok()
+}
+
+fn failed(kind: ErrorKind, msg: &str) -> Error {
+    Error::Io(IoError::new(kind, msg))
+}
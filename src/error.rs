@@ -1,5 +1,24 @@
 use std::fmt;
-use std::io::Error as IoError;
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::Arc;
+
+use crate::request::Request;
+use crate::response::Response;
+
+type ErrorFn = dyn Fn(&Request, &Error) + Send + Sync;
+
+/// Callback told about every failed request, see
+/// [`Agent::on_error()`](struct.Agent.html#method.on_error).
+///
+/// *Internal API*
+#[derive(Clone)]
+pub(crate) struct ErrorHook(pub Arc<ErrorFn>);
+
+impl ::std::fmt::Debug for ErrorHook {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "ErrorHook")
+    }
+}
 
 /// Errors that are translated to ["synthetic" responses](struct.Response.html#method.synthetic).
 #[derive(Debug)]
@@ -12,8 +31,42 @@ pub enum Error {
     DnsFailed(String),
     /// Connection to server failed. Synthetic error `500`.
     ConnectionFailed(String),
+    /// The server refused the connection, nothing listens on the port.
+    /// Synthetic error `500`.
+    ConnectionRefused(IoError),
+    /// Connecting took longer than [`timeout_connect()`](struct.Request.html#method.timeout_connect).
+    /// Synthetic error `500`.
+    ConnectTimeout(IoError),
+    /// Reading the response took longer than [`timeout_read()`](struct.Request.html#method.timeout_read).
+    /// Synthetic error `500`.
+    ReadTimeout(IoError),
+    /// Sending the request took longer than [`timeout_write()`](struct.Request.html#method.timeout_write).
+    /// Synthetic error `500`.
+    WriteTimeout(IoError),
+    /// The TLS handshake failed, such as for an invalid certificate.
+    /// Synthetic error `500`.
+    Tls(IoError),
+    /// The proxy could not be understood. Synthetic error `400`.
+    BadProxy(String),
+    /// The proxy refused or failed to connect us. Synthetic error `500`.
+    ProxyFailed(String),
+    /// A certificate revocation list that could not be parsed.
+    BadCrl(String),
+    /// A certificate or private key that could not be parsed.
+    BadCertificate(String),
+    /// An [`AgentConfig`](struct.AgentConfig.html) that could not be applied.
+    /// Synthetic error `400`.
+    BadConfig(String),
     /// Too many redirects. Synthetic error `500`.
     TooManyRedirects,
+    /// A redirect from `https` to `http`, refused unless
+    /// [`allow_downgrade_redirects()`](struct.Request.html#method.allow_downgrade_redirects)
+    /// is set. Holds the url redirected to. Synthetic error `500`.
+    InsecureRedirect(String),
+    /// A redirect to a url the
+    /// [`RedirectPolicy`](struct.RedirectPolicy.html) doesn't allow. Holds the
+    /// url redirected to. Synthetic error `500`.
+    ForbiddenRedirect(String),
     /// We fail to read the status line. This happens for pooled connections when
     /// TLS fails and we don't notice until trying to read.
     BadStatusRead,
@@ -23,15 +76,38 @@ pub enum Error {
     BadHeader,
     /// Some unspecified `std::io::Error`. Synthetic error `500`.
     Io(IoError),
+    /// A `4xx` or `5xx` response, given as an error by
+    /// [`Response::into_result()`](struct.Response.html#method.into_result) when
+    /// [`error_on_status()`](struct.Request.html#method.error_on_status) is set.
+    /// The response is kept so its body can still be read.
+    Status(u16, Box<Response>),
+    /// The response body did not have the digest given to
+    /// [`verify_checksum()`](struct.Request.html#method.verify_checksum), as
+    /// `(expected, actual)` hex. Given inside the `std::io::Error` of the failed read.
+    ChecksumMismatch(String, String),
+    /// The agent's rate limit for the host was reached and the agent is set
+    /// to reject rather than wait. Synthetic error `429`.
+    RateLimited(String),
+    /// No place came free within the queue timeout of the agent's
+    /// [`max_concurrent_requests()`](struct.Agent.html#method.max_concurrent_requests).
+    /// Synthetic error `503`.
+    QueueTimeout,
+    /// The agent's OAuth2 token endpoint didn't give a token. Synthetic error `401`.
+    OAuth2(String),
 }
 
 impl Error {
     // If the error is bad status read, which might happen if a TLS connections is
     // closed and we only discover it when trying to read the status line from it.
     pub(crate) fn is_bad_status_read(&self) -> bool {
-        match self {
-            Error::BadStatusRead => true,
-            _ => false,
+        matches!(self, Error::BadStatusRead)
+    }
+
+    /// An error of sending the request, where a timeout is of the write.
+    pub(crate) fn from_write(err: IoError) -> Error {
+        match Error::from(err) {
+            Error::ReadTimeout(err) => Error::WriteTimeout(err),
+            err => err,
         }
     }
 
@@ -42,11 +118,28 @@ impl Error {
             Error::UnknownScheme(_) => 400,
             Error::DnsFailed(_) => 400,
             Error::ConnectionFailed(_) => 500,
+            Error::ConnectionRefused(_) => 500,
+            Error::ConnectTimeout(_) => 500,
+            Error::ReadTimeout(_) => 500,
+            Error::WriteTimeout(_) => 500,
+            Error::Tls(_) => 500,
+            Error::BadProxy(_) => 400,
+            Error::ProxyFailed(_) => 500,
+            Error::BadCrl(_) => 500,
+            Error::BadCertificate(_) => 500,
+            Error::BadConfig(_) => 400,
             Error::TooManyRedirects => 500,
+            Error::InsecureRedirect(_) => 500,
+            Error::ForbiddenRedirect(_) => 500,
             Error::BadStatusRead => 500,
             Error::BadStatus => 500,
             Error::BadHeader => 500,
             Error::Io(_) => 500,
+            Error::Status(status, _) => *status,
+            Error::ChecksumMismatch(_, _) => 500,
+            Error::RateLimited(_) => 429,
+            Error::QueueTimeout => 503,
+            Error::OAuth2(_) => 401,
         }
     }
 
@@ -57,11 +150,28 @@ impl Error {
             Error::UnknownScheme(_) => "Unknown Scheme",
             Error::DnsFailed(_) => "Dns Failed",
             Error::ConnectionFailed(_) => "Connection Failed",
+            Error::ConnectionRefused(_) => "Connection Refused",
+            Error::ConnectTimeout(_) => "Connect Timeout",
+            Error::ReadTimeout(_) => "Read Timeout",
+            Error::WriteTimeout(_) => "Write Timeout",
+            Error::Tls(_) => "TLS Failed",
+            Error::BadProxy(_) => "Bad Proxy",
+            Error::ProxyFailed(_) => "Proxy Failed",
+            Error::BadCrl(_) => "Bad CRL",
+            Error::BadCertificate(_) => "Bad Certificate",
+            Error::BadConfig(_) => "Bad Config",
             Error::TooManyRedirects => "Too Many Redirects",
+            Error::InsecureRedirect(_) => "Insecure Redirect",
+            Error::ForbiddenRedirect(_) => "Forbidden Redirect",
             Error::BadStatusRead => "Failed to read status line",
             Error::BadStatus => "Bad Status",
             Error::BadHeader => "Bad Header",
             Error::Io(_) => "Network Error",
+            Error::Status(_, resp) => resp.status_text(),
+            Error::ChecksumMismatch(_, _) => "Checksum Mismatch",
+            Error::RateLimited(_) => "Too Many Requests",
+            Error::QueueTimeout => "Queue Timeout",
+            Error::OAuth2(_) => "OAuth2 Failed",
         }
     }
 
@@ -72,25 +182,84 @@ impl Error {
             Error::UnknownScheme(scheme) => format!("Unknown Scheme: {}", scheme),
             Error::DnsFailed(err) => format!("Dns Failed: {}", err),
             Error::ConnectionFailed(err) => format!("Connection Failed: {}", err),
+            Error::ConnectionRefused(err) => format!("Connection Refused: {}", err),
+            Error::ConnectTimeout(err) => format!("Connect Timeout: {}", err),
+            Error::ReadTimeout(err) => format!("Read Timeout: {}", err),
+            Error::WriteTimeout(err) => format!("Write Timeout: {}", err),
+            Error::Tls(err) => format!("TLS Failed: {}", err),
+            Error::BadProxy(err) => format!("Bad Proxy: {}", err),
+            Error::ProxyFailed(err) => format!("Proxy Failed: {}", err),
+            Error::BadCrl(err) => format!("Bad CRL: {}", err),
+            Error::BadCertificate(err) => format!("Bad Certificate: {}", err),
+            Error::BadConfig(err) => format!("Bad Config: {}", err),
             Error::TooManyRedirects => "Too Many Redirects".to_string(),
+            Error::InsecureRedirect(url) => format!("Insecure Redirect: {}", url),
+            Error::ForbiddenRedirect(url) => format!("Forbidden Redirect: {}", url),
             Error::BadStatusRead => "Failed to read status line".to_string(),
             Error::BadStatus => "Bad Status".to_string(),
             Error::BadHeader => "Bad Header".to_string(),
             Error::Io(ioe) => format!("Network Error: {}", ioe),
+            Error::Status(status, resp) => format!("Status {}: {}", status, resp.status_text()),
+            Error::ChecksumMismatch(expected, actual) => {
+                format!("Checksum Mismatch: expected {}, got {}", expected, actual)
+            }
+            Error::RateLimited(host) => format!("Rate limited: {}", host),
+            Error::QueueTimeout => "Queue Timeout: too many concurrent requests".to_string(),
+            Error::OAuth2(err) => format!("OAuth2 Failed: {}", err),
         }
     }
 }
 
+/// Errors of reading, a timeout is of the read. Sending the request maps its
+/// errors with `Error::from_write()`.
 impl From<IoError> for Error {
     fn from(err: IoError) -> Error {
-        Error::Io(err)
+        if is_tls(&err) {
+            return Error::Tls(err);
+        }
+        match err.kind() {
+            // a socket with a read timeout gives WouldBlock on unix.
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => Error::ReadTimeout(err),
+            _ => Error::Io(err),
+        }
     }
 }
 
+/// rustls gives its errors wrapped in an io error of kind `InvalidData`.
+#[cfg(feature = "tls")]
+fn is_tls(err: &IoError) -> bool {
+    err.kind() == ErrorKind::InvalidData
+        && err
+            .get_ref()
+            .map(|e| e.is::<rustls::TLSError>())
+            .unwrap_or(false)
+}
+
+#[cfg(not(feature = "tls"))]
+fn is_tls(_err: &IoError) -> bool {
+    false
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.body_text())
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ConnectionRefused(err)
+            | Error::ConnectTimeout(err)
+            | Error::ReadTimeout(err)
+            | Error::WriteTimeout(err)
+            | Error::Io(err) => Some(err),
+            // the rustls error rather than the io error wrapping it.
+            Error::Tls(err) => match err.get_ref() {
+                Some(inner) => Some(inner),
+                None => Some(err),
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::request::Request;
+use crate::response::Response;
+
+/// Told about each step of the requests made by an agent, see
+/// [`Agent::event_listener()`](struct.Agent.html#method.event_listener).
+///
+/// Every method does nothing by default, so a listener only implements those
+/// it cares about. The durations are of the step itself. When redirects are
+/// followed, the steps are told again for each of them.
+///
+/// ```
+/// use std::net::SocketAddr;
+/// use std::time::Duration;
+/// use ureq::{EventListener, Request, Response};
+///
+/// struct Log;
+///
+/// impl EventListener for Log {
+///     fn on_connect(&self, addr: SocketAddr, duration: Duration) {
+///         println!("connected to {} in {:?}", addr, duration);
+///     }
+///
+///     fn on_complete(&self, request: &Request, response: &Response, total: Duration) {
+///         println!("{} {} in {:?}", request.get_url(), response.status(), total);
+///     }
+/// }
+///
+/// let agent = ureq::agent().event_listener(Log).build();
+/// ```
+#[allow(unused_variables)]
+pub trait EventListener: Send + Sync {
+    /// A host name was looked up. Not told when the request gives the address with
+    /// [`Request::connect_addr()`](struct.Request.html#method.connect_addr).
+    fn on_resolve(&self, host: &str, addrs: &[SocketAddr], duration: Duration) {}
+
+    /// A connection was opened, to the proxy when one is used.
+    fn on_connect(&self, addr: SocketAddr, duration: Duration) {}
+
+    /// The TLS handshake with `host` is done. With a listener set, the handshake
+    /// is made when connecting rather than with the first write, to time it.
+    fn on_tls_handshake(&self, host: &str, duration: Duration) {}
+
+    /// The request head and body were written, `bytes` in all.
+    fn on_request_sent(&self, method: &str, url: &str, bytes: u64, duration: Duration) {}
+
+    /// The head of a response was read, `duration` after the request was sent.
+    /// The body is still to be read.
+    fn on_response_headers(&self, url: &str, response: &Response, duration: Duration) {}
+
+    /// A redirect from `from` to `to` is followed.
+    fn on_redirect(&self, status: u16, from: &str, to: &str) {}
+
+    /// The request is done, with the response or a
+    /// [synthetic](struct.Response.html#method.synthetic) one for an error.
+    fn on_complete(&self, request: &Request, response: &Response, total: Duration) {}
+}
+
+impl ::std::fmt::Debug for dyn EventListener {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "EventListener")
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::thread;
+use std::time::Duration;
+
+use crate::stream::Stream;
+
+/// Failures injected into the connections of an agent, see
+/// [`Agent::fault_latency()`](struct.Agent.html#method.fault_latency) and friends.
+///
+/// *Internal API*
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FaultConfig {
+    pub latency: Option<Duration>,
+    pub reset_after: Option<u64>,
+    pub truncate_body: Option<u64>,
+    pub malformed_headers: bool,
+}
+
+/// A stream failing the way the config says, counting from the start of each
+/// response, that is the first read after writing a request.
+///
+/// *Internal API*
+#[derive(Debug)]
+pub(crate) struct FaultStream {
+    stream: Stream,
+    config: FaultConfig,
+    /// Whether the latency is still to be waited for this response.
+    waiting: bool,
+    received: u64,
+    body: u64,
+    /// The last bytes of the head, to find where it ends.
+    tail: [u8; 4],
+    head_done: bool,
+    line_breaks: usize,
+    malformed: bool,
+    reset: bool,
+    cut: bool,
+}
+
+impl FaultStream {
+    pub fn new(stream: Stream, config: FaultConfig) -> Self {
+        FaultStream {
+            stream,
+            config,
+            waiting: false,
+            received: 0,
+            body: 0,
+            tail: [0; 4],
+            head_done: false,
+            line_breaks: 0,
+            malformed: false,
+            reset: false,
+            cut: false,
+        }
+    }
+
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+
+    pub fn stream_mut(&mut self) -> &mut Stream {
+        &mut self.stream
+    }
+
+    /// A connection that failed is not reused.
+    pub fn is_broken(&self) -> bool {
+        self.reset || self.cut
+    }
+}
+
+impl Read for FaultStream {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.reset {
+            return Err(reset());
+        }
+        if self.cut {
+            return Ok(0);
+        }
+        if self.waiting {
+            self.waiting = false;
+            if let Some(latency) = self.config.latency {
+                thread::sleep(latency);
+            }
+        }
+        let amount = self.stream.read(buf)?;
+        for (i, byte) in buf[..amount].iter_mut().enumerate() {
+            if Some(self.received) == self.config.reset_after {
+                self.reset = true;
+                return if i == 0 { Err(reset()) } else { Ok(i) };
+            }
+            if self.head_done && Some(self.body) == self.config.truncate_body {
+                self.cut = true;
+                return Ok(i);
+            }
+            self.received += 1;
+            if self.head_done {
+                self.body += 1;
+                continue;
+            }
+            if *byte == b'\n' {
+                self.line_breaks += 1;
+            } else if *byte == b':'
+                && self.line_breaks > 0
+                && self.config.malformed_headers
+                && !self.malformed
+            {
+                // a header line without a colon.
+                *byte = b' ';
+                self.malformed = true;
+            }
+            self.tail.rotate_left(1);
+            self.tail[3] = *byte;
+            self.head_done = &self.tail == b"\r\n\r\n" || &self.tail[2..] == b"\n\n";
+        }
+        Ok(amount)
+    }
+}
+
+fn reset() -> IoError {
+    IoError::new(
+        ErrorKind::ConnectionReset,
+        "Connection reset (injected fault)",
+    )
+}
+
+impl Write for FaultStream {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        // a new request, so what comes next is a new response.
+        self.waiting = true;
+        self.received = 0;
+        self.body = 0;
+        self.tail = [0; 4];
+        self.head_done = false;
+        self.line_breaks = 0;
+        self.malformed = false;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.stream.flush()
+    }
+}
@@ -0,0 +1,51 @@
+use std::fs::File;
+use std::io::{Cursor, ErrorKind};
+
+use url::Url;
+
+use crate::error::Error;
+use crate::request::mime_from_extension;
+use crate::response::{self, Response};
+use crate::stream::Stream;
+
+/// A response streaming the file of a `file:` url, with a `Content-Type` guessed
+/// from its extension. A missing file is a `404`, and methods other than `GET`
+/// and `HEAD` are a `405`.
+pub(crate) fn response(method: &str, url: &Url) -> Result<Response, Error> {
+    if method != "GET" && method != "HEAD" {
+        return Ok(Response::new(
+            405,
+            "Method Not Allowed",
+            "Only GET of files",
+        ));
+    }
+    let path = url
+        .to_file_path()
+        .map_err(|_| Error::BadUrl(format!("Not a local file: {}", url)))?;
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Ok(Response::new(404, "Not Found", "No such file"));
+        }
+        Err(e) => return Err(Error::Io(e)),
+    };
+    let meta = file.metadata()?;
+    if !meta.is_file() {
+        return Ok(Response::new(404, "Not Found", "Not a file"));
+    }
+
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        mime_from_extension(ext),
+        meta.len()
+    );
+    let mut resp = Response::do_from_read(Cursor::new(head))?;
+    let stream = if method == "HEAD" {
+        Stream::Cursor(Cursor::new(vec![]))
+    } else {
+        Stream::File(file)
+    };
+    response::set_stream(&mut resp, url.to_string(), None, stream);
+    Ok(resp)
+}
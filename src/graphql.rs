@@ -0,0 +1,129 @@
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::SerdeValue;
+
+/// The body of a GraphQL response, see
+/// [`Response::into_graphql()`](struct.Response.html#method.into_graphql).
+///
+/// A response can have both data and errors, when only some fields failed.
+#[derive(Debug, Clone)]
+pub struct GraphqlResponse<T> {
+    data: Option<T>,
+    errors: Vec<GraphqlError>,
+}
+
+impl<T> GraphqlResponse<T> {
+    /// The `data` member, `None` when absent or `null`.
+    pub fn data(&self) -> Option<&T> {
+        self.data.as_ref()
+    }
+
+    /// The `errors` member, empty when absent.
+    pub fn errors(&self) -> &[GraphqlError] {
+        &self.errors
+    }
+
+    /// The data if there are no errors, otherwise the errors.
+    pub fn into_result(self) -> Result<T, Vec<GraphqlError>> {
+        match (self.data, self.errors.is_empty()) {
+            (Some(data), true) => Ok(data),
+            (None, true) => Err(vec![GraphqlError::new("No data in response")]),
+            (_, false) => Err(self.errors),
+        }
+    }
+}
+
+/// An error of a [`GraphqlResponse`](struct.GraphqlResponse.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphqlError {
+    message: String,
+    path: Vec<SerdeValue>,
+    extensions: Option<SerdeValue>,
+}
+
+impl GraphqlError {
+    fn new(message: &str) -> Self {
+        GraphqlError {
+            message: message.to_string(),
+            path: vec![],
+            extensions: None,
+        }
+    }
+
+    /// What went wrong.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The field the error is about, as names and list indexes, such as
+    /// `["hero", "friends", 1, "name"]`. Empty for errors of the whole request.
+    pub fn path(&self) -> &[SerdeValue] {
+        &self.path
+    }
+
+    /// The server specific `extensions` member, such as an error code.
+    pub fn extensions(&self) -> Option<&SerdeValue> {
+        self.extensions.as_ref()
+    }
+}
+
+impl fmt::Display for GraphqlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for GraphqlError {}
+
+/// The standard JSON envelope of a query and its variables.
+pub(crate) fn request_body(query: &str, variables: SerdeValue) -> SerdeValue {
+    if variables.is_null() {
+        json!({ "query": query })
+    } else {
+        json!({ "query": query, "variables": variables })
+    }
+}
+
+/// Split a response body into its data and errors.
+pub(crate) fn parse<T: DeserializeOwned>(mut body: SerdeValue) -> IoResult<GraphqlResponse<T>> {
+    if !body.is_object() {
+        return Err(read_error("not an object"));
+    }
+    let data = match body["data"].take() {
+        SerdeValue::Null => None,
+        data => Some(serde_json::from_value(data).map_err(read_error)?),
+    };
+    let errors = match body["errors"].take() {
+        SerdeValue::Array(errors) => errors.into_iter().map(parse_error).collect(),
+        _ => vec![],
+    };
+    Ok(GraphqlResponse { data, errors })
+}
+
+fn parse_error(mut error: SerdeValue) -> GraphqlError {
+    let message = match &error["message"] {
+        SerdeValue::String(message) => message.clone(),
+        other => other.to_string(),
+    };
+    let path = match error["path"].take() {
+        SerdeValue::Array(path) => path,
+        _ => vec![],
+    };
+    let extensions = Some(error["extensions"].take()).filter(|e| !e.is_null());
+    GraphqlError {
+        message,
+        path,
+        extensions,
+    }
+}
+
+fn read_error(e: impl fmt::Display) -> IoError {
+    IoError::new(
+        ErrorKind::InvalidData,
+        format!("Failed to read GraphQL response: {}", e),
+    )
+}
@@ -0,0 +1,364 @@
+use std::cell::RefCell;
+use std::io::{Read, Result as IoResult};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use url::Url;
+
+use crate::agent::AgentState;
+use crate::body::SizedReader;
+use crate::date::civil_from_days;
+use crate::header::Header;
+use crate::time::{self, Instant};
+use crate::unit::{self, Unit};
+use crate::{Response, SerdeValue};
+
+/// Recorded exchanges of an agent, see [`Agent::record_har()`](struct.Agent.html#method.record_har).
+///
+/// *Internal API*
+#[derive(Debug)]
+pub(crate) struct HarRecorder {
+    max_body: usize,
+    redact: bool,
+    // entries handed out by `take()`, so indexes of bodies still being read stay valid.
+    taken: usize,
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug)]
+struct HarEntry {
+    started: SystemTime,
+    method: String,
+    url: String,
+    request_headers: Vec<Header>,
+    request_body: Vec<u8>,
+    request_body_size: usize,
+    status: u16,
+    status_text: String,
+    http_version: String,
+    response_headers: Vec<Header>,
+    response_body: Vec<u8>,
+    response_body_size: usize,
+    // timings in milliseconds, -1 when not applicable.
+    connect: f64,
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+impl HarRecorder {
+    pub fn new(max_body: usize, redact: bool) -> Self {
+        HarRecorder {
+            max_body,
+            redact,
+            taken: 0,
+            entries: vec![],
+        }
+    }
+
+    /// The recorded session as a HAR 1.2 `log` object.
+    pub fn to_json(&self) -> SerdeValue {
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "ureq", "version": env!("CARGO_PKG_VERSION") },
+                "entries": self.entries.iter().map(HarEntry::to_json).collect::<Vec<_>>(),
+            }
+        })
+    }
+
+    /// Like `to_json()`, but removes the entries from the recorder.
+    pub fn take(&mut self) -> SerdeValue {
+        let json = self.to_json();
+        self.taken += self.entries.len();
+        self.entries.clear();
+        json
+    }
+}
+
+impl HarEntry {
+    fn to_json(&self) -> SerdeValue {
+        let query: Vec<_> = Url::parse(&self.url)
+            .map(|u| {
+                u.query_pairs()
+                    .map(|(name, value)| json!({ "name": name, "value": value }))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let request_type = crate::header::get_header(&self.request_headers, "content-type");
+        let response_type = crate::header::get_header(&self.response_headers, "content-type");
+        let mut request = json!({
+            "method": self.method,
+            "url": self.url,
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": headers_json(&self.request_headers),
+            "queryString": query,
+            "headersSize": -1,
+            "bodySize": self.request_body_size,
+        });
+        if self.request_body_size > 0 {
+            let mut post_data = body_json(&self.request_body);
+            post_data["mimeType"] = json!(request_type.unwrap_or(""));
+            request["postData"] = post_data;
+        }
+        let mut content = body_json(&self.response_body);
+        content["size"] = json!(self.response_body_size);
+        content["mimeType"] = json!(response_type.unwrap_or(""));
+        let time: f64 = [self.connect, self.send, self.wait, self.receive]
+            .iter()
+            .filter(|t| **t > 0.0)
+            .sum();
+        json!({
+            "startedDateTime": iso8601(self.started),
+            "time": time,
+            "request": request,
+            "response": {
+                "status": self.status,
+                "statusText": self.status_text,
+                "httpVersion": self.http_version,
+                "cookies": [],
+                "headers": headers_json(&self.response_headers),
+                "content": content,
+                "redirectURL": crate::header::get_header(&self.response_headers, "location")
+                    .unwrap_or(""),
+                "headersSize": -1,
+                "bodySize": self.response_body_size,
+            },
+            "cache": {},
+            "timings": {
+                "blocked": -1,
+                "dns": -1,
+                "connect": self.connect,
+                "send": self.send,
+                "wait": self.wait,
+                "receive": self.receive,
+                "ssl": -1,
+            },
+        })
+    }
+}
+
+fn headers_json(headers: &[Header]) -> SerdeValue {
+    headers
+        .iter()
+        .map(|h| json!({ "name": h.name(), "value": h.value() }))
+        .collect()
+}
+
+/// The headers with the values of credentials replaced by `***`.
+fn redacted_headers(headers: &[Header]) -> Vec<Header> {
+    headers
+        .iter()
+        .map(|h| {
+            if unit::is_secret_header(h.name()) || h.is_name("set-cookie") {
+                Header::new(h.name(), "***")
+            } else {
+                h.clone()
+            }
+        })
+        .collect()
+}
+
+fn body_json(body: &[u8]) -> SerdeValue {
+    match std::str::from_utf8(body) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "text": base64::encode(body), "encoding": "base64" }),
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Captures one request/response exchange in `unit::connect`.
+///
+/// *Internal API*
+pub(crate) struct Exchange {
+    started: SystemTime,
+    max_body: usize,
+    request_body: Rc<RefCell<(Vec<u8>, usize)>>,
+}
+
+impl Exchange {
+    /// Starts timing if the agent of the unit is recording.
+    pub fn start(unit: &Unit) -> Option<Exchange> {
+        let recorder = (*unit.agent).as_ref().and_then(|s| s.har.as_ref())?;
+        Some(Exchange {
+            started: time::now(),
+            max_body: recorder.lock().unwrap().max_body,
+            request_body: Rc::new(RefCell::new((vec![], 0))),
+        })
+    }
+
+    /// Wrap the body to keep a copy of what is sent.
+    pub fn capture_body(&self, body: SizedReader) -> SizedReader {
+        SizedReader {
+            size: body.size,
+            reader: Box::new(CaptureRead {
+                reader: body.reader,
+                max: self.max_body,
+                captured: Rc::clone(&self.request_body),
+            }),
+            preview: body.preview,
+            replay: body.replay,
+        }
+    }
+
+    /// Record the exchange once the response head is read. Returns the index
+    /// of the entry, which the response body is later recorded into.
+    pub fn finish(self, unit: &Unit, redir: bool, resp: &Response) -> Option<usize> {
+        let recorder = (*unit.agent).as_ref().and_then(|s| s.har.as_ref())?;
+        let (request_body, request_body_size) = self.request_body.replace((vec![], 0));
+        let redact = recorder.lock().unwrap().redact;
+        let mut url = if redact {
+            unit::redacted(&unit.url)
+        } else {
+            unit.url.clone()
+        };
+        url.set_query(None);
+        url.set_fragment(None);
+        let mut request_headers = unit::prelude_headers(unit, redir);
+        let mut response_headers = resp.headers.clone();
+        if redact {
+            request_headers = redacted_headers(&request_headers);
+            response_headers = redacted_headers(&response_headers);
+        }
+        let entry = HarEntry {
+            started: self.started,
+            method: unit.method.clone(),
+            url: format!("{}{}", url, unit.query_string),
+            request_headers,
+            request_body,
+            request_body_size,
+            status: resp.status(),
+            status_text: resp.status_text().to_string(),
+            http_version: resp.http_version().to_string(),
+            response_headers,
+            response_body: vec![],
+            response_body_size: 0,
+            connect: resp.stats.connect.map(millis).unwrap_or(-1.0),
+            send: millis(resp.stats.send),
+            wait: millis(resp.stats.wait),
+            receive: -1.0,
+        };
+        let mut recorder = recorder.lock().unwrap();
+        recorder.entries.push(entry);
+        Some(recorder.taken + recorder.entries.len() - 1)
+    }
+}
+
+/// Keeps a copy of (the start of) the request body as it is sent.
+struct CaptureRead {
+    reader: Box<dyn Read + 'static>,
+    max: usize,
+    captured: Rc<RefCell<(Vec<u8>, usize)>>,
+}
+
+impl Read for CaptureRead {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let amount = self.reader.read(buf)?;
+        let (captured, size) = &mut *self.captured.borrow_mut();
+        let keep = amount.min(self.max.saturating_sub(captured.len()));
+        captured.extend_from_slice(&buf[..keep]);
+        *size += amount;
+        Ok(amount)
+    }
+}
+
+/// Records the response body into the entry as it is read by the user.
+///
+/// *Internal API*
+pub(crate) struct BodyRecorder {
+    reader: Box<dyn Read + Send>,
+    agent: Arc<Option<AgentState>>,
+    index: usize,
+    max: usize,
+    captured: Vec<u8>,
+    size: usize,
+    start: Instant,
+    done: bool,
+}
+
+impl BodyRecorder {
+    pub fn wrap(
+        reader: Box<dyn Read + Send>,
+        agent: Arc<Option<AgentState>>,
+        index: usize,
+    ) -> Box<dyn Read + Send> {
+        let max = {
+            match (*agent).as_ref().and_then(|s| s.har.as_ref()) {
+                Some(recorder) => recorder.lock().unwrap().max_body,
+                None => return reader,
+            }
+        };
+        Box::new(BodyRecorder {
+            reader,
+            agent,
+            index,
+            max,
+            captured: vec![],
+            size: 0,
+            start: Instant::now(),
+            done: false,
+        })
+    }
+
+    fn record(&mut self) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+        let recorder = match (*self.agent).as_ref().and_then(|s| s.har.as_ref()) {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let mut recorder = recorder.lock().unwrap();
+        // the entry is gone if it was taken before the body was read.
+        let index = self.index.checked_sub(recorder.taken);
+        if let Some(entry) = index.and_then(|i| recorder.entries.get_mut(i)) {
+            entry.response_body = std::mem::take(&mut self.captured);
+            entry.response_body_size = self.size;
+            entry.receive = millis(self.start.elapsed());
+        }
+    }
+}
+
+impl Read for BodyRecorder {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let amount = self.reader.read(buf)?;
+        let keep = amount.min(self.max.saturating_sub(self.captured.len()));
+        self.captured.extend_from_slice(&buf[..keep]);
+        self.size += amount;
+        if amount == 0 {
+            self.record();
+        }
+        Ok(amount)
+    }
+}
+
+impl Drop for BodyRecorder {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
+
+/// Format as ISO 8601 with milliseconds, like `2009-07-24T19:20:30.045Z`.
+fn iso8601(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since.subsec_millis()
+    )
+}
@@ -1,5 +1,6 @@
 use crate::error::Error;
-use std::str::FromStr;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
 
 #[derive(Clone)]
 /// Wrapper type for a header line.
@@ -42,7 +43,7 @@ impl Header {
     /// assert_eq!("127.0.0.1", header.value());
     /// ```
     pub fn value(&self) -> &str {
-        &self.line.as_str()[self.index + 1..].trim()
+        self.line.as_str()[self.index + 1..].trim()
     }
 
     /// Compares the given str to the header name ignoring case.
@@ -56,13 +57,37 @@ impl Header {
     pub fn is_name(&self, other: &str) -> bool {
         self.name().eq_ignore_ascii_case(other)
     }
+
+    /// The items of a comma separated value, like of `Vary` or `Connection`.
+    /// Items are trimmed and empty ones skipped. A comma in a quoted string
+    /// doesn't split, and the quotes are kept.
+    ///
+    /// ```
+    /// let header = "If-None-Match: \"a,b\", W/\"c\",,"
+    ///     .parse::<ureq::Header>()
+    ///     .unwrap();
+    /// assert_eq!(header.values(), vec!["\"a,b\"", "W/\"c\""]);
+    /// ```
+    pub fn values(&self) -> Vec<&str> {
+        split_list(self.value())
+    }
+
+    /// A header with the items joined as a comma separated value.
+    ///
+    /// ```
+    /// let header = ureq::Header::list("Vary", &["Accept-Encoding", " ", "Origin"]);
+    /// assert_eq!(header.value(), "Accept-Encoding, Origin");
+    /// ```
+    pub fn list(name: &str, items: &[&str]) -> Self {
+        Header::new(name, &join_list(items))
+    }
 }
 
-pub fn get_header<'a, 'b>(headers: &'b [Header], name: &'a str) -> Option<&'b str> {
+pub fn get_header<'b>(headers: &'b [Header], name: &str) -> Option<&'b str> {
     headers.iter().find(|h| h.is_name(name)).map(|h| h.value())
 }
 
-pub fn get_all_headers<'a, 'b>(headers: &'b [Header], name: &'a str) -> Vec<&'b str> {
+pub fn get_all_headers<'b>(headers: &'b [Header], name: &str) -> Vec<&'b str> {
     headers
         .iter()
         .filter(|h| h.is_name(name))
@@ -74,20 +99,211 @@ pub fn has_header(headers: &[Header], name: &str) -> bool {
     get_header(headers, name).is_some()
 }
 
+/// Add a header, replacing any with the same name where the first of them was,
+/// so headers keep the order they were first set in.
 pub fn add_header(headers: &mut Vec<Header>, header: Header) {
     let name = header.name();
     if !name.starts_with("x-") && !name.starts_with("X-") {
-        headers.retain(|h| h.name() != name);
+        if let Some(pos) = headers.iter().position(|h| h.name() == name) {
+            headers.retain(|h| h.name() != name);
+            headers.insert(pos, header);
+            return;
+        }
     }
     headers.push(header);
 }
 
+/// Stable sort of headers so that the named ones go first, in the given order.
+pub(crate) fn order_headers(headers: &mut [Header], order: &[String]) {
+    if order.is_empty() {
+        return;
+    }
+    headers.sort_by_key(|h| {
+        order
+            .iter()
+            .position(|n| h.is_name(n))
+            .unwrap_or(order.len())
+    });
+}
+
+/// Split a comma separated value outside of quoted strings, see
+/// [`Header::values()`].
+pub(crate) fn split_list(value: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == ',' && !quoted {
+            items.push(&value[start..i]);
+            start = i + 1;
+        }
+    }
+    items.push(&value[start..]);
+    items
+        .into_iter()
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Join items with `, `, skipping empty ones.
+pub(crate) fn join_list(items: &[&str]) -> String {
+    let items: Vec<_> = items
+        .iter()
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .collect();
+    items.join(", ")
+}
+
+/// A list like `Accept-Language` in order of preference. Items without a `q`
+/// weight after the first get one going down by `0.1` with the position.
+pub(crate) fn quality_list(items: &[&str]) -> String {
+    let items: Vec<_> = items
+        .iter()
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .enumerate()
+        .map(|(i, item)| {
+            let weighted = item
+                .split(';')
+                .skip(1)
+                .any(|param| param.trim().starts_with("q="));
+            if i == 0 || weighted {
+                item.to_string()
+            } else {
+                let q = 10usize.saturating_sub(i).max(1);
+                format!("{};q=0.{}", item, q)
+            }
+        })
+        .collect();
+    items.join(", ")
+}
+
+/// Parse parameters like `; name=value; other="quoted \\"value\\""` up to the end
+/// or a `,`. Names are lowercased, a name without value gets an empty value.
+pub(crate) fn parse_params(chars: &mut Peekable<Chars>) -> Vec<(String, String)> {
+    let mut params = vec![];
+    loop {
+        skip(chars, char::is_whitespace);
+        if chars.peek() != Some(&';') {
+            break;
+        }
+        chars.next();
+        skip(chars, char::is_whitespace);
+        let name = take_token(chars);
+        skip(chars, char::is_whitespace);
+        let value = if chars.peek() == Some(&'=') {
+            chars.next();
+            skip(chars, char::is_whitespace);
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                take_quoted(chars)
+            } else {
+                take_token(chars)
+            }
+        } else {
+            String::new()
+        };
+        if !name.is_empty() {
+            params.push((name.to_ascii_lowercase(), value));
+        }
+    }
+    params
+}
+
+pub(crate) fn skip(chars: &mut Peekable<Chars>, pred: impl Fn(char) -> bool) {
+    while chars.peek().map(|c| pred(*c)).unwrap_or(false) {
+        chars.next();
+    }
+}
+
+pub(crate) fn take_token(chars: &mut Peekable<Chars>) -> String {
+    let mut token = String::new();
+    while let Some(c) = chars.peek() {
+        if c.is_whitespace() || *c == ';' || *c == ',' || *c == '=' {
+            break;
+        }
+        token.push(*c);
+        chars.next();
+    }
+    token
+}
+
+pub(crate) fn take_quoted(chars: &mut Peekable<Chars>) -> String {
+    let mut quoted = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => quoted.extend(chars.next()),
+            _ => quoted.push(c),
+        }
+    }
+    quoted
+}
+
+/// The file name of a `Content-Disposition` value, preferring the RFC 5987
+/// `filename*` over `filename`. Any directory part is removed.
+pub(crate) fn disposition_filename(value: &str) -> Option<String> {
+    let mut chars = value.chars().peekable();
+    take_token(&mut chars);
+    let params = parse_params(&mut chars);
+    let param = |name: &str| params.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+
+    let name = param("filename*")
+        .and_then(|v| decode_ext_value(v))
+        .or_else(|| param("filename").cloned())?;
+
+    // never let a server pick the directory.
+    let name = name.rsplit(['/', '\\']).next().unwrap_or("");
+    match name.trim() {
+        "" | "." | ".." => None,
+        name => Some(name.to_string()),
+    }
+}
+
+/// Decode an RFC 5987 value like `UTF-8\'en\'%e2%82%ac%20rates`.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let encoded = parts.next()?.as_bytes();
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%' && i + 2 < encoded.len() {
+            let hex = std::str::from_utf8(&encoded[i + 1..i + 3]).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.iter().map(|b| *b as char).collect())
+    } else {
+        None
+    }
+}
+
 impl FromStr for Header {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         //
         let line = s.to_string();
-        let index = s.find(':').ok_or_else(|| Error::BadHeader)?;
+        let index = s.find(':').ok_or(Error::BadHeader)?;
 
         // no value?
         if index >= s.len() {
@@ -97,10 +313,7 @@ impl FromStr for Header {
         // https://www.w3.org/Protocols/rfc2616/rfc2616-sec4.html#sec4.2
         // The field value MAY be preceded by any amount of LWS, though a single SP is preferred.
         let value_from = &s[index..];
-        let voff = match value_from.find(|c: char| !c.is_whitespace()) {
-            Some(n) => n,
-            None => 0,
-        };
+        let voff = value_from.find(|c: char| !c.is_whitespace()).unwrap_or(0);
 
         let index = index + voff;
 
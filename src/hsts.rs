@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use url::{Host, Url};
+
+use crate::agent::AgentState;
+use crate::time;
+
+/// Hosts that told us to only use https, from their `Strict-Transport-Security`
+/// headers.
+///
+/// *Internal API*
+#[derive(Debug, Default)]
+pub(crate) struct HstsStore {
+    hosts: HashMap<String, HstsEntry>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HstsEntry {
+    expires: SystemTime,
+    include_subdomains: bool,
+}
+
+impl HstsStore {
+    /// Remember the `Strict-Transport-Security` header of an https response from
+    /// `host`. A `max-age=0` forgets the host.
+    pub fn record(&mut self, host: &str, value: &str) {
+        let mut max_age = None;
+        let mut include_subdomains = false;
+        for directive in value.split(';') {
+            let directive = directive.trim();
+            let (name, arg) = match directive.split_once('=') {
+                Some((name, arg)) => (name.trim(), arg.trim().trim_matches('"')),
+                None => (directive, ""),
+            };
+            if name.eq_ignore_ascii_case("max-age") {
+                max_age = arg.parse::<u64>().ok();
+            } else if name.eq_ignore_ascii_case("includeSubDomains") {
+                include_subdomains = true;
+            }
+        }
+        // without a valid max-age the header is ignored.
+        let max_age = match max_age {
+            Some(max_age) => max_age,
+            None => return,
+        };
+        let host = host.to_ascii_lowercase();
+        if max_age == 0 {
+            self.hosts.remove(&host);
+            return;
+        }
+        let expires = time::now()
+            .checked_add(Duration::from_secs(max_age))
+            .unwrap_or_else(far_future);
+        let entry = HstsEntry {
+            expires,
+            include_subdomains,
+        };
+        self.hosts.insert(host, entry);
+    }
+
+    /// Whether requests to `host` must use https.
+    pub fn is_known(&self, host: &str) -> bool {
+        let now = time::now();
+        let host = host.to_ascii_lowercase();
+        let live = |name: &str| self.hosts.get(name).filter(|e| e.expires > now);
+        if live(&host).is_some() {
+            return true;
+        }
+        // parent domains that include their subdomains.
+        let mut rest = &host[..];
+        while let Some((_, parent)) = rest.split_once('.') {
+            if live(parent).map(|e| e.include_subdomains).unwrap_or(false) {
+                return true;
+            }
+            rest = parent;
+        }
+        false
+    }
+
+    /// The hosts that haven't expired, one per line as
+    /// `host expires-unix-seconds [includeSubDomains]`.
+    pub fn export(&self) -> String {
+        let now = time::now();
+        let mut lines: Vec<_> = self
+            .hosts
+            .iter()
+            .filter(|(_, entry)| entry.expires > now)
+            .map(|(host, entry)| {
+                let secs = entry
+                    .expires
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                let subdomains = if entry.include_subdomains {
+                    " includeSubDomains"
+                } else {
+                    ""
+                };
+                format!("{} {}{}\n", host, secs, subdomains)
+            })
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+
+    /// Add hosts given by [`export()`](#method.export), skipping lines that
+    /// can't be understood.
+    pub fn import(&mut self, data: &str) {
+        for line in data.lines() {
+            let mut parts = line.split_whitespace();
+            let host = match parts.next() {
+                Some(host) => host.to_ascii_lowercase(),
+                None => continue,
+            };
+            let secs = match parts.next().and_then(|s| s.parse::<u64>().ok()) {
+                Some(secs) => secs,
+                None => continue,
+            };
+            let entry = HstsEntry {
+                expires: UNIX_EPOCH
+                    .checked_add(Duration::from_secs(secs))
+                    .unwrap_or_else(far_future),
+                include_subdomains: parts.next() == Some("includeSubDomains"),
+            };
+            self.hosts.insert(host, entry);
+        }
+    }
+}
+
+fn far_future() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(u32::MAX as u64)
+}
+
+/// The url to request instead of `url`: the https one when the host is known
+/// to require it.
+pub(crate) fn upgrade(state: &Option<AgentState>, url: &Url) -> Option<Url> {
+    if url.scheme() != "http" {
+        return None;
+    }
+    let store = state.as_ref()?.hsts.as_ref()?;
+    let host = match url.host() {
+        Some(Host::Domain(host)) => host,
+        _ => return None,
+    };
+    if !store.read().unwrap().is_known(host) {
+        return None;
+    }
+    let mut upgraded = url.clone();
+    if upgraded.port() == Some(80) {
+        upgraded.set_port(None).ok()?;
+    }
+    upgraded.set_scheme("https").ok()?;
+    Some(upgraded)
+}
+
+/// Remember the `Strict-Transport-Security` header of a response, which only
+/// counts when it came over https from a host that isn't an ip address.
+pub(crate) fn save(state: &Option<AgentState>, url: &Url, value: Option<&str>) {
+    let (store, value) = match (state.as_ref().and_then(|s| s.hsts.as_ref()), value) {
+        (Some(store), Some(value)) => (store, value),
+        _ => return,
+    };
+    if url.scheme() != "https" {
+        return;
+    }
+    if let Some(Host::Domain(host)) = url.host() {
+        store.write().unwrap().record(host, value);
+    }
+}
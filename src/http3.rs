@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::future::Future;
+use std::io::{Cursor, Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::{Buf, Bytes};
+use h3::client::{RequestStream, SendRequest};
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::rustls;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use tokio::runtime::{Handle, Runtime};
+
+use crate::agent::AgentState;
+use crate::error::Error;
+use crate::header;
+use crate::response::Response;
+use crate::stream::{self, Stream};
+use crate::time::Instant;
+use crate::unit::Unit;
+
+/// Seconds an `Alt-Svc` without `ma` holds.
+const DEFAULT_MAX_AGE: u64 = 86_400;
+
+/// Headers of a HTTP/1.1 connection that HTTP/3 doesn't have.
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "transfer-encoding",
+    "upgrade",
+];
+
+type Sender = SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+/// The HTTP/3 connections of an agent, and the origins that said they speak it.
+///
+/// *Internal API*
+#[derive(Default)]
+pub(crate) struct Http3 {
+    /// Runs the QUIC connections, started on first use.
+    runtime: Mutex<Option<Runtime>>,
+    /// By host and port.
+    connections: Mutex<HashMap<(String, u16), Connection>>,
+    /// The HTTP/3 port of `https` origins by their host and port, and until when.
+    alt_svc: Mutex<HashMap<(String, u16), (u16, Instant)>>,
+}
+
+impl ::std::fmt::Debug for Http3 {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "Http3")
+    }
+}
+
+struct Connection {
+    quic: quinn::Connection,
+    sender: Sender,
+}
+
+impl Http3 {
+    fn handle(&self) -> IoResult<Handle> {
+        let mut runtime = self.runtime.lock().unwrap();
+        if runtime.is_none() {
+            *runtime = Some(
+                tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(1)
+                    .thread_name("ureq-http3")
+                    .enable_all()
+                    .build()?,
+            );
+        }
+        Ok(runtime.as_ref().unwrap().handle().clone())
+    }
+
+    /// The HTTP/3 port the origin advertised, if it still holds.
+    fn advertised(&self, host: &str, port: u16) -> Option<u16> {
+        let alt_svc = self.alt_svc.lock().unwrap();
+        match alt_svc.get(&(host.to_string(), port)) {
+            Some((h3_port, until)) if *until > Instant::now() => Some(*h3_port),
+            _ => None,
+        }
+    }
+
+    fn forget(&self, host: &str, port: u16) {
+        self.alt_svc
+            .lock()
+            .unwrap()
+            .remove(&(host.to_string(), port));
+    }
+
+    /// Take in an `Alt-Svc` header of the origin. Only alternatives on the same
+    /// host are used.
+    fn save(&self, host: &str, port: u16, value: &str) {
+        let key = (host.to_string(), port);
+        let mut alt_svc = self.alt_svc.lock().unwrap();
+        if value.trim().eq_ignore_ascii_case("clear") {
+            alt_svc.remove(&key);
+            return;
+        }
+        for item in header::split_list(value) {
+            let mut params = item.split(';').map(str::trim);
+            let authority = match params.next().and_then(|a| a.strip_prefix("h3=")) {
+                Some(authority) => authority.trim_matches('"'),
+                None => continue,
+            };
+            let h3_port = match authority.rsplit_once(':') {
+                Some((alt_host, alt_port)) if alt_host.is_empty() || alt_host == host => {
+                    match alt_port.parse::<u16>() {
+                        Ok(alt_port) => alt_port,
+                        Err(_) => continue,
+                    }
+                }
+                _ => continue,
+            };
+            let max_age = params
+                .find_map(|p| p.strip_prefix("ma="))
+                .and_then(|ma| ma.parse().ok())
+                .unwrap_or(DEFAULT_MAX_AGE);
+            let until = Instant::now() + Duration::from_secs(max_age);
+            alt_svc.insert(key, (h3_port, until));
+            return;
+        }
+    }
+
+    /// A connection to the host, reused while it's open.
+    fn sender(
+        &self,
+        unit: &Unit,
+        agent: &AgentState,
+        handle: &Handle,
+        host: &str,
+        port: u16,
+    ) -> Result<(Sender, SocketAddr), Error> {
+        let key = (host.to_string(), port);
+        if let Some(conn) = self.connections.lock().unwrap().get(&key) {
+            if conn.quic.close_reason().is_none() {
+                return Ok((conn.sender.clone(), conn.quic.remote_address()));
+            }
+        }
+
+        let ips = stream::target_addrs(unit, host, port)?;
+        let addr = stream::pick_addr(unit, &ips);
+        let bind: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0_u16; 8], 0).into()
+        };
+        let insecure = agent.tls_settings.insecure_hosts.iter().any(|h| h == host);
+        let config = client_config(insecure)?;
+        let sni = host.trim_matches(['[', ']']);
+        // quinn spawns its tasks on the current runtime.
+        let connecting = {
+            let _runtime = handle.enter();
+            quinn::Endpoint::client(bind)?
+                .connect_with(config, addr, sni)
+                .map_err(|e| Error::ConnectionFailed(e.to_string()))?
+        };
+        let quic = match block_on(handle, unit.timeout_connect, connecting) {
+            Ok(Ok(quic)) => quic,
+            Ok(Err(err)) => return Err(Error::ConnectionFailed(err.to_string())),
+            Err(err) => return Err(Error::ConnectTimeout(err)),
+        };
+        let (mut driver, sender) = block_on(
+            handle,
+            unit.timeout_connect,
+            h3::client::new(h3_quinn::Connection::new(quic.clone())),
+        )?
+        .map_err(|e| Error::ConnectionFailed(e.to_string()))?;
+        handle.spawn(async move {
+            let _ = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+        });
+
+        self.connections.lock().unwrap().insert(
+            key,
+            Connection {
+                quic,
+                sender: sender.clone(),
+            },
+        );
+        Ok((sender, addr))
+    }
+}
+
+/// TLS 1.3 with the `h3` protocol, trusting the webpki roots unless the host's
+/// certificates aren't verified.
+fn client_config(insecure: bool) -> Result<quinn::ClientConfig, Error> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| Error::Tls(IoError::other(e)))?;
+    let mut config = if insecure {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAny(provider)))
+            .with_no_client_auth()
+    } else {
+        let roots = rustls::RootCertStore {
+            roots: webpki_roots_h3::TLS_SERVER_ROOTS.to_vec(),
+        };
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+    config.alpn_protocols = vec![b"h3".to_vec()];
+    let config = QuicClientConfig::try_from(config).map_err(|e| Error::Tls(IoError::other(e)))?;
+    Ok(quinn::ClientConfig::new(Arc::new(config)))
+}
+
+/// For [`Agent::accept_invalid_certs_for_host()`](struct.Agent.html#method.accept_invalid_certs_for_host).
+#[derive(Debug)]
+struct AcceptAny(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAny {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer,
+        _intermediates: &[CertificateDer],
+        _server_name: &ServerName,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        rustls::crypto::verify_tls12_signature(message, cert, dss, algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        rustls::crypto::verify_tls13_signature(message, cert, dss, algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Run the future on the runtime, at most `millis` unless `0`.
+fn block_on<F: Future>(handle: &Handle, millis: u64, future: F) -> IoResult<F::Output> {
+    handle.block_on(async {
+        match millis {
+            0 => Ok(future.await),
+            millis => tokio::time::timeout(Duration::from_millis(millis), future)
+                .await
+                .map_err(|_| IoError::new(ErrorKind::TimedOut, "HTTP/3 timed out")),
+        }
+    })
+}
+
+fn h3_error(err: impl std::fmt::Display) -> IoError {
+    IoError::other(err.to_string())
+}
+
+/// A stream of the request when it's to go over HTTP/3. `None` to connect
+/// over TCP, also when connecting to an origin that advertised HTTP/3 fails.
+pub(crate) fn connect(unit: &Unit) -> Result<Option<Stream>, Error> {
+    if unit.url.scheme() != "https" || !(unit.http3 || unit.http3_only) {
+        return Ok(None);
+    }
+    let agent = match (*unit.agent).as_ref() {
+        Some(agent) => agent,
+        None if unit.http3_only => {
+            return Err(Error::ConnectionFailed("HTTP/3 needs a built agent".into()))
+        }
+        None => return Ok(None),
+    };
+    let host = unit.url.host_str().unwrap_or("").to_ascii_lowercase();
+    let port = unit.url.port_or_known_default().unwrap_or(443);
+    let h3_port = if unit.http3_only {
+        port
+    } else {
+        match agent.http3.advertised(&host, port) {
+            Some(h3_port) => h3_port,
+            None => return Ok(None),
+        }
+    };
+    if !unit.proxies.is_empty() {
+        if unit.http3_only {
+            return Err(Error::ConnectionFailed(
+                "HTTP/3 doesn't go through a proxy".into(),
+            ));
+        }
+        return Ok(None);
+    }
+    let authority = match unit.url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.clone(),
+    };
+    match H3Stream::open(unit, agent, &host, h3_port, authority) {
+        Ok(stream) => Ok(Some(Stream::Http3(Box::new(stream)))),
+        Err(err) if unit.http3_only => Err(err),
+        Err(_err) => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(error = %_err, "HTTP/3 failed, using TCP");
+            agent.http3.forget(&host, port);
+            Ok(None)
+        }
+    }
+}
+
+/// Take in the `Alt-Svc` header of a response of an agent using HTTP/3.
+pub(crate) fn save_alt_svc(unit: &Unit, resp: &Response) {
+    if !unit.http3 || unit.url.scheme() != "https" {
+        return;
+    }
+    if let (Some(agent), Some(value)) = ((*unit.agent).as_ref(), resp.header("alt-svc")) {
+        let host = unit.url.host_str().unwrap_or("").to_ascii_lowercase();
+        let port = unit.url.port_or_known_default().unwrap_or(443);
+        agent.http3.save(&host, port, value);
+    }
+}
+
+/// A request over a HTTP/3 connection.
+///
+/// The request is written to it as it would be to a HTTP/1.1 connection, and
+/// is sent once the response is read, which is then given as if it came over
+/// HTTP/1.1, with `HTTP/3` in its status line.
+///
+/// *Internal API*
+pub(crate) struct H3Stream {
+    handle: Handle,
+    sender: Sender,
+    peer: SocketAddr,
+    authority: String,
+    chunked: bool,
+    timeout_read: u64,
+    timeout_write: u64,
+    written: Vec<u8>,
+    head: Option<Cursor<Vec<u8>>>,
+    stream: Option<RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>>,
+    data: Bytes,
+}
+
+impl H3Stream {
+    fn open(
+        unit: &Unit,
+        agent: &AgentState,
+        host: &str,
+        port: u16,
+        authority: String,
+    ) -> Result<H3Stream, Error> {
+        let handle = agent.http3.handle()?;
+        let (sender, peer) = agent.http3.sender(unit, agent, &handle, host, port)?;
+        Ok(H3Stream {
+            handle,
+            sender,
+            peer,
+            authority,
+            chunked: unit.is_chunked,
+            timeout_read: unit.timeout_read,
+            timeout_write: unit.timeout_write,
+            written: vec![],
+            head: None,
+            stream: None,
+            data: Bytes::new(),
+        })
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Send the written request, giving the head of the response.
+    fn exchange(&mut self) -> IoResult<Vec<u8>> {
+        let split = self
+            .written
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| {
+                IoError::new(ErrorKind::InvalidInput, "Response read before the request")
+            })?;
+        let head = String::from_utf8_lossy(&self.written[..split]).to_string();
+        let mut body = self.written[split + 4..].to_vec();
+        if self.chunked {
+            let mut decoded = vec![];
+            chunked_transfer::Decoder::new(&body[..]).read_to_end(&mut decoded)?;
+            body = decoded;
+        }
+
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap_or("").split(' ');
+        let method = request_line.next().unwrap_or("GET");
+        let target = request_line.next().unwrap_or("/");
+        let mut request = http::Request::builder()
+            .method(method)
+            .uri(format!("https://{}{}", self.authority, target));
+        for line in lines {
+            if let Some((name, value)) = line.split_once(':') {
+                let name = name.trim();
+                if !CONNECTION_HEADERS
+                    .iter()
+                    .any(|h| h.eq_ignore_ascii_case(name))
+                {
+                    request = request.header(name, value.trim());
+                }
+            }
+        }
+        let request = request
+            .body(())
+            .map_err(|e| IoError::new(ErrorKind::InvalidInput, e))?;
+
+        let sender = &mut self.sender;
+        let mut stream = block_on(&self.handle, self.timeout_write, async {
+            let mut stream = sender.send_request(request).await?;
+            if !body.is_empty() {
+                stream.send_data(Bytes::from(body)).await?;
+            }
+            stream.finish().await?;
+            Ok::<_, h3::error::StreamError>(stream)
+        })?
+        .map_err(h3_error)?;
+        let response =
+            block_on(&self.handle, self.timeout_read, stream.recv_response())?.map_err(h3_error)?;
+        self.stream = Some(stream);
+
+        let status = response.status();
+        let mut head = format!(
+            "HTTP/3 {} {}\r\n",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or("")
+        );
+        for (name, value) in response.headers() {
+            head.push_str(&format!(
+                "{}: {}\r\n",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            ));
+        }
+        head.push_str("\r\n");
+        Ok(head.into_bytes())
+    }
+}
+
+impl Read for H3Stream {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.head.is_none() {
+            self.head = Some(Cursor::new(self.exchange()?));
+        }
+        let amount = self.head.as_mut().unwrap().read(buf)?;
+        if amount > 0 || buf.is_empty() {
+            return Ok(amount);
+        }
+        while !self.data.has_remaining() {
+            let stream = match self.stream.as_mut() {
+                Some(stream) => stream,
+                None => return Ok(0),
+            };
+            match block_on(&self.handle, self.timeout_read, stream.recv_data())? {
+                Ok(Some(mut data)) => self.data = data.copy_to_bytes(data.remaining()),
+                Ok(None) => {
+                    self.stream = None;
+                    return Ok(0);
+                }
+                Err(err) => return Err(h3_error(err)),
+            }
+        }
+        let amount = buf.len().min(self.data.len());
+        self.data.copy_to_slice(&mut buf[..amount]);
+        Ok(amount)
+    }
+}
+
+impl Write for H3Stream {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
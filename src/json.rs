@@ -0,0 +1,43 @@
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult};
+
+use serde::de::DeserializeOwned;
+
+use crate::SerdeValue;
+
+/// Serialize a value for a request body.
+#[cfg(not(feature = "simd-json"))]
+pub(crate) fn to_vec(value: &SerdeValue) -> Vec<u8> {
+    serde_json::to_vec(value).expect("Bad JSON in payload")
+}
+
+/// Serialize a value for a request body.
+#[cfg(feature = "simd-json")]
+pub(crate) fn to_vec(value: &SerdeValue) -> Vec<u8> {
+    simd_json::serde::to_vec(value).expect("Bad JSON in payload")
+}
+
+/// Deserialize a response body as it is read.
+#[cfg(not(feature = "simd-json"))]
+pub(crate) fn from_reader<T: DeserializeOwned>(reader: impl Read) -> IoResult<T> {
+    serde_json::from_reader(reader).map_err(|e| match e.io_error_kind() {
+        // errors from the reader, such as a size limit, are kept as is.
+        Some(_) => e.into(),
+        None => read_error(e),
+    })
+}
+
+/// Deserialize a response body. simd-json parses in place, so the whole body is
+/// read into memory first.
+#[cfg(feature = "simd-json")]
+pub(crate) fn from_reader<T: DeserializeOwned>(mut reader: impl Read) -> IoResult<T> {
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf)?;
+    simd_json::serde::from_slice(&mut buf).map_err(read_error)
+}
+
+fn read_error(e: impl std::fmt::Display) -> IoError {
+    IoError::new(
+        ErrorKind::InvalidData,
+        format!("Failed to read JSON: {}", e),
+    )
+}
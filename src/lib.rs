@@ -1,4 +1,14 @@
 #![warn(clippy::all)]
+// the browser makes the requests, the sockets are unused there.
+#![cfg_attr(
+    all(feature = "wasm", target_arch = "wasm32"),
+    allow(dead_code, unused_imports)
+)]
+// and rustls is unused when the Security framework does TLS.
+#![cfg_attr(
+    all(feature = "security-framework", target_vendor = "apple"),
+    allow(dead_code, unused_imports)
+)]
 //! ureq is a minimal request library.
 //!
 //! The goals of this library are:
@@ -93,15 +103,74 @@
 //!
 
 mod agent;
+#[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+mod apple_tls;
+mod background;
 mod body;
+mod byteranges;
+mod cache_control;
+#[cfg(feature = "checksum")]
+mod checksum;
+mod concurrency;
+mod config;
+mod content_type;
+#[cfg(feature = "cookie")]
+mod cookie_policy;
+mod data_url;
+mod date;
+mod decode;
+mod download;
 mod error;
+mod events;
+mod faults;
+#[cfg(feature = "file")]
+mod file_url;
+#[cfg(feature = "json")]
+mod graphql;
 mod header;
+mod hsts;
+#[cfg(feature = "http3")]
+mod http3;
+#[cfg(feature = "json")]
+mod json;
+mod link;
+mod metrics;
+mod multipart;
+#[cfg(feature = "negotiate")]
+mod negotiate;
+#[cfg(feature = "ntlm")]
+mod ntlm;
+#[cfg(feature = "oauth2")]
+mod oauth2;
+#[cfg(feature = "pac")]
+mod pac;
 mod pool;
+mod prepared;
+mod proxy;
+mod ratelimit;
+mod redirect;
 mod request;
+mod request_id;
 mod response;
+mod retry;
+#[cfg(feature = "tls")]
+mod revocation;
+#[cfg(feature = "signatures")]
+mod signature;
+#[cfg(all(feature = "sockopt", target_os = "linux"))]
+mod sockopt;
+mod sse;
 mod stream;
+mod time;
+#[cfg(feature = "tls")]
+mod tls;
 mod unit;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod wasm;
+mod webdav;
 
+#[cfg(feature = "har")]
+mod har;
 #[cfg(feature = "json")]
 mod serde_macros;
 
@@ -109,10 +178,42 @@ mod serde_macros;
 mod test;
 
 pub use crate::agent::Agent;
+#[cfg(feature = "async")]
+pub use crate::background::AsyncRequest;
+pub use crate::background::ResponseHandle;
+pub use crate::body::Body;
+pub use crate::byteranges::{ByteRanges, RangePart};
+pub use crate::cache_control::CacheControl;
+#[cfg(feature = "checksum")]
+pub use crate::checksum::Algorithm;
+pub use crate::config::AgentConfig;
+pub use crate::content_type::ContentType;
+pub use crate::date::{format_http_date, parse_http_date};
+pub use crate::download::Download;
 pub use crate::error::Error;
+pub use crate::events::EventListener;
+#[cfg(feature = "json")]
+pub use crate::graphql::{GraphqlError, GraphqlResponse};
 pub use crate::header::Header;
-pub use crate::request::{Request, IpVersion};
-pub use crate::response::Response;
+pub use crate::link::{Link, Pages};
+pub use crate::metrics::{AgentStats, MetricsObserver, RequestMetrics};
+pub use crate::multipart::Multipart;
+pub use crate::prepared::PreparedRequest;
+pub use crate::proxy::Proxy;
+pub use crate::redirect::RedirectPolicy;
+pub use crate::request::{ArrayStyle, IpVersion, QueryEncoding, Request};
+#[cfg(feature = "json")]
+pub use crate::response::JsonLines;
+pub use crate::response::{Chunks, Response};
+#[cfg(feature = "tls")]
+pub use crate::revocation::Crl;
+#[cfg(feature = "signatures")]
+pub use crate::signature::SigningKey;
+pub use crate::sse::{Event, Events, Subscription};
+pub use crate::stream::Direction;
+#[cfg(feature = "tls")]
+pub use crate::tls::{CertificateVerifier, ClientIdentity, TlsCertificate};
+pub use crate::webdav::Depth;
 
 // re-export
 #[cfg(feature = "cookie")]
@@ -130,10 +231,64 @@ pub fn agent() -> Agent {
 /// ```
 /// ureq::request("GET", "https://www.google.com").call();
 /// ```
+///
+/// A `data:` url gives its contents as a `200 OK` response, without any
+/// network traffic.
+///
+/// ```
+/// let resp = ureq::request("GET", "data:text/plain;base64,SGVsbG8=").call();
+/// assert_eq!(resp.content_type(), "text/plain");
+/// assert_eq!(resp.into_string().unwrap(), "Hello");
+/// ```
 pub fn request(method: &str, path: &str) -> Request {
     Agent::new().request(method, path)
 }
 
+/// Make a request to `.await`, setting the HTTP method via a string. The
+/// request is made on a thread, so any async runtime will do.
+///
+/// ```
+/// # async fn run() {
+/// let resp = ureq::async_request("GET", "/my_page")
+///     .set("Accept", "text/html")
+///     .call_background()
+///     .await;
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub fn async_request(method: &str, path: &str) -> AsyncRequest {
+    request(method, path).into()
+}
+
+/// Make a GET request to `.await`.
+///
+/// ```
+/// # async fn run() {
+/// let resp = ureq::async_get("/my_page").call().await;
+/// println!("{}", resp.status());
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub fn async_get(path: &str) -> AsyncRequest {
+    async_request("GET", path)
+}
+
+/// Make a POST request to `.await`.
+#[cfg(feature = "async")]
+pub fn async_post(path: &str) -> AsyncRequest {
+    async_request("POST", path)
+}
+
+/// Make a request with an already parsed url.
+///
+/// ```
+/// let url = url::Url::parse("https://www.google.com").unwrap();
+/// ureq::request_url("GET", &url).call();
+/// ```
+pub fn request_url(method: &str, url: &url::Url) -> Request {
+    Agent::new().request_url(method, url)
+}
+
 /// Make a GET request.
 pub fn get(path: &str) -> Request {
     request("GET", path)
@@ -169,6 +324,11 @@ pub fn options(path: &str) -> Request {
     request("OPTIONS", path)
 }
 
+/// Make an `OPTIONS *` request to the server of a url.
+pub fn options_asterisk(url: &str) -> Request {
+    Agent::new().options_asterisk(url)
+}
+
 /// Make an CONNECT request.
 pub fn connect(path: &str) -> Request {
     request("CONNECT", path)
@@ -179,6 +339,41 @@ pub fn patch(path: &str) -> Request {
     request("PATCH", path)
 }
 
+/// Make a WebDAV PROPFIND request, with `Depth: 1`.
+pub fn propfind(path: &str) -> Request {
+    Agent::new().propfind(path)
+}
+
+/// Make a WebDAV PROPPATCH request.
+pub fn proppatch(path: &str) -> Request {
+    request("PROPPATCH", path)
+}
+
+/// Make a WebDAV MKCOL request.
+pub fn mkcol(path: &str) -> Request {
+    request("MKCOL", path)
+}
+
+/// Make a WebDAV COPY request to a destination.
+pub fn copy(path: &str, destination: &str) -> Request {
+    Agent::new().copy(path, destination)
+}
+
+/// Make a WebDAV MOVE request to a destination.
+pub fn move_(path: &str, destination: &str) -> Request {
+    Agent::new().move_(path, destination)
+}
+
+/// Make a WebDAV LOCK request.
+pub fn lock(path: &str) -> Request {
+    request("LOCK", path)
+}
+
+/// Make a WebDAV UNLOCK request of a lock token.
+pub fn unlock(path: &str, token: &str) -> Request {
+    Agent::new().unlock(path, token)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use url::Url;
+
+use crate::agent::Agent;
+use crate::header;
+use crate::response::Response;
+
+/// A link from an RFC 8288 `Link` header, see [`Response::links()`](struct.Response.html#method.links).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    url: String,
+    params: Vec<(String, String)>,
+}
+
+impl Link {
+    /// The target, resolved against the response url when relative.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The `rel` parameter, such as `next`. Can hold several space separated
+    /// relation types.
+    pub fn rel(&self) -> Option<&str> {
+        self.param("rel")
+    }
+
+    /// A parameter by its (case insensitive) name, such as `title`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Links of all `Link` header values, keyed by each lowercased relation type.
+/// The first link wins when several have the same relation type.
+pub(crate) fn links(resp: &Response) -> HashMap<String, Link> {
+    let base = Url::parse(resp.get_url()).ok();
+    let mut map = HashMap::new();
+    for value in resp.all("link") {
+        for mut link in parse(value) {
+            if let Some(url) = base.as_ref().and_then(|b| b.join(&link.url).ok()) {
+                link.url = url.to_string();
+            }
+            let rels: Vec<String> = link
+                .rel()
+                .unwrap_or("")
+                .split_whitespace()
+                .map(|r| r.to_ascii_lowercase())
+                .collect();
+            for rel in rels {
+                map.entry(rel).or_insert_with(|| link.clone());
+            }
+        }
+    }
+    map
+}
+
+/// Parse one header value like `<https://x/?page=2>; rel="next", <https://x/?page=5>; rel=last`.
+/// Malformed links are skipped.
+fn parse(value: &str) -> Vec<Link> {
+    let mut links = vec![];
+    let mut chars = value.chars().peekable();
+    loop {
+        header::skip(&mut chars, |c| c.is_whitespace() || c == ',');
+        match chars.next() {
+            Some('<') => (),
+            Some(_) => {
+                // not a link, skip to the next one.
+                header::skip(&mut chars, |c| c != ',');
+                continue;
+            }
+            None => break,
+        }
+        let url: String = chars.by_ref().take_while(|c| *c != '>').collect();
+        let params = header::parse_params(&mut chars);
+        links.push(Link { url, params });
+        // anything up to the next link is garbage.
+        header::skip(&mut chars, |c| c != ',');
+    }
+    links
+}
+
+/// Iterator over the pages of a listing, see [`Agent::paginate()`](struct.Agent.html#method.paginate).
+#[derive(Debug)]
+pub struct Pages<'a> {
+    agent: &'a Agent,
+    next: Option<String>,
+}
+
+impl<'a> Pages<'a> {
+    pub(crate) fn new(agent: &'a Agent, url: &str) -> Self {
+        Pages {
+            agent,
+            next: Some(url.to_string()),
+        }
+    }
+}
+
+impl<'a> Iterator for Pages<'a> {
+    type Item = Response;
+
+    fn next(&mut self) -> Option<Response> {
+        let url = self.next.take()?;
+        let resp = self.agent.get(&url).call();
+        if resp.ok() {
+            self.next = links(&resp).remove("next").map(|l| l.url);
+        }
+        Some(resp)
+    }
+}
@@ -1,8 +1,224 @@
-use ureq::IpVersion;
+//! `ureq`, a small curl-like command line client built on the library.
+//!
+//! ```text
+//! ureq [OPTIONS] <URL>
+//! ```
+//!
+//! Requires feature `ureq = { version = "*", features = ["cli"] }`
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::process;
+
+use ureq::{Proxy, Request, Response};
+
+const USAGE: &str = "\
+Usage: ureq [OPTIONS] <URL>
+
+Options:
+  -X, --request <METHOD>     Method to use, GET or POST with data by default
+  -H, --header <LINE>        Header to send, like 'Accept: text/plain'
+  -d, --data <DATA>          Body to send, @file to read it from a file, @- from stdin
+      --json <DATA>          Like --data, sent and accepted as application/json
+  -o, --output <FILE>        Write the body to a file instead of stdout
+  -i, --include              Print the response head before the body
+  -f, --fail                 Exit with 22 and no body on 4xx and 5xx responses
+  -v, --verbose              Print the request and response heads to stderr
+  -x, --proxy <URL>          SOCKS5 proxy, like socks5h://localhost:1080
+  -k, --insecure             Accept any certificate of the host
+      --connect-timeout <S>  Seconds to wait for the connection
+  -m, --timeout <S>          Seconds to wait for each read and write
+      --max-redirs <N>       Redirects to follow, 0 for none
+  -h, --help                 Print this help
+";
+
+/// The options of one invocation.
+#[derive(Debug, Default)]
+struct Args {
+    url: String,
+    method: Option<String>,
+    headers: Vec<(String, String)>,
+    data: Option<String>,
+    json: bool,
+    output: Option<String>,
+    include: bool,
+    fail: bool,
+    verbose: bool,
+    proxy: Option<String>,
+    insecure: bool,
+    connect_timeout: Option<u64>,
+    timeout: Option<u64>,
+    max_redirs: Option<u32>,
+}
 
 fn main() {
-    ureq::get("https://google.com/")
-        .set_preferred_ip_version(IpVersion::V6)
-        .call();
-    println!("done");
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("ureq: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    match run(&args) {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            eprintln!("ureq: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// The options, or `None` when asked for help.
+fn parse_args(mut argv: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut args = Args::default();
+    let mut url = None;
+    while let Some(arg) = argv.next() {
+        let mut value = |name: &str| argv.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-X" | "--request" => args.method = Some(value(&arg)?.to_ascii_uppercase()),
+            "-H" | "--header" => {
+                let line = value(&arg)?;
+                let (name, val) = line
+                    .split_once(':')
+                    .ok_or_else(|| format!("Bad header: {}", line))?;
+                args.headers
+                    .push((name.trim().to_string(), val.trim().to_string()));
+            }
+            "-d" | "--data" => args.data = Some(value(&arg)?),
+            "--json" => {
+                args.data = Some(value(&arg)?);
+                args.json = true;
+            }
+            "-o" | "--output" => args.output = Some(value(&arg)?),
+            "-i" | "--include" => args.include = true,
+            "-f" | "--fail" => args.fail = true,
+            "-v" | "--verbose" => args.verbose = true,
+            "-x" | "--proxy" => args.proxy = Some(value(&arg)?),
+            "-k" | "--insecure" => args.insecure = true,
+            "--connect-timeout" => args.connect_timeout = Some(number(&arg, value(&arg)?)?),
+            "-m" | "--timeout" => args.timeout = Some(number(&arg, value(&arg)?)?),
+            "--max-redirs" => args.max_redirs = Some(number(&arg, value(&arg)?)? as u32),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("Unknown option: {}", arg))
+            }
+            _ if url.is_some() => return Err(format!("More than one url: {}", arg)),
+            _ => url = Some(arg),
+        }
+    }
+    args.url = url.ok_or("No url given")?;
+    Ok(Some(args))
+}
+
+fn number(name: &str, value: String) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} needs a number, not {}", name, value))
+}
+
+/// Make the request and write out the response, giving the exit code.
+fn run(args: &Args) -> Result<i32, String> {
+    let body = match &args.data {
+        Some(data) => Some(read_data(data)?),
+        None => None,
+    };
+
+    let mut agent = ureq::agent();
+    if let Some(proxy) = &args.proxy {
+        agent.set_proxy(Proxy::new(proxy).map_err(|e| e.to_string())?);
+    }
+    if args.insecure {
+        insecure(&mut agent, &args.url)?;
+    }
+    let agent = agent.verbose(args.verbose).build();
+
+    let method = match (&args.method, &body) {
+        (Some(method), _) => method.clone(),
+        (None, Some(_)) => "POST".to_string(),
+        (None, None) => "GET".to_string(),
+    };
+    let mut req = agent.request(&method, &args.url);
+    if args.json {
+        req.set("Content-Type", "application/json")
+            .set("Accept", "application/json");
+    }
+    for (name, value) in &args.headers {
+        req.set(name, value);
+    }
+    apply_limits(&mut req, args);
+
+    let resp = match &body {
+        Some(body) => req.send_bytes(body),
+        None => req.call(),
+    };
+    if let Some(err) = resp.synthetic_error() {
+        return Err(err.to_string());
+    }
+    write_response(resp, args)
+}
+
+fn apply_limits(req: &mut Request, args: &Args) {
+    if let Some(secs) = args.connect_timeout {
+        req.timeout_connect(secs * 1000);
+    }
+    if let Some(secs) = args.timeout {
+        req.timeout_read(secs * 1000).timeout_write(secs * 1000);
+    }
+    if let Some(n) = args.max_redirs {
+        req.redirects(n);
+    }
+}
+
+#[cfg(feature = "tls")]
+fn insecure(agent: &mut ureq::Agent, url: &str) -> Result<(), String> {
+    let host = ureq::get(url).get_host().map_err(|e| e.to_string())?;
+    agent.accept_invalid_certs_for_host(&host);
+    Ok(())
+}
+
+#[cfg(not(feature = "tls"))]
+fn insecure(_agent: &mut ureq::Agent, _url: &str) -> Result<(), String> {
+    Err("--insecure needs feature tls".to_string())
+}
+
+/// The body of `--data`, read from a file or stdin for `@file` and `@-`.
+fn read_data(data: &str) -> Result<Vec<u8>, String> {
+    match data.strip_prefix('@') {
+        Some("-") => {
+            let mut buf = vec![];
+            io::stdin()
+                .read_to_end(&mut buf)
+                .map_err(|e| format!("Reading stdin: {}", e))?;
+            Ok(buf)
+        }
+        Some(path) => fs::read(path).map_err(|e| format!("Reading {}: {}", path, e)),
+        None => Ok(data.as_bytes().to_vec()),
+    }
+}
+
+fn write_response(resp: Response, args: &Args) -> Result<i32, String> {
+    if args.fail && resp.status() >= 400 {
+        eprintln!("ureq: The requested URL returned error: {}", resp.status());
+        return Ok(22);
+    }
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if args.include {
+        stdout
+            .write_all(resp.raw_head())
+            .map_err(|e| e.to_string())?;
+    }
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => {
+            Box::new(File::create(path).map_err(|e| format!("Creating {}: {}", path, e))?)
+        }
+        None => Box::new(stdout),
+    };
+    io::copy(&mut resp.into_reader(), &mut out).map_err(|e| e.to_string())?;
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(0)
 }
@@ -0,0 +1,292 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Receives measurements of every request made by an agent, see
+/// [`Agent::metrics_observer()`](struct.Agent.html#method.metrics_observer).
+///
+/// Meant for wiring up exporters such as prometheus or statsd.
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use ureq::{MetricsObserver, RequestMetrics};
+///
+/// #[derive(Default)]
+/// struct Counter(AtomicUsize);
+///
+/// impl MetricsObserver for Counter {
+///     fn on_request(&self, metrics: &RequestMetrics) {
+///         println!("{} {} {}", metrics.method(), metrics.host(), metrics.status_class());
+///         self.0.fetch_add(1, Ordering::SeqCst);
+///     }
+/// }
+///
+/// let agent = ureq::agent().metrics_observer(Counter::default()).build();
+/// ```
+pub trait MetricsObserver: Send + Sync {
+    /// Called once per request, when the response head has been read or the
+    /// request failed.
+    fn on_request(&self, metrics: &RequestMetrics);
+}
+
+impl ::std::fmt::Debug for dyn MetricsObserver {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "MetricsObserver")
+    }
+}
+
+/// Phase timings and byte counts of one request/response exchange.
+///
+/// *Internal API*
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ExchangeStats {
+    /// Time to open the connection, `None` for a pooled connection.
+    pub connect: Option<Duration>,
+    pub send: Duration,
+    pub wait: Duration,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Bytes written and read for a request, across the redirects followed and
+/// the retries made for it. Shared with the body reader of the response,
+/// which adds the body as it is read.
+///
+/// *Internal API*
+#[derive(Debug, Default)]
+pub(crate) struct ByteCounts {
+    written: AtomicU64,
+    read: AtomicU64,
+    /// The totals of the agent, which the bytes are added to as well.
+    agent: Option<Arc<AgentCounters>>,
+}
+
+impl ByteCounts {
+    pub fn new(agent: Option<Arc<AgentCounters>>) -> Self {
+        ByteCounts {
+            agent,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&self, written: u64, read: u64) {
+        self.written.fetch_add(written, Ordering::Relaxed);
+        self.read.fetch_add(read, Ordering::Relaxed);
+        if let Some(agent) = &self.agent {
+            agent.bytes_sent.fetch_add(written, Ordering::Relaxed);
+            agent.bytes_received.fetch_add(read, Ordering::Relaxed);
+        }
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    pub fn read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+}
+
+/// Measurements of one request, passed to a [`MetricsObserver`].
+///
+/// When redirects are followed, the phase durations and byte counts are of
+/// the last exchange, while [`total()`](#method.total) covers all of them.
+#[derive(Debug)]
+pub struct RequestMetrics<'a> {
+    pub(crate) method: &'a str,
+    pub(crate) host: &'a str,
+    pub(crate) status: u16,
+    pub(crate) error: Option<&'a Error>,
+    pub(crate) stats: ExchangeStats,
+    pub(crate) total: Duration,
+}
+
+impl<'a> RequestMetrics<'a> {
+    /// The HTTP verb, such as `GET`.
+    pub fn method(&self) -> &str {
+        self.method
+    }
+
+    /// The host the request was sent to, empty if the url was invalid.
+    pub fn host(&self) -> &str {
+        self.host
+    }
+
+    /// The response status. For failed requests this is the status of the
+    /// synthetic response, see [`error()`](#method.error).
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The status as a label: `"1xx"` to `"5xx"`, or `"error"` when the request
+    /// failed without a response from the server.
+    pub fn status_class(&self) -> &'static str {
+        if self.error.is_some() {
+            return "error";
+        }
+        match self.status / 100 {
+            1 => "1xx",
+            2 => "2xx",
+            3 => "3xx",
+            4 => "4xx",
+            _ => "5xx",
+        }
+    }
+
+    /// The error if the request failed, such as `Error::ConnectionFailed`.
+    pub fn error(&self) -> Option<&Error> {
+        self.error
+    }
+
+    /// Whether the server answered with a 2xx status.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && self.status >= 200 && self.status < 300
+    }
+
+    /// Bytes written for the request head and body.
+    pub fn bytes_sent(&self) -> u64 {
+        self.stats.bytes_sent
+    }
+
+    /// Bytes read for the response head. The response body is read after
+    /// the observer is called and is not included.
+    pub fn bytes_received(&self) -> u64 {
+        self.stats.bytes_received
+    }
+
+    /// Time to open the connection. `None` if a pooled connection was reused
+    /// or no connection was made.
+    pub fn connect_duration(&self) -> Option<Duration> {
+        self.stats.connect
+    }
+
+    /// Time to write the request head and body.
+    pub fn send_duration(&self) -> Duration {
+        self.stats.send
+    }
+
+    /// Time from the request being sent until the response head was read.
+    pub fn wait_duration(&self) -> Duration {
+        self.stats.wait
+    }
+
+    /// Time for the whole call, including redirects.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+}
+
+/// Totals of all requests made by an agent, see
+/// [`Agent::stats()`](struct.Agent.html#method.stats).
+///
+/// *Internal API*
+#[derive(Debug, Default)]
+pub(crate) struct AgentCounters {
+    requests: AtomicU64,
+    connections_opened: AtomicU64,
+    connections_reused: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    client_errors: AtomicU64,
+    server_errors: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl AgentCounters {
+    /// Count a finished request by the class of its response.
+    pub fn request(&self, status: u16, error: Option<&Error>) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let class = match (error, status / 100) {
+            (Some(_), _) => &self.failed,
+            (None, 4) => &self.client_errors,
+            (None, 5) => &self.server_errors,
+            _ => return,
+        };
+        class.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection(&self, reused: bool) {
+        let count = if reused {
+            &self.connections_reused
+        } else {
+            &self.connections_opened
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> AgentStats {
+        let get = |count: &AtomicU64| count.load(Ordering::Relaxed);
+        AgentStats {
+            requests: get(&self.requests),
+            connections_opened: get(&self.connections_opened),
+            connections_reused: get(&self.connections_reused),
+            bytes_sent: get(&self.bytes_sent),
+            bytes_received: get(&self.bytes_received),
+            client_errors: get(&self.client_errors),
+            server_errors: get(&self.server_errors),
+            failed: get(&self.failed),
+        }
+    }
+}
+
+/// Totals of the requests made by an agent since it was built, given by
+/// [`Agent::stats()`](struct.Agent.html#method.stats).
+///
+/// Redirects followed and retries made for a request count as one request,
+/// but their connections and bytes are all counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AgentStats {
+    requests: u64,
+    connections_opened: u64,
+    connections_reused: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    client_errors: u64,
+    server_errors: u64,
+    failed: u64,
+}
+
+impl AgentStats {
+    /// Requests made, whether they succeeded or not.
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// New connections opened to servers or proxies.
+    pub fn connections_opened(&self) -> u64 {
+        self.connections_opened
+    }
+
+    /// Pooled connections used again.
+    pub fn connections_reused(&self) -> u64 {
+        self.connections_reused
+    }
+
+    /// Bytes written for request heads and bodies.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Bytes read for response heads, and for bodies as far as they were read.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Requests answered with a `4xx` status.
+    pub fn client_errors(&self) -> u64 {
+        self.client_errors
+    }
+
+    /// Requests answered with a `5xx` status.
+    pub fn server_errors(&self) -> u64 {
+        self.server_errors
+    }
+
+    /// Requests that failed without a response from the server, such as for a
+    /// refused connection or a timeout, see [`Error`](enum.Error.html).
+    pub fn failed(&self) -> u64 {
+        self.failed
+    }
+}
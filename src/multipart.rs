@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Cursor, Read, Result as IoResult};
+use std::path::Path;
+
+use crate::request_id::uuid_v4;
+
+/// A `multipart/form-data` body, sent with
+/// [`Request::send_multipart()`](struct.Request.html#method.send_multipart).
+///
+/// Parts are streamed when sent, so parts backed by files or readers are never
+/// held in memory.
+///
+/// ```
+/// let form = ureq::Multipart::new()
+///     .text("title", "Holiday")
+///     .bytes("thumb", "thumb.png", "image/png", vec![0x89, b'P', b'N', b'G'])
+///     .reader("log", "log.txt", "text/plain", std::io::empty(), None);
+///
+/// let r = ureq::post("/upload").send_multipart(form);
+/// println!("{:?}", r);
+/// ```
+pub struct Multipart {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+struct Part {
+    head: String,
+    body: Box<dyn Read>,
+    len: Option<u64>,
+}
+
+impl ::std::fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "Multipart[{} parts]", self.parts.len())
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Multipart::new()
+    }
+}
+
+impl Multipart {
+    /// An empty form with a random boundary.
+    pub fn new() -> Self {
+        Multipart {
+            boundary: format!("ureq-{}", uuid_v4()),
+            parts: vec![],
+        }
+    }
+
+    /// Add a text field.
+    pub fn text(self, name: &str, value: &str) -> Self {
+        let value = value.as_bytes().to_vec();
+        let len = value.len() as u64;
+        self.part(name, None, None, Box::new(Cursor::new(value)), Some(len))
+    }
+
+    /// Add a file field from bytes in memory.
+    pub fn bytes(self, name: &str, filename: &str, content_type: &str, data: Vec<u8>) -> Self {
+        let len = data.len() as u64;
+        self.part(
+            name,
+            Some(filename),
+            Some(content_type),
+            Box::new(Cursor::new(data)),
+            Some(len),
+        )
+    }
+
+    /// Add a file field read from a reader as the body is sent.
+    ///
+    /// With a known `len` for all parts, the form is sent with a `Content-Length`,
+    /// otherwise it is sent chunked. The reader must give exactly `len` bytes.
+    pub fn reader(
+        self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        reader: impl Read + 'static,
+        len: Option<u64>,
+    ) -> Self {
+        self.part(
+            name,
+            Some(filename),
+            Some(content_type),
+            Box::new(reader),
+            len,
+        )
+    }
+
+    /// Add a file field from a file on disk. The file name is sent without its
+    /// directory, and the content type is guessed from the extension.
+    pub fn file(self, name: &str, path: impl AsRef<Path>) -> IoResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let content_type = crate::request::mime_from_extension(ext);
+        Ok(self.reader(name, filename, content_type, file, Some(len)))
+    }
+
+    fn part(
+        mut self,
+        name: &str,
+        filename: Option<&str>,
+        content_type: Option<&str>,
+        body: Box<dyn Read>,
+        len: Option<u64>,
+    ) -> Self {
+        let mut head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape(name)
+        );
+        if let Some(filename) = filename {
+            head.push_str(&format!("; filename=\"{}\"", escape(filename)));
+        }
+        head.push_str("\r\n");
+        if let Some(content_type) = content_type {
+            head.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        head.push_str("\r\n");
+        self.parts.push(Part { head, body, len });
+        self
+    }
+
+    /// The `Content-Type` header value, with the boundary.
+    pub(crate) fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// The body as a reader, and its length if known for all parts.
+    pub(crate) fn into_read(self) -> (Box<dyn Read>, Option<u64>) {
+        let tail = format!("--{}--\r\n", self.boundary);
+        let mut len = Some(tail.len() as u64);
+        let mut readers: Vec<Box<dyn Read>> = vec![];
+        for part in self.parts {
+            len = match (len, part.len) {
+                (Some(len), Some(part_len)) => Some(len + part.head.len() as u64 + part_len + 2),
+                _ => None,
+            };
+            readers.push(Box::new(Cursor::new(part.head.into_bytes())));
+            readers.push(part.body);
+            readers.push(Box::new(Cursor::new(b"\r\n".to_vec())));
+        }
+        readers.push(Box::new(Cursor::new(tail.into_bytes())));
+        let reader = Box::new(Concat {
+            readers: readers.into(),
+        });
+        (reader, len)
+    }
+}
+
+/// Reads the readers one after the other.
+struct Concat {
+    readers: VecDeque<Box<dyn Read>>,
+}
+
+impl Read for Concat {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        while let Some(reader) = self.readers.front_mut() {
+            let amount = reader.read(buf)?;
+            if amount > 0 || buf.is_empty() {
+                return Ok(amount);
+            }
+            self.readers.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+/// Escape a name for a quoted header parameter, like browsers do.
+fn escape(name: &str) -> String {
+    name.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
@@ -0,0 +1,207 @@
+use crate::response::Response;
+
+/// Whether a `401` response asks for `Negotiate` authentication.
+pub(crate) fn is_challenge(resp: &Response) -> bool {
+    resp.status() == 401
+        && resp.all("www-authenticate").iter().any(|value| {
+            let scheme = value.split_whitespace().next().unwrap_or("");
+            scheme.eq_ignore_ascii_case("negotiate")
+        })
+}
+
+/// The base64 SPNEGO token to send as `Authorization: Negotiate <token>` to
+/// `host`, for the service principal `HTTP@host`. `None` when there is no
+/// GSSAPI library or no credentials, such as without a Kerberos ticket.
+#[cfg(unix)]
+pub(crate) fn token(host: &str) -> Option<String> {
+    let gss = gss::library()?;
+    let token = gss.init_sec_context(&format!("HTTP@{}", host))?;
+    Some(base64::encode(&token))
+}
+
+/// SSPI isn't supported, so there is never a token.
+#[cfg(not(unix))]
+pub(crate) fn token(_host: &str) -> Option<String> {
+    None
+}
+
+/// GSSAPI, loaded when first needed so that building doesn't need Kerberos.
+#[cfg(unix)]
+mod gss {
+    use std::ffi::CString;
+    use std::os::raw::c_void;
+    use std::ptr;
+
+    use lazy_static::lazy_static;
+
+    #[repr(C)]
+    struct Oid {
+        length: u32,
+        elements: *const c_void,
+    }
+
+    #[repr(C)]
+    struct Buffer {
+        length: usize,
+        value: *mut c_void,
+    }
+
+    type Name = *mut c_void;
+    type Context = *mut c_void;
+
+    type ImportName = unsafe extern "C" fn(*mut u32, *mut Buffer, *const Oid, *mut Name) -> u32;
+    type InitSecContext = unsafe extern "C" fn(
+        *mut u32,
+        *mut c_void,
+        *mut Context,
+        Name,
+        *const Oid,
+        u32,
+        u32,
+        *mut c_void,
+        *mut Buffer,
+        *mut *mut Oid,
+        *mut Buffer,
+        *mut u32,
+        *mut u32,
+    ) -> u32;
+    type ReleaseBuffer = unsafe extern "C" fn(*mut u32, *mut Buffer) -> u32;
+    type ReleaseName = unsafe extern "C" fn(*mut u32, *mut Name) -> u32;
+    type DeleteSecContext = unsafe extern "C" fn(*mut u32, *mut Context, *mut Buffer) -> u32;
+
+    /// 1.2.840.113554.1.2.1.4, names like `HTTP@host`.
+    const HOSTBASED_SERVICE: &[u8] = b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x01\x04";
+    /// 1.3.6.1.5.5.2
+    const SPNEGO: &[u8] = b"\x2b\x06\x01\x05\x05\x02";
+    const MUTUAL_FLAG: u32 = 2;
+
+    const LIBRARIES: &[&str] = &[
+        "libgssapi_krb5.so.2",
+        "libgssapi_krb5.so",
+        "libgssapi.so.3",
+        "libgssapi.so",
+        "/System/Library/Frameworks/GSS.framework/GSS",
+    ];
+
+    pub struct Gss {
+        import_name: ImportName,
+        init_sec_context: InitSecContext,
+        release_buffer: ReleaseBuffer,
+        release_name: ReleaseName,
+        delete_sec_context: DeleteSecContext,
+    }
+
+    lazy_static! {
+        static ref LIBRARY: Option<Gss> = LIBRARIES.iter().find_map(|name| load(name));
+    }
+
+    pub fn library() -> Option<&'static Gss> {
+        LIBRARY.as_ref()
+    }
+
+    fn load(name: &str) -> Option<Gss> {
+        let name = CString::new(name).ok()?;
+        // never closed, the functions are kept for the life of the process.
+        let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return None;
+        }
+        let symbol = |name: &str| {
+            let name = CString::new(name).ok()?;
+            let sym = unsafe { libc::dlsym(handle, name.as_ptr()) };
+            Some(sym).filter(|sym| !sym.is_null())
+        };
+        // SAFETY: the symbols are the GSSAPI functions with these signatures, RFC 2744.
+        unsafe {
+            Some(Gss {
+                import_name: std::mem::transmute::<*mut c_void, ImportName>(symbol(
+                    "gss_import_name",
+                )?),
+                init_sec_context: std::mem::transmute::<*mut c_void, InitSecContext>(symbol(
+                    "gss_init_sec_context",
+                )?),
+                release_buffer: std::mem::transmute::<*mut c_void, ReleaseBuffer>(symbol(
+                    "gss_release_buffer",
+                )?),
+                release_name: std::mem::transmute::<*mut c_void, ReleaseName>(symbol(
+                    "gss_release_name",
+                )?),
+                delete_sec_context: std::mem::transmute::<*mut c_void, DeleteSecContext>(symbol(
+                    "gss_delete_sec_context",
+                )?),
+            })
+        }
+    }
+
+    /// Whether a major status is an error, `GSS_ERROR()`.
+    fn is_error(major: u32) -> bool {
+        major & 0xffff_0000 != 0
+    }
+
+    impl Gss {
+        /// The first token of a security context with `service`, using the
+        /// default credentials.
+        pub fn init_sec_context(&self, service: &str) -> Option<Vec<u8>> {
+            let mut minor = 0;
+            let mut service = service.as_bytes().to_vec();
+            let mut service_buf = Buffer {
+                length: service.len(),
+                value: service.as_mut_ptr() as *mut c_void,
+            };
+            let name_type = Oid {
+                length: HOSTBASED_SERVICE.len() as u32,
+                elements: HOSTBASED_SERVICE.as_ptr() as *const c_void,
+            };
+            let mech = Oid {
+                length: SPNEGO.len() as u32,
+                elements: SPNEGO.as_ptr() as *const c_void,
+            };
+
+            let mut name: Name = ptr::null_mut();
+            let major =
+                unsafe { (self.import_name)(&mut minor, &mut service_buf, &name_type, &mut name) };
+            if is_error(major) {
+                return None;
+            }
+
+            let mut context: Context = ptr::null_mut();
+            let mut output = Buffer {
+                length: 0,
+                value: ptr::null_mut(),
+            };
+            let major = unsafe {
+                (self.init_sec_context)(
+                    &mut minor,
+                    ptr::null_mut(),
+                    &mut context,
+                    name,
+                    &mech,
+                    MUTUAL_FLAG,
+                    0,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    &mut output,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                )
+            };
+            let token = if is_error(major) || output.value.is_null() {
+                None
+            } else {
+                let bytes =
+                    unsafe { std::slice::from_raw_parts(output.value as *const u8, output.length) };
+                Some(bytes.to_vec())
+            };
+
+            unsafe {
+                (self.release_buffer)(&mut minor, &mut output);
+                if !context.is_null() {
+                    (self.delete_sec_context)(&mut minor, &mut context, ptr::null_mut());
+                }
+                (self.release_name)(&mut minor, &mut name);
+            }
+            token
+        }
+    }
+}
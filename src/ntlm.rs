@@ -0,0 +1,254 @@
+use std::collections::hash_map::RandomState;
+use std::convert::TryInto;
+use std::hash::{BuildHasher, Hasher};
+use std::time::UNIX_EPOCH;
+
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+
+use crate::response::Response;
+use crate::time;
+
+const SIGNATURE: &[u8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const ALWAYS_SIGN: u32 = 0x0000_8000;
+const EXTENDED_SESSION_SECURITY: u32 = 0x0008_0000;
+const FLAGS: u32 =
+    NEGOTIATE_UNICODE | REQUEST_TARGET | NEGOTIATE_NTLM | ALWAYS_SIGN | EXTENDED_SESSION_SECURITY;
+
+/// The `MsvAvTimestamp` pair of the target info.
+const AV_TIMESTAMP: u16 = 7;
+
+/// User and password for NTLM, see
+/// [`Agent::ntlm_auth()`](struct.Agent.html#method.ntlm_auth).
+///
+/// *Internal API*
+#[derive(Clone)]
+pub(crate) struct NtlmCredentials {
+    pub domain: String,
+    pub user: String,
+    pub password: String,
+}
+
+impl ::std::fmt::Debug for NtlmCredentials {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "NtlmCredentials({}\\{})", self.domain, self.user)
+    }
+}
+
+impl NtlmCredentials {
+    /// A user given as `DOMAIN\user`, or just `user`.
+    pub fn new(user: &str, password: &str) -> Self {
+        let (domain, user) = user.split_once('\\').unwrap_or(("", user));
+        NtlmCredentials {
+            domain: domain.to_string(),
+            user: user.to_string(),
+            password: password.to_string(),
+        }
+    }
+}
+
+/// Where the handshake of a request is at.
+///
+/// *Internal API*
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// The negotiate message (type 1) was sent.
+    Negotiated,
+    /// The authenticate message (type 3) was sent.
+    Authenticated,
+}
+
+/// The `Authorization` value that answers the `WWW-Authenticate: NTLM` challenge
+/// of a `401` response, and the step it gets the handshake to. `None` when the
+/// response isn't a challenge we can answer, which ends the handshake.
+pub(crate) fn answer(
+    creds: &NtlmCredentials,
+    step: Option<Step>,
+    resp: &Response,
+) -> Option<(String, Step)> {
+    if resp.status() != 401 {
+        return None;
+    }
+    let challenge = resp.all("www-authenticate").into_iter().find_map(|value| {
+        let mut parts = value.trim().splitn(2, ' ');
+        let scheme = parts.next()?;
+        if !scheme.eq_ignore_ascii_case("ntlm") {
+            return None;
+        }
+        Some(parts.next().unwrap_or("").trim())
+    })?;
+    match (step, challenge.is_empty()) {
+        (None, true) => Some((header(&negotiate_message()), Step::Negotiated)),
+        (Some(Step::Negotiated), false) => {
+            let challenge = base64::decode(challenge).ok()?;
+            let message = authenticate_message(creds, &challenge, client_challenge())?;
+            Some((header(&message), Step::Authenticated))
+        }
+        _ => None,
+    }
+}
+
+fn header(message: &[u8]) -> String {
+    format!("NTLM {}", base64::encode(message))
+}
+
+/// Type 1, without domain or workstation.
+pub(crate) fn negotiate_message() -> Vec<u8> {
+    let mut msg = SIGNATURE.to_vec();
+    msg.extend_from_slice(&1_u32.to_le_bytes());
+    msg.extend_from_slice(&FLAGS.to_le_bytes());
+    // empty domain and workstation buffers.
+    msg.extend_from_slice(&[0; 16]);
+    msg
+}
+
+/// Type 3 answering the challenge (type 2) of the server, with an NTLMv2 response.
+pub(crate) fn authenticate_message(
+    creds: &NtlmCredentials,
+    challenge: &[u8],
+    client_challenge: [u8; 8],
+) -> Option<Vec<u8>> {
+    if challenge.len() < 32 || &challenge[..8] != SIGNATURE || u32_at(challenge, 8)? != 2 {
+        return None;
+    }
+    let server_flags = u32_at(challenge, 20)?;
+    let server_challenge = &challenge[24..32];
+    let target_info = match buffer_at(challenge, 40) {
+        Some(info) => info,
+        None => &[],
+    };
+
+    // with a timestamp from the server, the LMv2 response is left out.
+    let timestamp = av_pair(target_info, AV_TIMESTAMP).and_then(|t| t.try_into().ok());
+    let lm_response = match timestamp {
+        Some(_) => vec![0; 24],
+        None => lm_v2_response(creds, server_challenge, &client_challenge),
+    };
+    let timestamp = timestamp
+        .map(u64::from_le_bytes)
+        .unwrap_or_else(filetime_now);
+    let nt_response = nt_v2_response(
+        creds,
+        server_challenge,
+        &client_challenge,
+        timestamp,
+        target_info,
+    );
+
+    let domain = utf16le(&creds.domain);
+    let user = utf16le(&creds.user);
+    let fields: [&[u8]; 6] = [&lm_response, &nt_response, &domain, &user, &[], &[]];
+
+    let mut msg = SIGNATURE.to_vec();
+    msg.extend_from_slice(&3_u32.to_le_bytes());
+    let mut offset = 64;
+    let mut payload = vec![];
+    for field in &fields {
+        msg.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(offset as u32).to_le_bytes());
+        payload.extend_from_slice(field);
+        offset += field.len();
+    }
+    msg.extend_from_slice(&(FLAGS & server_flags | NEGOTIATE_UNICODE).to_le_bytes());
+    msg.extend_from_slice(&payload);
+    Some(msg)
+}
+
+/// NTOWFv2, the key of the NTLMv2 responses.
+fn nt_v2_hash(creds: &NtlmCredentials) -> Vec<u8> {
+    let nt_hash = Md4::digest(utf16le(&creds.password));
+    let identity = format!("{}{}", creds.user.to_uppercase(), creds.domain);
+    hmac_md5(&nt_hash, &[&utf16le(&identity)])
+}
+
+pub(crate) fn nt_v2_response(
+    creds: &NtlmCredentials,
+    server_challenge: &[u8],
+    client_challenge: &[u8; 8],
+    timestamp: u64,
+    target_info: &[u8],
+) -> Vec<u8> {
+    let mut blob = vec![1, 1, 0, 0, 0, 0, 0, 0];
+    blob.extend_from_slice(&timestamp.to_le_bytes());
+    blob.extend_from_slice(client_challenge);
+    blob.extend_from_slice(&[0; 4]);
+    blob.extend_from_slice(target_info);
+    blob.extend_from_slice(&[0; 4]);
+    let mut response = hmac_md5(&nt_v2_hash(creds), &[server_challenge, &blob]);
+    response.extend_from_slice(&blob);
+    response
+}
+
+pub(crate) fn lm_v2_response(
+    creds: &NtlmCredentials,
+    server_challenge: &[u8],
+    client_challenge: &[u8; 8],
+) -> Vec<u8> {
+    let mut response = hmac_md5(&nt_v2_hash(creds), &[server_challenge, client_challenge]);
+    response.extend_from_slice(client_challenge);
+    response
+}
+
+fn hmac_md5(key: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+    let mut mac = Hmac::<Md5>::new_from_slice(key).expect("hmac takes keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn utf16le(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+fn u32_at(msg: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(msg.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// The bytes of the security buffer (length, max length, offset) at `at`.
+fn buffer_at(msg: &[u8], at: usize) -> Option<&[u8]> {
+    let len = u16::from_le_bytes(msg.get(at..at + 2)?.try_into().ok()?) as usize;
+    let offset = u32_at(msg, at + 4)? as usize;
+    msg.get(offset..offset.checked_add(len)?)
+}
+
+/// The value of an `AV_PAIR` of the target info.
+fn av_pair(mut info: &[u8], id: u16) -> Option<&[u8]> {
+    while info.len() >= 4 {
+        let pair_id = u16::from_le_bytes([info[0], info[1]]);
+        let len = u16::from_le_bytes([info[2], info[3]]) as usize;
+        let value = info.get(4..4 + len)?;
+        if pair_id == id {
+            return Some(value);
+        }
+        // MsvAvEOL
+        if pair_id == 0 {
+            return None;
+        }
+        info = &info[4 + len..];
+    }
+    None
+}
+
+/// Windows time, 100 nanosecond intervals since 1601.
+fn filetime_now() -> u64 {
+    const UNIX_EPOCH_AS_FILETIME: u64 = 116_444_736_000_000_000;
+    let since_unix = time::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() / 100)
+        .unwrap_or(0);
+    UNIX_EPOCH_AS_FILETIME + since_unix as u64
+}
+
+/// A nonce from the std hasher keys, like the request ids.
+fn client_challenge() -> [u8; 8] {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(filetime_now());
+    hasher.finish().to_le_bytes()
+}
@@ -0,0 +1,200 @@
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use url::form_urlencoded;
+
+use crate::agent::{Agent, AgentState};
+use crate::error::Error;
+use crate::header;
+use crate::time::Instant;
+use crate::unit::Unit;
+
+/// Tokens are refreshed this long before they expire, or at half their life
+/// when that is sooner.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Where and how the agent gets its tokens.
+///
+/// *Internal API*
+#[derive(Clone, Debug)]
+pub(crate) struct OAuth2Config {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Used for the first token, the client credentials grant without one.
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// The token of an agent, fetched by one request at a time while the others
+/// wait for it.
+///
+/// *Internal API*
+#[derive(Debug)]
+pub(crate) struct OAuth2 {
+    config: OAuth2Config,
+    /// Sends the token requests with the settings of the agent.
+    agent: Agent,
+    state: Mutex<TokenState>,
+    fetched: Condvar,
+}
+
+#[derive(Debug)]
+struct TokenState {
+    token: Option<Token>,
+    /// The latest refresh token, servers may rotate it with each use.
+    refresh_token: Option<String>,
+    fetching: bool,
+}
+
+#[derive(Debug)]
+struct Token {
+    access: String,
+    refresh_at: Option<Instant>,
+}
+
+/// Clears `fetching` and wakes the waiting requests once a fetch is over,
+/// also when it panics.
+struct Fetching<'a>(&'a OAuth2);
+
+impl Drop for Fetching<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.fetching = false;
+        self.0.fetched.notify_all();
+    }
+}
+
+impl OAuth2 {
+    pub fn new(config: OAuth2Config, agent: Agent) -> Self {
+        OAuth2 {
+            agent,
+            state: Mutex::new(TokenState {
+                token: None,
+                refresh_token: config.refresh_token.clone(),
+                fetching: false,
+            }),
+            config,
+            fetched: Condvar::new(),
+        }
+    }
+
+    /// The cached token, or a new one once it expires.
+    fn token(&self) -> Result<String, Error> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(token) = &state.token {
+                if token
+                    .refresh_at
+                    .map(|at| Instant::now() < at)
+                    .unwrap_or(true)
+                {
+                    return Ok(token.access.clone());
+                }
+            }
+            if !state.fetching {
+                break;
+            }
+            state = self.fetched.wait(state).unwrap();
+        }
+        state.fetching = true;
+        let refresh_token = state.refresh_token.clone();
+        drop(state);
+
+        let fetching = Fetching(self);
+        let result = self.fetch(refresh_token);
+        drop(fetching);
+
+        let mut state = self.state.lock().unwrap();
+        let (token, refresh_token) = result?;
+        let access = token.access.clone();
+        state.token = Some(token);
+        if refresh_token.is_some() {
+            state.refresh_token = refresh_token;
+        }
+        Ok(access)
+    }
+
+    /// Forget the token after a `401`, unless another request got a new one.
+    fn expired(&self, access: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.token.as_ref().map(|t| t.access == access) == Some(true) {
+            state.token = None;
+        }
+    }
+
+    fn fetch(&self, refresh_token: Option<String>) -> Result<(Token, Option<String>), Error> {
+        let config = &self.config;
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        match &refresh_token {
+            Some(refresh_token) => form
+                .append_pair("grant_type", "refresh_token")
+                .append_pair("refresh_token", refresh_token),
+            None => form.append_pair("grant_type", "client_credentials"),
+        };
+        if let Some(scope) = &config.scope {
+            form.append_pair("scope", scope);
+        }
+        // not a request of the agent itself, which would want a token for it.
+        let resp = self
+            .agent
+            .post(&config.token_url)
+            .auth(&config.client_id, &config.client_secret)
+            .set("Content-Type", "application/x-www-form-urlencoded")
+            .set("Accept", "application/json")
+            .send_string(&form.finish());
+        if let Some(err) = resp.synthetic_error() {
+            return Err(Error::OAuth2(format!("Token request failed: {}", err)));
+        }
+        if !resp.ok() {
+            return Err(Error::OAuth2(format!(
+                "Token endpoint answered {}",
+                resp.status()
+            )));
+        }
+        let json = resp
+            .into_json()
+            .map_err(|e| Error::OAuth2(format!("Bad token response: {}", e)))?;
+        let access = json["access_token"]
+            .as_str()
+            .ok_or_else(|| Error::OAuth2("No access_token in token response".to_string()))?;
+        let refresh_at = json["expires_in"].as_u64().map(|secs| {
+            let life = Duration::from_secs(secs);
+            Instant::now() + life - EXPIRY_MARGIN.min(life / 2)
+        });
+        let token = Token {
+            access: access.to_string(),
+            refresh_at,
+        };
+        let refresh_token = json["refresh_token"].as_str().map(|s| s.to_string());
+        Ok((token, refresh_token))
+    }
+}
+
+/// Get the token to send, for requests to the host they were made for that
+/// don't set their own `Authorization`.
+pub(crate) fn authorize(unit: &mut Unit) -> Result<(), Error> {
+    let oauth2 = match (*unit.agent).as_ref().and_then(|a| a.oauth2.as_ref()) {
+        Some(oauth2) => oauth2,
+        None => return Ok(()),
+    };
+    if unit.oauth2_token.is_some()
+        || unit.url.host_str() != Some(unit.first_host.as_str())
+        || header::has_header(&unit.headers, "authorization")
+    {
+        return Ok(());
+    }
+    unit.oauth2_token = Some(oauth2.token()?);
+    Ok(())
+}
+
+/// Whether to send the request again with a new token.
+pub(crate) fn should_retry(unit: &Unit, status: u16) -> bool {
+    status == 401 && unit.oauth2_token.is_some() && !unit.oauth2_retried
+}
+
+pub(crate) fn expired(agent: &Option<AgentState>, access: &str) {
+    if let Some(oauth2) = agent.as_ref().and_then(|a| a.oauth2.as_ref()) {
+        oauth2.expired(access);
+    }
+}
@@ -0,0 +1,340 @@
+use std::fs;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use boa_engine::{
+    js_string, Context, JsArgs, JsNativeError, JsResult, JsString, JsValue, NativeFunction, Source,
+};
+use url::Url;
+
+use crate::agent::Agent;
+use crate::error::Error;
+use crate::proxy::{self, Proxy};
+use crate::stream;
+use crate::unit::Unit;
+
+/// The url and host to find the proxy for, and where to send the result.
+type Query = (String, String, Sender<Result<String, String>>);
+
+/// A proxy auto-config file, see [`Agent::proxy_pac()`](struct.Agent.html#method.proxy_pac).
+///
+/// *Internal API*
+#[derive(Debug)]
+pub(crate) struct Pac {
+    location: String,
+    /// Fetches an `http(s)` location with the settings of the agent.
+    agent: Agent,
+    /// The thread running the script, compiled once on first use.
+    script: Mutex<Option<Sender<Query>>>,
+}
+
+impl Pac {
+    pub fn new(location: &str, agent: Agent) -> Self {
+        Pac {
+            location: location.to_string(),
+            agent,
+            script: Mutex::new(None),
+        }
+    }
+
+    /// The proxies to try in turn for a url by `FindProxyForURL`, `None` for `DIRECT`.
+    pub fn find_proxies(&self, url: &Url) -> Result<Vec<Option<Proxy>>, Error> {
+        // like browsers, scripts don't get the path and query of https urls.
+        let url_arg = match url.scheme() {
+            "https" => format!("https://{}/", url.host_str().unwrap_or("")),
+            _ => url.to_string(),
+        };
+        let host = url.host_str().unwrap_or("").trim_matches(['[', ']']);
+        let (reply, result) = mpsc::channel();
+        let sent = self
+            .script()?
+            .send((url_arg, host.to_string(), reply))
+            .is_ok();
+        let result = match result.recv() {
+            Ok(result) if sent => result,
+            _ => Err("the script stopped".to_string()),
+        }
+        .map_err(|e| Error::BadProxy(format!("PAC script failed: {}", e)))?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(host, result = %result, "proxy auto-config");
+
+        parse_result(&result)
+    }
+
+    /// The thread of the script, loading and compiling it the first time.
+    fn script(&self) -> Result<Sender<Query>, Error> {
+        let mut script = self.script.lock().unwrap();
+        if let Some(sender) = &*script {
+            return Ok(sender.clone());
+        }
+        let text = self.load()?;
+        let (sender, queries) = mpsc::channel::<Query>();
+        let (ready, compiled) = mpsc::channel();
+        // a boa context can't move between threads, it stays on its own.
+        thread::Builder::new()
+            .name("ureq-pac".to_string())
+            .spawn(move || {
+                let mut ctx = match compile(&text) {
+                    Ok(ctx) => ctx,
+                    Err(e) => {
+                        let _ = ready.send(Err(e.to_string()));
+                        return;
+                    }
+                };
+                let _ = ready.send(Ok(()));
+                // ends once the `Pac` is dropped.
+                for (url, host, reply) in queries {
+                    let result = find_proxy(&mut ctx, &url, &host).map_err(|e| e.to_string());
+                    let _ = reply.send(result);
+                }
+            })?;
+        compiled
+            .recv()
+            .unwrap_or_else(|_| Err("the script stopped".to_string()))
+            .map_err(|e| Error::BadProxy(format!("PAC script failed: {}", e)))?;
+        *script = Some(sender.clone());
+        Ok(sender)
+    }
+
+    fn load(&self) -> Result<String, Error> {
+        let location = &self.location;
+        if location.starts_with("http://") || location.starts_with("https://") {
+            let resp = self.agent.get(location).call();
+            if let Some(err) = resp.synthetic_error() {
+                return Err(Error::BadProxy(format!(
+                    "Failed to fetch PAC file {}: {}",
+                    location, err
+                )));
+            }
+            if !resp.ok() {
+                return Err(Error::BadProxy(format!(
+                    "Failed to fetch PAC file {}: {}",
+                    location,
+                    resp.status()
+                )));
+            }
+            Ok(resp.into_string()?)
+        } else {
+            Ok(fs::read_to_string(location)?)
+        }
+    }
+}
+
+/// Connect to the host by the first entry of the PAC result that works. Gives
+/// the error of the last one tried.
+pub(crate) fn connect(
+    unit: &Unit,
+    pac: &Pac,
+    hostname: &str,
+    port: u16,
+) -> Result<TcpStream, Error> {
+    let mut last_err = None;
+    for proxy in pac.find_proxies(&unit.url)? {
+        let result = match &proxy {
+            Some(proxy) => proxy::connect(unit, proxy, hostname, port),
+            None => stream::target_addrs(unit, hostname, port)
+                .and_then(|ips| stream::connect_any(unit, &ips)),
+        };
+        match result {
+            Ok(sock) => return Ok(sock),
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(proxy = ?proxy, error = %err, "PAC entry failed, trying the next");
+                last_err = Some(err);
+            }
+        }
+    }
+    Err(last_err.expect("PAC result without entries"))
+}
+
+/// A context with the PAC functions and the script.
+fn compile(script: &str) -> JsResult<Context> {
+    let mut ctx = Context::default();
+    // a broken script must not hang the request.
+    ctx.runtime_limits_mut().set_loop_iteration_limit(1_000_000);
+    ctx.register_global_builtin_callable(
+        js_string!("dnsResolve"),
+        1,
+        NativeFunction::from_fn_ptr(dns_resolve),
+    )?;
+    ctx.register_global_builtin_callable(
+        js_string!("myIpAddress"),
+        0,
+        NativeFunction::from_fn_ptr(my_ip_address),
+    )?;
+    ctx.eval(Source::from_bytes(PAC_UTILS))?;
+    ctx.eval(Source::from_bytes(script))?;
+    Ok(ctx)
+}
+
+/// Run `FindProxyForURL(url, host)` of a compiled script.
+fn find_proxy(ctx: &mut Context, url: &str, host: &str) -> JsResult<String> {
+    let find = ctx
+        .global_object()
+        .get(js_string!("FindProxyForURL"), ctx)?;
+    let find = find
+        .as_callable()
+        .ok_or_else(|| JsNativeError::typ().with_message("FindProxyForURL is not a function"))?;
+    let args = [
+        JsValue::from(JsString::from(url)),
+        JsValue::from(JsString::from(host)),
+    ];
+    let result = find.call(&JsValue::undefined(), &args, ctx)?;
+    Ok(result.to_string(ctx)?.to_std_string_escaped())
+}
+
+/// The usable entries of a result like `PROXY a:3128; SOCKS5 b:1080; DIRECT`.
+/// `HTTPS` entries are skipped, TLS to the proxy is not supported.
+fn parse_result(result: &str) -> Result<Vec<Option<Proxy>>, Error> {
+    if result.trim().is_empty() {
+        return Ok(vec![None]);
+    }
+    let mut proxies = vec![];
+    for entry in result.split(';') {
+        let mut parts = entry.split_whitespace();
+        let kind = parts.next().unwrap_or("").to_ascii_uppercase();
+        match (&kind[..], parts.next()) {
+            ("DIRECT", _) => proxies.push(None),
+            ("PROXY", Some(addr)) => proxies.push(Some(Proxy::http(addr)?)),
+            // SOCKS5 proxies resolve the host, like in browsers.
+            ("SOCKS5", Some(addr)) => {
+                proxies.push(Some(Proxy::new(&format!("socks5h://{}", addr))?))
+            }
+            ("SOCKS", Some(addr)) => proxies.push(Some(Proxy::new(&format!("socks5://{}", addr))?)),
+            _ => (),
+        }
+    }
+    if proxies.is_empty() {
+        return Err(Error::ProxyFailed(format!(
+            "No supported proxy in PAC result: {}",
+            result
+        )));
+    }
+    Ok(proxies)
+}
+
+fn dns_resolve(_this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let host = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    let ip = (host.as_str(), 0)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.find(|a| a.is_ipv4()));
+    Ok(match ip {
+        Some(addr) => JsValue::from(JsString::from(addr.ip().to_string().as_str())),
+        None => JsValue::null(),
+    })
+}
+
+fn my_ip_address(_this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    // connecting a udp socket sends nothing, but picks the outgoing interface.
+    let ip = UdpSocket::bind("0.0.0.0:0")
+        .and_then(|sock| sock.connect("198.51.100.1:53").map(|_| sock))
+        .and_then(|sock| sock.local_addr())
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string());
+    Ok(JsValue::from(JsString::from(ip.as_str())))
+}
+
+/// The PAC functions that don't need the system.
+const PAC_UTILS: &str = r#"
+function isPlainHostName(host) {
+    return host.indexOf('.') == -1;
+}
+function dnsDomainIs(host, domain) {
+    return host.length >= domain.length &&
+        host.substring(host.length - domain.length) == domain;
+}
+function localHostOrDomainIs(host, hostdom) {
+    return host == hostdom || hostdom.lastIndexOf(host + '.', 0) == 0;
+}
+function isResolvable(host) {
+    return dnsResolve(host) !== null;
+}
+function ipToNum(ip) {
+    var p = ip.split('.');
+    return ((p[0] << 24) | (p[1] << 16) | (p[2] << 8) | p[3]) >>> 0;
+}
+function isInNet(host, pattern, mask) {
+    var ip = /^\d+\.\d+\.\d+\.\d+$/.test(host) ? host : dnsResolve(host);
+    if (ip === null) {
+        return false;
+    }
+    var m = ipToNum(mask);
+    return ((ipToNum(ip) & m) >>> 0) == ((ipToNum(pattern) & m) >>> 0);
+}
+function dnsDomainLevels(host) {
+    return host.split('.').length - 1;
+}
+function shExpMatch(str, pattern) {
+    pattern = pattern.replace(/[.+^${}()|[\]\\]/g, '\\$&')
+        .replace(/\*/g, '.*').replace(/\?/g, '.');
+    return new RegExp('^' + pattern + '$').test(str);
+}
+function pacArgs(args) {
+    var list = Array.prototype.slice.call(args);
+    var gmt = list[list.length - 1] == 'GMT';
+    if (gmt) {
+        list.pop();
+    }
+    return { list: list, gmt: gmt, now: new Date() };
+}
+function inRange(start, end, cur) {
+    return start <= end ? (start <= cur && cur <= end) : (cur >= start || cur <= end);
+}
+function weekdayRange() {
+    var a = pacArgs(arguments);
+    var days = ['SUN', 'MON', 'TUE', 'WED', 'THU', 'FRI', 'SAT'];
+    var day = a.gmt ? a.now.getUTCDay() : a.now.getDay();
+    var start = days.indexOf(a.list[0]);
+    var end = a.list.length > 1 ? days.indexOf(a.list[1]) : start;
+    return start >= 0 && end >= 0 && inRange(start, end, day);
+}
+function timeRange() {
+    var a = pacArgs(arguments), l = a.list;
+    var cur = a.gmt
+        ? a.now.getUTCHours() * 3600 + a.now.getUTCMinutes() * 60 + a.now.getUTCSeconds()
+        : a.now.getHours() * 3600 + a.now.getMinutes() * 60 + a.now.getSeconds();
+    switch (l.length) {
+        case 1: return inRange(l[0] * 3600, l[0] * 3600 + 3599, cur);
+        case 2: return inRange(l[0] * 3600, l[1] * 3600 - 1, cur);
+        case 4: return inRange(l[0] * 3600 + l[1] * 60, l[2] * 3600 + l[3] * 60 - 1, cur);
+        case 6: return inRange(l[0] * 3600 + l[1] * 60 + l[2], l[3] * 3600 + l[4] * 60 + l[5], cur);
+    }
+    return false;
+}
+function dateRange() {
+    var a = pacArgs(arguments), l = a.list;
+    var months = ['JAN', 'FEB', 'MAR', 'APR', 'MAY', 'JUN',
+        'JUL', 'AUG', 'SEP', 'OCT', 'NOV', 'DEC'];
+    var cur = a.gmt
+        ? [a.now.getUTCDate(), a.now.getUTCMonth(), a.now.getUTCFullYear()]
+        : [a.now.getDate(), a.now.getMonth(), a.now.getFullYear()];
+    // each argument is a day (0), a month (1) or a year (2).
+    function date(args) {
+        var d = cur.slice();
+        for (var i = 0; i < args.length; i++) {
+            var v = args[i];
+            if (typeof v == 'string') {
+                d[1] = months.indexOf(v.toUpperCase());
+            } else if (v > 31) {
+                d[2] = v;
+            } else {
+                d[0] = v;
+            }
+        }
+        return d[2] * 10000 + d[1] * 100 + d[0];
+    }
+    var now = date([]);
+    if (l.length == 1) {
+        return date(l) == now;
+    }
+    var half = l.length / 2;
+    return inRange(date(l.slice(0, half)), date(l.slice(half)), now);
+}
+"#;
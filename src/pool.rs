@@ -1,57 +1,107 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::io::{Read, Result as IoResult};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Result as IoResult};
+use std::sync::Mutex;
 
+use crate::proxy::Proxy;
 use crate::stream::Stream;
 use crate::unit::Unit;
 
-use url::Url;
-
 pub const DEFAULT_HOST: &str = "localhost";
 
+/// Locks the connections are spread over, so that threads connecting to
+/// different hosts rarely wait for each other.
+const SHARDS: usize = 16;
+
+/// Bytes of an unread body read off when it is dropped, so that the connection
+/// can be pooled.
+pub(crate) const DEFAULT_DRAIN_LIMIT: u64 = 16 * 1024;
+
 /// Holder of recycled connections.
 ///
 /// *Internal API*
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub(crate) struct ConnectionPool {
     // the actual pooled connection. however only one per hostname:port.
-    recycle: HashMap<PoolKey, Stream>,
+    shards: Vec<Mutex<HashMap<PoolKey, Stream>>>,
+    /// Probe connections before handing them out, dropping closed ones.
+    health_check: bool,
+    /// Most bytes of an unread body to read off to pool its connection.
+    pub(crate) drain_limit: u64,
+}
+
+impl Default for ConnectionPool {
+    fn default() -> Self {
+        ConnectionPool {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            health_check: false,
+            drain_limit: DEFAULT_DRAIN_LIMIT,
+        }
+    }
 }
 
 impl ConnectionPool {
-    pub fn new() -> Self {
+    pub fn new(health_check: bool, drain_limit: u64) -> Self {
         ConnectionPool {
+            health_check,
+            drain_limit,
             ..Default::default()
         }
     }
 
     /// How the unit::connect tries to get a pooled connection.
-    pub fn try_get_connection(&mut self, url: &Url) -> Option<Stream> {
-        self.recycle.remove(&PoolKey::new(url))
+    pub fn try_get_connection(&self, unit: &Unit) -> Option<Stream> {
+        let key = PoolKey::new(unit);
+        let mut stream = self.shard(&key).lock().unwrap().remove(&key)?;
+        if self.health_check && stream.is_stale() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("pooled connection was closed, not reusing it");
+            return None;
+        }
+        Some(stream)
+    }
+
+    fn add(&self, key: PoolKey, stream: Stream) {
+        self.shard(&key).lock().unwrap().insert(key, stream);
+    }
+
+    fn shard(&self, key: &PoolKey) -> &Mutex<HashMap<PoolKey, Stream>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
     }
 
     #[cfg(test)]
     pub fn len(&self) -> usize {
-        self.recycle.len()
+        self.shards.iter().map(|s| s.lock().unwrap().len()).sum()
     }
 
+    /// Debug of a pooled connection to a host, by any scheme or proxy.
     #[cfg(test)]
-    pub fn get(&self, hostname: &str, port: u16) -> Option<&Stream> {
-        let key = PoolKey {
-            hostname: hostname.into(),
-            port,
-        };
-        self.recycle.get(&key)
+    pub fn get(&self, hostname: &str, port: u16) -> Option<String> {
+        self.shards.iter().find_map(|shard| {
+            let shard = shard.lock().unwrap();
+            shard
+                .iter()
+                .find(|(key, _)| key.hostname == hostname && key.port == port)
+                .map(|(_, stream)| format!("{:?}", stream))
+        })
     }
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
 struct PoolKey {
+    /// The proxies asked for, a tunnel through one is no connection for another.
+    proxies: Vec<Proxy>,
+    scheme: String,
     hostname: String,
     port: u16,
 }
 
 impl PoolKey {
-    fn new(url: &Url) -> Self {
+    fn new(unit: &Unit) -> Self {
+        let url = &unit.url;
         let port = if cfg!(test) {
             if let Some(p) = url.port_or_known_default() {
                 Some(p)
@@ -64,29 +114,58 @@ impl PoolKey {
             url.port_or_known_default()
         };
         PoolKey {
+            proxies: unit.proxies.clone(),
+            scheme: url.scheme().to_string(),
             hostname: url.host_str().unwrap_or(DEFAULT_HOST).into(),
             port: port.expect("Failed to get port for pool key"),
         }
     }
 }
 
+/// Put a connection that is done with the unit in the pool of its agent, or
+/// let it deallocate.
+pub(crate) fn recycle(unit: &Unit, stream: Stream) {
+    if let Some(agent) = (*unit.agent).as_ref() {
+        // a connection to an explicit address is not one to the url host.
+        if !stream.is_poolable() || !unit.is_poolable() || unit.body_cut_short {
+            // just let it deallocate
+            return;
+        }
+        // insert back into pool
+        let key = PoolKey::new(unit);
+        agent.pool.add(key, stream);
+    }
+}
+
+/// How much of a body is left to read, when the framing tells.
+///
+/// *Internal API*
+pub(crate) trait BodyLeft {
+    fn body_left(&self) -> Option<u64> {
+        None
+    }
+}
+
 /// Read wrapper that returns the stream to the pool once the
 /// read is exhausted (reached a 0).
 ///
 /// *Internal API*
-pub(crate) struct PoolReturnRead<R: Read + Sized> {
+pub(crate) struct PoolReturnRead<R: Read + BodyLeft> {
     // unit that contains the agent where we want to return the reader.
     unit: Option<Unit>,
     // pointer to underlying stream.
-    // this pointer forces the entire PoolReturnRead to be !Sync and !Send
-    // that's a good thing, because the pool return logic is certainly not
-    // thread safe.
+    // this pointer makes PoolReturnRead !Sync, which is a good thing, because
+    // the pool return logic is certainly not thread safe.
     stream: *mut Stream,
     // wrapped reader around the same stream
     reader: Option<R>,
 }
 
-impl<R: Read + Sized> PoolReturnRead<R> {
+// the stream pointer points into the ReclaimingRead of the reader, which moves
+// along with it.
+unsafe impl<R: Read + BodyLeft + Send> Send for PoolReturnRead<R> {}
+
+impl<R: Read + BodyLeft> PoolReturnRead<R> {
     pub fn new(unit: Option<Unit>, stream: *mut Stream, reader: R) -> Self {
         PoolReturnRead {
             unit,
@@ -104,19 +183,51 @@ impl<R: Read + Sized> PoolReturnRead<R> {
             if self.stream.is_null() {
                 return;
             }
-            let state = &mut unit.agent.lock().unwrap();
             // bring back stream here to either go into pool or dealloc
             let stream = unsafe { *Box::from_raw(self.stream) };
             self.stream = ::std::ptr::null_mut();
-            if let Some(agent) = state.as_mut() {
-                if !stream.is_poolable() {
-                    // just let it deallocate
-                    return;
+            recycle(&unit, stream);
+        }
+    }
+
+    /// Read off what is left of the body, if no more than the drain limit of
+    /// the agent, so that the connection can go back to the pool. Otherwise the
+    /// connection is closed.
+    ///
+    /// Only a body of known length is waited for. Of any other, like a chunked
+    /// stream of events, only what already arrived is read, since more may not
+    /// come for a long time.
+    fn drain(&mut self) {
+        let limit = match self.unit.as_ref().and_then(|u| (*u.agent).as_ref()) {
+            Some(agent) => agent.pool.drain_limit,
+            None => 0,
+        };
+        let drained = match self.reader.as_mut() {
+            _ if self.stream.is_null() => false,
+            Some(reader) if limit > 0 => match reader.body_left() {
+                Some(left) if left <= limit => io::copy(reader, &mut io::sink()).is_ok(),
+                Some(_) => false,
+                None => {
+                    // the reader is not in use, so the stream can be borrowed.
+                    let stream = unsafe { &*self.stream };
+                    stream.set_nonblocking(true).is_ok()
+                        && matches!(
+                            io::copy(&mut reader.take(limit + 1), &mut io::sink()),
+                            Ok(n) if n <= limit
+                        )
+                        && stream.set_nonblocking(false).is_ok()
                 }
-                // insert back into pool
-                let key = PoolKey::new(&unit.url);
-                agent.pool().recycle.insert(key, stream);
-            }
+            },
+            _ => false,
+        };
+        if drained {
+            return;
+        }
+        self.unit = None;
+        self.reader.take();
+        if !self.stream.is_null() {
+            let _stream = unsafe { Box::from_raw(self.stream) };
+            self.stream = ::std::ptr::null_mut();
         }
     }
 
@@ -128,7 +239,7 @@ impl<R: Read + Sized> PoolReturnRead<R> {
     }
 }
 
-impl<R: Read + Sized> Read for PoolReturnRead<R> {
+impl<R: Read + BodyLeft> Read for PoolReturnRead<R> {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
         let amount = self.do_read(buf)?;
         // only if the underlying reader is exhausted can we send a new
@@ -140,8 +251,11 @@ impl<R: Read + Sized> Read for PoolReturnRead<R> {
     }
 }
 
-impl<R: Read + Sized> Drop for PoolReturnRead<R> {
+impl<R: Read + BodyLeft> Drop for PoolReturnRead<R> {
     fn drop(&mut self) {
+        if self.unit.is_some() {
+            self.drain();
+        }
         self.return_connection();
     }
 }
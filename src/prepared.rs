@@ -0,0 +1,76 @@
+use url::Url;
+
+use crate::body::Body;
+use crate::error::Error;
+use crate::header::Header;
+use crate::request::Request;
+use crate::response::Response;
+use crate::unit::{self, Unit};
+
+/// A request with its url and headers worked out, see
+/// [`Request::prepare()`](struct.Request.html#method.prepare).
+///
+/// The headers include those added when sending, such as `Host`, `User-Agent`,
+/// `Content-Length` and the cookies of the agent. They are worked out the same
+/// way each time it is sent, so cookies the agent got since preparing are sent
+/// too, and so is a new request id when the agent sets one.
+#[derive(Debug, Clone)]
+pub struct PreparedRequest {
+    request: Request,
+    url: Url,
+    headers: Vec<Header>,
+    body: Body,
+    body_len: Option<u64>,
+}
+
+impl PreparedRequest {
+    pub(crate) fn new(request: &Request, body: Body) -> Result<PreparedRequest, Error> {
+        let url = request.to_url()?;
+        let reader = body.to_read();
+        let unit = Unit::new(request, &url, true, &reader);
+        let mut url = unit.url.clone();
+        let headers = unit::prelude_headers(&unit, false);
+        let query = unit.query_string.trim_start_matches('?');
+        url.set_query(Some(query).filter(|q| !q.is_empty()));
+        Ok(PreparedRequest {
+            request: request.clone(),
+            url,
+            headers,
+            body_len: reader.size.map(|size| size as u64),
+            body,
+        })
+    }
+
+    /// The method, like `GET`.
+    pub fn method(&self) -> &str {
+        &self.request.method
+    }
+
+    /// The url with the query parameters of the request added.
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// The headers as name and value pairs, in the order they are sent.
+    pub fn headers(&self) -> Vec<(&str, &str)> {
+        self.headers.iter().map(|h| (h.name(), h.value())).collect()
+    }
+
+    /// The value of the first header with this name, if any.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.is_name(name))
+            .map(|h| h.value())
+    }
+
+    /// The length of the body, `None` when there is no body.
+    pub fn body_len(&self) -> Option<u64> {
+        self.body_len
+    }
+
+    /// Send the request, which can be done any number of times.
+    pub fn send(&self) -> Response {
+        self.request.clone().send_body(self.body.clone())
+    }
+}
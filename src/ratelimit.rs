@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::time::Instant;
+use crate::unit::Unit;
+
+/// Requests per second allowed to hosts, see
+/// [`Agent::rate_limit()`](struct.Agent.html#method.rate_limit).
+///
+/// *Internal API*
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RateLimitConfig {
+    pub default: u32,
+    pub hosts: HashMap<String, u32>,
+    pub reject: bool,
+}
+
+impl RateLimitConfig {
+    fn per_second(&self, host: &str) -> u32 {
+        self.hosts.get(host).cloned().unwrap_or(self.default)
+    }
+}
+
+/// One token bucket per host.
+///
+/// *Internal API*
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: HashMap<String, Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Goes negative when requests wait for tokens not yet refilled.
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Take a token for the host, returning how long to wait before sending.
+    fn reserve(&mut self, host: &str) -> Result<Duration, Error> {
+        let per_second = self.config.per_second(host);
+        if per_second == 0 {
+            return Ok(Duration::from_secs(0));
+        }
+        let rate = f64::from(per_second);
+        let now = Instant::now();
+        let bucket = self.buckets.entry(host.to_string()).or_insert(Bucket {
+            tokens: rate,
+            last: now,
+        });
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.last = now;
+
+        if bucket.tokens < 1.0 && self.config.reject {
+            return Err(Error::RateLimited(host.to_string()));
+        }
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Ok(Duration::from_secs(0))
+        } else {
+            Ok(Duration::from_secs_f64(-bucket.tokens / rate))
+        }
+    }
+}
+
+/// Wait for, or fail on, the rate limit of the unit's host.
+pub(crate) fn acquire(unit: &Unit) -> Result<(), Error> {
+    let host = unit.url.host_str().unwrap_or("");
+    let wait = {
+        match (*unit.agent).as_ref().and_then(|s| s.rate_limiter.as_ref()) {
+            Some(limiter) => limiter.lock().unwrap().reserve(host)?,
+            None => return Ok(()),
+        }
+    };
+    // sleep without holding the rate limiter lock.
+    if wait > Duration::from_secs(0) {
+        thread::sleep(wait);
+    }
+    Ok(())
+}
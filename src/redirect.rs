@@ -0,0 +1,86 @@
+use url::Url;
+
+use crate::unit;
+
+/// The urls redirects may be followed to, see
+/// [`Agent::redirect_policy()`](struct.Agent.html#method.redirect_policy) and
+/// [`Request::redirect_policy()`](struct.Request.html#method.redirect_policy).
+///
+/// A redirect elsewhere fails the call with
+/// [`Error::ForbiddenRedirect`](enum.Error.html#variant.ForbiddenRedirect),
+/// instead of making a request to it. The default allows any url.
+///
+/// ```
+/// // only https, only to the api or its subdomains.
+/// let policy = ureq::RedirectPolicy::new()
+///     .allow_scheme("https")
+///     .allow_host("api.example.com")
+///     .allow_host("*.api.example.com");
+///
+/// let agent = ureq::agent()
+///     .redirect_policy(policy)
+///     .build();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RedirectPolicy {
+    same_origin: bool,
+    schemes: Vec<String>,
+    hosts: Vec<String>,
+}
+
+impl RedirectPolicy {
+    /// A policy allowing any url, until restricted.
+    pub fn new() -> Self {
+        RedirectPolicy::default()
+    }
+
+    /// A policy allowing only urls of the same scheme, host and port as the
+    /// url redirected from.
+    pub fn same_origin() -> Self {
+        RedirectPolicy {
+            same_origin: true,
+            ..Default::default()
+        }
+    }
+
+    /// Allow a scheme, such as `https`. Once one is allowed, others are not.
+    pub fn allow_scheme(mut self, scheme: &str) -> Self {
+        self.schemes.push(scheme.to_ascii_lowercase());
+        self
+    }
+
+    /// Allow a host, any subdomain of it for one like `*.example.com`. Once
+    /// one is allowed, others are not.
+    pub fn allow_host(mut self, host: &str) -> Self {
+        let host = unit::ascii_host(host.trim_matches(['[', ']']));
+        self.hosts.push(host.to_ascii_lowercase());
+        self
+    }
+
+    /// Whether the redirect from one url to the other may be followed.
+    pub(crate) fn allows(&self, from: &Url, to: &Url) -> bool {
+        if self.same_origin && from.origin() != to.origin() {
+            return false;
+        }
+        if !self.schemes.is_empty() && !self.schemes.iter().any(|s| s == to.scheme()) {
+            return false;
+        }
+        if self.hosts.is_empty() {
+            return true;
+        }
+        let host = to
+            .host_str()
+            .unwrap_or_default()
+            .trim_matches(['[', ']'])
+            .to_ascii_lowercase();
+        self.hosts
+            .iter()
+            .any(|allowed| match allowed.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .map(|sub| sub.len() > 1 && sub.ends_with('.'))
+                    .unwrap_or(false),
+                None => *allowed == host,
+            })
+    }
+}
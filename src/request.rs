@@ -1,34 +1,99 @@
+use std::cmp::Ordering;
 use std::io::Read;
-use std::sync::{Arc, Mutex};
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "charset"))]
+use std::io::{Error as IoError, ErrorKind};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 use lazy_static::lazy_static;
 use qstring::QString;
 use url::Url;
 
 use crate::agent::{self, Agent, AgentState};
-use crate::body::Payload;
-use crate::error::Error;
+use crate::background::{self, ResponseHandle};
+use crate::body::{Body, BodyPreview, Payload, SizedReader};
+#[cfg(feature = "checksum")]
+use crate::checksum::{Algorithm, Checksum};
+use crate::concurrency;
+use crate::content_type::ContentType;
+use crate::data_url;
+use crate::error::{Error, ErrorHook};
+use crate::events::EventListener;
+use crate::faults::FaultConfig;
+#[cfg(feature = "file")]
+use crate::file_url;
+#[cfg(feature = "json")]
+use crate::graphql;
 use crate::header::{self, Header};
+use crate::metrics::{MetricsObserver, RequestMetrics};
+use crate::multipart::Multipart;
+#[cfg(feature = "ntlm")]
+use crate::ntlm::NtlmCredentials;
+#[cfg(feature = "pac")]
+use crate::pac::Pac;
 use crate::pool;
+use crate::prepared::PreparedRequest;
+use crate::proxy::Proxy;
+use crate::ratelimit;
+use crate::redirect::RedirectPolicy;
+use crate::request_id::{self, RequestIdConfig};
+use crate::response::EarlyHintsHook;
+use crate::retry;
+#[cfg(feature = "signatures")]
+use crate::signature::{Signing, SigningKey};
+#[cfg(all(feature = "sockopt", target_os = "linux"))]
+use crate::sockopt::SocketOptions;
+use crate::stream::WireHook;
+use crate::time::Instant;
+#[cfg(feature = "tls")]
+use crate::tls::{ClientIdentity, TlsCertificate, TlsOverrides};
 use crate::unit::{self, Unit};
+use crate::webdav::Depth;
 use crate::Response;
 
 #[cfg(feature = "json")]
 use super::SerdeValue;
 
 lazy_static! {
-    static ref URL_BASE: Url =
-        { Url::parse("http://localhost/").expect("Failed to parse URL_BASE") };
+    static ref URL_BASE: Url = Url::parse("http://localhost/").expect("Failed to parse URL_BASE");
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub enum IpVersion {
     V4,
+    #[default]
     V6,
 }
 
-impl Default for IpVersion {
-    fn default() -> Self { IpVersion::V6 }
+/// How query parameters set with [`.query()`](struct.Request.html#method.query) and
+/// [`.query_str()`](struct.Request.html#method.query_str) are percent-encoded on the wire.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum QueryEncoding {
+    /// Encode controls, space and `"<>\`&?=`. Everything else, including an already
+    /// encoded `%xx`, is sent as is.
+    #[default]
+    Standard,
+    /// Encode everything except the RFC 3986 unreserved characters `A-Z a-z 0-9 - . _ ~`.
+    Strict,
+    /// Like `Strict`, but space is sent as `+` (`application/x-www-form-urlencoded`).
+    Form,
+    /// No encoding at all, for parameters that are already percent-encoded.
+    PreEncoded,
+}
+
+/// How [`.query_array()`](struct.Request.html#method.query_array) sends the
+/// values of a parameter, for `id` and the values `1` and `2`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ArrayStyle {
+    /// `id=1&id=2`
+    #[default]
+    Repeat,
+    /// `id[]=1&id[]=2`, as PHP and Rails expect.
+    Brackets,
+    /// `id=1,2`
+    Comma,
 }
 
 /// Request instances are builders that creates a request.
@@ -40,22 +105,92 @@ impl Default for IpVersion {
 ///     .query("foo", "bar baz") // add ?foo=bar%20baz
 ///     .call();                 // run the request
 /// ```
+///
+/// The builder methods return `&mut Request`, so a chain that is kept or returned
+/// ends with [`take()`](#method.take) to get the request out of it.
+///
+/// ```
+/// fn api_request(path: &str) -> ureq::Request {
+///     ureq::get(path)
+///         .set("Accept", "application/json")
+///         .timeout_read(5_000)
+///         .take()
+/// }
+///
+/// let resp = api_request("/users").call();
+/// ```
 #[derive(Clone, Default)]
 pub struct Request {
-    pub(crate) agent: Arc<Mutex<Option<AgentState>>>,
+    pub(crate) agent: Arc<Option<AgentState>>,
 
     // via agent
     pub(crate) method: String,
     path: String,
+    /// The url as given already parsed, used as is until the path is changed.
+    parsed_url: Option<Url>,
+    /// A `.` or `..` path segment or param, refused when the url is made.
+    dot_segment: Option<String>,
 
     // from request itself
-    pub(crate) headers: Vec<Header>,
+    /// The agent's headers until one is set, so clones don't copy them.
+    pub(crate) headers: Arc<Vec<Header>>,
     pub(crate) query: QString,
+    pub(crate) query_encoding: QueryEncoding,
+    pub(crate) array_style: ArrayStyle,
+    pub(crate) timeout_resolve: u64,
     pub(crate) timeout_connect: u64,
     pub(crate) timeout_read: u64,
     pub(crate) timeout_write: u64,
+    pub(crate) chunk_size: Option<usize>,
     pub(crate) redirects: u32,
+    #[cfg(feature = "negotiate")]
+    pub(crate) negotiate: bool,
+    #[cfg(all(feature = "sockopt", target_os = "linux"))]
+    pub(crate) socket_options: SocketOptions,
+    #[cfg(feature = "ntlm")]
+    pub(crate) ntlm: Option<NtlmCredentials>,
+    pub(crate) allow_downgrade: bool,
+    pub(crate) redirect_policy: RedirectPolicy,
+    pub(crate) header_order: Vec<String>,
+    /// Send `*` as the request target instead of the url path.
+    pub(crate) asterisk_form: bool,
+    pub(crate) no_decompress: bool,
+    pub(crate) error_on_status: bool,
+    pub(crate) verbose: bool,
+    pub(crate) lenient_parsing: bool,
+    #[cfg(feature = "json")]
+    pub(crate) json_limit: Option<u64>,
+    /// Urls tried in order when the request fails to connect.
+    fallback_urls: Vec<String>,
+    /// Statuses that also make the request try the next url.
+    fallback_statuses: Vec<u16>,
+    pub(crate) absolute_urls: bool,
+    pub(crate) host_header: Option<String>,
+    pub(crate) connect_addr: Option<SocketAddr>,
+    #[cfg(feature = "tls")]
+    pub(crate) tls_overrides: Option<TlsOverrides>,
+    #[cfg(feature = "signatures")]
+    pub(crate) signing: Option<Signing>,
+    #[cfg(feature = "checksum")]
+    pub(crate) checksum: Option<Checksum>,
     pub(crate) preferred_ip_version: IpVersion,
+    pub(crate) wire_hook: Option<WireHook>,
+    pub(crate) faults: Option<FaultConfig>,
+    pub(crate) error_hook: Option<ErrorHook>,
+    pub(crate) early_hints: Option<EarlyHintsHook>,
+    pub(crate) proxies: Vec<Proxy>,
+    #[cfg(feature = "pac")]
+    pub(crate) pac: Option<Arc<Pac>>,
+    pub(crate) metrics: Option<Arc<dyn MetricsObserver>>,
+    pub(crate) events: Option<Arc<dyn EventListener>>,
+    pub(crate) request_id: Option<RequestIdConfig>,
+    pub(crate) idempotency_key: Option<String>,
+    #[cfg(feature = "http3")]
+    pub(crate) http3: bool,
+    #[cfg(feature = "http3")]
+    pub(crate) http3_only: bool,
+    /// The body of the last call, for `Debug`.
+    pub(crate) sent_body: Option<BodyPreview>,
 }
 
 impl ::std::fmt::Debug for Request {
@@ -63,15 +198,24 @@ impl ::std::fmt::Debug for Request {
         let (path, query) = self
             .to_url()
             .map(|u| {
-                let query = unit::combine_query(&u, &self.query, true);
+                let query = unit::combine_query(&u, &self.query, self.query_encoding, true);
                 (u.path().to_string(), query)
             })
             .unwrap_or_else(|_| ("BAD_URL".to_string(), "BAD_URL".to_string()));
+        let (path, query) = if self.asterisk_form {
+            ("*".to_string(), String::new())
+        } else {
+            (path, query)
+        };
         write!(
             f,
-            "Request({} {}{}, {:?})",
+            "Request({} {}{}, {:?}",
             self.method, path, query, self.headers
-        )
+        )?;
+        if let Some(body) = &self.sent_body {
+            write!(f, ", body: {}", body)?;
+        }
+        write!(f, ")")
     }
 }
 
@@ -81,12 +225,51 @@ impl Request {
             agent: Arc::clone(&agent.state),
             method,
             path,
-            headers: agent.headers.clone(),
-            redirects: 5,
+            headers: Arc::clone(&agent.headers),
+            header_order: agent.header_order.clone(),
+            wire_hook: agent.wire_hook.clone(),
+            faults: agent.faults,
+            error_hook: agent.error_hook.clone(),
+            early_hints: agent.early_hints.clone(),
+            proxies: agent.proxies.clone(),
+            redirect_policy: agent.redirect_policy.clone(),
+            #[cfg(feature = "pac")]
+            pac: (*agent.state).as_ref().and_then(|s| s.pac.clone()),
+            metrics: agent.metrics.clone(),
+            events: agent.events.clone(),
+            request_id: agent.request_id.clone(),
+            idempotency_key: agent.idempotency_key.clone(),
+            error_on_status: agent.error_on_status,
+            verbose: agent.verbose,
+            #[cfg(feature = "http3")]
+            http3: agent.http3,
+            #[cfg(feature = "http3")]
+            http3_only: agent.http3_only,
+            lenient_parsing: agent.lenient_parsing,
+            #[cfg(feature = "json")]
+            json_limit: agent.json_limit,
+            absolute_urls: agent.absolute_urls,
+            #[cfg(feature = "negotiate")]
+            negotiate: agent.negotiate,
+            #[cfg(all(feature = "sockopt", target_os = "linux"))]
+            socket_options: agent.socket_options,
+            #[cfg(feature = "ntlm")]
+            ntlm: agent.ntlm.clone(),
+            redirects: agent.redirects.unwrap_or(5),
+            timeout_resolve: agent.timeout_resolve,
+            timeout_connect: agent.timeout_connect,
+            timeout_read: agent.timeout_read,
+            timeout_write: agent.timeout_write,
             ..Default::default()
         }
     }
 
+    pub(crate) fn with_url(agent: &Agent, method: String, url: &Url) -> Request {
+        let mut req = Request::new(agent, method, url.to_string());
+        req.parsed_url = Some(url.clone());
+        req
+    }
+
     /// "Builds" this request which is effectively the same as cloning.
     /// This is needed when we use a chain of request builders, but
     /// don't want to send the request at the end of the chain.
@@ -96,10 +279,27 @@ impl Request {
     ///     .set("X-Foo-Bar", "Baz")
     ///     .build();
     /// ```
+    ///
+    /// The headers are shared between the clones until one of them sets a
+    /// header, so a template request can be cloned in a loop cheaply.
     pub fn build(&self) -> Request {
         self.clone()
     }
 
+    /// Move the request out of a chain of builders, like
+    /// [`build()`](#method.build) without cloning. What is left behind is an
+    /// empty request.
+    ///
+    /// ```
+    /// let mut r = ureq::get("/my_page")
+    ///     .set("X-Foo-Bar", "Baz")
+    ///     .take();
+    /// r.call();
+    /// ```
+    pub fn take(&mut self) -> Request {
+        std::mem::take(self)
+    }
+
     /// Executes the request and blocks the caller until done.
     ///
     /// Use `.timeout_connect()` and `.timeout_read()` to avoid blocking forever.
@@ -115,14 +315,163 @@ impl Request {
         self.do_call(Payload::Empty)
     }
 
+    /// Executes the request like [`call()`](#method.call), but gives a transport
+    /// failure as `Err` instead of a synthetic response. With
+    /// [`error_on_status()`](#method.error_on_status), `4xx` and `5xx` responses
+    /// are errors too.
+    ///
+    /// ```
+    /// let r = ureq::get("/my_page")
+    ///     .error_on_status(true)
+    ///     .try_call();
+    ///
+    /// match r {
+    ///     Ok(resp) => println!("{}", resp.status()),
+    ///     Err(ureq::Error::Status(code, resp)) => println!("{} {}", code, resp.status_text()),
+    ///     Err(e) => println!("failed: {}", e),
+    /// }
+    /// ```
+    pub fn try_call(&mut self) -> Result<Response, Error> {
+        self.call().into_result()
+    }
+
+    /// Executes the request like [`call()`](#method.call) on a thread of the
+    /// agent, see [`Agent::background_threads()`](struct.Agent.html#method.background_threads),
+    /// and returns at once. The response is collected from the handle.
+    ///
+    /// ```
+    /// let agent = ureq::agent();
+    ///
+    /// let first = agent.get("/my_page").call_background();
+    /// let second = agent.get("/other_page").call_background();
+    ///
+    /// println!("{} {}", first.join().status(), second.join().status());
+    /// ```
+    pub fn call_background(&mut self) -> ResponseHandle {
+        background::spawn(&self.agent, self.clone())
+    }
+
     fn do_call(&mut self, payload: Payload) -> Response {
-        self.to_url()
-            .and_then(|url| {
-                let reader = payload.into_read();
-                let unit = Unit::new(&self, &url, true, &reader);
-                unit::connect(&self, unit, true, 0, reader, false)
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "request",
+            method = %self.method,
+            host = tracing::field::Empty,
+            status = tracing::field::Empty,
+            duration_ms = tracing::field::Empty
+        );
+        #[cfg(feature = "tracing")]
+        let _enter = span.enter();
+
+        let start = Instant::now();
+        let mut host = String::new();
+
+        // one id for the call, also when redirected or retried.
+        let request_id = self.request_id.as_ref().map(|config| {
+            let value = match self.header(&config.header) {
+                Some(value) => value.to_string(),
+                None => (config.generate)(),
+            };
+            Header::new(&config.header, &value)
+        });
+        // and one key for all attempts of a request that isn't idempotent.
+        let idempotency_key = self
+            .idempotency_key
+            .as_ref()
+            .filter(|name| !unit::is_idempotent(&self.method) && !self.has(name))
+            .map(|name| Header::new(name, &request_id::uuid_v4()));
+
+        let mut reader = payload.into_read();
+        self.sent_body = reader.preview.take();
+
+        // kept to send the body again to a fallback url.
+        let again = if self.fallback_urls.is_empty() {
+            None
+        } else {
+            reader.rewind()
+        };
+        let mut send = |url: Result<Url, Error>, reader: SizedReader| -> Response {
+            url.and_then(|url| {
+                #[cfg(feature = "tracing")]
+                span.record("host", url.host_str().unwrap_or(pool::DEFAULT_HOST));
+                host = url.host_str().unwrap_or("").to_string();
+                if url.scheme() == "data" {
+                    return data_url::response(&url);
+                }
+                #[cfg(feature = "file")]
+                {
+                    if url.scheme() == "file" {
+                        return file_url::response(&self.method, &url);
+                    }
+                }
+                let mut unit = Unit::new(self, &url, true, &reader);
+                unit.request_id = request_id.clone();
+                unit.idempotency_key = idempotency_key.clone();
+                unit.permit = concurrency::acquire(&self.agent)?;
+                ratelimit::acquire(&unit)?;
+                retry::record_request(&self.agent);
+                unit::connect(self, unit, true, 0, reader, false)
             })
             .unwrap_or_else(|e| e.into())
+        };
+
+        let mut resp = send(self.to_url(), reader);
+        for fallback in &self.fallback_urls {
+            if !self.falls_back(&resp) {
+                break;
+            }
+            let reader = match again.as_ref().and_then(|r| r.rewind()) {
+                Some(reader) => reader,
+                None => break,
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(url = %fallback, "trying fallback url");
+            let url = Url::parse(fallback).map_err(|e| Error::BadUrl(format!("{}", e)));
+            resp = send(url, reader);
+        }
+
+        resp.request_id = request_id.map(|h| h.value().to_string());
+        resp.error_on_status = self.error_on_status;
+        #[cfg(feature = "checksum")]
+        {
+            resp.checksum = self.checksum.clone();
+        }
+
+        if let Some(hook) = &self.error_hook {
+            resp = notify_error(self, hook, resp);
+        }
+
+        if let Some(state) = (*self.agent).as_ref() {
+            state
+                .counters
+                .request(resp.status(), resp.synthetic_error());
+        }
+
+        if let Some(events) = &self.events {
+            events.on_complete(self, &resp, start.elapsed());
+        }
+
+        if let Some(observer) = &self.metrics {
+            observer.on_request(&RequestMetrics {
+                method: &self.method,
+                host: &host,
+                status: resp.status(),
+                error: resp.synthetic_error(),
+                stats: resp.stats,
+                total: start.elapsed(),
+            });
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            span.record("status", resp.status());
+            span.record("duration_ms", start.elapsed().as_millis() as u64);
+            if let Some(err) = resp.synthetic_error() {
+                tracing::debug!(error = %err, "request failed");
+            }
+        }
+
+        resp
     }
 
     /// Send data a json value.
@@ -143,7 +492,120 @@ impl Request {
     /// ```
     #[cfg(feature = "json")]
     pub fn send_json(&mut self, data: SerdeValue) -> Response {
-        self.do_call(Payload::JSON(data))
+        self.do_call(Payload::Json(data))
+    }
+
+    /// Send a GraphQL query with its variables in the standard JSON envelope,
+    /// `{"query": ..., "variables": ...}`, with `Content-Type: application/json`
+    /// unless the request sets another. Variables that are `null` are left out.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// The response is read with
+    /// [`Response::into_graphql()`](struct.Response.html#method.into_graphql).
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate ureq;
+    ///
+    /// fn main() {
+    /// let r = ureq::post("/graphql")
+    ///     .send_graphql("query Hero($id: ID!) { hero(id: $id) { name } }", json!({ "id": "1" }));
+    /// println!("{:?}", r);
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn send_graphql(&mut self, query: &str, variables: SerdeValue) -> Response {
+        if !self.has("content-type") {
+            self.set("Content-Type", "application/json");
+        }
+        self.send_json(graphql::request_body(query, variables))
+    }
+
+    /// Send a value serialized as [MessagePack](https://msgpack.org), with
+    /// `Content-Type: application/msgpack` unless the request sets another.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["msgpack"] }`
+    ///
+    /// Structs are serialized as maps with field names. A value that fails to
+    /// serialize gives a synthetic error response.
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    ///
+    /// let mut data = BTreeMap::new();
+    /// data.insert("name", "martin");
+    ///
+    /// let r = ureq::post("/my_page")
+    ///     .send_msgpack(&data);
+    /// println!("{:?}", r);
+    /// ```
+    #[cfg(feature = "msgpack")]
+    pub fn send_msgpack(&mut self, data: &impl serde::Serialize) -> Response {
+        let bytes = match rmp_serde::to_vec_named(data) {
+            Ok(bytes) => bytes,
+            Err(e) => return Error::Io(IoError::new(ErrorKind::InvalidInput, e)).into(),
+        };
+        if !self.has("content-type") {
+            self.set("Content-Type", "application/msgpack");
+        }
+        self.do_call(Payload::Bytes(bytes))
+    }
+
+    /// Send a value serialized as [CBOR](https://cbor.io), with
+    /// `Content-Type: application/cbor` unless the request sets another.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["cbor"] }`
+    ///
+    /// A value that fails to serialize gives a synthetic error response.
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    ///
+    /// let mut data = BTreeMap::new();
+    /// data.insert("temperature", 21);
+    ///
+    /// let r = ureq::post("/my_page")
+    ///     .send_cbor(&data);
+    /// println!("{:?}", r);
+    /// ```
+    #[cfg(feature = "cbor")]
+    pub fn send_cbor(&mut self, data: &impl serde::Serialize) -> Response {
+        let mut bytes = vec![];
+        if let Err(e) = ciborium::ser::into_writer(data, &mut bytes) {
+            let msg = format!("Failed to write CBOR: {}", e);
+            return Error::Io(IoError::new(ErrorKind::InvalidInput, msg)).into();
+        }
+        if !self.has("content-type") {
+            self.set("Content-Type", "application/cbor");
+        }
+        self.do_call(Payload::Bytes(bytes))
+    }
+
+    /// Send a [prost](https://docs.rs/prost) protobuf message, with
+    /// `Content-Type: application/x-protobuf` unless the request sets another.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["protobuf"] }`
+    ///
+    /// ```
+    /// #[derive(Clone, PartialEq, prost::Message)]
+    /// struct Hello {
+    ///     #[prost(string, tag = "1")]
+    ///     name: String,
+    /// }
+    ///
+    /// let hello = Hello { name: "martin".to_string() };
+    ///
+    /// let r = ureq::post("/my_page")
+    ///     .send_proto(&hello);
+    /// println!("{:?}", r);
+    /// ```
+    #[cfg(feature = "protobuf")]
+    pub fn send_proto(&mut self, message: &impl prost::Message) -> Response {
+        if !self.has("content-type") {
+            self.set("Content-Type", "application/x-protobuf");
+        }
+        self.do_call(Payload::Bytes(message.encode_to_vec()))
     }
 
     /// Send data as bytes.
@@ -165,6 +627,142 @@ impl Request {
         self.do_call(Payload::Bytes(data.to_owned()))
     }
 
+    /// Send a body of a known length from a reader, with `Content-Length: len`.
+    ///
+    /// Use this over [`send()`](#method.send) for servers that reject chunked
+    /// uploads. Exactly `len` bytes must be read, a shorter or longer reader
+    /// gives a synthetic `Error::Io` response.
+    ///
+    /// ```
+    /// let data = std::io::Cursor::new(b"Hello world!".to_vec());
+    ///
+    /// let r = ureq::put("/my_page")
+    ///     .send_sized(data, 12);
+    /// println!("{:?}", r);
+    /// ```
+    pub fn send_sized(&mut self, reader: impl Read + 'static, len: usize) -> Response {
+        self.do_call(Payload::Sized(Box::new(reader), len))
+    }
+
+    /// Send the contents of a file.
+    ///
+    /// The `Content-Length` header is set from the file size and, unless set
+    /// already, the `Content-Type` is guessed from the file extension. If the
+    /// file can't be opened, this gives a synthetic `Error::Io` response.
+    ///
+    /// ```
+    /// let r = ureq::put("/my_page")
+    ///     .send_file("Cargo.toml");
+    /// println!("{:?}", r);
+    /// ```
+    pub fn send_file(&mut self, path: impl AsRef<Path>) -> Response {
+        let path = path.as_ref();
+        let body = match Body::file(path) {
+            Ok(body) => body,
+            Err(e) => return Error::Io(e).into(),
+        };
+        if !self.has("content-type") {
+            let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            self.set("Content-Type", mime_from_extension(ext));
+        }
+        self.do_call(Payload::Body(body))
+    }
+
+    /// Send a [`Body`](struct.Body.html), which is sent again when following a
+    /// `307` or `308` redirect, or when retrying on a new connection.
+    ///
+    /// The `Content-Length` header is set from the size of the body.
+    ///
+    /// ```
+    /// let body = ureq::Body::bytes(b"Hello world!".to_vec());
+    ///
+    /// let r = ureq::put("/my_page")
+    ///     .send_body(body);
+    /// println!("{:?}", r);
+    /// ```
+    pub fn send_body(&mut self, body: Body) -> Response {
+        self.do_call(Payload::Body(body))
+    }
+
+    /// Work out the url and headers of this request without a body, to look at
+    /// them before sending. The [`PreparedRequest`](struct.PreparedRequest.html)
+    /// can be sent any number of times.
+    ///
+    /// ```
+    /// let prepared = ureq::get("https://cool.server/innit")
+    ///     .query("page", "2")
+    ///     .prepare()
+    ///     .unwrap();
+    /// assert_eq!(prepared.url(), "https://cool.server/innit?page=2");
+    /// assert_eq!(prepared.header("Host"), Some("cool.server"));
+    /// ```
+    pub fn prepare(&self) -> Result<PreparedRequest, Error> {
+        PreparedRequest::new(self, Body::empty())
+    }
+
+    /// Like [`prepare()`](#method.prepare), with a body to send.
+    ///
+    /// ```
+    /// let prepared = ureq::post("https://cool.server/innit")
+    ///     .prepare_body(ureq::Body::text("Hello world!"))
+    ///     .unwrap();
+    /// assert_eq!(prepared.body_len(), Some(12));
+    /// assert_eq!(prepared.header("Content-Length"), Some("12"));
+    /// ```
+    pub fn prepare_body(&self, body: Body) -> Result<PreparedRequest, Error> {
+        PreparedRequest::new(self, body)
+    }
+
+    /// Send a `multipart/form-data` body, as browsers do for forms with file uploads.
+    ///
+    /// The `Content-Type` is set with the boundary of the form. The body is sent with
+    /// a `Content-Length` when the length of all parts is known, otherwise chunked.
+    ///
+    /// ```
+    /// let form = ureq::Multipart::new()
+    ///     .text("name", "martin")
+    ///     .text("rust", "true");
+    ///
+    /// let r = ureq::post("/my_page")
+    ///     .send_multipart(form);
+    /// println!("{:?}", r);
+    /// ```
+    pub fn send_multipart(&mut self, form: Multipart) -> Response {
+        self.set("Content-Type", &form.content_type());
+        match form.into_read() {
+            (reader, Some(len)) => self.do_call(Payload::Sized(reader, len as usize)),
+            (reader, None) => {
+                if !self.has("transfer-encoding") {
+                    self.set("Transfer-Encoding", "chunked");
+                }
+                self.do_call(Payload::Reader(reader))
+            }
+        }
+    }
+
+    /// Send a body from an iterator of chunks with `Transfer-Encoding: chunked`.
+    /// Each item is sent as a chunk as soon as it is produced, for data that is
+    /// produced incrementally. Items over the [`chunk_size()`](#method.chunk_size)
+    /// are split in several chunks and empty items are skipped.
+    ///
+    /// ```
+    /// let lines = (0..3).map(|i| format!("line {}\n", i).into_bytes());
+    ///
+    /// let r = ureq::post("/my_page")
+    ///     .send_chunks(lines);
+    /// println!("{:?}", r);
+    /// ```
+    pub fn send_chunks<I>(&mut self, chunks: I) -> Response
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: 'static,
+    {
+        if !self.has("transfer-encoding") {
+            self.set("Transfer-Encoding", "chunked");
+        }
+        self.do_call(Payload::Chunks(Box::new(chunks.into_iter())))
+    }
+
     /// Send data as a string.
     ///
     /// The `Content-Length` header is implicitly set to the length of the serialized value.
@@ -193,142 +791,711 @@ impl Request {
         self.do_call(Payload::Text(text, charset))
     }
 
+    /// Send data as a string encoded in the given charset, which is also set as
+    /// the `charset` of the `Content-Type` header. A `Content-Type` set before
+    /// keeps its media type, or it is `text/plain`.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["charset"] }`
+    ///
+    /// Characters the charset has no bytes for are sent as `?`. An unknown
+    /// charset label is a synthetic error `500`, without anything being sent.
+    ///
+    /// ```
+    /// // this example requires features = ["charset"]
+    ///
+    /// let r = ureq::post("/my_page")
+    ///     .set("Content-Type", "text/csv")
+    ///     .send_string_with_charset("Hällo;Wörld", "windows-1252");
+    /// println!("{:?}", r);
+    /// ```
+    #[cfg(feature = "charset")]
+    pub fn send_string_with_charset(&mut self, data: &str, charset: &str) -> Response {
+        if encoding::label::encoding_from_whatwg_label(charset).is_none() {
+            let err = IoError::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown charset: {}", charset),
+            );
+            return Error::Io(err).into();
+        }
+        let content_type = with_charset(self.header("content-type"), charset);
+        self.set("Content-Type", &content_type);
+        self.do_call(Payload::Text(data.into(), charset.to_string()))
+    }
+
     /// Send data from a reader.
     ///
     /// The `Content-Length` header is not set because we can't know the length of the reader.
     ///
+    /// Should the server answer with an error status, like `401` or `413`,
+    /// before the body is sent in full, the rest isn't sent and that response
+    /// is returned. The connection is then closed instead of being reused.
+    ///
     /// ```
     /// use std::io::Cursor;
     ///
     /// let text = "Hello there!\n";
     /// let read = Cursor::new(text.to_string().into_bytes());
     ///
-    /// let resp = ureq::post("/somewhere")
-    ///     .set("Content-Type", "text/plain")
-    ///     .send(read);
+    /// let resp = ureq::post("/somewhere")
+    ///     .set("Content-Type", "text/plain")
+    ///     .send(read);
+    /// ```
+    pub fn send(&mut self, reader: impl Read + 'static) -> Response {
+        self.do_call(Payload::Reader(Box::new(reader)))
+    }
+
+    /// Set a header field.
+    ///
+    /// ```
+    /// let r = ureq::get("/my_page")
+    ///     .set("X-API-Key", "foobar")
+    ///     .set("Accept", "text/plain")
+    ///     .call();
+    ///
+    ///  if r.ok() {
+    ///      println!("yay got {}", r.into_string().unwrap());
+    ///  } else {
+    ///      println!("Oh no error!");
+    ///  }
+    /// ```
+    pub fn set(&mut self, header: &str, value: &str) -> &mut Request {
+        header::add_header(Arc::make_mut(&mut self.headers), Header::new(header, value));
+        self
+    }
+
+    /// Don't decode the `Content-Encoding` of the response body, so it is read
+    /// as the server sent it, matching the `Content-Encoding` and `Content-Length`
+    /// headers. For proxies and mirrors that forward the bytes as they are.
+    ///
+    /// No `Accept-Encoding` is then sent unless set. Setting one without this
+    /// still decodes the encodings it lists that have a decoder enabled, and
+    /// leaves the others to the caller.
+    ///
+    /// ```
+    /// let req = ureq::get("https://cool.server/archive")
+    ///     .set("Accept-Encoding", "gzip")
+    ///     .no_decompress()
+    ///     .build();
+    /// ```
+    pub fn no_decompress(&mut self) -> &mut Request {
+        self.no_decompress = true;
+        self
+    }
+
+    /// Set the `Content-Type` header, quoting parameter values as needed.
+    ///
+    /// ```
+    /// let content_type = ureq::ContentType::new("text", "plain").param("charset", "utf-8");
+    ///
+    /// let req = ureq::post("https://cool.server/notes")
+    ///     .content_type(&content_type)
+    ///     .build();
+    /// assert_eq!(req.header("Content-Type"), Some("text/plain; charset=utf-8"));
+    /// ```
+    pub fn content_type(&mut self, content_type: &ContentType) -> &mut Request {
+        self.set("Content-Type", &content_type.to_string())
+    }
+
+    /// Set the `Accept` header to the media types in order of preference, each
+    /// optionally with a quality like `text/plain; q=0.5`.
+    ///
+    /// ```
+    /// let req = ureq::get("https://cool.server/innit")
+    ///     .accept(&["application/json", "text/plain; q=0.5"])
+    ///     .build();
+    /// assert_eq!(req.header("Accept"), Some("application/json, text/plain; q=0.5"));
+    /// ```
+    pub fn accept(&mut self, media_types: &[&str]) -> &mut Request {
+        let value: Vec<_> = media_types.iter().map(|t| t.trim()).collect();
+        self.set("Accept", &value.join(", "))
+    }
+
+    /// Set the `Accept-Language` header to the languages in order of preference.
+    /// Languages without a `q` weight get one from their position.
+    ///
+    /// ```
+    /// let req = ureq::get("https://cool.server/innit")
+    ///     .accept_language(&["sv-SE", "sv", "en;q=0.5"])
+    ///     .build();
+    /// assert_eq!(req.header("Accept-Language"), Some("sv-SE, sv;q=0.9, en;q=0.5"));
+    /// ```
+    pub fn accept_language(&mut self, languages: &[&str]) -> &mut Request {
+        self.set("Accept-Language", &header::quality_list(languages))
+    }
+
+    /// Write these headers first, in this order. Names not in the list, and the
+    /// listed ones that are not sent, are ignored.
+    ///
+    /// Otherwise `Host`, `User-Agent` and `Accept` go first unless set on the
+    /// request, followed by the other headers in the order they were first set.
+    /// Setting a header again replaces its value but keeps its place.
+    ///
+    /// ```
+    /// let req = ureq::get("https://cool.server/innit")
+    ///     .set("X-Signature", "abc")
+    ///     .set("Date", "Tue, 15 Nov 1994 08:12:31 GMT")
+    ///     .header_order(&["Date", "Host"])
+    ///     .build();
+    /// ```
+    pub fn header_order(&mut self, names: &[&str]) -> &mut Request {
+        self.header_order = names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    /// Set the WebDAV `Depth` header, which says whether a `PROPFIND`, `COPY`
+    /// or `LOCK` of a collection applies to its members.
+    ///
+    /// ```
+    /// use ureq::Depth;
+    ///
+    /// let req = ureq::propfind("https://cloud.example.com/dav/")
+    ///     .depth(Depth::Zero)
+    ///     .build();
+    /// assert_eq!(req.header("Depth"), Some("0"));
+    /// ```
+    pub fn depth(&mut self, depth: Depth) -> &mut Request {
+        self.set("Depth", depth.as_str())
+    }
+
+    /// Set the WebDAV `Overwrite` header of a `COPY` or `MOVE`. With `false`,
+    /// an existing destination fails the request with `412 Precondition Failed`.
+    pub fn overwrite(&mut self, overwrite: bool) -> &mut Request {
+        self.set("Overwrite", if overwrite { "T" } else { "F" })
+    }
+
+    /// Send this as the `Host` header instead of the host of the url, such as
+    /// for a virtual host on a server reached by its IP. Redirects to another
+    /// host send that host as usual. International names are sent in punycode,
+    /// as they are for urls.
+    ///
+    /// ```
+    /// let r = ureq::get("http://10.0.0.5/status")
+    ///     .host_header("virtualhost.example")
+    ///     .build();
+    /// ```
+    pub fn host_header(&mut self, host: &str) -> &mut Request {
+        self.host_header = Some(unit::ascii_host(host));
+        self
+    }
+
+    /// Connect to this address instead of resolving the host of the url. The
+    /// url host is still sent as `Host` and used for TLS SNI and certificate
+    /// verification, so a specific backend behind a load balancer can be tested.
+    ///
+    /// These connections are never pooled. Redirects to another host connect
+    /// to that host as usual.
+    ///
+    /// ```
+    /// let addr = "10.0.0.5:443".parse().unwrap();
+    ///
+    /// let r = ureq::get("https://www.example.com/status")
+    ///     .connect_addr(addr)
+    ///     .build();
+    /// ```
+    pub fn connect_addr(&mut self, addr: SocketAddr) -> &mut Request {
+        self.connect_addr = Some(addr);
+        self
+    }
+
+    /// Sign the request with HTTP Message Signatures (RFC 9421), adding
+    /// `Signature-Input` and `Signature` headers over the covered components:
+    /// header names, like `content-digest`, and the derived components
+    /// `@method`, `@target-uri`, `@authority`, `@scheme`, `@request-target`,
+    /// `@path` and `@query`.
+    ///
+    /// The signature is made when the request is sent, with its creation time,
+    /// and again for each redirect. Sending fails when a covered header is
+    /// not sent.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["signatures"] }`
+    ///
+    /// ```
+    /// let key = ureq::SigningKey::hmac_sha256("my-key", b"secret");
+    ///
+    /// let r = ureq::post("https://api.example/payments")
+    ///     .set("Content-Type", "application/json")
+    ///     .sign(&key, &["@method", "@target-uri", "content-type"])
+    ///     .build();
+    /// ```
+    #[cfg(feature = "signatures")]
+    pub fn sign(&mut self, key: &SigningKey, components: &[&str]) -> &mut Request {
+        self.signing = Some(Signing {
+            key: key.clone(),
+            components: components.iter().map(|c| c.to_ascii_lowercase()).collect(),
+        });
+        self
+    }
+
+    /// Trust these certificates, on top of the roots of the agent, such as
+    /// the CA of an internal service.
+    ///
+    /// Requests with TLS settings of their own are never pooled, and the
+    /// settings don't follow redirects to another host.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let pem = "-----BEGIN CERTIFICATE-----\nMAA=\n-----END CERTIFICATE-----\n";
+    /// let ca = ureq::TlsCertificate::from_pem(pem)?;
+    ///
+    /// let r = ureq::get("https://internal.example/")
+    ///     .add_root_certificate(&ca)
+    ///     .build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn add_root_certificate(&mut self, cert: &TlsCertificate) -> &mut Request {
+        let overrides = self.tls_overrides.get_or_insert_with(Default::default);
+        overrides.roots.extend(cert.0.iter().cloned());
+        self
+    }
+
+    /// Present this certificate and key to servers asking for a client
+    /// certificate.
+    #[cfg(feature = "tls")]
+    pub fn client_identity(&mut self, identity: &ClientIdentity) -> &mut Request {
+        let overrides = self.tls_overrides.get_or_insert_with(Default::default);
+        overrides.identity = Some(identity.clone());
+        self
+    }
+
+    /// Send this name as TLS SNI, and verify the server certificate for it,
+    /// instead of the host of the url.
+    ///
+    /// ```
+    /// let r = ureq::get("https://10.0.0.5/")
+    ///     .tls_server_name("backend.example")
+    ///     .build();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn tls_server_name(&mut self, name: &str) -> &mut Request {
+        let overrides = self.tls_overrides.get_or_insert_with(Default::default);
+        overrides.server_name = Some(unit::ascii_host(name));
+        self
+    }
+
+    /// Set IP version to use.
+    ///
+    /// ```
+    /// use ureq::IpVersion;
+    ///
+    /// let r = ureq::get("/my_page")
+    ///     .set_preferred_ip_version(IpVersion::V4)
+    ///     .call();
+    /// println!("{:?}", r);
+    /// ```
+    pub fn set_preferred_ip_version(&mut self, ip_version: IpVersion) -> &mut Request {
+        self.preferred_ip_version = ip_version;
+        self
+    }
+
+    /// Returns the value for a set header.
+    ///
+    /// ```
+    /// let req = ureq::get("/my_page")
+    ///     .set("X-API-Key", "foobar")
+    ///     .build();
+    /// assert_eq!("foobar", req.header("x-api-Key").unwrap());
+    /// ```
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header::get_header(&self.headers, name)
+    }
+
+    /// A list of the set header names in this request. Lowercased to be uniform.
+    ///
+    /// ```
+    /// let req = ureq::get("/my_page")
+    ///     .set("X-API-Key", "foobar")
+    ///     .set("Content-Type", "application/json")
+    ///     .build();
+    /// assert_eq!(req.header_names(), vec!["x-api-key", "content-type"]);
+    /// ```
+    pub fn header_names(&self) -> Vec<String> {
+        self.headers
+            .iter()
+            .map(|h| h.name().to_ascii_lowercase())
+            .collect()
+    }
+
+    /// Tells if the header has been set.
+    ///
+    /// ```
+    /// let req = ureq::get("/my_page")
+    ///     .set("X-API-Key", "foobar")
+    ///     .build();
+    /// assert_eq!(true, req.has("x-api-Key"));
+    /// ```
+    pub fn has(&self, name: &str) -> bool {
+        header::has_header(&self.headers, name)
+    }
+
+    /// All headers corresponding values for the give name, or empty vector.
+    ///
+    /// ```
+    /// let req = ureq::get("/my_page")
+    ///     .set("X-Forwarded-For", "1.2.3.4")
+    ///     .set("X-Forwarded-For", "2.3.4.5")
+    ///     .build();
+    /// assert_eq!(req.all("x-forwarded-for"), vec![
+    ///     "1.2.3.4",
+    ///     "2.3.4.5",
+    /// ]);
+    /// ```
+    pub fn all(&self, name: &str) -> Vec<&str> {
+        header::get_all_headers(&self.headers, name)
+    }
+
+    /// Set a query parameter.
+    ///
+    /// For example, to set `?format=json&dest=/login`
+    ///
+    /// ```
+    /// let r = ureq::get("/my_page")
+    ///     .query("format", "json")
+    ///     .query("dest", "/login")
+    ///     .call();
+    ///
+    /// println!("{:?}", r);
+    /// ```
+    pub fn query(&mut self, param: &str, value: &str) -> &mut Request {
+        self.query.add_pair((param, value));
+        self
+    }
+
+    /// Set a query parameter with several values, sent in the
+    /// [`ArrayStyle`](enum.ArrayStyle.html) set with
+    /// [`.query_array_style()`](#method.query_array_style), repeating the
+    /// parameter by default. No values add no parameter.
+    ///
+    /// ```
+    /// use ureq::ArrayStyle;
+    ///
+    /// let req = ureq::get("/items")
+    ///     .query_array("id", &["1", "2", "3"])
+    ///     .build();
+    /// assert_eq!(req.get_query().unwrap(), "?id=1&id=2&id=3");
+    ///
+    /// let req = ureq::get("/items")
+    ///     .query_array_style(ArrayStyle::Comma)
+    ///     .query_array("id", &["1", "2", "3"])
+    ///     .build();
+    /// assert_eq!(req.get_query().unwrap(), "?id=1,2,3");
+    /// ```
+    pub fn query_array<V: AsRef<str>>(&mut self, param: &str, values: &[V]) -> &mut Request {
+        if values.is_empty() {
+            return self;
+        }
+        match self.array_style {
+            ArrayStyle::Repeat => {
+                for value in values {
+                    self.query.add_pair((param, value.as_ref()));
+                }
+            }
+            ArrayStyle::Brackets => {
+                let param = format!("{}[]", param);
+                for value in values {
+                    self.query.add_pair((param.as_str(), value.as_ref()));
+                }
+            }
+            ArrayStyle::Comma => {
+                let joined: Vec<&str> = values.iter().map(|v| v.as_ref()).collect();
+                self.query.add_pair((param, joined.join(",")));
+            }
+        }
+        self
+    }
+
+    /// How [`.query_array()`](#method.query_array) sends its values, see
+    /// [`ArrayStyle`](enum.ArrayStyle.html). Only arrays added after this are
+    /// affected.
+    ///
+    /// ```
+    /// use ureq::ArrayStyle;
+    ///
+    /// let req = ureq::get("/items")
+    ///     .query_array_style(ArrayStyle::Brackets)
+    ///     .query_array("tag", &["a", "b"])
+    ///     .build();
+    /// assert_eq!(req.get_query().unwrap(), "?tag[]=a&tag[]=b");
+    /// ```
+    pub fn query_array_style(&mut self, style: ArrayStyle) -> &mut Request {
+        self.array_style = style;
+        self
+    }
+
+    /// Set query parameters from the fields of a serializable struct (or map).
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// `None` fields are left out and sequences repeat the parameter once per
+    /// element. Nested structs are sent as their JSON text. Values that don't
+    /// serialize to a struct or map add no parameters.
+    ///
     /// ```
-    pub fn send(&mut self, reader: impl Read + 'static) -> Response {
-        self.do_call(Payload::Reader(Box::new(reader)))
+    /// #[macro_use]
+    /// extern crate ureq;
+    ///
+    /// fn main() {
+    /// let req = ureq::get("/my_page")
+    ///     .query_struct(&json!({ "state": "open", "label": ["bug", "ui"], "page": null }))
+    ///     .build();
+    /// assert_eq!(req.get_query().unwrap(), "?label=bug&label=ui&state=open");
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn query_struct(&mut self, params: &impl serde::Serialize) -> &mut Request {
+        fn to_param(value: &SerdeValue) -> Option<String> {
+            match value {
+                SerdeValue::Null => None,
+                SerdeValue::String(s) => Some(s.clone()),
+                v => Some(v.to_string()),
+            }
+        }
+        if let Ok(SerdeValue::Object(map)) = serde_json::to_value(params) {
+            for (param, value) in map.iter() {
+                let values = match value {
+                    SerdeValue::Array(items) => items.iter().filter_map(to_param).collect(),
+                    v => to_param(v).into_iter().collect::<Vec<_>>(),
+                };
+                for value in values {
+                    self.query.add_pair((param.as_str(), value));
+                }
+            }
+        }
+        self
     }
 
-    /// Set a header field.
+    /// Set query parameters as a string.
+    ///
+    /// For example, to set `?format=json&dest=/login`
     ///
     /// ```
     /// let r = ureq::get("/my_page")
-    ///     .set("X-API-Key", "foobar")
-    ///     .set("Accept", "text/plain")
+    ///     .query_str("?format=json&dest=/login")
     ///     .call();
-    ///
-    ///  if r.ok() {
-    ///      println!("yay got {}", r.into_string().unwrap());
-    ///  } else {
-    ///      println!("Oh no error!");
-    ///  }
+    /// println!("{:?}", r);
     /// ```
-    pub fn set(&mut self, header: &str, value: &str) -> &mut Request {
-        header::add_header(&mut self.headers, Header::new(header, value));
+    pub fn query_str(&mut self, query: &str) -> &mut Request {
+        if self.query_encoding == QueryEncoding::PreEncoded {
+            // add_str() would decode the pairs.
+            let query = query.trim_start_matches('?');
+            for pair in query.split('&').filter(|p| !p.is_empty()) {
+                let mut split = pair.splitn(2, '=');
+                let param = split.next().unwrap_or("");
+                self.query.add_pair((param, split.next().unwrap_or("")));
+            }
+        } else {
+            self.query.add_str(query);
+        }
         self
     }
 
-    /// Set IP version to use.
+    /// The query parameters added so far with [`.query()`](#method.query) and
+    /// friends, in order. Parameters in the url itself are not included.
     ///
     /// ```
-    /// let req = ureq::get("/my_page")
-    ///     .set_preferred_ip_version(IpVersion::V4)
-    ///     .call();
+    /// let req = ureq::get("https://cool.server/innit?foo=bar")
+    ///     .query("format", "json")
+    ///     .query("debug", "true")
+    ///     .build();
+    /// assert_eq!(req.query_pairs(), vec![("format", "json"), ("debug", "true")]);
+    /// ```
+    pub fn query_pairs(&self) -> Vec<(&str, &str)> {
+        self.query.to_pairs()
+    }
+
+    /// Remove all query parameters with a name, as added with
+    /// [`.query()`](#method.query) and friends. Parameters in the url itself are kept.
     ///
-    /// assert_eq!(IpVersion::V4, req.preferred_api_version);
     /// ```
-    pub fn set_preferred_ip_version(&mut self, ip_version: IpVersion) -> &mut Request {
-        self.preferred_ip_version = ip_version;
+    /// let req = ureq::get("https://cool.server/innit")
+    ///     .query("format", "json")
+    ///     .query("debug", "true")
+    ///     .remove_query("debug")
+    ///     .build();
+    /// assert_eq!(req.get_query().unwrap(), "?format=json");
+    /// ```
+    pub fn remove_query(&mut self, name: &str) -> &mut Request {
+        self.edit_query(|pairs| pairs.retain(|p| p.0 != name));
         self
     }
 
-    /// Returns the value for a set header.
+    /// Set a query parameter, removing those with the same name added before
+    /// with [`.query()`](#method.query) and friends, which always add. The
+    /// parameter goes last. Parameters in the url itself are kept.
     ///
     /// ```
-    /// let req = ureq::get("/my_page")
-    ///     .set("X-API-Key", "foobar")
+    /// let req = ureq::get("https://cool.server/innit")
+    ///     .query("page", "1")
+    ///     .query("format", "json")
+    ///     .query_replace("page", "2")
     ///     .build();
-    /// assert_eq!("foobar", req.header("x-api-Key").unwrap());
+    /// assert_eq!(req.get_query().unwrap(), "?format=json&page=2");
     /// ```
-    pub fn header<'a>(&self, name: &'a str) -> Option<&str> {
-        header::get_header(&self.headers, name)
+    pub fn query_replace(&mut self, name: &str, value: &str) -> &mut Request {
+        self.remove_query(name).query(name, value)
     }
 
-    /// A list of the set header names in this request. Lowercased to be uniform.
+    /// Sort the query parameters added with [`.query()`](#method.query) and friends
+    /// by name, for a stable order as needed by signatures and cache keys. The sort
+    /// is stable, parameters with the same name keep their order. Parameters in the
+    /// url itself are not sorted and go first.
     ///
     /// ```
-    /// let req = ureq::get("/my_page")
-    ///     .set("X-API-Key", "foobar")
-    ///     .set("Content-Type", "application/json")
+    /// let req = ureq::get("https://cool.server/innit")
+    ///     .query("b", "2")
+    ///     .query("a", "1")
+    ///     .sort_query()
     ///     .build();
-    /// assert_eq!(req.header_names(), vec!["x-api-key", "content-type"]);
+    /// assert_eq!(req.get_query().unwrap(), "?a=1&b=2");
     /// ```
-    pub fn header_names(&self) -> Vec<String> {
-        self.headers
-            .iter()
-            .map(|h| h.name().to_ascii_lowercase())
-            .collect()
+    pub fn sort_query(&mut self) -> &mut Request {
+        self.sort_query_by(|a, b| a.0.cmp(b.0))
     }
 
-    /// Tells if the header has been set.
+    /// Sort the query parameters added so far with a comparator of `(name, value)`
+    /// pairs, see [`.sort_query()`](#method.sort_query).
     ///
     /// ```
-    /// let req = ureq::get("/my_page")
-    ///     .set("X-API-Key", "foobar")
+    /// let req = ureq::get("https://cool.server/innit")
+    ///     .query("a", "1")
+    ///     .query("b", "2")
+    ///     .sort_query_by(|x, y| y.0.cmp(x.0))
     ///     .build();
-    /// assert_eq!(true, req.has("x-api-Key"));
+    /// assert_eq!(req.get_query().unwrap(), "?b=2&a=1");
     /// ```
-    pub fn has<'a>(&self, name: &'a str) -> bool {
-        header::has_header(&self.headers, name)
+    pub fn sort_query_by<F>(&mut self, mut compare: F) -> &mut Request
+    where
+        F: FnMut((&str, &str), (&str, &str)) -> Ordering,
+    {
+        self.edit_query(|pairs| pairs.sort_by(|a, b| compare((a.0, a.1), (b.0, b.1))));
+        self
     }
 
-    /// All headers corresponding values for the give name, or empty vector.
+    /// Change the list of added query parameters. Each is `(name, value, raw)`,
+    /// where `raw` is the rendered name of parameters without any value, like `?flag`.
+    fn edit_query<F>(&mut self, edit: F)
+    where
+        F: FnOnce(&mut Vec<(&str, &str, Option<&str>)>),
+    {
+        let rendered = self.query.to_string();
+        let mut pairs: Vec<_> = rendered
+            .split('&')
+            .zip(self.query.to_pairs())
+            .map(|(raw, (param, value))| (param, value, Some(raw).filter(|r| !r.contains('='))))
+            .collect();
+        edit(&mut pairs);
+        let mut query = QString::default();
+        for (param, value, raw) in pairs {
+            match raw {
+                Some(raw) => query.add_str(raw),
+                None => query.add_pair((param, value)),
+            }
+        }
+        self.query = query;
+    }
+
+    /// How query parameters are percent-encoded. Defaults to
+    /// [`QueryEncoding::Standard`](enum.QueryEncoding.html).
+    ///
+    /// With `QueryEncoding::PreEncoded`, call this before
+    /// [`.query_str()`](#method.query_str) since that otherwise decodes its input.
     ///
     /// ```
+    /// use ureq::QueryEncoding;
+    ///
     /// let req = ureq::get("/my_page")
-    ///     .set("X-Forwarded-For", "1.2.3.4")
-    ///     .set("X-Forwarded-For", "2.3.4.5")
+    ///     .query_encoding(QueryEncoding::Form)
+    ///     .query("q", "rust & http")
     ///     .build();
-    /// assert_eq!(req.all("x-forwarded-for"), vec![
-    ///     "1.2.3.4",
-    ///     "2.3.4.5",
-    /// ]);
+    /// assert_eq!(req.get_query().unwrap(), "?q=rust+%26+http");
+    ///
+    /// let req = ureq::get("/my_page")
+    ///     .query_encoding(QueryEncoding::PreEncoded)
+    ///     .query_str("ids=1%2C2")
+    ///     .build();
+    /// assert_eq!(req.get_query().unwrap(), "?ids=1%2C2");
     /// ```
-    pub fn all<'a>(&self, name: &'a str) -> Vec<&str> {
-        header::get_all_headers(&self.headers, name)
+    pub fn query_encoding(&mut self, encoding: QueryEncoding) -> &mut Request {
+        self.query_encoding = encoding;
+        self
     }
 
-    /// Set a query parameter.
+    /// Append a path segment to the url, percent-encoding everything but the
+    /// RFC 3986 unreserved characters so that `/`, `?` or `#` in the value
+    /// can't change the meaning of the url.
     ///
-    /// For example, to set `?format=json&dest=/login`
+    /// A `.` or `..`, also spelled `%2e`, would be a dot-segment that url
+    /// normalization removes along with the segment before it, so the request
+    /// fails with [`Error::BadUrl`](enum.Error.html#variant.BadUrl) instead.
     ///
     /// ```
-    /// let r = ureq::get("/my_page")
-    ///     .query("format", "json")
-    ///     .query("dest", "/login")
-    ///     .call();
+    /// let req = ureq::get("https://cool.server/users")
+    ///     .path_segment("martin/../admin?x")
+    ///     .path_segment("repos")
+    ///     .build();
+    /// assert_eq!(req.get_path().unwrap(), "/users/martin%2F..%2Fadmin%3Fx/repos");
+    /// ```
+    pub fn path_segment(&mut self, segment: &str) -> &mut Request {
+        if is_dot_segment(segment) {
+            self.dot_segment = Some(segment.to_string());
+            return self;
+        }
+        let end = self.path.find(['?', '#']).unwrap_or(self.path.len());
+        let slash = if self.path[..end].ends_with('/') {
+            ""
+        } else {
+            "/"
+        };
+        let segment = format!("{}{}", slash, unit::encode_unreserved(segment, false));
+        self.path.insert_str(end, &segment);
+        self.parsed_url = None;
+        self
+    }
+
+    /// Replace a `{name}` placeholder in the url with a percent-encoded value,
+    /// see [`.path_segment()`](#method.path_segment).
     ///
-    /// println!("{:?}", r);
     /// ```
-    pub fn query(&mut self, param: &str, value: &str) -> &mut Request {
-        self.query.add_pair((param, value));
+    /// let req = ureq::get("https://cool.server/users/{id}/repos")
+    ///     .path_param("id", "a b/c")
+    ///     .build();
+    /// assert_eq!(req.get_path().unwrap(), "/users/a%20b%2Fc/repos");
+    /// ```
+    pub fn path_param(&mut self, name: &str, value: &str) -> &mut Request {
+        if is_dot_segment(value) {
+            self.dot_segment = Some(value.to_string());
+            return self;
+        }
+        let placeholder = format!("{{{}}}", name);
+        self.path = self
+            .path
+            .replace(&placeholder, &unit::encode_unreserved(value, false));
+        self.parsed_url = None;
         self
     }
 
-    /// Set query parameters as a string.
+    /// Timeout for the DNS lookup of the host, separate from
+    /// [`timeout_connect()`](#method.timeout_connect), which only starts once
+    /// the host is resolved.
     ///
-    /// For example, to set `?format=json&dest=/login`
+    /// The default is `0`, which means waiting as long as the system resolver does,
+    /// often 30 seconds or more for an unresponsive DNS server.
     ///
     /// ```
     /// let r = ureq::get("/my_page")
-    ///     .query_str("?format=json&dest=/login")
+    ///     .timeout_resolve(2_000) // wait max 2 seconds for DNS
     ///     .call();
     /// println!("{:?}", r);
     /// ```
-    pub fn query_str(&mut self, query: &str) -> &mut Request {
-        self.query.add_str(query);
+    pub fn timeout_resolve(&mut self, millis: u64) -> &mut Request {
+        self.timeout_resolve = millis;
+        self
+    }
+
+    /// Like [`timeout_resolve()`](#method.timeout_resolve), with a `Duration`.
+    pub fn timeout_resolve_duration(&mut self, timeout: Duration) -> &mut Request {
+        self.timeout_resolve = duration_millis(timeout);
         self
     }
 
@@ -347,6 +1514,20 @@ impl Request {
         self
     }
 
+    /// Like [`timeout_connect()`](#method.timeout_connect), with a `Duration`.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let r = ureq::get("/my_page")
+    ///     .timeout_connect_duration(Duration::from_secs(1))
+    ///     .call();
+    /// ```
+    pub fn timeout_connect_duration(&mut self, timeout: Duration) -> &mut Request {
+        self.timeout_connect = duration_millis(timeout);
+        self
+    }
+
     /// Timeout for the individual reads of the socket.
     ///
     /// The default is `0`, which means it can block forever.
@@ -362,6 +1543,12 @@ impl Request {
         self
     }
 
+    /// Like [`timeout_read()`](#method.timeout_read), with a `Duration`.
+    pub fn timeout_read_duration(&mut self, timeout: Duration) -> &mut Request {
+        self.timeout_read = duration_millis(timeout);
+        self
+    }
+
     /// Timeout for the individual writes to the socket.
     ///
     /// The default is `0`, which means it can block forever.
@@ -377,6 +1564,30 @@ impl Request {
         self
     }
 
+    /// Like [`timeout_write()`](#method.timeout_write), with a `Duration`.
+    pub fn timeout_write_duration(&mut self, timeout: Duration) -> &mut Request {
+        self.timeout_write = duration_millis(timeout);
+        self
+    }
+
+    /// The largest chunk to send when the body is sent with
+    /// `Transfer-Encoding: chunked`. Smaller chunks are sent when the body
+    /// reader returns less at a time.
+    ///
+    /// The default is 8KB.
+    ///
+    /// ```
+    /// let r = ureq::post("/my_page")
+    ///     .set("Transfer-Encoding", "chunked")
+    ///     .chunk_size(64 * 1024)
+    ///     .send_string("Hello World!");
+    /// println!("{:?}", r);
+    /// ```
+    pub fn chunk_size(&mut self, size: usize) -> &mut Request {
+        self.chunk_size = Some(size);
+        self
+    }
+
     /// Basic auth.
     ///
     /// These are the same
@@ -428,6 +1639,175 @@ impl Request {
         self
     }
 
+    /// Follow redirects from an `https` url to a plain `http` one.
+    ///
+    /// Defaults to `false`, since such a redirect sends the headers and body
+    /// unencrypted. Instead of following it, the call fails with
+    /// [`Error::InsecureRedirect`](enum.Error.html#variant.InsecureRedirect).
+    ///
+    /// ```
+    /// let r = ureq::get("https://my-server.com/legacy")
+    ///     .allow_downgrade_redirects(true)
+    ///     .call();
+    /// println!("{:?}", r);
+    /// ```
+    pub fn allow_downgrade_redirects(&mut self, allow: bool) -> &mut Request {
+        self.allow_downgrade = allow;
+        self
+    }
+
+    /// Only follow redirects the policy allows, such as to a host of an
+    /// allowlist, for urls that are only partly trusted. Defaults to the
+    /// agent's policy. See [`RedirectPolicy`](struct.RedirectPolicy.html).
+    ///
+    /// ```
+    /// let r = ureq::get("https://my-server.com/files/1")
+    ///     .redirect_policy(ureq::RedirectPolicy::new().allow_host("cdn.my-server.com"))
+    ///     .call();
+    /// println!("{:?}", r);
+    /// ```
+    pub fn redirect_policy(&mut self, policy: RedirectPolicy) -> &mut Request {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Make [`Response::into_result()`](struct.Response.html#method.into_result)
+    /// and [`try_call()`](#method.try_call) give `4xx` and `5xx` responses as
+    /// [`Error::Status`](enum.Error.html#variant.Status). Defaults to the agent's setting.
+    ///
+    /// ```
+    /// let r = ureq::get("/my_page")
+    ///     .error_on_status(true)
+    ///     .call()
+    ///     .into_result();
+    /// println!("{:?}", r);
+    /// ```
+    pub fn error_on_status(&mut self, enabled: bool) -> &mut Request {
+        self.error_on_status = enabled;
+        self
+    }
+
+    /// Print the exchange to stderr like `curl -v`: `*` lines about the
+    /// connection, `>` lines with the request line and headers sent, and `<`
+    /// lines with the status line and headers received, also of redirects and
+    /// interim responses. Bodies are not printed, and the values of
+    /// `Authorization`, `Proxy-Authorization` and `Cookie` headers are shown as
+    /// `***`. Defaults to the agent's setting.
+    ///
+    /// ```
+    /// let r = ureq::get("/my_page")
+    ///     .verbose(true)
+    ///     .call();
+    /// ```
+    pub fn verbose(&mut self, enabled: bool) -> &mut Request {
+        self.verbose = enabled;
+        self
+    }
+
+    /// Absolute urls, such as of mirrors, tried in order when the request fails
+    /// to connect, which is a failed DNS lookup, a refused or timed out connection
+    /// or a failed TLS handshake. The query and headers of the request go with
+    /// each. A body read from a reader can't be sent again, so isn't tried
+    /// elsewhere.
+    ///
+    /// ```
+    /// let r = ureq::get("https://mirror-a.example.com/pkg.tar.gz")
+    ///     .fallback_urls(&[
+    ///         "https://mirror-b.example.com/pkg.tar.gz",
+    ///         "https://mirror-c.example.com/pkg.tar.gz",
+    ///     ])
+    ///     .fallback_on_status(&[404, 502, 503])
+    ///     .call();
+    /// ```
+    pub fn fallback_urls(&mut self, urls: &[&str]) -> &mut Request {
+        self.fallback_urls = urls.iter().map(|u| u.to_string()).collect();
+        self
+    }
+
+    /// Also try the next of the [`fallback_urls()`](#method.fallback_urls) on
+    /// responses with these statuses.
+    pub fn fallback_on_status(&mut self, statuses: &[u16]) -> &mut Request {
+        self.fallback_statuses = statuses.to_vec();
+        self
+    }
+
+    /// Whether the response is a failure to try the next fallback url for.
+    fn falls_back(&self, resp: &Response) -> bool {
+        match resp.synthetic_error() {
+            Some(err) => matches!(
+                err,
+                Error::DnsFailed(_)
+                    | Error::ConnectionFailed(_)
+                    | Error::ConnectionRefused(_)
+                    | Error::ConnectTimeout(_)
+                    | Error::Tls(_)
+            ),
+            None => self.fallback_statuses.contains(&resp.status()),
+        }
+    }
+
+    /// Tolerate response heads that break the HTTP spec in ways seen from real
+    /// servers: header values folded onto lines starting with a space, status
+    /// lines without a reason phrase or with extra spaces, lines ending in a
+    /// lone `\n`, empty lines before the status line and the status line sent
+    /// twice. Off by default, and defaults to the agent's setting.
+    ///
+    /// [`Response::raw_head()`](struct.Response.html#method.raw_head) still
+    /// gives the head as it was received.
+    ///
+    /// ```
+    /// let r = ureq::get("http://legacy-device.local/status")
+    ///     .lenient_parsing(true)
+    ///     .call();
+    /// println!("{:?}", r);
+    /// ```
+    pub fn lenient_parsing(&mut self, enabled: bool) -> &mut Request {
+        self.lenient_parsing = enabled;
+        self
+    }
+
+    /// Send the request over HTTP/3 if the url is `https`, without falling back
+    /// to TCP. Defaults to the agent's setting, see
+    /// [`Agent::http3()`](struct.Agent.html#method.http3).
+    ///
+    /// ```
+    /// let r = ureq::get("https://cloudflare-quic.com/")
+    ///     .http3_only(true)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "http3")]
+    pub fn http3_only(&mut self, enabled: bool) -> &mut Request {
+        self.http3_only = enabled;
+        self
+    }
+
+    /// Verify the digest of the response body as it is read. If the body
+    /// doesn't match `expected_hex`, the read of its end fails with an
+    /// `std::io::Error` of kind `InvalidData` holding an
+    /// [`Error::ChecksumMismatch`](enum.Error.html#variant.ChecksumMismatch).
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["checksum"] }`
+    ///
+    /// ```
+    /// use ureq::Algorithm;
+    ///
+    /// let r = ureq::get("/my_file.tar.gz")
+    ///     .verify_checksum(
+    ///         Algorithm::Sha256,
+    ///         "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+    ///     )
+    ///     .call();
+    /// println!("{:?}", r);
+    /// ```
+    #[cfg(feature = "checksum")]
+    pub fn verify_checksum(&mut self, algorithm: Algorithm, expected_hex: &str) -> &mut Request {
+        self.checksum = Some(Checksum {
+            algorithm,
+            expected: expected_hex.to_ascii_lowercase(),
+        });
+        self
+    }
+
     // pub fn retry(&self, times: u16) -> Request {
     //     unimplemented!()
     // }
@@ -468,8 +1848,9 @@ impl Request {
 
     /// Get the url this request was created with.
     ///
-    /// This value is not normalized, it is exactly as set.
-    /// It does not contain any added query parameters.
+    /// This value is not normalized, it is exactly as set, so it still holds
+    /// any `user:pass@` of the url. It does not contain any added query
+    /// parameters.
     ///
     /// Example:
     /// ```
@@ -481,6 +1862,90 @@ impl Request {
         &self.path
     }
 
+    /// The absolute url this request will be sent to, including query parameters
+    /// added with [`.query()`](#method.query). Credentials of the url are left
+    /// out, they are only sent in the `Authorization` header.
+    ///
+    /// Example:
+    /// ```
+    /// let req = ureq::get("/innit?foo=bar")
+    ///     .query("format", "json")
+    ///     .build();
+    /// assert_eq!(req.get_url_resolved().unwrap(), "http://localhost/innit?foo=bar&format=json");
+    /// ```
+    pub fn get_url_resolved(&self) -> Result<String, Error> {
+        self.to_url().map(|u| self.with_query(unit::redacted(&u)))
+    }
+
+    /// The url with the query parameters of the request and without fragment.
+    fn with_query(&self, mut url: Url) -> String {
+        let query = unit::combine_query(&url, &self.query, self.query_encoding, true);
+        url.set_query(None);
+        url.set_fragment(None);
+        format!("{}{}", url, query)
+    }
+
+    /// An equivalent `curl` command line, handy for bug reports.
+    ///
+    /// With `redact` set, the values of `Authorization`, `Proxy-Authorization` and
+    /// `Cookie` headers, as well as any password in the url, are replaced by `***`.
+    /// The body isn't known until the request is sent, so methods that usually carry
+    /// one get a `--data-binary @-` placeholder reading from stdin.
+    ///
+    /// Example:
+    /// ```
+    /// let req = ureq::post("https://cool.server/innit")
+    ///     .set("Authorization", "Bearer secret")
+    ///     .set("Content-Type", "application/json")
+    ///     .build();
+    /// assert_eq!(
+    ///     req.to_curl(true),
+    ///     "curl -X POST 'https://cool.server/innit' -H 'Authorization: ***' \
+    ///      -H 'Content-Type: application/json' --data-binary @-"
+    /// );
+    /// ```
+    pub fn to_curl(&self, redact: bool) -> String {
+        fn quote(s: &str) -> String {
+            format!("'{}'", s.replace('\'', "'\\''"))
+        }
+        let mut url = self
+            .to_url()
+            .map(|u| self.with_query(u))
+            .unwrap_or_else(|_| self.path.clone());
+        if redact {
+            if let Ok(mut u) = Url::parse(&url) {
+                if u.password().is_some() {
+                    u.set_password(Some("***")).ok();
+                    url = u.to_string();
+                }
+            }
+        }
+        let mut cmd = match &self.method[..] {
+            "GET" => "curl".to_string(),
+            "HEAD" => "curl --head".to_string(),
+            m => format!("curl -X {}", m),
+        };
+        cmd.push_str(&format!(" {}", quote(&url)));
+        for h in self.headers.iter() {
+            let sensitive = ["authorization", "proxy-authorization", "cookie"]
+                .iter()
+                .any(|n| h.is_name(n));
+            let value = if redact && sensitive {
+                "***"
+            } else {
+                h.value()
+            };
+            cmd.push_str(&format!(
+                " -H {}",
+                quote(&format!("{}: {}", h.name(), value))
+            ));
+        }
+        if ["POST", "PUT", "PATCH"].contains(&&self.method[..]) {
+            cmd.push_str(" --data-binary @-");
+        }
+        cmd
+    }
+
     /// Normalizes and returns the host that will be used for this request.
     ///
     /// Example:
@@ -521,7 +1986,7 @@ impl Request {
     /// ```
     pub fn get_query(&self) -> Result<String, Error> {
         self.to_url()
-            .map(|u| unit::combine_query(&u, &self.query, true))
+            .map(|u| unit::combine_query(&u, &self.query, self.query_encoding, true))
     }
 
     /// The normalized path of this request.
@@ -536,9 +2001,145 @@ impl Request {
         self.to_url().map(|u| u.path().to_string())
     }
 
-    fn to_url(&self) -> Result<Url, Error> {
+    pub(crate) fn to_url(&self) -> Result<Url, Error> {
+        if let Some(segment) = &self.dot_segment {
+            return Err(Error::BadUrl(format!("Dot-segment in path: {}", segment)));
+        }
+        if let Some(url) = &self.parsed_url {
+            return Ok(url.clone());
+        }
+        let path = match split_zone(&self.path) {
+            Some((path, _)) => path,
+            None => self.path.clone(),
+        };
+        if self.absolute_urls {
+            return Url::parse(&path).map_err(|e| match e {
+                url::ParseError::RelativeUrlWithoutBase => {
+                    Error::BadUrl(format!("Not an absolute url: {}", path))
+                }
+                e => Error::BadUrl(format!("{}", e)),
+            });
+        }
         URL_BASE
-            .join(&self.path)
+            .join(&path)
             .map_err(|e| Error::BadUrl(format!("{}", e)))
     }
+
+    /// The zone id of an IPv6 link-local host, like `eth0` in `http://[fe80::1%eth0]/`.
+    pub(crate) fn zone(&self) -> Option<String> {
+        split_zone(&self.path).map(|(_, zone)| zone)
+    }
+}
+
+/// A timeout in millis, rounded up so that a short timeout doesn't become `0`,
+/// which means none.
+pub(crate) fn duration_millis(timeout: Duration) -> u64 {
+    let millis = timeout.as_nanos().div_ceil(1_000_000);
+    millis.min(u128::from(u64::MAX)) as u64
+}
+
+/// A `Content-Type` with `charset` as its only charset parameter, `text/plain`
+/// when there is none.
+#[cfg(feature = "charset")]
+fn with_charset(content_type: Option<&str>, charset: &str) -> String {
+    let mut parts = content_type.unwrap_or("text/plain").split(';');
+    let media_type = parts.next().unwrap_or("").trim();
+    let media_type = if media_type.is_empty() {
+        "text/plain"
+    } else {
+        media_type
+    };
+    let mut value = media_type.to_string();
+    for param in parts.map(str::trim).filter(|p| !p.is_empty()) {
+        let name = param.split('=').next().unwrap_or("").trim();
+        if !name.eq_ignore_ascii_case("charset") {
+            value.push_str("; ");
+            value.push_str(param);
+        }
+    }
+    value.push_str("; charset=");
+    value.push_str(charset);
+    value
+}
+
+/// Split the zone id off an IPv6 host literal, which the url crate can't parse.
+/// Takes both `[fe80::1%eth0]` and the RFC 6874 `[fe80::1%25eth0]`.
+/// Whether url normalization takes the path segment for `.` or `..`.
+fn is_dot_segment(segment: &str) -> bool {
+    let segment = segment.to_ascii_lowercase().replace("%2e", ".");
+    segment == "." || segment == ".."
+}
+
+fn split_zone(url: &str) -> Option<(String, String)> {
+    let start = url.find("://")? + 3;
+    let end = url[start..]
+        .find(['/', '?', '#'])
+        .map(|i| start + i)
+        .unwrap_or(url.len());
+    let authority = &url[start..end];
+    let open = authority.find('[')?;
+    let close = authority.find(']')?;
+    let percent = authority[open..close].find('%')? + open;
+
+    let zone = &authority[percent + 1..close];
+    let zone = match zone.strip_prefix("25") {
+        Some(rest) if !rest.is_empty() => rest,
+        _ => zone,
+    };
+    let url = format!(
+        "{}{}{}",
+        &url[..start + percent],
+        &authority[close..],
+        &url[end..]
+    );
+    Some((url, zone.to_string()))
+}
+
+/// Call the error hook if the response is a failure, and give the response back.
+fn notify_error(req: &Request, hook: &ErrorHook, resp: Response) -> Response {
+    if let Some(err) = resp.synthetic_error() {
+        (hook.0)(req, err);
+        return resp;
+    }
+    if req.error_on_status && resp.error() {
+        // lend the response to the hook as the error into_result() would give.
+        let err = Error::Status(resp.status(), Box::new(resp));
+        (hook.0)(req, &err);
+        return match err {
+            Error::Status(_, resp) => *resp,
+            _ => unreachable!(),
+        };
+    }
+    resp
+}
+
+/// The media type for a file extension, `application/octet-stream` if unknown.
+pub(crate) fn mime_from_extension(ext: &str) -> &'static str {
+    match &ext.to_ascii_lowercase()[..] {
+        "txt" => "text/plain",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "wasm" => "application/wasm",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/vnd.microsoft.icon",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
 }
@@ -0,0 +1,58 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use crate::time;
+
+type IdFn = dyn Fn() -> String + Send + Sync;
+
+/// Header name and generator for request ids, see
+/// [`Agent::request_id()`](struct.Agent.html#method.request_id).
+///
+/// *Internal API*
+#[derive(Clone)]
+pub(crate) struct RequestIdConfig {
+    pub header: String,
+    pub generate: Arc<IdFn>,
+}
+
+impl ::std::fmt::Debug for RequestIdConfig {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "RequestIdConfig({})", self.header)
+    }
+}
+
+/// A random (version 4) UUID such as `1b4e28ba-2fa1-41d2-883f-0016d3cca427`.
+///
+/// The randomness comes from the std hasher keys, which is plenty for
+/// correlating logs but not meant for anything security related.
+pub(crate) fn uuid_v4() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = time::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+
+    let mut bytes = [0_u8; 16];
+    for chunk in bytes.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(nanos);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
@@ -1,21 +1,61 @@
-use std::io::{Cursor, Error as IoError, ErrorKind, Read, Result as IoResult};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+#[cfg(feature = "json")]
+use std::io::{BufRead, BufReader, Lines};
+#[cfg(feature = "json")]
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 use chunked_transfer::Decoder as ChunkDecoder;
-
+#[cfg(feature = "cookie")]
+use cookie::Cookie;
+
+use crate::byteranges::ByteRanges;
+use crate::cache_control::CacheControl;
+#[cfg(feature = "checksum")]
+use crate::checksum::{Checksum, VerifyRead};
+use crate::content_type::ContentType;
+use crate::date::parse_http_date;
+use crate::decode;
 use crate::error::Error;
-use crate::header::Header;
-use crate::pool::PoolReturnRead;
+#[cfg(feature = "json")]
+use crate::graphql::{self, GraphqlResponse};
+use crate::header::{self, Header};
+#[cfg(feature = "json")]
+use crate::json;
+use crate::link::{self, Link};
+use crate::metrics::{ByteCounts, ExchangeStats};
+use crate::pool::{BodyLeft, PoolReturnRead};
+use crate::request_id;
+use crate::sse::Events;
 use crate::stream::Stream;
 use crate::unit::Unit;
 
-#[cfg(feature = "json")]
-use serde_json;
-
 #[cfg(feature = "charset")]
 use encoding::label::encoding_from_whatwg_label;
 #[cfg(feature = "charset")]
-use encoding::DecoderTrap;
+use encoding::{DecoderTrap, EncoderTrap, EncodingRef};
+
+type EarlyHintsFn = dyn Fn(&[Header]) + Send + Sync;
+
+/// Callback given the headers of `103 Early Hints` responses, see
+/// [`Agent::on_early_hints()`](struct.Agent.html#method.on_early_hints).
+///
+/// *Internal API*
+#[derive(Clone)]
+pub(crate) struct EarlyHintsHook(pub Arc<EarlyHintsFn>);
+
+impl ::std::fmt::Debug for EarlyHintsHook {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "EarlyHintsHook")
+    }
+}
 
 pub const DEFAULT_CONTENT_TYPE: &str = "text/plain";
 pub const DEFAULT_CHARACTER_SET: &str = "utf-8";
@@ -36,15 +76,46 @@ pub const DEFAULT_CHARACTER_SET: &str = "utf-8";
 ///
 /// // response is consumed, and body has been read.
 /// ```
+///
+/// A response is also a `Read` of the body, like
+/// [`into_reader()`](#method.into_reader), so it can be read while its headers
+/// are still at hand.
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// let mut resp = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"
+///     .parse::<ureq::Response>()
+///     .unwrap();
+///
+/// let mut body = vec![];
+/// std::io::copy(&mut resp, &mut body)?;
+/// assert_eq!(body, b"hello");
+/// assert_eq!(resp.header("Content-Length"), Some("5"));
+/// # Ok(())
+/// # }
+/// ```
 pub struct Response {
     url: Option<String>,
     error: Option<Error>,
     status_line: String,
+    /// The head as it was read, up to and including the empty line.
+    raw_head: Vec<u8>,
     index: ResponseStatusIndex,
     status: u16,
-    headers: Vec<Header>,
+    pub(crate) headers: Vec<Header>,
     unit: Option<Unit>,
     stream: Option<Stream>,
+    pub(crate) stats: ExchangeStats,
+    /// Bytes of the whole exchange, shared with the body reader.
+    bytes: Arc<ByteCounts>,
+    pub(crate) request_id: Option<String>,
+    pub(crate) error_on_status: bool,
+    // behind a mutex to keep Response (and Error::Status) Sync.
+    tee: Mutex<Vec<Box<dyn Write + Send>>>,
+    /// The body once reading the response itself started it.
+    body: Mutex<Option<BodyRead>>,
+    #[cfg(feature = "checksum")]
+    pub(crate) checksum: Option<Checksum>,
 }
 
 /// index into status_line where we split: HTTP/1.1 200 OK
@@ -90,11 +161,76 @@ impl Response {
         self.url.as_ref().map(|s| &s[..]).unwrap_or("")
     }
 
+    /// Bytes written for the request, heads and bodies, including those of the
+    /// redirects followed and retries made to get this response.
+    ///
+    /// ```
+    /// let mut resp = ureq::get("http://localhost/my_page").call();
+    /// std::io::copy(&mut resp, &mut std::io::sink()).ok();
+    ///
+    /// println!("sent {} bytes, got {}", resp.bytes_written(), resp.bytes_read());
+    /// ```
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes.written()
+    }
+
+    /// Bytes read for the response heads, including those of redirects and
+    /// interim responses, and for the body as far as it has been read through
+    /// this response. The body bytes are counted as received, with any chunked
+    /// framing and before decompression.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes.read()
+    }
+
+    /// The id sent with the request when the agent is configured with
+    /// [`Agent::request_id()`](struct.Agent.html#method.request_id). Also set
+    /// for synthetic error responses.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
     /// The entire status line like: `HTTP/1.1 200 OK`
     pub fn status_line(&self) -> &str {
         self.status_line.as_str()
     }
 
+    /// The head of the response exactly as the server sent it: the status line
+    /// and the header lines, each ending with `\r\n`, then the empty line.
+    ///
+    /// Unlike [`header()`](#method.header) and [`all()`](#method.all), this keeps
+    /// the case, order and duplicates of the headers, as well as lines that
+    /// couldn't be parsed as headers.
+    ///
+    /// ```
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     x-dup: 1\r\n\
+    ///     X-Dup:  2\r\n\
+    ///     not a header\r\n\
+    ///     \r\n";
+    /// let resp = s.parse::<ureq::Response>().unwrap();
+    ///
+    /// assert_eq!(resp.raw_head(), s.as_bytes());
+    /// assert_eq!(resp.all("x-dup"), vec!["1", "2"]);
+    /// ```
+    pub fn raw_head(&self) -> &[u8] {
+        &self.raw_head
+    }
+
+    /// The address the response came from, which is the one of the resolved
+    /// addresses of the host that accepted the connection, or the proxy's.
+    /// `None` once the body is being read, and for responses not from a socket.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.stream.as_ref()?.peer_addr()
+    }
+
+    /// The protocol the server picked by ALPN in the TLS handshake, like `http/1.1`,
+    /// if any was offered with
+    /// [`Agent::alpn_protocols()`](struct.Agent.html#method.alpn_protocols).
+    pub fn alpn_protocol(&self) -> Option<&str> {
+        let protocol = self.stream.as_ref()?.alpn_protocol()?;
+        std::str::from_utf8(protocol).ok()
+    }
+
     /// The http version: `HTTP/1.1`
     pub fn http_version(&self) -> &str {
         &self.status_line.as_str()[0..self.index.http_version]
@@ -107,11 +243,11 @@ impl Response {
 
     /// The status text: `OK`
     pub fn status_text(&self) -> &str {
-        &self.status_line.as_str()[self.index.response_code + 1..].trim()
+        self.status_line.as_str()[self.index.response_code + 1..].trim()
     }
 
     /// The header corresponding header value for the give name, if any.
-    pub fn header<'a>(&self, name: &'a str) -> Option<&str> {
+    pub fn header(&self, name: &str) -> Option<&str> {
         self.headers
             .iter()
             .find(|h| h.is_name(name))
@@ -119,7 +255,8 @@ impl Response {
     }
 
     /// A list of the header names in this response.
-    /// Lowercased to be uniform.
+    /// Lowercased to be uniform, see [`headers_raw()`](#method.headers_raw)
+    /// for the names as received.
     pub fn headers_names(&self) -> Vec<String> {
         self.headers
             .iter()
@@ -127,13 +264,85 @@ impl Response {
             .collect()
     }
 
+    /// All headers as name and value pairs, in the order they were received.
+    /// Names are as the server sent them, and repeated headers are all kept.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nX-Id: 7\r\nSet-Cookie: b=2\r\n\r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     resp.headers(),
+    ///     vec![("Set-Cookie", "a=1"), ("X-Id", "7"), ("Set-Cookie", "b=2")]
+    /// );
+    /// ```
+    pub fn headers(&self) -> Vec<(&str, &str)> {
+        self.headers.iter().map(|h| (h.name(), h.value())).collect()
+    }
+
+    /// The headers as received, in order, with the names cased as the server
+    /// sent them, to forward them to systems that care about the casing.
+    /// Lookups like [`header()`](#method.header) still ignore the case.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\nx-REQUEST-id: 7\r\nETag: \"a\"\r\n\r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    /// let names: Vec<_> = resp.headers_raw().iter().map(|h| h.name()).collect();
+    /// assert_eq!(names, vec!["x-REQUEST-id", "ETag"]);
+    /// assert_eq!(resp.header("X-Request-Id"), Some("7"));
+    /// ```
+    pub fn headers_raw(&self) -> &[Header] {
+        &self.headers
+    }
+
     /// Tells if the response has the named header.
-    pub fn has<'a>(&self, name: &'a str) -> bool {
+    pub fn has(&self, name: &str) -> bool {
         self.header(name).is_some()
     }
 
+    /// The directives of all `Cache-Control` headers, `None` without one.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\nCache-Control: private\r\nCache-Control: no-cache=\"Set-Cookie\"\r\n\r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    /// let cc = resp.cache_control().unwrap();
+    /// assert!(cc.private());
+    /// assert_eq!(cc.get("no-cache"), Some("Set-Cookie"));
+    /// assert_eq!(cc.max_age(), None);
+    /// ```
+    pub fn cache_control(&self) -> Option<CacheControl> {
+        let values = self.all("cache-control");
+        if values.is_empty() {
+            return None;
+        }
+        let mut cc = CacheControl::default();
+        for value in values {
+            cc.add(value);
+        }
+        Some(cc)
+    }
+
+    /// The items of all the named headers as one comma separated list, for
+    /// headers like `Vary`, `Connection` or `Content-Encoding`. See
+    /// [`Header::values()`](struct.Header.html#method.values).
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\nVary: Accept-Encoding, Origin\r\nVary: Cookie\r\n\r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    /// assert_eq!(resp.header_list("vary"), vec!["Accept-Encoding", "Origin", "Cookie"]);
+    /// ```
+    pub fn header_list(&self, name: &str) -> Vec<&str> {
+        self.all(name)
+            .into_iter()
+            .flat_map(header::split_list)
+            .collect()
+    }
+
     /// All headers corresponding values for the give name, or empty vector.
-    pub fn all<'a>(&self, name: &'a str) -> Vec<&str> {
+    pub fn all(&self, name: &str) -> Vec<&str> {
         self.headers
             .iter()
             .filter(|h| h.is_name(name))
@@ -141,6 +350,95 @@ impl Response {
             .collect()
     }
 
+    /// The cookies of all `Set-Cookie` headers, in the order they were received.
+    /// Headers that don't parse as a cookie are skipped.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\nSet-Cookie: session=abc; HttpOnly; Max-Age=60\r\n\r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    /// let cookies = resp.cookies();
+    /// assert_eq!(cookies[0].name(), "session");
+    /// assert_eq!(cookies[0].value(), "abc");
+    /// assert_eq!(cookies[0].http_only(), Some(true));
+    /// ```
+    #[cfg(feature = "cookie")]
+    pub fn cookies(&self) -> Vec<Cookie<'static>> {
+        self.all("set-cookie")
+            .into_iter()
+            .filter_map(|raw| Cookie::parse_encoded(raw.to_string()).ok())
+            .collect()
+    }
+
+    /// The `Date` header as a time, if present and a valid HTTP date.
+    pub fn date(&self) -> Option<SystemTime> {
+        self.header("date").and_then(parse_http_date)
+    }
+
+    /// The `Expires` header as a time. Invalid dates, such as `0`, mean already
+    /// expired, but are returned as `None` here.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.header("expires").and_then(parse_http_date)
+    }
+
+    /// The `Last-Modified` header as a time.
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     Last-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\n\
+    ///     \r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// let time = UNIX_EPOCH + Duration::from_secs(784111777);
+    /// assert_eq!(resp.last_modified(), Some(time));
+    /// ```
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        self.header("last-modified").and_then(parse_http_date)
+    }
+
+    /// The file name from the `Content-Disposition` header, if any. The RFC 5987
+    /// `filename*` form (UTF-8 or ISO-8859-1) is preferred over `filename`.
+    ///
+    /// Directory parts are stripped, so the name can't point outside the
+    /// directory it's saved in.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Disposition: attachment; filename=\"EURO rates.txt\"; \
+    ///         filename*=UTF-8''%e2%82%ac%20rates.txt\r\n\
+    ///     \r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(resp.attachment_filename().unwrap(), "€ rates.txt");
+    /// ```
+    pub fn attachment_filename(&self) -> Option<String> {
+        self.header("content-disposition")
+            .and_then(header::disposition_filename)
+    }
+
+    /// The RFC 8288 `Link` headers, keyed by relation type such as `next`,
+    /// `prev` or `last`. Relative urls are resolved against the response url.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     Link: <https://api.example.com/items?page=2>; rel=\"next\", \
+    ///           <https://api.example.com/items?page=5>; rel=\"last\"\r\n\
+    ///     \r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// let links = resp.links();
+    /// assert_eq!(links["next"].url(), "https://api.example.com/items?page=2");
+    /// assert_eq!(links["last"].url(), "https://api.example.com/items?page=5");
+    /// ```
+    pub fn links(&self) -> HashMap<String, Link> {
+        link::links(self)
+    }
+
     /// Whether the response status is: 200 <= status <= 299
     pub fn ok(&self) -> bool {
         self.status >= 200 && self.status <= 299
@@ -205,8 +503,35 @@ impl Response {
 
     /// Get the actual underlying error when the response is
     /// ["synthetic"](struct.Response.html#method.synthetic).
-    pub fn synthetic_error(&self) -> &Option<Error> {
-        &self.error
+    ///
+    /// ```
+    /// let resp = ureq::get("http://[bad").call();
+    ///
+    /// assert!(resp.synthetic());
+    /// assert!(matches!(resp.synthetic_error(), Some(ureq::Error::BadUrl(_))));
+    /// ```
+    pub fn synthetic_error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    /// Turn the response into a `Result`. A synthetic response gives its
+    /// [`synthetic_error()`](#method.synthetic_error), and when the request was made
+    /// with [`error_on_status()`](struct.Request.html#method.error_on_status), a
+    /// `4xx` or `5xx` response gives [`Error::Status`](enum.Error.html#variant.Status).
+    ///
+    /// ```
+    /// let resp = ureq::get("http://[bad").call();
+    ///
+    /// assert!(matches!(resp.into_result(), Err(ureq::Error::BadUrl(_))));
+    /// ```
+    pub fn into_result(mut self) -> Result<Response, Error> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if self.error_on_status && self.error() {
+            return Err(Error::Status(self.status, Box::new(self)));
+        }
+        Ok(self)
     }
 
     /// The content type part of the "Content-Type" header without
@@ -230,6 +555,44 @@ impl Response {
             .unwrap_or(DEFAULT_CONTENT_TYPE)
     }
 
+    /// The "Content-Type" header with its parameters, `None` without one or
+    /// when it isn't a `type/subtype`.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\nContent-Type: Text/HTML; Charset=\"utf-8\"\r\n\r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    /// let content_type = resp.content_type_parsed().unwrap();
+    /// assert_eq!(content_type.essence(), "text/html");
+    /// assert_eq!(content_type.charset(), Some("utf-8"));
+    /// ```
+    pub fn content_type_parsed(&self) -> Option<ContentType> {
+        self.header("content-type")?.parse().ok()
+    }
+
+    /// Whether the "Content-Type" header is of a media type, ignoring case and
+    /// parameters like the charset. The pattern can end in a `*` wildcard, like
+    /// `text/*` or `*/*`. Without the header, nothing matches.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\nContent-Type: Application/JSON; charset=utf-8\r\n\r\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    /// assert!(resp.content_type_matches("application/json"));
+    /// assert!(resp.content_type_matches("application/*"));
+    /// assert!(!resp.content_type_matches("text/plain"));
+    /// ```
+    pub fn content_type_matches(&self, pattern: &str) -> bool {
+        let content_type = match self.header("content-type") {
+            Some(header) => header.split(';').next().unwrap_or("").trim(),
+            None => return false,
+        };
+        let (kind, subtype) = split_media_type(content_type);
+        let (want_kind, want_subtype) = split_media_type(pattern.trim());
+        let matches = |want: &str, got: &str| want == "*" || want.eq_ignore_ascii_case(got);
+        matches(want_kind, kind) && matches(want_subtype, subtype)
+    }
+
     /// The character set part of the "Content-Type" header.native_tls
     ///
     /// Example:
@@ -243,6 +606,78 @@ impl Response {
         charset_from_content_type(self.header("content-type"))
     }
 
+    /// The length of the body, `Some(0)` for responses without a body and `None`
+    /// when the length is not known up front, as for `Transfer-Encoding: chunked`.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    /// assert_eq!(resp.content_length(), Some(5));
+    ///
+    /// let resp = ureq::Response::new(304, "Not Modified", "");
+    /// assert!(!resp.has_body());
+    /// assert_eq!(resp.content_length(), Some(0));
+    /// ```
+    pub fn content_length(&self) -> Option<u64> {
+        if !self.has_body() {
+            return Some(0);
+        }
+        let is_http10 = self.http_version().eq_ignore_ascii_case("HTTP/1.0");
+        let is_chunked = self
+            .header("transfer-encoding")
+            .map(|enc| !enc.is_empty())
+            .unwrap_or(false);
+        if is_chunked && !is_http10 {
+            return None;
+        }
+        self.header("content-length")
+            .and_then(|l| l.trim().parse::<u64>().ok())
+    }
+
+    /// Whether the response can have a body. Responses to `HEAD` requests and
+    /// `1xx`, `204 No Content` and `304 Not Modified` responses never have one,
+    /// whatever their headers say.
+    pub fn has_body(&self) -> bool {
+        let is_head = self.unit.as_ref().map(|u| u.is_head()).unwrap_or(false);
+        let status = self.status();
+        !(is_head || status / 100 == 1 || status == 204 || status == 304)
+    }
+
+    /// Look at up to the first `n` bytes of the body, or fewer if the body is
+    /// shorter, without consuming them. They are still read by
+    /// [`into_string()`](#method.into_string), [`into_reader()`](#method.into_reader)
+    /// and the other ways of reading the body. Useful to sniff what the body is
+    /// before deciding how to read it.
+    ///
+    /// The bytes are those `into_reader()` gives, after any `Content-Encoding`
+    /// is decoded. Peeking again past the bytes already peeked at reads more.
+    ///
+    /// ```
+    /// let s = "HTTP/1.1 500 Internal Server Error\r\n\
+    ///     Content-Type: application/octet-stream\r\n\
+    ///     \r\n\
+    ///     <html>Oops</html>";
+    /// let mut resp = s.parse::<ureq::Response>().unwrap();
+    ///
+    /// assert_eq!(resp.peek(6).unwrap(), b"<html>");
+    /// assert_eq!(resp.into_string().unwrap(), "<html>Oops</html>");
+    /// ```
+    pub fn peek(&mut self, n: usize) -> IoResult<&[u8]> {
+        self.started_body().peek(n)
+    }
+
+    /// The body reader kept in the response, started if need be.
+    fn started_body(&mut self) -> &mut BodyRead {
+        if self.body.get_mut().unwrap().is_none() {
+            let reader = self.body_reader();
+            *self.body.get_mut().unwrap() = Some(BodyRead::new(reader));
+        }
+        self.body.get_mut().unwrap().as_mut().unwrap()
+    }
+
     /// Turn this response into a `impl Read` of the body.
     ///
     /// 1. If `Transfer-Encoding: chunked`, the returned reader will unchunk it
@@ -250,6 +685,10 @@ impl Response {
     /// 2. If `Content-Length` is set, the returned reader is limited to this byte
     ///    length regardless of how many bytes the server sends.
     /// 3. If no length header, the reader is until server stream end.
+    /// 4. A `Content-Encoding` with a decoder enabled by a feature is decoded,
+    ///    unless the request was made with
+    ///    [`no_decompress()`](struct.Request.html#method.no_decompress) or set
+    ///    its own `Accept-Encoding` without it.
     ///
     /// Example:
     ///
@@ -270,48 +709,286 @@ impl Response {
     ///
     /// assert_eq!(bytes.len(), len);
     /// ```
-    pub fn into_reader(self) -> impl Read {
+    pub fn into_reader(mut self) -> impl Read {
+        self.body_reader()
+    }
+
+    /// Turn this response into a `impl Read` of exactly the body bytes the server
+    /// sent, with `Transfer-Encoding: chunked` undone and limited to `Content-Length`
+    /// like [`into_reader()`](#method.into_reader), but with no other transformation.
+    ///
+    /// Unlike `into_reader()`, this is guaranteed to never decode a charset or
+    /// a `Content-Encoding`, so it is the one to use for binary protocols. The
+    /// exception is a body already started by reading the response itself,
+    /// which is continued as it is.
+    ///
+    /// ```
+    /// use std::io::Read;
+    ///
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Transfer-Encoding: chunked\r\n\
+    ///     Content-Type: text/plain; charset=iso-8859-1\r\n\
+    ///     \r\n\
+    ///     3\r\n\x00\x01\x02\r\n0\r\n\r\n";
+    /// let resp = s.parse::<ureq::Response>().unwrap();
+    ///
+    /// let mut bytes = vec![];
+    /// resp.into_raw_reader().read_to_end(&mut bytes).unwrap();
+    /// assert_eq!(bytes, vec![0, 1, 2]);
+    /// ```
+    pub fn into_raw_reader(mut self) -> impl Read {
+        match self.body.get_mut().unwrap().take() {
+            Some(body) => body.into_reader(),
+            None => self.take_transfer_reader(),
+        }
+    }
+
+    /// Turn this response into an iterator of the chunks of a
+    /// `Transfer-Encoding: chunked` body, each given as soon as all of it has
+    /// arrived. Useful for servers that send one message per chunk.
+    ///
+    /// Like [`into_raw_reader()`](#method.into_raw_reader), no `Content-Encoding`
+    /// is decoded. A body that isn't chunked, or that was already started by
+    /// reading the response itself, is given as a single chunk.
+    ///
+    /// ```
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Transfer-Encoding: chunked\r\n\
+    ///     \r\n\
+    ///     5\r\nHello\r\n6\r\n World\r\n0\r\n\r\n";
+    /// let resp = s.parse::<ureq::Response>().unwrap();
+    ///
+    /// let chunks: Vec<Vec<u8>> = resp.into_chunks().collect::<Result<_, _>>().unwrap();
+    /// assert_eq!(chunks, vec![b"Hello".to_vec(), b" World".to_vec()]);
+    /// ```
+    pub fn into_chunks(mut self) -> Chunks {
+        let boundary = Arc::new(AtomicBool::new(false));
+        let reader = match self.body.get_mut().unwrap().take() {
+            Some(body) => body.into_reader(),
+            None => self.take_framed_reader(Some(boundary.clone())),
+        };
+        Chunks {
+            reader,
+            boundary,
+            done: false,
+        }
+    }
+
+    /// Read the rest of the body and throw it away, so that the connection goes
+    /// back to the pool. For when only the status or headers matter. Returns the
+    /// number of bytes thrown away.
+    ///
+    /// Nothing is decoded. A body over `max_bytes` is an error, and the rest of it
+    /// is left like when dropping the response, see
+    /// [`Agent::drain_limit()`](struct.Agent.html#method.drain_limit).
+    ///
+    /// ```
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Length: 5\r\n\
+    ///     \r\n\
+    ///     Hello";
+    /// let resp = s.parse::<ureq::Response>().unwrap();
+    ///
+    /// assert_eq!(resp.discard(1024).unwrap(), 5);
+    /// ```
+    pub fn discard(self, max_bytes: u64) -> IoResult<u64> {
+        let reader = self.into_raw_reader();
+        let amount = io::copy(
+            &mut reader.take(max_bytes.saturating_add(1)),
+            &mut io::sink(),
+        )?;
+        if amount > max_bytes {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                "Response body is over the size limit",
+            ));
+        }
+        Ok(amount)
+    }
+
+    /// Copy the body into a writer, such as a cache file, as it is read by
+    /// [`into_reader()`](#method.into_reader), [`into_string()`](#method.into_string)
+    /// or any other way of reading the body. Can be called several times to
+    /// copy into several writers.
+    ///
+    /// A failed write fails the read of the body.
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Type: text/plain\r\n\
+    ///     \r\n\
+    ///     Hello World!!!";
+    /// let resp = s.parse::<ureq::Response>().unwrap();
+    ///
+    /// let cache = std::env::temp_dir().join("ureq-tee-doc.txt");
+    /// let text = resp.tee(std::fs::File::create(&cache)?).into_string()?;
+    ///
+    /// assert_eq!(text, "Hello World!!!");
+    /// assert_eq!(std::fs::read_to_string(&cache)?, "Hello World!!!");
+    /// # std::fs::remove_file(&cache)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tee(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.tee.get_mut().unwrap().push(Box::new(writer));
+        self
+    }
+
+    /// Save the body to a file without ever leaving a partial file at `path`.
+    ///
+    /// The body is streamed to a temporary file in the same directory, which is
+    /// renamed to `path` once the whole body is written. If anything fails, the
+    /// temporary file is removed and `path` is left as it was.
+    ///
+    /// Returns the number of bytes saved.
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Type: text/plain\r\n\
+    ///     \r\n\
+    ///     Hello World!!!";
+    /// let resp = s.parse::<ureq::Response>().unwrap();
+    ///
+    /// let path = std::env::temp_dir().join("ureq-save-atomic-doc.txt");
+    /// assert_eq!(resp.save_atomic(&path)?, 14);
+    /// assert_eq!(std::fs::read_to_string(&path)?, "Hello World!!!");
+    /// # std::fs::remove_file(&path)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn save_atomic(self, path: impl AsRef<Path>) -> IoResult<u64> {
+        let path = path.as_ref();
+        let tmp = tmp_path(path)?;
+
+        let write = |tmp: &Path| -> IoResult<u64> {
+            let mut file = File::create(tmp)?;
+            let amount = io::copy(&mut self.into_reader(), &mut file)?;
+            file.sync_all()?;
+            Ok(amount)
+        };
+        match write(&tmp).and_then(|amount| fs::rename(&tmp, path).map(|_| amount)) {
+            Ok(amount) => Ok(amount),
+            Err(e) => {
+                fs::remove_file(&tmp).ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// Split the response into its status, headers and body reader, so the
+    /// headers can be handed to other code while the body is read elsewhere.
+    ///
+    /// The reader is the same as from [`into_reader()`](#method.into_reader).
+    ///
+    /// ```
+    /// use std::io::Read;
+    ///
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Type: text/plain\r\n\
+    ///     \r\n\
+    ///     Hello World!!!";
+    /// let resp = s.parse::<ureq::Response>().unwrap();
+    ///
+    /// let (status, headers, mut reader) = resp.into_parts();
+    /// assert_eq!(status, 200);
+    /// assert_eq!(headers[0].name(), "Content-Type");
+    ///
+    /// let mut body = String::new();
+    /// reader.read_to_string(&mut body).unwrap();
+    /// assert_eq!(body, "Hello World!!!");
+    /// ```
+    pub fn into_parts(self) -> (u16, Vec<Header>, impl Read) {
+        let status = self.status;
+        let headers = self.headers.clone();
+        (status, headers, self.into_reader())
+    }
+
+    /// The body with its `Content-Encoding` decoded, continuing the one started
+    /// by reading the response itself, if any.
+    fn body_reader(&mut self) -> Box<dyn Read + Send> {
+        if let Some(body) = self.body.get_mut().unwrap().take() {
+            return body.into_reader();
+        }
+        let encoding = self.header("content-encoding").map(|e| e.to_string());
+        let decompress = match (&self.unit, &encoding) {
+            (Some(unit), Some(encoding)) => unit.decodes(encoding),
+            _ => true,
+        };
+        let reader = self.take_transfer_reader();
+        match encoding {
+            Some(encoding) if decompress => decode::decoder(&encoding, reader),
+            _ => reader,
+        }
+    }
+
+    /// The body with only the transfer framing removed. The stream and unit
+    /// are moved into it.
+    fn take_transfer_reader(&mut self) -> Box<dyn Read + Send> {
+        self.take_framed_reader(None)
+    }
+
+    /// Like `take_transfer_reader()`, but a chunked body is undone by a `ChunkRead`
+    /// flagging the ends of the chunks in `boundary`.
+    fn take_framed_reader(&mut self, boundary: Option<Arc<AtomicBool>>) -> Box<dyn Read + Send> {
         //
 
+        let tee = std::mem::take(self.tee.get_mut().unwrap());
+        #[cfg(feature = "checksum")]
+        let checksum = self.checksum.take();
+
         let is_http10 = self.http_version().eq_ignore_ascii_case("HTTP/1.0");
         let is_close = self
-            .header("connection")
-            .map(|c| c.eq_ignore_ascii_case("close"))
-            .unwrap_or(false);
+            .header_list("connection")
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case("close"));
 
-        let is_head = (&self.unit).as_ref().map(|u| u.is_head()).unwrap_or(false);
+        let no_body = !self.has_body();
 
         let is_chunked = self
             .header("transfer-encoding")
             .map(|enc| !enc.is_empty()) // whatever it says, do chunked
             .unwrap_or(false);
 
-        let use_chunked = !is_http10 && !is_head && is_chunked;
+        let use_chunked = !is_http10 && !no_body && is_chunked;
 
-        let limit_bytes = if is_http10 || is_close {
-            None
-        } else if is_head {
-            // head requests never have a body
+        let limit_bytes = if no_body {
+            // head requests, 204 and 304 never have a body
             Some(0)
+        } else if is_http10 || is_close {
+            None
         } else {
             self.header("content-length")
                 .and_then(|l| l.parse::<usize>().ok())
         };
 
-        let stream = Box::new(self.stream.expect("No reader in response?!"));
+        let unit = self.unit.take();
+        let stream = Box::new(self.stream.take().expect("No reader in response?!"));
         let stream_ptr = Box::into_raw(stream);
         let mut reclaiming_read = ReclaimingRead {
             stream: stream_ptr,
             dealloc: false,
+            // only bytes off the network count, not those of data: urls or files.
+            bytes: unit.as_ref().map(|_| self.bytes.clone()),
         };
-        let unit = self.unit;
 
-        match (use_chunked, limit_bytes) {
+        #[cfg(feature = "har")]
+        let har_entry = unit
+            .as_ref()
+            .and_then(|u| u.har_entry.map(|i| (u.agent.clone(), i)));
+
+        let reader = match (use_chunked, limit_bytes) {
+            (true, _) if boundary.is_some() => Box::new(PoolReturnRead::new(
+                unit,
+                stream_ptr,
+                ChunkRead::new(reclaiming_read, boundary.unwrap()),
+            )) as Box<dyn Read + Send>,
             (true, _) => Box::new(PoolReturnRead::new(
                 unit,
                 stream_ptr,
                 ChunkDecoder::new(reclaiming_read),
-            )) as Box<dyn Read>,
+            )) as Box<dyn Read + Send>,
             (false, Some(len)) => Box::new(PoolReturnRead::new(
                 unit,
                 stream_ptr,
@@ -321,7 +998,31 @@ impl Response {
                 reclaiming_read.dealloc = true; // dealloc when read drops.
                 Box::new(reclaiming_read)
             }
+        };
+
+        #[cfg(feature = "checksum")]
+        let reader = match checksum {
+            Some(checksum) => Box::new(VerifyRead::new(reader, checksum)),
+            None => reader,
+        };
+
+        let reader = if tee.is_empty() {
+            reader
+        } else {
+            Box::new(TeeRead {
+                reader,
+                writers: tee,
+            })
+        };
+
+        #[cfg(feature = "har")]
+        {
+            if let Some((agent, index)) = har_entry {
+                return crate::har::BodyRecorder::wrap(reader, agent, index);
+            }
         }
+
+        reader
     }
 
     /// Turn this response into a String of the response body. By default uses `utf-8`,
@@ -343,59 +1044,412 @@ impl Response {
     /// assert!(text.contains("hello"));
     /// ```
     ///
-    /// ## Charset support
+    /// ## Charset support
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["charset"] }`
+    ///
+    /// Attempts to respect the character encoding of the `Content-Type` header and
+    /// falls back to `utf-8`.
+    ///
+    /// I.e. `Content-Length: text/plain; charset=iso-8859-1` would be decoded in latin-1.
+    /// All encodings of the [WHATWG Encoding Standard](https://encoding.spec.whatwg.org/)
+    /// are supported, such as `shift_jis`, `gb18030` and `euc-kr`, by any of their labels.
+    ///
+    /// For `text/html` without a charset in the header, the first 1024 bytes of the
+    /// body are searched for a `<meta charset="...">` or `<meta http-equiv="Content-Type">`
+    /// tag, as browsers do.
+    ///
+    pub fn into_string(self) -> IoResult<String> {
+        #[cfg(feature = "charset")]
+        {
+            let declared = self
+                .header("content-type")
+                .map(|h| h.to_ascii_lowercase().contains("charset="))
+                .unwrap_or(false);
+            let is_html = self.content_type().eq_ignore_ascii_case("text/html");
+            let charset = self.charset().to_string();
+
+            let mut buf: Vec<u8> = vec![];
+            self.into_reader().read_to_end(&mut buf)?;
+
+            // like browsers, look for a <meta> charset when the header has none.
+            let sniffed = if is_html && !declared {
+                sniff_meta_charset(&buf[..buf.len().min(1024)])
+            } else {
+                None
+            };
+
+            let encoding = sniffed
+                .and_then(|label| encoding_from_whatwg_label(&label))
+                .or_else(|| encoding_from_whatwg_label(&charset))
+                .or_else(|| encoding_from_whatwg_label(DEFAULT_CHARACTER_SET))
+                .unwrap();
+            Ok(encoding.decode(&buf, DecoderTrap::Replace).unwrap())
+        }
+        #[cfg(not(feature = "charset"))]
+        {
+            let mut buf: Vec<u8> = vec![];
+            self.into_reader().read_to_end(&mut buf)?;
+            Ok(String::from_utf8_lossy(&buf).to_string())
+        }
+    }
+
+    /// Turn this response into a String, decoded with the given charset
+    /// regardless of the `Content-Type` header. For servers that send a wrong
+    /// or missing charset.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["charset"] }`
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if the charset label is unknown.
+    ///
+    /// ```
+    /// // this example requires features = ["charset"]
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Type: text/plain; charset=iso-8859-1\r\n\
+    ///     \r\n\
+    ///     caf\u{e9}"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// // the server claims latin-1, but the body is utf-8.
+    /// assert_eq!(resp.into_string_with_charset("utf-8").unwrap(), "café");
+    /// ```
+    #[cfg(feature = "charset")]
+    pub fn into_string_with_charset(self, charset: &str) -> IoResult<String> {
+        let encoding = encoding_from_whatwg_label(charset).ok_or_else(|| {
+            IoError::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown charset: {}", charset),
+            )
+        })?;
+        self.decode_with(encoding)
+    }
+
+    /// Turn this response into the bytes of its text in another charset, like
+    /// `windows-1252`, for systems that need a particular encoding. The body is
+    /// decoded as by [`into_string()`](#method.into_string) and then encoded.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["charset"] }`
+    ///
+    /// Characters the charset has no bytes for become `?`. Fails with
+    /// `ErrorKind::InvalidInput` if the charset label is unknown.
+    ///
+    /// ```
+    /// // this example requires features = ["charset"]
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Type: text/plain; charset=utf-8\r\n\
+    ///     \r\n\
+    ///     caf\u{e9} \u{20ac}5"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// let bytes = resp.into_string_encoded_as("windows-1252").unwrap();
+    /// assert_eq!(bytes, b"caf\xe9 \x805");
+    /// ```
+    #[cfg(feature = "charset")]
+    pub fn into_string_encoded_as(self, charset: &str) -> IoResult<Vec<u8>> {
+        let encoding = encoding_from_whatwg_label(charset).ok_or_else(|| {
+            IoError::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown charset: {}", charset),
+            )
+        })?;
+        let text = self.into_string()?;
+        Ok(encoding.encode(&text, EncoderTrap::Replace).unwrap())
+    }
+
+    #[cfg(feature = "charset")]
+    fn decode_with(self, encoding: EncodingRef) -> IoResult<String> {
+        let mut buf: Vec<u8> = vec![];
+        self.into_reader().read_to_end(&mut buf)?;
+        Ok(encoding.decode(&buf, DecoderTrap::Replace).unwrap())
+    }
+
+    /// Turn this response into a (serde) JSON value of the response body.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// let resp =
+    ///     ureq::get("https://ureq.s3.eu-central-1.amazonaws.com/hello_world.json")
+    ///         .call();
+    ///
+    /// let json = resp.into_json().unwrap();
+    ///
+    /// assert_eq!(json["hello"], "world");
+    /// ```
+    ///
+    /// With feature `simd-json` the body is parsed by simd-json instead, which
+    /// reads it into memory first.
+    ///
+    /// A body over the [`json_limit()`](struct.Agent.html#method.json_limit) of
+    /// the agent fails with `ErrorKind::InvalidData`.
+    #[cfg(feature = "json")]
+    pub fn into_json(self) -> IoResult<serde_json::Value> {
+        if let Some(max_bytes) = self.unit.as_ref().and_then(|u| u.json_limit) {
+            return self.into_json_limited(max_bytes);
+        }
+        let reader = self.into_reader();
+        json::from_reader(reader)
+    }
+
+    /// Read a GraphQL response, splitting the `data`, deserialized into `T`,
+    /// from the `errors`.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// The body is read whatever the status, since servers send errors with
+    /// `4xx` responses as well. A body that isn't a JSON object, or data that
+    /// doesn't fit `T`, fails with `ErrorKind::InvalidData`.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     \r\n\
+    ///     {\"data\": {\"hero\": null}, \"errors\": [{\"message\": \"Not found\", \"path\": [\"hero\"]}]}"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// let graphql = resp.into_graphql::<ureq::SerdeValue>().unwrap();
+    /// assert_eq!(graphql.errors()[0].message(), "Not found");
+    /// assert!(graphql.into_result().is_err());
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn into_graphql<T: serde::de::DeserializeOwned>(self) -> IoResult<GraphqlResponse<T>> {
+        let body = self.into_json()?;
+        graphql::parse(body)
+    }
+
+    /// Deserialize the body into `T` while it is read, failing if the body is
+    /// larger than `max_bytes`. The body is never held in memory as a whole,
+    /// so this is suitable for large documents. With feature `simd-json` it is,
+    /// but never more than `max_bytes` of it.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// A body over the limit fails with `ErrorKind::InvalidData`.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     \r\n\
+    ///     [1, 2, 3]"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// let numbers: Vec<u32> = resp.into_json_limited(10 * 1024 * 1024).unwrap();
+    /// assert_eq!(numbers, vec![1, 2, 3]);
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn into_json_limited<T: serde::de::DeserializeOwned>(self, max_bytes: u64) -> IoResult<T> {
+        let reader = CappedRead {
+            reader: self.into_reader(),
+            left: max_bytes,
+        };
+        json::from_reader(BufReader::new(reader))
+    }
+
+    /// Turn this response into an iterator of values from a newline delimited JSON
+    /// body ([JSON Lines](https://jsonlines.org)), read as they arrive.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// Empty lines are skipped. A line that isn't valid JSON for `T` gives an
+    /// `ErrorKind::InvalidData` item, and iteration continues with the next line.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Type: application/x-ndjson\r\n\
+    ///     \r\n\
+    ///     {\"id\":1}\n{\"id\":2}\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// let ids: Vec<u64> = resp
+    ///     .into_json_lines::<ureq::SerdeValue>()
+    ///     .map(|event| event.unwrap()["id"].as_u64().unwrap())
+    ///     .collect();
+    /// assert_eq!(ids, vec![1, 2]);
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn into_json_lines<T: serde::de::DeserializeOwned>(self) -> JsonLines<T> {
+        let reader: Box<dyn Read> = Box::new(self.into_reader());
+        JsonLines {
+            lines: BufReader::new(reader).lines(),
+            _type: PhantomData,
+        }
+    }
+
+    /// Turn this response into an iterator of the events of a `text/event-stream`
+    /// body ([server-sent events](https://html.spec.whatwg.org/multipage/server-sent-events.html)),
+    /// read as they arrive. The iteration ends with the body.
+    ///
+    /// See [`Agent::subscribe()`](struct.Agent.html#method.subscribe) for a stream
+    /// that is reconnected to.
+    ///
+    /// ```
+    /// let resp = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Type: text/event-stream\r\n\
+    ///     \r\n\
+    ///     event: greeting\ndata: hello\ndata: world\n\n"
+    ///     .parse::<ureq::Response>()
+    ///     .unwrap();
+    ///
+    /// let event = resp.into_events().next().unwrap().unwrap();
+    /// assert_eq!(event.event(), "greeting");
+    /// assert_eq!(event.data(), "hello\nworld");
+    /// ```
+    pub fn into_events(self) -> Events {
+        Events::new(Box::new(self.into_reader()), None)
+    }
+
+    /// Turn this response into the ranges of a `206 Partial Content` response,
+    /// read as they arrive. A `multipart/byteranges` body, the answer to a
+    /// request for several ranges, gives a part per range with its own
+    /// `Content-Range`. Any other body is a single part with the `Content-Range`
+    /// and `Content-Type` of the response.
+    ///
+    /// Fails with `ErrorKind::InvalidData` for a `multipart/byteranges` response
+    /// without a boundary. See [`ByteRanges`](struct.ByteRanges.html) for an example.
+    pub fn into_byte_ranges(mut self) -> IoResult<ByteRanges> {
+        if self
+            .content_type()
+            .eq_ignore_ascii_case("multipart/byteranges")
+        {
+            let boundary = content_type_param(self.header("content-type"), "boundary")
+                .filter(|b| !b.is_empty())
+                .map(|b| b.to_string())
+                .ok_or_else(|| {
+                    IoError::new(
+                        ErrorKind::InvalidData,
+                        "No boundary in multipart/byteranges response",
+                    )
+                })?;
+            return Ok(ByteRanges::multipart(self.body_reader(), &boundary));
+        }
+        let headers = self
+            .headers
+            .iter()
+            .filter(|h| h.is_name("content-range") || h.is_name("content-type"))
+            .cloned()
+            .collect();
+        Ok(ByteRanges::single(self.body_reader(), headers))
+    }
+
+    /// Turn this response into a value deserialized from [MessagePack](https://msgpack.org).
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["msgpack"] }`
+    ///
+    /// Fails with `ErrorKind::InvalidData` unless the `Content-Type` is
+    /// `application/msgpack` (or the `x-msgpack`/`vnd.msgpack` variants).
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    ///
+    /// let resp = ureq::get("/my_page").call();
+    ///
+    /// if resp.ok() {
+    ///     let map: BTreeMap<String, String> = resp.into_msgpack().unwrap();
+    /// }
+    /// ```
+    #[cfg(feature = "msgpack")]
+    pub fn into_msgpack<T: serde::de::DeserializeOwned>(self) -> IoResult<T> {
+        self.expect_content_type(&[
+            "application/msgpack",
+            "application/x-msgpack",
+            "application/vnd.msgpack",
+        ])?;
+        let reader = self.into_reader();
+        rmp_serde::from_read(reader).map_err(|e| {
+            IoError::new(
+                ErrorKind::InvalidData,
+                format!("Failed to read MessagePack: {}", e),
+            )
+        })
+    }
+
+    /// Turn this response into a value deserialized from [CBOR](https://cbor.io).
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["cbor"] }`
     ///
-    /// Requires feature `ureq = { version = "*", features = ["charset"] }`
+    /// Fails with `ErrorKind::InvalidData` unless the `Content-Type` is
+    /// `application/cbor`.
     ///
-    /// Attempts to respect the character encoding of the `Content-Type` header and
-    /// falls back to `utf-8`.
+    /// ```
+    /// use std::collections::BTreeMap;
     ///
-    /// I.e. `Content-Length: text/plain; charset=iso-8859-1` would be decoded in latin-1.
+    /// let resp = ureq::get("/my_page").call();
     ///
-    pub fn into_string(self) -> IoResult<String> {
-        #[cfg(feature = "charset")]
-        {
-            let encoding = encoding_from_whatwg_label(self.charset())
-                .or_else(|| encoding_from_whatwg_label(DEFAULT_CHARACTER_SET))
-                .unwrap();
-            let mut buf: Vec<u8> = vec![];
-            self.into_reader().read_to_end(&mut buf)?;
-            Ok(encoding.decode(&buf, DecoderTrap::Replace).unwrap())
-        }
-        #[cfg(not(feature = "charset"))]
-        {
-            let mut buf: Vec<u8> = vec![];
-            self.into_reader().read_to_end(&mut buf)?;
-            Ok(String::from_utf8_lossy(&buf).to_string())
-        }
+    /// if resp.ok() {
+    ///     let map: BTreeMap<String, i64> = resp.into_cbor().unwrap();
+    /// }
+    /// ```
+    #[cfg(feature = "cbor")]
+    pub fn into_cbor<T: serde::de::DeserializeOwned>(self) -> IoResult<T> {
+        self.expect_content_type(&["application/cbor"])?;
+        let reader = self.into_reader();
+        ciborium::de::from_reader(reader).map_err(|e| {
+            IoError::new(
+                ErrorKind::InvalidData,
+                format!("Failed to read CBOR: {}", e),
+            )
+        })
     }
 
-    /// Turn this response into a (serde) JSON value of the response body.
+    /// Turn this response into a [prost](https://docs.rs/prost) protobuf message.
     ///
-    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    /// Requires feature `ureq = { version = "*", features = ["protobuf"] }`
     ///
-    /// Example:
+    /// Fails with `ErrorKind::InvalidData` unless the `Content-Type` is
+    /// `application/x-protobuf` (or the `protobuf`/`vnd.google.protobuf`
+    /// variants). The body is read into memory before it is decoded.
     ///
     /// ```
-    /// let resp =
-    ///     ureq::get("https://ureq.s3.eu-central-1.amazonaws.com/hello_world.json")
-    ///         .call();
+    /// #[derive(Clone, PartialEq, prost::Message)]
+    /// struct Hello {
+    ///     #[prost(string, tag = "1")]
+    ///     name: String,
+    /// }
     ///
-    /// let json = resp.into_json().unwrap();
+    /// let resp = ureq::get("/my_page").call();
     ///
-    /// assert_eq!(json["hello"], "world");
+    /// if resp.ok() {
+    ///     let hello: Hello = resp.into_proto().unwrap();
+    /// }
     /// ```
-    #[cfg(feature = "json")]
-    pub fn into_json(self) -> IoResult<serde_json::Value> {
-        let reader = self.into_reader();
-        serde_json::from_reader(reader).map_err(|e| {
+    #[cfg(feature = "protobuf")]
+    pub fn into_proto<T: prost::Message + Default>(self) -> IoResult<T> {
+        self.expect_content_type(&[
+            "application/x-protobuf",
+            "application/protobuf",
+            "application/vnd.google.protobuf",
+        ])?;
+        let mut buf = vec![];
+        self.into_reader().read_to_end(&mut buf)?;
+        T::decode(&buf[..]).map_err(|e| {
             IoError::new(
                 ErrorKind::InvalidData,
-                format!("Failed to read JSON: {}", e),
+                format!("Failed to read protobuf: {}", e),
             )
         })
     }
 
+    #[cfg(any(feature = "msgpack", feature = "cbor", feature = "protobuf"))]
+    fn expect_content_type(&self, expected: &[&str]) -> IoResult<()> {
+        let content_type = self.content_type();
+        if expected
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(content_type.trim()))
+        {
+            Ok(())
+        } else {
+            Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!("Expected {}, got {}", expected[0], content_type),
+            ))
+        }
+    }
+
     /// Create a response from a Read trait impl.
     ///
     /// This is hopefully useful for unit tests.
@@ -415,36 +1469,92 @@ impl Response {
         Self::do_from_read(reader).unwrap_or_else(|e| e.into())
     }
 
-    fn do_from_read(mut reader: impl Read) -> Result<Response, Error> {
+    pub(crate) fn do_from_read(reader: impl Read) -> Result<Response, Error> {
+        Self::read_head(reader, false)
+    }
+
+    /// Read the head of a response. `lenient` tolerates the quirks of some
+    /// servers, see [`Request::lenient_parsing()`](struct.Request.html#method.lenient_parsing).
+    pub(crate) fn read_head(mut reader: impl Read, lenient: bool) -> Result<Response, Error> {
+        let mut raw_head = vec![];
+        let mut read_line = |reader: &mut dyn Read| -> IoResult<String> {
+            let (line, ending) = read_head_line(reader, lenient)?;
+            raw_head.extend_from_slice(line.as_bytes());
+            raw_head.extend_from_slice(ending.as_bytes());
+            Ok(line)
+        };
+
         //
         // HTTP/1.1 200 OK\r\n
-        let status_line = read_next_line(&mut reader).map_err(|e| match e.kind() {
-            ErrorKind::ConnectionAborted => Error::BadStatusRead,
-            _ => Error::BadStatus,
-        })?;
+        let status_line = loop {
+            let line = read_line(&mut reader).map_err(|e| match e.kind() {
+                ErrorKind::ConnectionAborted => Error::BadStatusRead,
+                // keep timeouts and tls failures apart from a bad status line.
+                _ => match Error::from(e) {
+                    Error::Io(_) => Error::BadStatus,
+                    err => err,
+                },
+            })?;
+            // empty lines left over after the body of a previous response.
+            if !(lenient && line.trim().is_empty()) {
+                break line;
+            }
+        };
+        let status_line = if lenient {
+            tidy_status_line(&status_line)
+        } else {
+            status_line
+        };
 
         let (index, status) = parse_status_line(status_line.as_str())?;
 
         let mut headers: Vec<Header> = Vec::new();
         loop {
-            let line = read_next_line(&mut reader).map_err(|_| Error::BadHeader)?;
+            let line = read_line(&mut reader).map_err(|_| Error::BadHeader)?;
             if line.is_empty() {
                 break;
             }
+            if lenient {
+                // obsolete line folding continues the value of the previous header.
+                if line.starts_with([' ', '\t']) {
+                    if let Some(prev) = headers.pop() {
+                        let value = format!("{} {}", prev.value(), line.trim());
+                        headers.push(Header::new(prev.name(), &value));
+                    }
+                    continue;
+                }
+                // a status line sent twice.
+                if line.starts_with("HTTP/") {
+                    continue;
+                }
+            }
             if let Ok(header) = line.as_str().parse::<Header>() {
                 headers.push(header);
             }
         }
 
+        let head_len = raw_head.len() as u64;
         Ok(Response {
             url: None,
             error: None,
             status_line,
+            raw_head,
             index,
             status,
             headers,
             unit: None,
             stream: None,
+            stats: ExchangeStats {
+                bytes_received: head_len,
+                ..Default::default()
+            },
+            bytes: Arc::new(ByteCounts::default()),
+            request_id: None,
+            error_on_status: false,
+            tee: Mutex::new(vec![]),
+            body: Mutex::new(None),
+            #[cfg(feature = "checksum")]
+            checksum: None,
         })
     }
 
@@ -454,19 +1564,44 @@ impl Response {
     }
 }
 
+/// Split a media type like `text/html` into its type and subtype.
+fn split_media_type(media_type: &str) -> (&str, &str) {
+    match media_type.find('/') {
+        Some(i) => (&media_type[..i], &media_type[i + 1..]),
+        None => (media_type, ""),
+    }
+}
+
+/// A status line with single spaces between its parts, and without a space
+/// at the end when there is no reason phrase.
+fn tidy_status_line(line: &str) -> String {
+    let mut parts = line.trim().splitn(2, char::is_whitespace);
+    let version = parts.next().unwrap_or("");
+    let mut parts = parts
+        .next()
+        .unwrap_or("")
+        .trim_start()
+        .splitn(2, char::is_whitespace);
+    let code = parts.next().unwrap_or("");
+    match parts.next().map(str::trim).filter(|r| !r.is_empty()) {
+        Some(reason) => format!("{} {} {}", version, code, reason),
+        None => format!("{} {}", version, code),
+    }
+}
+
 /// parse a line like: HTTP/1.1 200 OK\r\n
 fn parse_status_line(line: &str) -> Result<(ResponseStatusIndex, u16), Error> {
     //
 
     let mut split = line.splitn(3, ' ');
 
-    let http_version = split.next().ok_or_else(|| Error::BadStatus)?;
+    let http_version = split.next().ok_or(Error::BadStatus)?;
     if http_version.len() < 5 {
         return Err(Error::BadStatus);
     }
     let index1 = http_version.len();
 
-    let status = split.next().ok_or_else(|| Error::BadStatus)?;
+    let status = split.next().ok_or(Error::BadStatus)?;
     if status.len() < 2 {
         return Err(Error::BadStatus);
     }
@@ -483,6 +1618,18 @@ fn parse_status_line(line: &str) -> Result<(ResponseStatusIndex, u16), Error> {
     ))
 }
 
+/// A new file next to `path` to write before renaming it to `path`.
+pub(crate) fn tmp_path(path: &Path) -> IoResult<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidInput, "No file name in path"))?;
+    Ok(path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        request_id::uuid_v4()
+    )))
+}
+
 impl FromStr for Response {
     type Err = Error;
     /// Parse a response from a string.
@@ -508,36 +1655,284 @@ impl FromStr for Response {
     }
 }
 
-impl Into<Response> for Error {
-    fn into(self) -> Response {
-        let status = self.status();
-        let status_text = self.status_text().to_string();
-        let body_text = self.body_text();
+impl From<Error> for Response {
+    fn from(val: Error) -> Self {
+        let status = val.status();
+        let status_text = val.status_text().to_string();
+        let body_text = val.body_text();
         let mut resp = Response::new(status, &status_text, &body_text);
-        resp.error = Some(self);
+        resp.error = Some(val);
+        // nothing was read from a server.
+        resp.stats = ExchangeStats::default();
         resp
     }
 }
 
+impl Drop for Response {
+    fn drop(&mut self) {
+        // an unread body is drained by its reader, to pool the connection.
+        if self.unit.is_none() || self.stream.is_none() {
+            return;
+        }
+        #[cfg(feature = "har")]
+        {
+            if let Some(unit) = self.unit.as_mut() {
+                unit.har_entry = None;
+            }
+        }
+        self.tee.get_mut().unwrap().clear();
+        drop(self.take_transfer_reader());
+    }
+}
+
+/// Copies everything read into writers.
+struct TeeRead {
+    reader: Box<dyn Read + Send>,
+    writers: Vec<Box<dyn Write + Send>>,
+}
+
+impl Read for TeeRead {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let amount = self.reader.read(buf)?;
+        for writer in &mut self.writers {
+            if amount == 0 && !buf.is_empty() {
+                writer.flush()?;
+            } else {
+                writer.write_all(&buf[..amount])?;
+            }
+        }
+        Ok(amount)
+    }
+}
+
+/// Fails reads once more than a number of bytes has been read.
+#[cfg(feature = "json")]
+struct CappedRead<R> {
+    reader: R,
+    left: u64,
+}
+
+#[cfg(feature = "json")]
+impl<R: Read> Read for CappedRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        // read one byte past the limit to know if it is exceeded.
+        let max = buf.len().min(self.left.saturating_add(1) as usize);
+        let amount = self.reader.read(&mut buf[..max])?;
+        if amount as u64 > self.left {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                "Response body is over the size limit",
+            ));
+        }
+        self.left -= amount as u64;
+        Ok(amount)
+    }
+}
+
+/// Iterator of the records of a JSON Lines body, see
+/// [`Response::into_json_lines()`](struct.Response.html#method.into_json_lines).
+#[cfg(feature = "json")]
+pub struct JsonLines<T> {
+    lines: Lines<BufReader<Box<dyn Read>>>,
+    _type: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "json")]
+impl<T> ::std::fmt::Debug for JsonLines<T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "JsonLines")
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> Iterator for JsonLines<T> {
+    type Item = IoResult<T>;
+
+    fn next(&mut self) -> Option<IoResult<T>> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(serde_json::from_str(&line).map_err(|e| {
+                IoError::new(
+                    ErrorKind::InvalidData,
+                    format!("Failed to read JSON line: {}", e),
+                )
+            }));
+        }
+    }
+}
+
+/// Iterator of the chunks of a body, see
+/// [`Response::into_chunks()`](struct.Response.html#method.into_chunks).
+pub struct Chunks {
+    reader: Box<dyn Read>,
+    boundary: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl ::std::fmt::Debug for Chunks {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "Chunks")
+    }
+}
+
+impl Iterator for Chunks {
+    type Item = IoResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<IoResult<Vec<u8>>> {
+        if self.done {
+            return None;
+        }
+        let mut chunk = vec![];
+        let mut buf = [0_u8; 8192];
+        loop {
+            let amount = match self.reader.read(&mut buf) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            chunk.extend_from_slice(&buf[..amount]);
+            if amount == 0 {
+                self.done = true;
+                return Some(chunk).filter(|c| !c.is_empty()).map(Ok);
+            }
+            if self.boundary.swap(false, Ordering::Relaxed) {
+                return Some(Ok(chunk));
+            }
+        }
+    }
+}
+
+/// Undoes `Transfer-Encoding: chunked` like `ChunkDecoder`, but never reads past
+/// the end of a chunk and sets `boundary` when one is fully read.
+struct ChunkRead<R> {
+    reader: R,
+    /// Bytes left of the current chunk, `None` before its size line.
+    left: Option<u64>,
+    done: bool,
+    boundary: Arc<AtomicBool>,
+}
+
+impl<R: Read> ChunkRead<R> {
+    fn new(reader: R, boundary: Arc<AtomicBool>) -> Self {
+        ChunkRead {
+            reader,
+            left: None,
+            done: false,
+            boundary,
+        }
+    }
+}
+
+impl<R: Read> Read for ChunkRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        let left = match self.left {
+            Some(left) => left,
+            None => {
+                let line = read_next_line(&mut self.reader)?;
+                // chunk extensions after ';' are ignored.
+                let size = line.split(';').next().unwrap_or("").trim();
+                let size = u64::from_str_radix(size, 16)
+                    .map_err(|_| IoError::new(ErrorKind::InvalidData, "Bad chunk size"))?;
+                if size == 0 {
+                    // the trailer, up to an empty line.
+                    while !read_next_line(&mut self.reader)?.is_empty() {}
+                    self.done = true;
+                    return Ok(0);
+                }
+                size
+            }
+        };
+        let max = buf.len().min(left.min(usize::MAX as u64) as usize);
+        let amount = self.reader.read(&mut buf[..max])?;
+        if amount == 0 {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "Unexpected EOF"));
+        }
+        let left = left - amount as u64;
+        if left == 0 {
+            if !read_next_line(&mut self.reader)?.is_empty() {
+                return Err(IoError::new(ErrorKind::InvalidData, "Bad chunk end"));
+            }
+            self.left = None;
+            self.boundary.store(true, Ordering::Relaxed);
+        } else {
+            self.left = Some(left);
+        }
+        Ok(amount)
+    }
+}
+
 /// "Give away" Unit and Stream to the response.
 ///
 /// *Internal API*
 pub(crate) fn set_stream(resp: &mut Response, url: String, unit: Option<Unit>, stream: Stream) {
     resp.url = Some(url);
+    if let Some(unit) = &unit {
+        resp.bytes = unit.bytes.clone();
+    }
     resp.unit = unit;
     resp.stream = Some(stream);
 }
 
-fn read_next_line<R: Read>(reader: &mut R) -> IoResult<String> {
+/// A line of a head and how it ended, which leniently can be a lone `\n`.
+fn read_head_line(reader: &mut dyn Read, lenient: bool) -> IoResult<(String, &'static str)> {
+    if !lenient {
+        return Ok((read_next_line(reader)?, "\r\n"));
+    }
+    let mut buf = Vec::new();
+    let mut one = [0_u8];
+    loop {
+        match reader.read(&mut one) {
+            Ok(0) if buf.is_empty() => {
+                return Err(IoError::new(ErrorKind::ConnectionAborted, "Unexpected EOF"))
+            }
+            Ok(0) => return Err(IoError::new(ErrorKind::UnexpectedEof, "Unexpected EOF")),
+            Err(e) if e.kind() == ErrorKind::ConnectionReset && buf.is_empty() => {
+                return Err(IoError::new(ErrorKind::ConnectionAborted, e))
+            }
+            Err(e) => return Err(e),
+            Ok(_) if one[0] == b'\n' => break,
+            Ok(_) => buf.push(one[0]),
+        }
+    }
+    let ending = if buf.last() == Some(&b'\r') {
+        buf.pop();
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let line = String::from_utf8(buf)
+        .map_err(|_| IoError::new(ErrorKind::InvalidInput, "Header is not in ASCII"))?;
+    Ok((line, ending))
+}
+
+fn read_next_line<R: Read + ?Sized>(reader: &mut R) -> IoResult<String> {
     let mut buf = Vec::new();
     let mut prev_byte_was_cr = false;
+    let mut one = [0_u8];
 
     loop {
-        let byte = reader.bytes().next();
-
-        let byte = match byte {
-            Some(b) => b?,
-            None => return Err(IoError::new(ErrorKind::ConnectionAborted, "Unexpected EOF")),
+        let byte = match reader.read(&mut one) {
+            // nothing read, the server closed the connection.
+            Ok(0) if buf.is_empty() => {
+                return Err(IoError::new(ErrorKind::ConnectionAborted, "Unexpected EOF"))
+            }
+            Ok(0) => return Err(IoError::new(ErrorKind::UnexpectedEof, "Unexpected EOF")),
+            Err(e) if e.kind() == ErrorKind::ConnectionReset && buf.is_empty() => {
+                return Err(IoError::new(ErrorKind::ConnectionAborted, e))
+            }
+            Err(e) => return Err(e),
+            Ok(_) => one[0],
         };
 
         if byte == b'\n' && prev_byte_was_cr {
@@ -560,10 +1955,11 @@ fn read_next_line<R: Read>(reader: &mut R) -> IoResult<String> {
 /// that api provides no way for us to get the underlying stream back. We need
 /// to get the stream both for sending responses and for pooling.
 pub(crate) struct ReclaimingRead {
-    // this pointer forces ReclaimingRead to be !Send and !Sync. That's a good
-    // thing, cause passing this reader around threads would not be safe.
+    // this pointer makes ReclaimingRead !Sync. That's a good thing, cause
+    // sharing this reader between threads would not be safe.
     stream: *mut Stream,
     dealloc: bool, // whether we are to dealloc stream on drop
+    bytes: Option<Arc<ByteCounts>>,
 }
 
 impl Read for ReclaimingRead {
@@ -573,6 +1969,9 @@ impl Read for ReclaimingRead {
                 return Ok(0);
             }
             let amount = (*self.stream).read(buf)?;
+            if let Some(bytes) = &self.bytes {
+                bytes.add(0, amount as u64);
+            }
             if amount == 0 {
                 if self.dealloc {
                     let _stream = Box::from_raw(self.stream);
@@ -584,6 +1983,10 @@ impl Read for ReclaimingRead {
     }
 }
 
+// the stream pointer is only used by the one reader that owns the stream, and
+// the pool return around it, which move together.
+unsafe impl Send for ReclaimingRead {}
+
 impl Drop for ReclaimingRead {
     fn drop(&mut self) {
         if self.dealloc && !self.stream.is_null() {
@@ -594,6 +1997,64 @@ impl Drop for ReclaimingRead {
     }
 }
 
+/// The body reader kept in a response that is read directly or peeked at.
+///
+/// *Internal API*
+struct BodyRead {
+    /// Bytes peeked at and not yet read.
+    peeked: Vec<u8>,
+    reader: Box<dyn Read + Send>,
+}
+
+impl BodyRead {
+    fn new(reader: Box<dyn Read + Send>) -> Self {
+        BodyRead {
+            peeked: vec![],
+            reader,
+        }
+    }
+
+    /// Buffer up to `n` bytes, fewer only at the end of the body.
+    fn peek(&mut self, n: usize) -> IoResult<&[u8]> {
+        let mut buf = [0_u8; 8192];
+        while self.peeked.len() < n {
+            let max = buf.len().min(n - self.peeked.len());
+            let amount = self.reader.read(&mut buf[..max])?;
+            if amount == 0 {
+                break;
+            }
+            self.peeked.extend_from_slice(&buf[..amount]);
+        }
+        Ok(&self.peeked[..n.min(self.peeked.len())])
+    }
+
+    fn into_reader(self) -> Box<dyn Read + Send> {
+        if self.peeked.is_empty() {
+            self.reader
+        } else {
+            Box::new(Cursor::new(self.peeked).chain(self.reader))
+        }
+    }
+}
+
+impl Read for BodyRead {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.peeked.is_empty() {
+            return self.reader.read(buf);
+        }
+        let amount = buf.len().min(self.peeked.len());
+        buf[..amount].copy_from_slice(&self.peeked[..amount]);
+        self.peeked.drain(..amount);
+        Ok(amount)
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.started_body().read(buf)
+    }
+}
+
 /// Limits a ReclaimingRead to a content size (as set by a "Content-Length" header).
 struct LimitedRead {
     reader: ReclaimingRead,
@@ -632,21 +2093,58 @@ impl Read for LimitedRead {
     }
 }
 
+impl BodyLeft for LimitedRead {
+    fn body_left(&self) -> Option<u64> {
+        Some((self.limit - self.position) as u64)
+    }
+}
+
+impl<R: Read> BodyLeft for ChunkDecoder<R> {}
+
+impl<R: Read> BodyLeft for ChunkRead<R> {}
+
 /// Extract the charset from a "Content-Type" header.
 ///
 /// "Content-Type: text/plain; charset=iso8859-1" -> "iso8859-1"
 ///
 /// *Internal API*
 pub(crate) fn charset_from_content_type(header: Option<&str>) -> &str {
-    header
-        .and_then(|header| {
-            header.find(';').and_then(|semi| {
-                (&header[semi + 1..])
-                    .find('=')
-                    .map(|equal| (&header[semi + equal + 2..]).trim())
-            })
-        })
-        .unwrap_or(DEFAULT_CHARACTER_SET)
+    content_type_param(header, "charset").unwrap_or(DEFAULT_CHARACTER_SET)
+}
+
+/// A parameter of a "Content-Type" header, unquoted.
+fn content_type_param<'a>(header: Option<&'a str>, name: &str) -> Option<&'a str> {
+    // text/plain; format=flowed; charset="iso-8859-1"
+    header?.split(';').skip(1).find_map(|param| {
+        let (param_name, value) = param.split_at(param.find('=')?);
+        if param_name.trim().eq_ignore_ascii_case(name) {
+            Some(value[1..].trim().trim_matches('"'))
+        } else {
+            None
+        }
+    })
+}
+
+/// Find the charset of a `<meta charset="x">` or `<meta content="text/html; charset=x">` tag.
+#[cfg(feature = "charset")]
+fn sniff_meta_charset(head: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+    let mut rest = head.as_str();
+    while let Some(start) = rest.find("<meta") {
+        let tag = &rest[start..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        if let Some(pos) = tag.find("charset=") {
+            let value = tag[pos + 8..].trim_start_matches(['"', '\'', ' ']);
+            let end = value
+                .find(|c: char| c == '"' || c == '\'' || c == ';' || c == '/' || c.is_whitespace())
+                .unwrap_or(value.len());
+            if end > 0 {
+                return Some(value[..end].to_string());
+            }
+        }
+        rest = &rest[start + 5..];
+    }
+    None
 }
 
 #[cfg(test)]
@@ -718,11 +2216,10 @@ mod tests {
     #[test]
     #[cfg(feature = "json")]
     fn parse_simple_json() {
-        let s = format!(
-            "HTTP/1.1 200 OK\r\n\
-             \r\n\
-             {{\"hello\":\"world\"}}"
-        );
+        let s = "HTTP/1.1 200 OK\r\n\
+                 \r\n\
+                 {\"hello\":\"world\"}"
+            .to_string();
         let resp = s.parse::<Response>().unwrap();
         let v = resp.into_json().unwrap();
         let compare = "{\"hello\":\"world\"}"
@@ -742,4 +2239,125 @@ mod tests {
         let v = resp.into_string().unwrap();
         assert_eq!(v, "Bad Status\n");
     }
+
+    #[test]
+    fn parse_links() {
+        let s = "HTTP/1.1 200 OK\r\n\
+                 Link: <https://x.test/a,b>; title=\"a \\\"quoted\\\", title\"; REL=Prev, junk\r\n\
+                 Link: <https://x.test/?page=3>;rel=next;foo, <https://x.test/other>; rel=next\r\n\
+                 \r\n";
+        let resp = s.parse::<Response>().unwrap();
+        let links = resp.links();
+        assert_eq!(links.len(), 2);
+        let prev = &links["prev"];
+        assert_eq!(prev.url(), "https://x.test/a,b");
+        assert_eq!(prev.param("title"), Some("a \"quoted\", title"));
+        assert_eq!(links["next"].url(), "https://x.test/?page=3");
+        assert_eq!(links["next"].param("foo"), Some(""));
+    }
+
+    #[test]
+    fn attachment_filename() {
+        let name = |value: &str| {
+            let s = format!("HTTP/1.1 200 OK\r\nContent-Disposition: {}\r\n\r\n", value);
+            s.parse::<Response>().unwrap().attachment_filename()
+        };
+        assert_eq!(name("attachment; filename=plain.txt").unwrap(), "plain.txt");
+        assert_eq!(
+            name("inline; FILENAME=\"a \\\"b\\\".txt\"").unwrap(),
+            "a \"b\".txt"
+        );
+        assert_eq!(
+            name("attachment; filename*=iso-8859-1'en'%A3%20rates").unwrap(),
+            "£ rates"
+        );
+        assert_eq!(
+            name("attachment; filename*=UTF-8''bad%zz; filename=fallback.txt").unwrap(),
+            "fallback.txt"
+        );
+        assert_eq!(
+            name("attachment; filename=\"../../etc/passwd\"").unwrap(),
+            "passwd"
+        );
+        assert_eq!(name("attachment; filename=\"..\""), None);
+        assert_eq!(name("attachment"), None);
+    }
+
+    #[test]
+    fn date_headers() {
+        let s = "HTTP/1.1 200 OK\r\n\
+                 Date: Thu, 29 Feb 2024 23:59:59 GMT\r\n\
+                 Expires: 0\r\n\
+                 \r\n";
+        let resp = s.parse::<Response>().unwrap();
+        let date = resp.date().unwrap();
+        assert_eq!(
+            crate::format_http_date(date),
+            "Thu, 29 Feb 2024 23:59:59 GMT"
+        );
+        assert_eq!(resp.expires(), None);
+        assert_eq!(resp.last_modified(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "charset")]
+    fn into_string_with_unknown_charset() {
+        let s = "HTTP/1.1 200 OK\r\n\r\nOK";
+        let resp = s.parse::<Response>().unwrap();
+        let err = resp.into_string_with_charset("klingon").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(feature = "charset")]
+    fn into_string_sniffs_html_meta_charset() {
+        let body = b"<html><head><meta http-equiv=\"Content-Type\" \
+                     content=\"text/html; charset=ISO-8859-1\"></head>caf\xe9</html>";
+        let mut s = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n".to_vec();
+        s.extend_from_slice(body);
+        let mut cursor = Cursor::new(s);
+        let mut resp = Response::do_from_read(&mut cursor).unwrap();
+        set_stream(&mut resp, "".into(), None, Stream::Cursor(cursor));
+        assert!(resp.into_string().unwrap().contains("caf\u{e9}"));
+
+        let s = "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/html; charset=utf-8\r\n\
+                 \r\n\
+                 <meta charset='iso-8859-1'>caf\u{e9}";
+        let resp = s.parse::<Response>().unwrap();
+        assert!(resp.into_string().unwrap().ends_with("caf\u{e9}"));
+    }
+
+    #[test]
+    fn charset_among_other_params() {
+        let charset = |ct| charset_from_content_type(Some(ct));
+        assert_eq!(
+            charset("text/plain; format=flowed; charset=iso-8859-1"),
+            "iso-8859-1"
+        );
+        assert_eq!(charset("text/html;CHARSET=\"Shift_JIS\""), "Shift_JIS");
+        assert_eq!(charset("text/plain; format=flowed"), "utf-8");
+    }
+
+    #[test]
+    #[cfg(feature = "charset")]
+    fn into_string_cjk_charsets() {
+        let cases: &[(&str, &[u8], &str)] = &[
+            ("shift_jis", b"\x93\xfa\x96\x7b", "日本"),
+            ("gb18030", b"\xc8\xd5\xb1\xbe", "日本"),
+            ("euc-kr", b"\xc0\xcf\xba\xbb", "일본"),
+        ];
+        for (charset, body, text) in cases {
+            let mut s = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset={}\r\n\r\n",
+                charset
+            )
+            .into_bytes();
+            s.extend_from_slice(body);
+            let mut cursor = Cursor::new(s);
+            let mut resp = Response::do_from_read(&mut cursor).unwrap();
+            set_stream(&mut resp, "".into(), None, Stream::Cursor(cursor));
+            assert_eq!(resp.into_string().unwrap(), *text, "{}", charset);
+        }
+    }
 }
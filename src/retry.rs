@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::agent::AgentState;
+use crate::time::Instant;
+
+/// The share of requests that may be retries, see
+/// [`Agent::retry_budget()`](struct.Agent.html#method.retry_budget).
+///
+/// *Internal API*
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryBudgetConfig {
+    pub ratio: f64,
+    pub window: Duration,
+}
+
+/// The requests and retries of the sliding window.
+///
+/// *Internal API*
+#[derive(Debug)]
+pub(crate) struct RetryBudget {
+    config: RetryBudgetConfig,
+    sent: Mutex<Sent>,
+}
+
+#[derive(Debug, Default)]
+struct Sent {
+    requests: VecDeque<Instant>,
+    retries: VecDeque<Instant>,
+}
+
+impl RetryBudget {
+    pub fn new(config: RetryBudgetConfig) -> Self {
+        RetryBudget {
+            config,
+            sent: Mutex::new(Sent::default()),
+        }
+    }
+
+    fn sent(&self, now: Instant) -> std::sync::MutexGuard<'_, Sent> {
+        let mut sent = self.sent.lock().unwrap();
+        let window = self.config.window;
+        expire(&mut sent.requests, now, window);
+        expire(&mut sent.retries, now, window);
+        sent
+    }
+}
+
+fn expire(times: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+    while times
+        .front()
+        .is_some_and(|t| now.duration_since(*t) > window)
+    {
+        times.pop_front();
+    }
+}
+
+/// Count a request against the budget of the agent, redirects included.
+pub(crate) fn record_request(state: &Option<AgentState>) {
+    if let Some(budget) = state.as_ref().and_then(|s| s.retry_budget.as_ref()) {
+        let now = Instant::now();
+        budget.sent(now).requests.push_back(now);
+    }
+}
+
+/// Whether the budget of the agent allows another retry, which is then counted.
+/// There's always room for one retry in the window, and no limit without a budget.
+pub(crate) fn allow(state: &Option<AgentState>) -> bool {
+    let budget = match state.as_ref().and_then(|s| s.retry_budget.as_ref()) {
+        Some(budget) => budget,
+        None => return true,
+    };
+    let now = Instant::now();
+    let mut sent = budget.sent(now);
+    let allowed = (budget.config.ratio * sent.requests.len() as f64).max(1.0);
+    if sent.retries.len() as f64 >= allowed {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("retry budget spent, not retrying");
+        return false;
+    }
+    sent.retries.push_back(now);
+    true
+}
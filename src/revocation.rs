@@ -0,0 +1,386 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rustls::{Certificate, TLSError};
+
+use crate::date::civil_from_days;
+use crate::error::Error;
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_ENUMERATED: u8 = 0x0a;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+
+const ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const ECDSA_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const RSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const RSA_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const RSA_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+/// id-ce-extKeyUsage
+const EXT_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+/// id-kp-OCSPSigning
+const OCSP_SIGNING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x09];
+
+/// A certificate revocation list (CRL) for
+/// [`Agent::add_crl()`](struct.Agent.html#method.add_crl).
+///
+/// The signature of the list is not checked, so it must come from a trusted source.
+///
+/// ```no_run
+/// let der = std::fs::read("revoked.crl").unwrap();
+/// let crl = ureq::Crl::from_der(&der).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Crl {
+    /// DER of the issuer name.
+    issuer: Vec<u8>,
+    /// Serial numbers of the revoked certificates.
+    serials: Vec<Vec<u8>>,
+}
+
+impl Crl {
+    /// Parse a DER encoded CRL, as published by certificate authorities.
+    pub fn from_der(der: &[u8]) -> Result<Crl, Error> {
+        parse_crl(der).ok_or_else(|| Error::BadCrl("Not a DER encoded CRL".to_string()))
+    }
+
+    /// Parse a PEM encoded CRL, starting with `-----BEGIN X509 CRL-----`.
+    pub fn from_pem(pem: &str) -> Result<Crl, Error> {
+        let der = pem_to_der(pem, "X509 CRL")
+            .ok_or_else(|| Error::BadCrl("Not a PEM encoded CRL".to_string()))?;
+        Crl::from_der(&der)
+    }
+
+    /// Whether the list revokes a DER encoded certificate.
+    pub(crate) fn revokes(&self, cert: &[u8]) -> bool {
+        match serial_and_issuer(cert) {
+            Some((serial, issuer)) => {
+                issuer == &self.issuer[..] && self.serials.iter().any(|s| s == serial)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Fail for a chain where any certificate is revoked by one of the lists.
+pub(crate) fn check_crls(crls: &[Crl], certs: &[Certificate]) -> Result<(), TLSError> {
+    for cert in certs {
+        if crls.iter().any(|crl| crl.revokes(&cert.0)) {
+            return Err(TLSError::General(
+                "Certificate is revoked by CRL".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Fail unless the OCSP response stapled by the server is signed by the issuer
+/// of the certificate, or a responder it delegated to, and says the certificate
+/// is good.
+pub(crate) fn check_ocsp(response: &[u8], certs: &[Certificate]) -> Result<(), TLSError> {
+    let fail = |msg: &str| Err(TLSError::General(msg.to_string()));
+    if response.is_empty() {
+        return fail("No OCSP response stapled");
+    }
+    let ocsp = match parse_ocsp(response) {
+        Some(ocsp) => ocsp,
+        None => return fail("Bad OCSP response"),
+    };
+    let serial = match certs.first().and_then(|c| serial_and_issuer(&c.0)) {
+        Some((serial, _)) => serial,
+        None => return fail("Bad certificate"),
+    };
+    // an issuer only known as trust anchor has no certificate to check with.
+    let issuer = match certs.get(1) {
+        Some(issuer) => &issuer.0[..],
+        None => return fail("No issuer certificate to check the OCSP response"),
+    };
+
+    let signed_by = |signer: &[u8]| verify_signed(signer, ocsp.alg, ocsp.tbs, ocsp.signature);
+    let delegated = |responder: &&[u8]| {
+        is_ocsp_signer(responder)
+            && signed_parts(responder)
+                .map(|(tbs, alg, sig)| verify_signed(issuer, alg, tbs, sig))
+                .unwrap_or(false)
+            && signed_by(responder)
+    };
+    if !signed_by(issuer) && !ocsp.certs.iter().any(delegated) {
+        return fail("OCSP response is not signed by the issuer");
+    }
+
+    let single = match ocsp.responses.iter().find(|r| r.serial == serial) {
+        Some(single) => single,
+        None => return fail("OCSP response is for another certificate"),
+    };
+    if let Some(next_update) = &single.next_update {
+        if *next_update < now() {
+            return fail("OCSP response is expired");
+        }
+    }
+    match single.status {
+        0x80 => Ok(()),
+        0xa1 => fail("Certificate is revoked by OCSP"),
+        _ => fail("Certificate has unknown OCSP status"),
+    }
+}
+
+/// The DER between the `BEGIN` and `END` lines of a PEM block.
+pub(crate) fn pem_to_der(pem: &str, label: &str) -> Option<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let start = pem.find(&begin)? + begin.len();
+    let stop = start + pem[start..].find(&end)?;
+    let text: String = pem[start..stop]
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    base64::decode(&text).ok()
+}
+
+/// Reads DER elements one by one.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+
+    /// The next element as `(tag, contents, whole encoding)`.
+    fn next_any(&mut self) -> Option<(u8, &'a [u8], &'a [u8])> {
+        let data = self.0;
+        let tag = *data.first()?;
+        let first = *data.get(1)? as usize;
+        let (len, head) = if first < 0x80 {
+            (first, 2)
+        } else {
+            let count = first & 0x7f;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let len = data
+                .get(2..2 + count)?
+                .iter()
+                .fold(0, |len, b| len << 8 | *b as usize);
+            (len, 2 + count)
+        };
+        let end = head.checked_add(len)?;
+        let contents = data.get(head..end)?;
+        let whole = &data[..end];
+        self.0 = &data[end..];
+        Some((tag, contents, whole))
+    }
+
+    /// The contents of the next element, which must have the tag.
+    fn next(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.next_any()? {
+            (t, contents, _) if t == tag => Some(contents),
+            _ => None,
+        }
+    }
+
+    /// Skip the next element if it has the tag.
+    fn skip_if(&mut self, tag: u8) -> Option<()> {
+        if self.peek() == Some(tag) {
+            self.next_any()?;
+        }
+        Some(())
+    }
+
+    /// The next element if it is a time, as `YYYYMMDDHHMMSSZ`.
+    fn next_time(&mut self) -> Option<String> {
+        let (tag, contents, _) = self.next_any()?;
+        let text = std::str::from_utf8(contents).ok()?;
+        match tag {
+            // two digit years from 1950 to 2049.
+            TAG_UTC_TIME if text.starts_with(|c| c < '5') => Some(format!("20{}", text)),
+            TAG_UTC_TIME => Some(format!("19{}", text)),
+            TAG_GENERALIZED_TIME => Some(text.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Serial number and DER of the issuer name of a certificate.
+fn serial_and_issuer(cert: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut tbs = Der(Der(Der(cert).next(TAG_SEQUENCE)?).next(TAG_SEQUENCE)?);
+    tbs.skip_if(0xa0)?;
+    let serial = tbs.next(TAG_INTEGER)?;
+    tbs.next(TAG_SEQUENCE)?;
+    let (_, _, issuer) = tbs.next_any()?;
+    Some((serial, issuer))
+}
+
+/// The signed part, signature algorithm and signature of a certificate or
+/// other signed structure.
+fn signed_parts(der: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let mut signed = Der(Der(der).next(TAG_SEQUENCE)?);
+    let (_, _, tbs) = signed.next_any()?;
+    let alg = Der(signed.next(TAG_SEQUENCE)?).next(TAG_OID)?;
+    // a bit string, starting with the number of unused bits.
+    let signature = signed.next(0x03)?.get(1..)?;
+    Some((tbs, alg, signature))
+}
+
+fn parse_crl(der: &[u8]) -> Option<Crl> {
+    let mut tbs = Der(Der(Der(der).next(TAG_SEQUENCE)?).next(TAG_SEQUENCE)?);
+    tbs.skip_if(TAG_INTEGER)?;
+    tbs.next(TAG_SEQUENCE)?;
+    let (_, _, issuer) = tbs.next_any()?;
+    tbs.next_time()?;
+    if let Some(TAG_UTC_TIME) | Some(TAG_GENERALIZED_TIME) = tbs.peek() {
+        tbs.next_time()?;
+    }
+    let mut serials = vec![];
+    if tbs.peek() == Some(TAG_SEQUENCE) {
+        let mut revoked = Der(tbs.next(TAG_SEQUENCE)?);
+        while revoked.peek().is_some() {
+            let serial = Der(revoked.next(TAG_SEQUENCE)?).next(TAG_INTEGER)?;
+            serials.push(serial.to_vec());
+        }
+    }
+    Some(Crl {
+        issuer: issuer.to_vec(),
+        serials,
+    })
+}
+
+/// A `BasicOCSPResponse`.
+struct Ocsp<'a> {
+    tbs: &'a [u8],
+    alg: &'a [u8],
+    signature: &'a [u8],
+    responses: Vec<SingleResponse<'a>>,
+    /// Certificates of a delegated responder.
+    certs: Vec<&'a [u8]>,
+}
+
+struct SingleResponse<'a> {
+    serial: &'a [u8],
+    /// `0x80` good, `0xa1` revoked, `0x82` unknown.
+    status: u8,
+    next_update: Option<String>,
+}
+
+fn parse_ocsp(der: &[u8]) -> Option<Ocsp<'_>> {
+    let mut outer = Der(Der(der).next(TAG_SEQUENCE)?);
+    // only a successful response has a body.
+    if outer.next(TAG_ENUMERATED)? != [0] {
+        return None;
+    }
+    let mut bytes = Der(Der(outer.next(0xa0)?).next(TAG_SEQUENCE)?);
+    bytes.next(TAG_OID)?;
+    let basic = bytes.next(TAG_OCTET_STRING)?;
+
+    let (tbs, alg, signature) = signed_parts(basic)?;
+    let mut rest = Der(Der(basic).next(TAG_SEQUENCE)?);
+    rest.next_any()?;
+    rest.next_any()?;
+    rest.next_any()?;
+    let mut certs = vec![];
+    if rest.peek() == Some(0xa0) {
+        let mut list = Der(Der(rest.next(0xa0)?).next(TAG_SEQUENCE)?);
+        while let Some((_, _, cert)) = list.next_any() {
+            certs.push(cert);
+        }
+    }
+
+    let mut data = Der(Der(tbs).next(TAG_SEQUENCE)?);
+    data.skip_if(0xa0)?;
+    // responder id, by name or key hash.
+    data.next_any()?;
+    data.next_time()?;
+    let mut list = Der(data.next(TAG_SEQUENCE)?);
+    let mut responses = vec![];
+    while list.peek().is_some() {
+        let mut single = Der(list.next(TAG_SEQUENCE)?);
+        let mut cert_id = Der(single.next(TAG_SEQUENCE)?);
+        cert_id.next(TAG_SEQUENCE)?;
+        cert_id.next(TAG_OCTET_STRING)?;
+        cert_id.next(TAG_OCTET_STRING)?;
+        let serial = cert_id.next(TAG_INTEGER)?;
+        let (status, _, _) = single.next_any()?;
+        single.next_time()?;
+        let next_update = match single.peek() {
+            Some(0xa0) => Some(Der(single.next(0xa0)?).next_time()?),
+            _ => None,
+        };
+        responses.push(SingleResponse {
+            serial,
+            status,
+            next_update,
+        });
+    }
+
+    Some(Ocsp {
+        tbs,
+        alg,
+        signature,
+        responses,
+        certs,
+    })
+}
+
+/// Whether a certificate may sign OCSP responses for its issuer.
+fn is_ocsp_signer(cert: &[u8]) -> bool {
+    let eku = || -> Option<bool> {
+        let mut tbs = Der(Der(Der(cert).next(TAG_SEQUENCE)?).next(TAG_SEQUENCE)?);
+        while tbs.peek()? != 0xa3 {
+            tbs.next_any()?;
+        }
+        let mut exts = Der(Der(tbs.next(0xa3)?).next(TAG_SEQUENCE)?);
+        while exts.peek().is_some() {
+            let mut ext = Der(exts.next(TAG_SEQUENCE)?);
+            if ext.next(TAG_OID)? != EXT_KEY_USAGE {
+                continue;
+            }
+            ext.skip_if(0x01)?;
+            let mut usages = Der(Der(ext.next(TAG_OCTET_STRING)?).next(TAG_SEQUENCE)?);
+            while usages.peek().is_some() {
+                if usages.next(TAG_OID)? == OCSP_SIGNING {
+                    return Some(true);
+                }
+            }
+        }
+        Some(false)
+    };
+    eku().unwrap_or(false)
+}
+
+/// Whether `signature` over `msg` is by the key of the `signer` certificate.
+fn verify_signed(signer: &[u8], alg: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+    let algs: &[&webpki::SignatureAlgorithm] = match alg {
+        ECDSA_SHA256 => &[&webpki::ECDSA_P256_SHA256, &webpki::ECDSA_P384_SHA256],
+        ECDSA_SHA384 => &[&webpki::ECDSA_P384_SHA384, &webpki::ECDSA_P256_SHA384],
+        RSA_SHA256 => &[&webpki::RSA_PKCS1_2048_8192_SHA256],
+        RSA_SHA384 => &[&webpki::RSA_PKCS1_2048_8192_SHA384],
+        RSA_SHA512 => &[&webpki::RSA_PKCS1_2048_8192_SHA512],
+        _ => &[],
+    };
+    let signer = match webpki::EndEntityCert::from(signer) {
+        Ok(signer) => signer,
+        Err(_) => return false,
+    };
+    algs.iter()
+        .any(|alg| signer.verify_signature(alg, msg, signature).is_ok())
+}
+
+/// The current time as `YYYYMMDDHHMMSSZ`, to compare with DER times.
+fn now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let secs = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::time::UNIX_EPOCH;
+
+use ed25519_dalek::Signer;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::header::Header;
+use crate::time;
+use crate::unit::Unit;
+
+/// Label of the signature in `Signature-Input` and `Signature`.
+const LABEL: &str = "sig1";
+
+/// A key to sign requests with, see
+/// [`Request::sign()`](struct.Request.html#method.sign).
+#[derive(Clone)]
+pub struct SigningKey {
+    id: String,
+    alg: Alg,
+}
+
+#[derive(Clone)]
+enum Alg {
+    HmacSha256(Vec<u8>),
+    Ed25519(Box<ed25519_dalek::SigningKey>),
+}
+
+impl SigningKey {
+    /// A secret shared with the server, sent as `keyid` so the server knows
+    /// which one it is.
+    pub fn hmac_sha256(key_id: &str, secret: &[u8]) -> SigningKey {
+        SigningKey {
+            id: key_id.to_string(),
+            alg: Alg::HmacSha256(secret.to_vec()),
+        }
+    }
+
+    /// An Ed25519 private key, the 32 bytes of its seed.
+    pub fn ed25519(key_id: &str, secret: &[u8; 32]) -> SigningKey {
+        SigningKey {
+            id: key_id.to_string(),
+            alg: Alg::Ed25519(Box::new(ed25519_dalek::SigningKey::from_bytes(secret))),
+        }
+    }
+
+    fn sign(&self, base: &[u8]) -> Vec<u8> {
+        match &self.alg {
+            Alg::HmacSha256(secret) => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(secret).expect("hmac takes keys of any size");
+                mac.update(base);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Alg::Ed25519(key) => key.sign(base).to_bytes().to_vec(),
+        }
+    }
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SigningKey[{}]", self.id)
+    }
+}
+
+/// The key and components a request is signed with.
+///
+/// *Internal API*
+#[derive(Clone, Debug)]
+pub(crate) struct Signing {
+    pub key: SigningKey,
+    /// Lowercased header names and derived components like `@method`.
+    pub components: Vec<String>,
+}
+
+/// Add `Signature-Input` and `Signature` to the headers about to be sent.
+pub(crate) fn sign(unit: &Unit, signing: &Signing, headers: &mut Vec<Header>) -> IoResult<()> {
+    let created = time::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    sign_at(unit, signing, headers, created)
+}
+
+pub(crate) fn sign_at(
+    unit: &Unit,
+    signing: &Signing,
+    headers: &mut Vec<Header>,
+    created: u64,
+) -> IoResult<()> {
+    let covered: Vec<String> = signing
+        .components
+        .iter()
+        .map(|c| format!("\"{}\"", c))
+        .collect();
+    let params = format!(
+        "({});created={};keyid=\"{}\"",
+        covered.join(" "),
+        created,
+        signing.key.id
+    );
+    let mut base = String::new();
+    for component in &signing.components {
+        let value = component_value(unit, headers, component)?;
+        base.push_str(&format!("\"{}\": {}\n", component, value));
+    }
+    base.push_str(&format!("\"@signature-params\": {}", params));
+
+    let signature = base64::encode(&signing.key.sign(base.as_bytes()));
+    headers.push(Header::new(
+        "Signature-Input",
+        &format!("{}={}", LABEL, params),
+    ));
+    headers.push(Header::new(
+        "Signature",
+        &format!("{}=:{}:", LABEL, signature),
+    ));
+    Ok(())
+}
+
+fn component_value(unit: &Unit, headers: &[Header], component: &str) -> IoResult<String> {
+    let url = &unit.url;
+    let path = match url.path() {
+        "" => "/",
+        path => path,
+    };
+    let query = &unit.query_string;
+    Ok(match component {
+        "@method" => unit.method.to_ascii_uppercase(),
+        "@scheme" => url.scheme().to_ascii_lowercase(),
+        "@authority" => authority(unit, headers),
+        "@target-uri" => format!(
+            "{}://{}{}{}",
+            url.scheme(),
+            authority(unit, headers),
+            path,
+            query
+        ),
+        "@request-target" => format!("{}{}", path, query),
+        "@path" => path.to_string(),
+        "@query" if query.is_empty() => "?".to_string(),
+        "@query" => query.to_string(),
+        _ if component.starts_with('@') => {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown signature component: {}", component),
+            ))
+        }
+        _ => {
+            let values: Vec<&str> = headers
+                .iter()
+                .filter(|h| h.is_name(component))
+                .map(|h| h.value().trim())
+                .collect();
+            if values.is_empty() {
+                return Err(IoError::new(
+                    ErrorKind::InvalidInput,
+                    format!("Signed header is not sent: {}", component),
+                ));
+            }
+            values.join(", ")
+        }
+    })
+}
+
+/// The `Host` sent, which is the authority of the url unless overridden.
+fn authority(unit: &Unit, headers: &[Header]) -> String {
+    match headers.iter().find(|h| h.is_name("host")) {
+        Some(host) => host.value().trim().to_ascii_lowercase(),
+        None => unit.url.host_str().unwrap_or_default().to_ascii_lowercase(),
+    }
+}
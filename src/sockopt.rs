@@ -0,0 +1,75 @@
+use std::io::{Error as IoError, Result as IoResult};
+use std::net::{SocketAddr, TcpStream};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// Options set on the sockets of an agent before connecting, see
+/// [`Agent::socket_mark()`](struct.Agent.html#method.socket_mark),
+/// [`Agent::dscp()`](struct.Agent.html#method.dscp) and
+/// [`Agent::tcp_fast_open()`](struct.Agent.html#method.tcp_fast_open).
+///
+/// *Internal API*
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SocketOptions {
+    pub mark: Option<u32>,
+    pub dscp: Option<u8>,
+    pub fast_open: bool,
+}
+
+impl SocketOptions {
+    pub fn is_set(&self) -> bool {
+        self.mark.is_some() || self.dscp.is_some() || self.fast_open
+    }
+}
+
+/// Connect a socket with the options set, so that they apply from the handshake on.
+pub(crate) fn connect(
+    addr: SocketAddr,
+    options: &SocketOptions,
+    timeout: Option<Duration>,
+) -> IoResult<TcpStream> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if let Some(mark) = options.mark {
+        socket.set_mark(mark)?;
+    }
+    if let Some(dscp) = options.dscp {
+        // the DSCP is the upper six bits of the TOS byte.
+        let tos = u32::from(dscp) << 2;
+        if addr.is_ipv6() {
+            socket.set_tclass_v6(tos)?;
+        } else {
+            socket.set_tos(tos)?;
+        }
+    }
+    if options.fast_open {
+        // unsupported by the kernel is a normal connect.
+        set_fast_open_connect(&socket).ok();
+    }
+    match timeout {
+        Some(timeout) => socket.connect_timeout(&addr.into(), timeout)?,
+        None => socket.connect(&addr.into())?,
+    }
+    Ok(socket.into())
+}
+
+/// With `TCP_FASTOPEN_CONNECT`, connecting waits for the first write, which then
+/// goes out in the SYN when the kernel has a Fast Open cookie of the server.
+fn set_fast_open_connect(socket: &Socket) -> IoResult<()> {
+    let enable: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_FASTOPEN_CONNECT,
+            &enable as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(IoError::last_os_error())
+    }
+}
@@ -0,0 +1,234 @@
+use std::io::{BufRead, BufReader, Read, Result as IoResult};
+use std::thread;
+use std::time::Duration;
+
+use crate::agent::Agent;
+use crate::error::Error;
+
+/// Wait before reconnecting when the server doesn't say with `retry:`.
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+/// An event of a `text/event-stream` response, see
+/// [`Response::into_events()`](struct.Response.html#method.into_events).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    id: Option<String>,
+    event: String,
+    data: String,
+}
+
+impl Event {
+    /// The last `id:` the server sent, in this event or an earlier one.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// The `event:` type, `message` when the server sent none.
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+
+    /// The `data:` lines, joined by newlines.
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+}
+
+/// Iterator of the events of a response body, read as they arrive.
+pub struct Events {
+    reader: BufReader<Box<dyn Read>>,
+    last_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Events {
+    pub(crate) fn new(reader: Box<dyn Read>, last_id: Option<String>) -> Self {
+        Events {
+            reader: BufReader::new(reader),
+            last_id,
+            retry: None,
+        }
+    }
+
+    /// The reconnection time the server asked for with `retry:`.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Read up to the blank line ending an event. Events without data are
+    /// skipped, and an event cut short by the end of the body is dropped.
+    fn next_event(&mut self) -> IoResult<Option<Event>> {
+        let mut event = String::new();
+        let mut data = String::new();
+        let mut has_data = false;
+        let mut buf = vec![];
+        loop {
+            buf.clear();
+            if self.reader.read_until(b'\n', &mut buf)? == 0 {
+                return Ok(None);
+            }
+            while buf.last() == Some(&b'\n') || buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+            let line = String::from_utf8_lossy(&buf);
+
+            if line.is_empty() {
+                if !has_data {
+                    event.clear();
+                    continue;
+                }
+                data.pop();
+                if event.is_empty() {
+                    event.push_str("message");
+                }
+                return Ok(Some(Event {
+                    id: self.last_id.clone(),
+                    event,
+                    data,
+                }));
+            }
+            if line.starts_with(':') {
+                // a comment, such as a keep-alive.
+                continue;
+            }
+            let (field, value) = match line.find(':') {
+                Some(i) => {
+                    let value = &line[i + 1..];
+                    (&line[..i], value.strip_prefix(' ').unwrap_or(value))
+                }
+                None => (&line[..], ""),
+            };
+            match field {
+                "data" => {
+                    data.push_str(value);
+                    data.push('\n');
+                    has_data = true;
+                }
+                "event" => event = value.to_string(),
+                "id" if !value.contains('\0') => {
+                    self.last_id = Some(value.to_string()).filter(|id| !id.is_empty());
+                }
+                "retry" => {
+                    if let Ok(millis) = value.parse::<u64>() {
+                        self.retry = Some(Duration::from_millis(millis));
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl ::std::fmt::Debug for Events {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "Events")
+    }
+}
+
+impl Iterator for Events {
+    type Item = IoResult<Event>;
+
+    fn next(&mut self) -> Option<IoResult<Event>> {
+        self.next_event().transpose()
+    }
+}
+
+/// Events of a url that is reconnected to when the stream ends or fails, see
+/// [`Agent::subscribe()`](struct.Agent.html#method.subscribe).
+#[derive(Debug)]
+pub struct Subscription {
+    agent: Agent,
+    path: String,
+    events: Option<Events>,
+    last_id: Option<String>,
+    retry: Duration,
+    connected: bool,
+    done: bool,
+}
+
+impl Subscription {
+    pub(crate) fn new(agent: &Agent, path: &str) -> Self {
+        Subscription {
+            agent: agent.clone(),
+            path: path.to_string(),
+            events: None,
+            last_id: None,
+            retry: DEFAULT_RETRY,
+            connected: false,
+            done: false,
+        }
+    }
+
+    /// The id of the last event, sent as `Last-Event-ID` when reconnecting.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_id.as_deref()
+    }
+
+    /// `Ok(None)` when the server asked not to reconnect.
+    fn connect(&mut self) -> Result<Option<Events>, Error> {
+        let mut req = self.agent.get(&self.path);
+        req.set("Accept", "text/event-stream")
+            .set("Cache-Control", "no-cache");
+        if let Some(id) = &self.last_id {
+            req.set("Last-Event-ID", id);
+        }
+        let resp = match req.call().into_result() {
+            Ok(resp) => resp,
+            Err(Error::Status(status, resp)) => {
+                self.done = status < 500;
+                return Err(Error::Status(status, resp));
+            }
+            Err(e) => return Err(e),
+        };
+        match resp.status() {
+            204 => Ok(None),
+            200..=299 => {
+                let reader = Box::new(resp.into_reader());
+                Ok(Some(Events::new(reader, self.last_id.clone())))
+            }
+            status => {
+                self.done = status < 500;
+                Err(Error::Status(status, Box::new(resp)))
+            }
+        }
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Result<Event, Error>> {
+        loop {
+            if self.done {
+                return None;
+            }
+            if self.events.is_none() {
+                if self.connected {
+                    thread::sleep(self.retry);
+                }
+                self.connected = true;
+                match self.connect() {
+                    Ok(Some(events)) => self.events = Some(events),
+                    Ok(None) => self.done = true,
+                    Err(e) => return Some(Err(e)),
+                }
+                continue;
+            }
+            let events = self.events.as_mut().unwrap();
+            let next = events.next();
+            // kept from events cut short too.
+            self.last_id = events.last_id.clone();
+            if let Some(retry) = events.retry {
+                self.retry = retry;
+            }
+            match next {
+                Some(Ok(event)) => return Some(Ok(event)),
+                Some(Err(e)) => {
+                    self.events = None;
+                    return Some(Err(e.into()));
+                }
+                None => self.events = None,
+            }
+        }
+    }
+}
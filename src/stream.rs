@@ -1,7 +1,9 @@
-use std::io::{Cursor, Read, Result as IoResult, Write};
+use std::io::{Cursor, ErrorKind, Read, Result as IoResult, Write};
 use std::net::SocketAddr;
 use std::net::TcpStream;
 use std::net::ToSocketAddrs;
+use std::sync::{mpsc, Arc};
+use std::thread;
 use std::time::Duration;
 
 #[cfg(feature = "tls")]
@@ -10,8 +12,41 @@ use rustls::ClientSession;
 use rustls::StreamOwned;
 
 use crate::error::Error;
-use crate::unit::Unit;
+use crate::faults::FaultStream;
+#[cfg(feature = "http3")]
+use crate::http3::H3Stream;
+use crate::proxy;
 use crate::request::IpVersion;
+use crate::time::Instant;
+#[cfg(feature = "tls")]
+use crate::tls::{TlsConfig, TlsSettings};
+use crate::unit::Unit;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+use crate::wasm::XhrStream;
+
+/// Which way bytes passed to an [`Agent::on_wire()`](struct.Agent.html#method.on_wire)
+/// hook went over the socket.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Written to the server.
+    Sent,
+    /// Read from the server.
+    Received,
+}
+
+type WireFn = dyn Fn(Direction, &[u8]) + Send + Sync;
+
+/// Callback receiving the plaintext bytes of a connection.
+///
+/// *Internal API*
+#[derive(Clone)]
+pub(crate) struct WireHook(pub Arc<WireFn>);
+
+impl ::std::fmt::Debug for WireHook {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "WireHook")
+    }
+}
 
 #[allow(clippy::large_enum_variant)]
 pub enum Stream {
@@ -19,24 +54,47 @@ pub enum Stream {
     #[cfg(feature = "tls")]
     Https(rustls::StreamOwned<rustls::ClientSession, TcpStream>),
     Cursor(Cursor<Vec<u8>>),
+    /// The file of a `file:` url.
+    #[cfg(feature = "file")]
+    File(std::fs::File),
     #[cfg(test)]
-    Test(Box<dyn Read + Send>, Vec<u8>),
+    Test(Box<dyn Read + Send + Sync>, Vec<u8>),
+    /// Another stream whose traffic is passed to a hook.
+    Wire(Box<Stream>, WireHook),
+    /// Another stream failing on purpose, for testing.
+    Fault(Box<FaultStream>),
+    /// TLS by the Security framework.
+    #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+    Apple(Box<security_framework::secure_transport::SslStream<TcpStream>>),
+    /// A synchronous `XMLHttpRequest` made by the browser.
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    Xhr(XhrStream),
+    /// A request over a HTTP/3 connection.
+    #[cfg(feature = "http3")]
+    Http3(Box<H3Stream>),
 }
 
 impl ::std::fmt::Debug for Stream {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
-        write!(
-            f,
-            "Stream[{}]",
-            match self {
-                Stream::Http(_) => "http",
-                #[cfg(feature = "tls")]
-                Stream::Https(_) => "https",
-                Stream::Cursor(_) => "cursor",
-                #[cfg(test)]
-                Stream::Test(_, _) => "test",
-            }
-        )
+        let kind = match self {
+            Stream::Http(_) => "http",
+            #[cfg(feature = "tls")]
+            Stream::Https(_) => "https",
+            Stream::Cursor(_) => "cursor",
+            #[cfg(feature = "file")]
+            Stream::File(_) => "file",
+            #[cfg(test)]
+            Stream::Test(_, _) => "test",
+            Stream::Wire(stream, _) => return stream.fmt(f),
+            Stream::Fault(stream) => return stream.stream().fmt(f),
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            Stream::Apple(_) => "https",
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            Stream::Xhr(_) => "xhr",
+            #[cfg(feature = "http3")]
+            Stream::Http3(_) => "http3",
+        };
+        write!(f, "Stream[{}]", kind)
     }
 }
 
@@ -46,14 +104,156 @@ impl Stream {
             Stream::Http(_) => true,
             #[cfg(feature = "tls")]
             Stream::Https(_) => true,
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            Stream::Apple(_) => true,
+            Stream::Wire(stream, _) => stream.is_poolable(),
+            Stream::Fault(stream) => !stream.is_broken() && stream.stream().is_poolable(),
             _ => false,
         }
     }
 
+    /// Whether an idle connection can't be used for another request, because
+    /// the server closed it or sent something unasked. Probes the socket
+    /// without blocking. TLS records are taken in, so a session ticket or other
+    /// handshake message doesn't count, only application data, the end of the
+    /// stream or an error. The Security framework only sees the socket.
+    pub fn is_stale(&mut self) -> bool {
+        let sock = match self {
+            Stream::Http(sock) => &*sock,
+            #[cfg(feature = "tls")]
+            Stream::Https(stream) => {
+                use rustls::Session;
+                if stream.sock.set_nonblocking(true).is_err() {
+                    return true;
+                }
+                let mut read = false;
+                let closed = loop {
+                    match stream.sess.read_tls(&mut stream.sock) {
+                        Ok(0) => break true,
+                        Ok(_) => read = true,
+                        Err(e) => break e.kind() != ErrorKind::WouldBlock,
+                    }
+                };
+                if stream.sock.set_nonblocking(false).is_err() {
+                    return true;
+                }
+                if closed || (read && stream.sess.process_new_packets().is_err()) {
+                    return true;
+                }
+                // plaintext no request was sent for.
+                return !stream.sess.wants_read();
+            }
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            Stream::Apple(stream) => stream.get_ref(),
+            Stream::Wire(stream, _) => return stream.is_stale(),
+            Stream::Fault(stream) => return stream.is_broken() || stream.stream_mut().is_stale(),
+            _ => return false,
+        };
+        if sock.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let probe = sock.peek(&mut [0]);
+        if sock.set_nonblocking(false).is_err() {
+            return true;
+        }
+        match probe {
+            Err(e) => e.kind() != ErrorKind::WouldBlock,
+            // end of stream, or bytes no request was sent for.
+            Ok(_) => true,
+        }
+    }
+
+    /// Whether the server sent something to read, such as an early response
+    /// while the request body is still being sent. Probes without blocking.
+    /// TLS records are taken in, so only application data counts and not a
+    /// session ticket. Always `false` for the Security framework and streams
+    /// without a socket.
+    pub(crate) fn has_response(&mut self) -> bool {
+        match self {
+            Stream::Http(sock) => {
+                if sock.set_nonblocking(true).is_err() {
+                    return false;
+                }
+                let probe = sock.peek(&mut [0]);
+                let _ = sock.set_nonblocking(false);
+                // the end of stream shows when writing.
+                matches!(probe, Ok(n) if n > 0)
+            }
+            #[cfg(feature = "tls")]
+            Stream::Https(stream) => {
+                use rustls::Session;
+                if stream.sock.set_nonblocking(true).is_err() {
+                    return false;
+                }
+                let mut read = false;
+                while let Ok(n) = stream.sess.read_tls(&mut stream.sock) {
+                    if n == 0 {
+                        break;
+                    }
+                    read = true;
+                }
+                let _ = stream.sock.set_nonblocking(false);
+                if read && stream.sess.process_new_packets().is_err() {
+                    // reading the response surfaces the error.
+                    return true;
+                }
+                // rustls wants no reads while it has plaintext.
+                !stream.sess.wants_read()
+            }
+            Stream::Wire(stream, _) => stream.has_response(),
+            Stream::Fault(stream) => !stream.is_broken() && stream.stream_mut().has_response(),
+            _ => false,
+        }
+    }
+
+    /// Make reads of the socket return `WouldBlock` instead of waiting, to read
+    /// only what already arrived. Other streams never wait.
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        match self {
+            Stream::Http(sock) => sock.set_nonblocking(nonblocking),
+            #[cfg(feature = "tls")]
+            Stream::Https(stream) => stream.sock.set_nonblocking(nonblocking),
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            Stream::Apple(stream) => stream.get_ref().set_nonblocking(nonblocking),
+            Stream::Wire(stream, _) => stream.set_nonblocking(nonblocking),
+            Stream::Fault(stream) => stream.stream().set_nonblocking(nonblocking),
+            _ => Ok(()),
+        }
+    }
+
+    /// The address of the other end of the socket.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Stream::Http(sock) => sock.peer_addr().ok(),
+            #[cfg(feature = "tls")]
+            Stream::Https(stream) => stream.sock.peer_addr().ok(),
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            Stream::Apple(stream) => stream.get_ref().peer_addr().ok(),
+            Stream::Wire(stream, _) => stream.peer_addr(),
+            Stream::Fault(stream) => stream.stream().peer_addr(),
+            #[cfg(feature = "http3")]
+            Stream::Http3(stream) => Some(stream.peer_addr()),
+            _ => None,
+        }
+    }
+
+    /// The protocol the server picked by ALPN, for TLS connections.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match self {
+            #[cfg(feature = "tls")]
+            Stream::Https(stream) => rustls::Session::get_alpn_protocol(&stream.sess),
+            Stream::Wire(stream, _) => stream.alpn_protocol(),
+            Stream::Fault(stream) => stream.stream().alpn_protocol(),
+            _ => None,
+        }
+    }
+
     #[cfg(test)]
     pub fn to_write_vec(&self) -> Vec<u8> {
         match self {
             Stream::Test(_, writer) => writer.clone(),
+            Stream::Wire(stream, _) => stream.to_write_vec(),
+            Stream::Fault(stream) => stream.stream().to_write_vec(),
             _ => panic!("to_write_vec on non Test stream"),
         }
     }
@@ -66,8 +266,24 @@ impl Read for Stream {
             #[cfg(feature = "tls")]
             Stream::Https(stream) => read_https(stream, buf),
             Stream::Cursor(read) => read.read(buf),
+            #[cfg(feature = "file")]
+            Stream::File(file) => file.read(buf),
             #[cfg(test)]
             Stream::Test(reader, _) => reader.read(buf),
+            Stream::Wire(stream, hook) => {
+                let amount = stream.read(buf)?;
+                if amount > 0 {
+                    (hook.0)(Direction::Received, &buf[..amount]);
+                }
+                Ok(amount)
+            }
+            Stream::Fault(stream) => stream.read(buf),
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            Stream::Apple(stream) => stream.read(buf),
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            Stream::Xhr(stream) => stream.read(buf),
+            #[cfg(feature = "http3")]
+            Stream::Http3(stream) => stream.read(buf),
         }
     }
 }
@@ -84,6 +300,7 @@ fn read_https(
     }
 }
 
+#[cfg(feature = "tls")]
 fn is_close_notify(e: &std::io::Error) -> bool {
     if e.kind() != std::io::ErrorKind::ConnectionAborted {
         return false;
@@ -92,7 +309,7 @@ fn is_close_notify(e: &std::io::Error) -> bool {
     if let Some(msg) = e.get_ref() {
         // :(
 
-        return msg.description().contains("CloseNotify");
+        return msg.to_string().contains("CloseNotify");
     }
 
     false
@@ -105,8 +322,22 @@ impl Write for Stream {
             #[cfg(feature = "tls")]
             Stream::Https(stream) => stream.write(buf),
             Stream::Cursor(_) => panic!("Write to read only stream"),
+            #[cfg(feature = "file")]
+            Stream::File(_) => panic!("Write to read only stream"),
             #[cfg(test)]
             Stream::Test(_, writer) => writer.write(buf),
+            Stream::Wire(stream, hook) => {
+                let amount = stream.write(buf)?;
+                (hook.0)(Direction::Sent, &buf[..amount]);
+                Ok(amount)
+            }
+            Stream::Fault(stream) => stream.write(buf),
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            Stream::Apple(stream) => stream.write(buf),
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            Stream::Xhr(stream) => stream.write(buf),
+            #[cfg(feature = "http3")]
+            Stream::Http3(stream) => stream.write(buf),
         }
     }
     fn flush(&mut self) -> IoResult<()> {
@@ -115,8 +346,18 @@ impl Write for Stream {
             #[cfg(feature = "tls")]
             Stream::Https(stream) => stream.flush(),
             Stream::Cursor(_) => panic!("Flush read only stream"),
+            #[cfg(feature = "file")]
+            Stream::File(_) => panic!("Flush read only stream"),
             #[cfg(test)]
             Stream::Test(_, writer) => writer.flush(),
+            Stream::Wire(stream, _) => stream.flush(),
+            Stream::Fault(stream) => stream.flush(),
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            Stream::Apple(stream) => stream.flush(),
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            Stream::Xhr(stream) => stream.flush(),
+            #[cfg(feature = "http3")]
+            Stream::Http3(stream) => stream.flush(),
         }
     }
 }
@@ -131,81 +372,210 @@ pub(crate) fn connect_http(unit: &Unit) -> Result<Stream, Error> {
 
 #[cfg(feature = "tls")]
 pub(crate) fn connect_https(unit: &Unit) -> Result<Stream, Error> {
-    use lazy_static::lazy_static;
-    use std::sync::Arc;
-
-    lazy_static! {
-        static ref TLS_CONF: Arc<rustls::ClientConfig> = {
-            let mut config = rustls::ClientConfig::new();
-            config
-                .root_store
-                .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
-            Arc::new(config)
-        };
-    }
-
     let hostname = unit.url.host_str().unwrap();
     let port = unit.url.port().unwrap_or(443);
 
-    let sni = webpki::DNSNameRef::try_from_ascii_str(hostname).unwrap();
-    let sess = rustls::ClientSession::new(&*TLS_CONF, sni);
+    let overrides = unit.tls_overrides.as_ref();
+    let server_name = overrides
+        .and_then(|o| o.server_name.as_deref())
+        .unwrap_or(hostname);
+    let sni = webpki::DNSNameRef::try_from_ascii_str(server_name)
+        .map_err(|_| Error::BadUrl(format!("Invalid TLS server name: {}", server_name)))?;
+    // the config is built once per agent, only the session is per connection.
+    let config = match ((*unit.agent).as_ref(), overrides) {
+        (Some(agent), Some(o)) if o.needs_config() => {
+            TlsConfig::with_overrides(&agent.tls_settings, o)
+        }
+        (None, Some(o)) if o.needs_config() => {
+            TlsConfig::with_overrides(&TlsSettings::default(), o)
+        }
+        (Some(agent), _) => agent.tls_config.clone(),
+        (None, _) => TlsConfig::default_config(),
+    };
+    let sess = rustls::ClientSession::new(&config.0, sni);
 
     let sock = connect_host(unit, hostname, port)?;
 
-    let stream = rustls::StreamOwned::new(sess, sock);
+    #[cfg(feature = "tracing")]
+    tracing::debug!(sni = hostname, "tls session started");
+
+    let mut stream = rustls::StreamOwned::new(sess, sock);
+
+    if let Some(events) = &unit.events {
+        // handshake now rather than on the first write, to time it.
+        let start = Instant::now();
+        while rustls::Session::is_handshaking(&stream.sess) {
+            rustls::Session::complete_io(&mut stream.sess, &mut stream.sock)?;
+        }
+        events.on_tls_handshake(hostname, start.elapsed());
+    }
 
     Ok(Stream::Https(stream))
 }
 
 pub(crate) fn connect_host(unit: &Unit, hostname: &str, port: u16) -> Result<TcpStream, Error> {
     //
-    let ips: Vec<SocketAddr> = format!("{}:{}", hostname, port)
-        .to_socket_addrs()
-        .map_err(|e| Error::DnsFailed(format!("{}", e)))?
-        .collect();
+    #[cfg(feature = "pac")]
+    match &unit.pac {
+        Some(pac) if unit.proxies.is_empty() => {
+            return crate::pac::connect(unit, pac, hostname, port)
+        }
+        _ => (),
+    }
+    if !unit.proxies.is_empty() {
+        return proxy::connect_any(unit, &unit.proxies, hostname, port);
+    }
+
+    let ips = target_addrs(unit, hostname, port)?;
+    connect_any(unit, &ips)
+}
+
+/// The addresses to connect to for the host of the url.
+pub(crate) fn target_addrs(
+    unit: &Unit,
+    hostname: &str,
+    port: u16,
+) -> Result<Vec<SocketAddr>, Error> {
+    let ips: Vec<SocketAddr> = match unit.connect_addr {
+        // the hostname is still used for Host and SNI.
+        Some(addr) => vec![addr],
+        None => {
+            let host = match &unit.zone {
+                // getaddrinfo understands both interface names and numbers.
+                Some(zone) => format!("{}%{}", hostname.trim_matches(['[', ']']), zone),
+                None => hostname.trim_matches(['[', ']']).to_string(),
+            };
+            let start = Instant::now();
+            let ips = resolve(host, port, unit.timeout_resolve)?;
+            if let Some(events) = &unit.events {
+                events.on_resolve(hostname, &ips, start.elapsed());
+            }
+            ips
+        }
+    };
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(host = hostname, addrs = ?ips, "dns resolved");
 
     if ips.is_empty() {
         return Err(Error::DnsFailed(format!("No ip address for {}", hostname)));
     }
+    Ok(ips)
+}
 
-    let sock_addr =
-        if let Some(ip) = ips.iter().find(
-            |&&ip|
-            match (ip, unit.preferred_ip_version) {
-                (std::net::SocketAddr::V6(_), IpVersion::V6) => true,
-                (std::net::SocketAddr::V4(_), IpVersion::V4) => true,
-                _ => false,
-            }) {
-            *ip
-        } else {
-            ips[0]
-        };
+/// The first address of the preferred IP version, or else the first address.
+pub(crate) fn pick_addr(unit: &Unit, ips: &[SocketAddr]) -> SocketAddr {
+    if let Some(ip) = ips.iter().find(|&&ip| {
+        matches!(
+            (ip, unit.preferred_ip_version),
+            (SocketAddr::V6(_), IpVersion::V6) | (SocketAddr::V4(_), IpVersion::V4)
+        )
+    }) {
+        *ip
+    } else {
+        ips[0]
+    }
+}
 
+/// Try the addresses in turn, those of the preferred IP version first, until
+/// one connects. Gives the error of the last one tried.
+pub(crate) fn connect_any(unit: &Unit, ips: &[SocketAddr]) -> Result<TcpStream, Error> {
+    let mut ordered = ips.to_vec();
+    // stable, so otherwise in the order of the resolver.
+    ordered.sort_by_key(|ip| match (ip, unit.preferred_ip_version) {
+        (SocketAddr::V6(_), IpVersion::V4) | (SocketAddr::V4(_), IpVersion::V6) => 1,
+        _ => 0,
+    });
+    let mut last_err = None;
+    for addr in ordered {
+        match connect_addr(unit, addr) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(addr = %addr, error = %err, "connect failed, trying next address");
+                last_err = Some(err);
+            }
+        }
+    }
+    Err(last_err.unwrap_or_else(|| Error::ConnectionFailed("No address to connect to".into())))
+}
+
+/// Open a socket with the timeouts of the unit.
+pub(crate) fn connect_addr(unit: &Unit, sock_addr: SocketAddr) -> Result<TcpStream, Error> {
     // connect with a configured timeout.
-    let stream = match unit.timeout_connect {
-        0 => TcpStream::connect(&sock_addr),
-        _ => TcpStream::connect_timeout(
-            &sock_addr,
-            Duration::from_millis(unit.timeout_connect as u64),
-        ),
+    let start = Instant::now();
+    let timeout = match unit.timeout_connect {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    };
+    #[cfg(all(feature = "sockopt", target_os = "linux"))]
+    let result = if unit.socket_options.is_set() {
+        crate::sockopt::connect(sock_addr, &unit.socket_options, timeout)
+    } else {
+        connect_plain(sock_addr, timeout)
+    };
+    #[cfg(not(all(feature = "sockopt", target_os = "linux")))]
+    let result = connect_plain(sock_addr, timeout);
+    let stream = result.map_err(|err| match err.kind() {
+        ErrorKind::ConnectionRefused => Error::ConnectionRefused(err),
+        ErrorKind::TimedOut => Error::ConnectTimeout(err),
+        _ => Error::ConnectionFailed(format!("{}", err)),
+    })?;
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(addr = %sock_addr, "connected");
+    if let Some(events) = &unit.events {
+        events.on_connect(sock_addr, start.elapsed());
     }
-    .map_err(|err| Error::ConnectionFailed(format!("{}", err)))?;
 
     // rust's absurd api returns Err if we set 0.
     if unit.timeout_read > 0 {
         stream
-            .set_read_timeout(Some(Duration::from_millis(unit.timeout_read as u64)))
+            .set_read_timeout(Some(Duration::from_millis(unit.timeout_read)))
             .ok();
     }
     if unit.timeout_write > 0 {
         stream
-            .set_write_timeout(Some(Duration::from_millis(unit.timeout_write as u64)))
+            .set_write_timeout(Some(Duration::from_millis(unit.timeout_write)))
             .ok();
     }
 
     Ok(stream)
 }
 
+fn connect_plain(addr: SocketAddr, timeout: Option<Duration>) -> IoResult<TcpStream> {
+    match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    }
+}
+
+/// Resolve a host, giving up after `timeout` millis unless it is `0`.
+///
+/// getaddrinfo can't be interrupted, so a timed out lookup is left to finish in
+/// its own thread.
+pub(crate) fn resolve(host: String, port: u16, timeout: u64) -> Result<Vec<SocketAddr>, Error> {
+    let lookup = move |host: String| {
+        (host.as_str(), port)
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect::<Vec<_>>())
+            .map_err(|e| Error::DnsFailed(format!("{}", e)))
+    };
+    if timeout == 0 {
+        return lookup(host);
+    }
+    let (tx, rx) = mpsc::channel();
+    let name = host.clone();
+    thread::spawn(move || {
+        // the receiver is gone if we timed out.
+        tx.send(lookup(name)).ok();
+    });
+    match rx.recv_timeout(Duration::from_millis(timeout)) {
+        Ok(result) => result,
+        Err(_) => Err(Error::DnsFailed(format!("Timed out resolving {}", host))),
+    }
+}
+
 #[cfg(test)]
 pub(crate) fn connect_test(unit: &Unit) -> Result<Stream, Error> {
     use crate::test;
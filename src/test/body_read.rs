@@ -80,3 +80,624 @@ fn no_reader_on_head() {
     reader.read_to_string(&mut text).unwrap();
     assert_eq!(text, "");
 }
+
+#[test]
+#[cfg(feature = "msgpack")]
+fn msgpack_read() {
+    use std::collections::BTreeMap;
+
+    test::set_handler("/msgpack_read", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Content-Type: application/msgpack"],
+            vec![0x81, 0xa1, b'a', 0x01],
+        )
+    });
+    let resp = get("test://host/msgpack_read").call();
+    let map: BTreeMap<String, u8> = resp.into_msgpack().unwrap();
+    assert_eq!(map["a"], 1);
+
+    test::set_handler("/msgpack_read", |_unit| {
+        test::make_response(200, "OK", vec![], vec![0x81, 0xa1, b'a', 0x01])
+    });
+    let resp = get("test://host/msgpack_read").call();
+    let err = resp.into_msgpack::<BTreeMap<String, u8>>().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Expected application/msgpack, got text/plain"
+    );
+}
+
+#[test]
+#[cfg(feature = "cbor")]
+fn cbor_read() {
+    use std::collections::BTreeMap;
+
+    test::set_handler("/cbor_read", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Content-Type: application/cbor"],
+            vec![0xa1, 0x61, b'a', 0x01],
+        )
+    });
+    let resp = get("test://host/cbor_read").call();
+    let map: BTreeMap<String, u8> = resp.into_cbor().unwrap();
+    assert_eq!(map["a"], 1);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json_lines_read() {
+    test::set_handler("/json_lines_read", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Transfer-Encoding: chunked"],
+            b"8\r\n[1]\n\n[2,\r\n5\r\n3]\nxx\r\n0\r\n\r\n".to_vec(),
+        )
+    });
+    let resp = get("test://host/json_lines_read").call();
+    let mut lines = resp.into_json_lines::<Vec<u8>>();
+    assert_eq!(lines.next().unwrap().unwrap(), vec![1]);
+    assert_eq!(lines.next().unwrap().unwrap(), vec![2, 3]);
+    assert!(lines.next().unwrap().is_err());
+    assert!(lines.next().is_none());
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json_limited_read() {
+    use std::io::ErrorKind;
+
+    test::set_handler("/json_limited_read", |_unit| {
+        test::make_response(200, "OK", vec![], b"[1,2,3]".to_vec())
+    });
+    let resp = get("test://host/json_limited_read").call();
+    let numbers: Vec<u8> = resp.into_json_limited(7).unwrap();
+    assert_eq!(numbers, vec![1, 2, 3]);
+
+    test::set_handler("/json_limited_read", |_unit| {
+        test::make_response(200, "OK", vec![], b"[1,2,3]".to_vec())
+    });
+    let resp = get("test://host/json_limited_read").call();
+    let err = resp.into_json_limited::<Vec<u8>>(6).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "Response body is over the size limit");
+}
+
+#[test]
+fn raw_reader_binary() {
+    test::set_handler("/raw_reader_binary", |_unit| {
+        let mut body = b"4\r\n".to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, 0x00, 0x80]);
+        body.extend_from_slice(b"\r\n0\r\n\r\n");
+        test::make_response(
+            200,
+            "OK",
+            vec![
+                "Transfer-Encoding: chunked",
+                "Content-Type: text/plain; charset=euc-kr",
+            ],
+            body,
+        )
+    });
+    let resp = get("test://host/raw_reader_binary").call();
+    let mut bytes = vec![];
+    resp.into_raw_reader().read_to_end(&mut bytes).unwrap();
+    assert_eq!(bytes, vec![0xff, 0xfe, 0x00, 0x80]);
+}
+
+#[test]
+fn chunks_read() {
+    test::set_handler("/chunks_read", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Transfer-Encoding: chunked"],
+            b"3;ext=1\r\none\r\n3\r\ntwo\r\n5\r\nthree\r\n0\r\nX-Trailer: 1\r\n\r\n".to_vec(),
+        )
+    });
+    let resp = get("test://host/chunks_read").call();
+    let chunks: Vec<_> = resp.into_chunks().map(|c| c.unwrap()).collect();
+    assert_eq!(
+        chunks,
+        vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+    );
+
+    test::set_handler("/chunks_read_truncated", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Transfer-Encoding: chunked"],
+            b"3\r\none\r\n5\r\ntw".to_vec(),
+        )
+    });
+    let resp = get("test://host/chunks_read_truncated").call();
+    let mut chunks = resp.into_chunks();
+    assert_eq!(chunks.next().unwrap().unwrap(), b"one".to_vec());
+    assert!(chunks.next().unwrap().is_err());
+    assert!(chunks.next().is_none());
+
+    test::set_handler("/chunks_read_plain", |_unit| {
+        test::make_response(200, "OK", vec!["Content-Length: 5"], b"whole".to_vec())
+    });
+    let resp = get("test://host/chunks_read_plain").call();
+    let chunks: Vec<_> = resp.into_chunks().map(|c| c.unwrap()).collect();
+    assert_eq!(chunks, vec![b"whole".to_vec()]);
+}
+
+#[test]
+fn peek_body() {
+    test::set_handler("/peek_body", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Transfer-Encoding: chunked"],
+            b"3\r\n{\"a\r\n5\r\n\": 1}\r\n0\r\n\r\n".to_vec(),
+        )
+    });
+    let mut resp = get("test://host/peek_body").call();
+    assert_eq!(resp.peek(1).unwrap(), b"{");
+    assert_eq!(resp.peek(5).unwrap(), b"{\"a\":");
+    assert_eq!(resp.peek(100).unwrap(), b"{\"a\": 1}");
+
+    let mut first = [0; 2];
+    resp.read_exact(&mut first).unwrap();
+    assert_eq!(&first, b"{\"");
+    assert_eq!(resp.peek(3).unwrap(), b"a\":");
+    assert_eq!(resp.into_string().unwrap(), "a\": 1}");
+}
+
+#[test]
+#[cfg(feature = "charset")]
+fn string_encoded_as() {
+    test::set_handler("/string_encoded_as", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Content-Type: text/plain; charset=Shift_JIS"],
+            vec![0x93, 0xfa, 0x96, 0x7b, b'!'],
+        )
+    });
+    let resp = get("test://host/string_encoded_as").call();
+    let bytes = resp.into_string_encoded_as("utf-8").unwrap();
+    assert_eq!(bytes, "日本!".as_bytes());
+
+    test::set_handler("/string_encoded_as_latin1", |_unit| {
+        test::make_response(200, "OK", vec![], "Hällo 日".as_bytes().to_vec())
+    });
+    let resp = get("test://host/string_encoded_as_latin1").call();
+    let bytes = resp.into_string_encoded_as("iso-8859-1").unwrap();
+    assert_eq!(bytes, b"H\xe4llo ?");
+
+    test::set_handler("/string_encoded_as_unknown", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = get("test://host/string_encoded_as_unknown").call();
+    let err = resp.into_string_encoded_as("no-such-charset").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn tee_body() {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    test::set_handler("/tee_body", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Transfer-Encoding: chunked"],
+            b"3\r\nhel\r\nb\r\nlo world!!!\r\n0\r\n\r\n".to_vec(),
+        )
+    });
+    let copy = Arc::new(Mutex::new(vec![]));
+    let text = get("test://host/tee_body")
+        .call()
+        .tee(Shared(Arc::clone(&copy)))
+        .into_string()
+        .unwrap();
+    assert_eq!(text, "hello world!!!");
+    assert_eq!(&*copy.lock().unwrap(), b"hello world!!!");
+}
+
+#[test]
+#[cfg(feature = "checksum")]
+fn verify_checksum() {
+    fn handler() {
+        test::set_handler("/verify_checksum", |_unit| {
+            test::make_response(
+                200,
+                "OK",
+                vec!["Transfer-Encoding: chunked"],
+                b"3\r\nhel\r\nb\r\nlo world!!!\r\n0\r\n\r\n".to_vec(),
+            )
+        });
+    }
+
+    // the digest of "hello world!!!", upper case is fine.
+    handler();
+    let text = get("test://host/verify_checksum")
+        .verify_checksum(
+            Algorithm::Sha256,
+            "A5F4396B45548597F81681147F53C66065D5137F2FBD85E6758A8983107228E4",
+        )
+        .call()
+        .into_string()
+        .unwrap();
+    assert_eq!(text, "hello world!!!");
+
+    // the digest of the empty string.
+    handler();
+    let err = get("test://host/verify_checksum")
+        .verify_checksum(
+            Algorithm::Sha256,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        )
+        .call()
+        .into_string()
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let inner = err.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+    assert!(matches!(inner, Error::ChecksumMismatch(_, _)));
+}
+
+#[test]
+fn save_atomic() {
+    let dir = std::env::temp_dir().join(format!("ureq-save-atomic-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file.txt");
+
+    test::set_handler("/save_atomic", |_unit| {
+        test::make_response(200, "OK", vec![], b"hello".to_vec())
+    });
+    let amount = get("test://host/save_atomic")
+        .call()
+        .save_atomic(&path)
+        .unwrap();
+    assert_eq!(amount, 5);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+
+    // a broken chunked body leaves the file as it was.
+    test::set_handler("/save_atomic", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Transfer-Encoding: chunked"],
+            b"3\r\nhel\r\nzz".to_vec(),
+        )
+    });
+    assert!(get("test://host/save_atomic")
+        .call()
+        .save_atomic(&path)
+        .is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn content_length_and_has_body() {
+    test::set_handler("/content_length_and_has_body", |_unit| {
+        test::make_response(200, "OK", vec!["Content-Length: 5"], b"hello".to_vec())
+    });
+    let resp = get("test://host/content_length_and_has_body").call();
+    assert!(resp.has_body());
+    assert_eq!(resp.content_length(), Some(5));
+
+    test::set_handler("/content_length_and_has_body", |_unit| {
+        test::make_response(200, "OK", vec!["Transfer-Encoding: chunked"], vec![])
+    });
+    let resp = get("test://host/content_length_and_has_body").call();
+    assert_eq!(resp.content_length(), None);
+
+    test::set_handler("/content_length_and_has_body", |_unit| {
+        test::make_response(200, "OK", vec!["Content-Length: 5"], vec![])
+    });
+    let resp = head("test://host/content_length_and_has_body").call();
+    assert!(!resp.has_body());
+    assert_eq!(resp.content_length(), Some(0));
+
+    // no length and a kept-alive connection, but nothing to wait for.
+    test::set_handler("/content_length_and_has_body", |_unit| {
+        test::make_response(204, "No Content", vec![], b"junk".to_vec())
+    });
+    let resp = get("test://host/content_length_and_has_body").call();
+    assert!(!resp.has_body());
+    assert_eq!(resp.content_length(), Some(0));
+    assert_eq!(resp.into_string().unwrap(), "");
+}
+
+#[test]
+fn events_read() {
+    test::set_handler("/events_read", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Content-Type: text/event-stream"],
+            b": keep-alive\r\n\r\nid: 1\r\ndata: a\r\n\r\nevent: ping\ndata\ndata:  b\n\nretry: 50\nid\n\n\ndata: c\n\ndata: cut"
+                .to_vec(),
+        )
+    });
+    let resp = get("test://host/events_read").call();
+    let mut events = resp.into_events();
+    let event = events.next().unwrap().unwrap();
+    assert_eq!(
+        (event.id(), event.event(), event.data()),
+        (Some("1"), "message", "a")
+    );
+    let event = events.next().unwrap().unwrap();
+    assert_eq!(
+        (event.id(), event.event(), event.data()),
+        (Some("1"), "ping", "\n b")
+    );
+    let event = events.next().unwrap().unwrap();
+    assert_eq!((event.id(), event.data()), (None, "c"));
+    assert_eq!(events.retry(), Some(std::time::Duration::from_millis(50)));
+    assert!(events.next().is_none());
+}
+
+#[test]
+fn no_decompress_keeps_encoded_body() {
+    test::set_handler("/no_decompress_keeps_encoded_body", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Content-Encoding: zstd", "Content-Length: 4"],
+            vec![0x28, 0xb5, 0x2f, 0xfd],
+        )
+    });
+    let resp = get("test://host/no_decompress_keeps_encoded_body")
+        .no_decompress()
+        .call();
+    assert_eq!(resp.header("Content-Encoding"), Some("zstd"));
+    assert_eq!(resp.header("Content-Length"), Some("4"));
+    let mut bytes = vec![];
+    resp.into_reader().read_to_end(&mut bytes).unwrap();
+    assert_eq!(bytes, vec![0x28, 0xb5, 0x2f, 0xfd]);
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn accept_encoding() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let gzip = {
+        let mut enc = GzEncoder::new(vec![], Compression::default());
+        enc.write_all(b"Hello gzip").unwrap();
+        enc.finish().unwrap()
+    };
+    let expected = if cfg!(feature = "zstd") {
+        "gzip, deflate, zstd"
+    } else {
+        "gzip, deflate"
+    };
+    let body = gzip.clone();
+    test::set_handler("/accept_encoding", move |unit| {
+        assert_eq!(unit.header("Accept-Encoding"), None);
+        test::make_response(200, "OK", vec!["Content-Encoding: gzip"], body.clone())
+    });
+    let resp = get("test://host/accept_encoding").call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains(&format!("\r\nAccept-Encoding: {}\r\n", expected)));
+    assert_eq!(resp.into_string().unwrap(), "Hello gzip");
+
+    // asked for by the user, it is still decoded.
+    let body = gzip.clone();
+    test::set_handler("/accept_encoding_user", move |_unit| {
+        test::make_response(200, "OK", vec!["Content-Encoding: gzip"], body.clone())
+    });
+    let resp = get("test://host/accept_encoding_user")
+        .set("Accept-Encoding", "gzip;q=1.0, br")
+        .call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("\r\nAccept-Encoding: gzip;q=1.0, br\r\n"));
+    assert_eq!(resp.into_string().unwrap(), "Hello gzip");
+
+    // not listed by the user, the body is theirs to decode.
+    let body = gzip.clone();
+    test::set_handler("/accept_encoding_unlisted", move |_unit| {
+        test::make_response(200, "OK", vec!["Content-Encoding: gzip"], body.clone())
+    });
+    let resp = get("test://host/accept_encoding_unlisted")
+        .set("Accept-Encoding", "br")
+        .call();
+    let mut bytes = vec![];
+    resp.into_reader().read_to_end(&mut bytes).unwrap();
+    assert_eq!(bytes, gzip);
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn zstd_read() {
+    test::set_handler("/zstd_read", |_unit| {
+        let body = zstd::encode_all(&b"Hello World!!!"[..], 3).unwrap();
+        test::make_response(200, "OK", vec!["Content-Encoding: zstd"], body)
+    });
+    let resp = get("test://host/zstd_read").call();
+    assert_eq!(resp.into_string().unwrap(), "Hello World!!!");
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn gzip_and_deflate_read() {
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    let gzip = {
+        let mut enc = GzEncoder::new(vec![], Compression::default());
+        enc.write_all(b"Hello gzip").unwrap();
+        enc.finish().unwrap()
+    };
+    let zlib = {
+        let mut enc = ZlibEncoder::new(vec![], Compression::default());
+        enc.write_all(b"Hello zlib").unwrap();
+        enc.finish().unwrap()
+    };
+    let raw = {
+        let mut enc = DeflateEncoder::new(vec![], Compression::default());
+        enc.write_all(b"Hello raw deflate").unwrap();
+        enc.finish().unwrap()
+    };
+    let cases = vec![
+        ("gzip", gzip, "Hello gzip"),
+        ("deflate", zlib, "Hello zlib"),
+        ("Deflate", raw, "Hello raw deflate"),
+        ("deflate", vec![], ""),
+    ];
+    for (encoding, body, expected) in cases {
+        let header = format!("Content-Encoding: {}", encoding);
+        test::set_handler("/gzip_and_deflate_read", move |_unit| {
+            test::make_response(200, "OK", vec![&header], body.clone())
+        });
+        let resp = get("test://host/gzip_and_deflate_read").call();
+        assert_eq!(resp.into_string().unwrap(), expected);
+    }
+}
+
+#[test]
+fn read_response_directly() {
+    test::set_handler("/read_response_directly", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Transfer-Encoding: chunked"],
+            b"5\r\nHello\r\n7\r\n World!\r\n0\r\n\r\n".to_vec(),
+        )
+    });
+    let mut resp = get("test://host/read_response_directly").call();
+    let mut start = [0; 3];
+    resp.read_exact(&mut start).unwrap();
+    assert_eq!(&start, b"Hel");
+    assert_eq!(resp.status(), 200);
+    // the rest continues where reading the response stopped.
+    assert_eq!(resp.into_string().unwrap(), "lo World!");
+}
+
+#[test]
+fn data_urls() {
+    let resp = get("data:,A%20brief%20note").call();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.header("Content-Type"),
+        Some("text/plain;charset=US-ASCII")
+    );
+    assert_eq!(resp.into_string().unwrap(), "A brief note");
+
+    let resp = get("data:image/png;BASE64,iVBO Rw0K#frag").call();
+    assert_eq!(resp.content_type(), "image/png");
+    let mut bytes = vec![];
+    resp.into_reader().read_to_end(&mut bytes).unwrap();
+    assert_eq!(bytes, vec![0x89, b'P', b'N', b'G', b'\r', b'\n']);
+
+    let resp = get("data:;charset=utf-8,%E2%82%AC%zz").call();
+    assert_eq!(resp.charset(), "utf-8");
+    assert_eq!(resp.into_string().unwrap(), "€%zz");
+
+    let resp = get("data:text/plain").call();
+    assert!(matches!(resp.synthetic_error(), Some(Error::BadUrl(_))));
+}
+
+#[test]
+#[cfg(feature = "file")]
+fn file_urls() {
+    let dir = std::env::temp_dir().join(format!("ureq-file-urls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("note.json");
+    std::fs::write(&path, "{\"a\":1}").unwrap();
+    let url = url::Url::from_file_path(&path).unwrap();
+
+    let resp = get(url.as_str()).call();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.content_type(), "application/json");
+    assert_eq!(resp.header("Content-Length"), Some("7"));
+    assert_eq!(resp.into_string().unwrap(), "{\"a\":1}");
+
+    let resp = head(url.as_str()).call();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.into_string().unwrap(), "");
+
+    assert_eq!(post(url.as_str()).call().status(), 405);
+    let missing = url::Url::from_file_path(dir.join("missing.txt")).unwrap();
+    assert_eq!(get(missing.as_str()).call().status(), 404);
+    let dir_url = url::Url::from_directory_path(&dir).unwrap();
+    assert_eq!(get(dir_url.as_str()).call().status(), 404);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn agent_json_limit() {
+    test::set_handler("/agent_json_limit", |_unit| {
+        test::make_response(200, "OK", vec![], b"[1, 2, 3, 4, 5, 6, 7, 8, 9]".to_vec())
+    });
+    test::set_handler("/agent_json_limit_ok", |_unit| {
+        test::make_response(200, "OK", vec![], b"[1, 2]".to_vec())
+    });
+    let agent = crate::agent().json_limit(10).build();
+    let err = agent
+        .get("test://host/agent_json_limit")
+        .call()
+        .into_json()
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let json = agent
+        .get("test://host/agent_json_limit_ok")
+        .call()
+        .into_json()
+        .unwrap();
+    assert_eq!(json, serde_json::json!([1, 2]));
+}
+
+#[test]
+#[cfg(feature = "protobuf")]
+fn proto_read() {
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Hello {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    test::set_handler("/proto_read", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Content-Type: application/x-protobuf"],
+            vec![0x0a, 0x02, b'h', b'i'],
+        )
+    });
+    let resp = get("test://host/proto_read").call();
+    let hello: Hello = resp.into_proto().unwrap();
+    assert_eq!(hello.name, "hi");
+
+    test::set_handler("/proto_read_bad", |_unit| {
+        test::make_response(
+            200,
+            "OK",
+            vec!["Content-Type: application/x-protobuf"],
+            vec![0x0a, 0x05, b'h'],
+        )
+    });
+    let resp = get("test://host/proto_read_bad").call();
+    let err = resp.into_proto::<Hello>().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
@@ -73,3 +73,353 @@ fn str_with_encoding() {
         [72, 228, 108, 108, 111, 32, 87, 246, 114, 108, 100, 33, 33, 33]
     );
 }
+
+#[test]
+#[cfg(feature = "charset")]
+fn str_with_cjk_encoding() {
+    test::set_handler("/str_with_cjk_encoding", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = post("test://host/str_with_cjk_encoding")
+        .set("Content-Type", "text/plain; charset=\"Shift_JIS\"")
+        .send_string("日本");
+    let vec = resp.to_write_vec();
+    assert_eq!(&vec[vec.len() - 4..], [0x93, 0xfa, 0x96, 0x7b]);
+}
+
+#[test]
+#[cfg(feature = "charset")]
+fn str_with_charset_argument() {
+    test::set_handler("/str_with_charset_argument", |unit| {
+        assert_eq!(
+            unit.header("Content-Type"),
+            Some("text/csv; header=present; charset=iso-8859-1")
+        );
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = post("test://host/str_with_charset_argument")
+        .set("Content-Type", "text/csv; Charset=utf-8; header=present")
+        .send_string_with_charset("Hä;Wö", "iso-8859-1");
+    let vec = resp.to_write_vec();
+    assert_eq!(&vec[vec.len() - 5..], [72, 228, 59, 87, 246]);
+
+    test::set_handler("/str_with_charset_argument_plain", |unit| {
+        assert_eq!(
+            unit.header("Content-Type"),
+            Some("text/plain; charset=shift_jis")
+        );
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = post("test://host/str_with_charset_argument_plain")
+        .send_string_with_charset("日本", "shift_jis");
+    let vec = resp.to_write_vec();
+    assert_eq!(&vec[vec.len() - 4..], [0x93, 0xfa, 0x96, 0x7b]);
+
+    let resp = post("test://host/str_with_charset_argument_unknown")
+        .send_string_with_charset("x", "no-such-charset");
+    assert!(matches!(resp.synthetic_error(), Some(Error::Io(_))));
+}
+
+#[test]
+#[cfg(feature = "msgpack")]
+fn msgpack_body() {
+    test::set_handler("/msgpack_body", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let mut data = std::collections::BTreeMap::new();
+    data.insert("a", 1);
+    let resp = post("test://host/msgpack_body").send_msgpack(&data);
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("\r\nContent-Type: application/msgpack\r\n"));
+    // fixmap(1) fixstr(1) "a" 1
+    assert!(vec.ends_with(&[0x81, 0xa1, b'a', 0x01]));
+}
+
+#[test]
+#[cfg(feature = "cbor")]
+fn cbor_body() {
+    test::set_handler("/cbor_body", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let mut data = std::collections::BTreeMap::new();
+    data.insert("a", 1);
+    let resp = post("test://host/cbor_body").send_cbor(&data);
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("\r\nContent-Type: application/cbor\r\n"));
+    // map(1) text(1) "a" unsigned(1)
+    assert!(vec.ends_with(&[0xa1, 0x61, b'a', 0x01]));
+}
+
+#[test]
+fn send_chunks() {
+    test::set_handler("/send_chunks", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let chunks = vec![b"hello".to_vec(), vec![], b" world!!!!!!!".to_vec()];
+    let resp = post("test://host/send_chunks").send_chunks(chunks);
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("\r\nTransfer-Encoding: chunked\r\n"));
+    assert!(!s.contains("Content-Length"));
+    assert!(s.ends_with("\r\n\r\n5\r\nhello\r\nd\r\n world!!!!!!!\r\n0\r\n\r\n"));
+}
+
+#[test]
+fn send_chunked_with_chunk_size() {
+    test::set_handler("/send_chunked_with_chunk_size", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = post("test://host/send_chunked_with_chunk_size")
+        .set("Transfer-Encoding", "chunked")
+        .chunk_size(4)
+        .send_string("Hello World");
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.ends_with("\r\n\r\n4\r\nHell\r\n4\r\no Wo\r\n3\r\nrld\r\n0\r\n\r\n"));
+}
+
+#[test]
+fn send_file() {
+    let path = std::env::temp_dir().join("ureq_send_file_test.json");
+    std::fs::write(&path, b"{\"a\":1}").unwrap();
+    test::set_handler("/send_file", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = put("test://host/send_file").send_file(&path);
+    std::fs::remove_file(&path).ok();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("\r\nContent-Type: application/json\r\n"));
+    assert!(s.contains("\r\nContent-Length: 7\r\n"));
+    assert!(s.ends_with("\r\n\r\n{\"a\":1}"));
+
+    let resp = put("test://host/send_file").send_file("/no/such/file.txt");
+    assert!(matches!(resp.synthetic_error(), Some(Error::Io(_))));
+}
+
+#[test]
+fn send_multipart() {
+    test::set_handler("/send_multipart", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let form = Multipart::new().text("na\"me", "martin").reader(
+        "file",
+        "a.txt",
+        "text/plain",
+        std::io::Cursor::new(b"hello".to_vec()),
+        Some(5),
+    );
+    let resp = post("test://host/send_multipart").send_multipart(form);
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    let boundary = s
+        .split("Content-Type: multipart/form-data; boundary=")
+        .nth(1)
+        .unwrap()
+        .split("\r\n")
+        .next()
+        .unwrap();
+    let body = format!(
+        "--{b}\r\n\
+         Content-Disposition: form-data; name=\"na%22me\"\r\n\
+         \r\n\
+         martin\r\n\
+         --{b}\r\n\
+         Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+         Content-Type: text/plain\r\n\
+         \r\n\
+         hello\r\n\
+         --{b}--\r\n",
+        b = boundary
+    );
+    assert!(s.contains(&format!("\r\nContent-Length: {}\r\n", body.len())));
+    assert!(s.ends_with(&format!("\r\n\r\n{}", body)));
+}
+
+#[test]
+fn send_multipart_unknown_length() {
+    test::set_handler("/send_multipart_unknown_length", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let form = Multipart::new().reader(
+        "file",
+        "a.bin",
+        "application/octet-stream",
+        std::io::empty(),
+        None,
+    );
+    let resp = post("test://host/send_multipart_unknown_length").send_multipart(form);
+    let s = String::from_utf8_lossy(&resp.to_write_vec()).to_string();
+    assert!(s.contains("\r\nTransfer-Encoding: chunked\r\n"));
+    assert!(!s.contains("Content-Length"));
+    assert!(s.ends_with("--\r\n\r\n0\r\n\r\n"));
+}
+
+#[test]
+fn send_sized() {
+    use std::io::{Cursor, ErrorKind};
+
+    test::set_handler("/send_sized", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = put("test://host/send_sized").send_sized(Cursor::new(b"hello".to_vec()), 5);
+    let s = String::from_utf8_lossy(&resp.to_write_vec()).to_string();
+    assert!(s.contains("\r\nContent-Length: 5\r\n"));
+    assert!(s.ends_with("\r\n\r\nhello"));
+
+    for (len, kind) in &[(6, ErrorKind::UnexpectedEof), (4, ErrorKind::InvalidInput)] {
+        test::set_handler("/send_sized", |_unit| {
+            test::make_response(200, "OK", vec![], vec![])
+        });
+        let resp = put("test://host/send_sized").send_sized(Cursor::new(b"hello".to_vec()), *len);
+        match resp.synthetic_error() {
+            Some(Error::Io(e)) => assert_eq!(e.kind(), *kind),
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn send_body() {
+    let path = std::env::temp_dir().join("ureq_send_body.txt");
+    std::fs::write(&path, "hello file").unwrap();
+    let body = Body::file(&path).unwrap();
+    for _ in 0..2 {
+        test::set_handler("/send_body", |_unit| {
+            test::make_response(200, "OK", vec![], vec![])
+        });
+        // a clone reads the file from the start again.
+        let resp = put("test://host/send_body").send_body(body.clone());
+        let s = String::from_utf8_lossy(&resp.to_write_vec()).to_string();
+        assert!(s.contains("\r\nContent-Length: 10\r\n"));
+        assert!(s.ends_with("\r\n\r\nhello file"));
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn file_body_readers_have_own_offset() {
+    use std::io::Read;
+    let path = std::env::temp_dir().join("ureq_file_body_readers.txt");
+    std::fs::write(&path, "hello file").unwrap();
+    let body = Body::file(&path).unwrap();
+    let mut a = body.to_read().reader;
+    let mut b = body.clone().to_read().reader;
+    let mut buf = [0_u8; 6];
+    a.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello ");
+    // reading the other clone doesn't move the first one.
+    let mut all = String::new();
+    b.read_to_string(&mut all).unwrap();
+    assert_eq!(all, "hello file");
+    let mut rest = String::new();
+    a.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "file");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn send_graphql() {
+    test::set_handler("/send_graphql", |unit| {
+        assert_eq!(unit.header("Content-Type").unwrap(), "application/json");
+        let body = br#"{"data":{"hero":{"name":"R2-D2"}},"errors":[{"message":"Slow","path":["hero",0],"extensions":{"code":"SLOW"}}]}"#;
+        test::make_response(200, "OK", vec![], body.to_vec())
+    });
+    let mut vars = SerdeMap::new();
+    vars.insert("id".into(), SerdeValue::String("1".into()));
+    let resp = post("test://host/send_graphql")
+        .send_graphql("{ hero { name } }", SerdeValue::Object(vars));
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.ends_with(r#"{"query":"{ hero { name } }","variables":{"id":"1"}}"#));
+
+    let graphql = resp.into_graphql::<SerdeValue>().unwrap();
+    assert_eq!(graphql.data().unwrap()["hero"]["name"], "R2-D2");
+    let error = &graphql.errors()[0];
+    assert_eq!(error.message(), "Slow");
+    assert_eq!(error.path()[1], 0);
+    assert_eq!(error.extensions().unwrap()["code"], "SLOW");
+    assert_eq!(graphql.into_result().unwrap_err().len(), 1);
+}
+
+#[test]
+fn prepared_request() {
+    let prepared = post("test://host/prepared_request")
+        .set("X-Sig", "abc")
+        .query("n", "1")
+        .prepare_body(Body::text("Hello"))
+        .unwrap();
+    assert_eq!(prepared.method(), "POST");
+    assert_eq!(prepared.url(), "test://host/prepared_request?n=1");
+    assert_eq!(prepared.body_len(), Some(5));
+    let expected: String = prepared
+        .headers()
+        .iter()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
+    assert!(expected.contains("Host: host\r\nUser-Agent: ureq/"));
+    assert!(expected.contains("X-Sig: abc\r\nContent-Length: 5\r\n"));
+
+    for _ in 0..2 {
+        test::set_handler("/prepared_request", |_unit| {
+            test::make_response(200, "OK", vec![], vec![])
+        });
+        let resp = prepared.send();
+        let vec = resp.to_write_vec();
+        let s = String::from_utf8_lossy(&vec);
+        let head = format!("POST /prepared_request?n=1 HTTP/1.1\r\n{}\r\n", expected);
+        assert_eq!(s, format!("{}Hello", head));
+    }
+}
+
+#[test]
+#[cfg(feature = "protobuf")]
+fn proto_body() {
+    test::set_handler("/proto_body", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = post("test://host/proto_body").send_proto(&"hi".to_string());
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("\r\nContent-Type: application/x-protobuf\r\n"));
+    // field 1, length delimited, 2 bytes.
+    assert!(vec.ends_with(&[0x0a, 0x02, b'h', b'i']));
+}
+
+#[test]
+fn early_error_response_stops_upload() {
+    use std::io::{Read, Write};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let (mut sock, _) = listener.accept().unwrap();
+        let mut head = vec![];
+        let mut byte = [0_u8];
+        while !head.ends_with(b"\r\n\r\n") {
+            sock.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        // rejected before the body, which is read off until the client hangs up.
+        sock.write_all(b"HTTP/1.1 413 Payload Too Large\r\nContent-Length: 4\r\n\r\nnope")
+            .unwrap();
+        let drained = std::io::copy(&mut sock, &mut std::io::sink()).unwrap_or(0);
+        tx.send(drained).unwrap();
+    });
+
+    let size = 64 * 1024 * 1024;
+    let resp =
+        post(&format!("http://127.0.0.1:{}/upload", port)).send(std::io::repeat(7).take(size));
+    assert_eq!(resp.status(), 413);
+    assert_eq!(resp.into_string().unwrap(), "nope");
+
+    // the connection is closed, not pooled with half a body sent.
+    let drained = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(drained < size / 4, "sent {} of {} bytes", drained, size);
+}
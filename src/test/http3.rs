@@ -0,0 +1,174 @@
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes};
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::rustls;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+use super::super::*;
+
+/// A self-signed certificate of `localhost` and its key.
+const CERT: &str = "MIIBlTCCATugAwIBAgIUJoudIJCb2r7OjGw0NBFNVGz50OkwCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJbG9jYWxob3N0MCAXDTI2MTAxODAzMzkyMFoYDzIxMjYwOTI0MDMzOTIwWjAUMRIwEAYDVQQDDAlsb2NhbGhvc3QwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAARbDeMxE3SYMVvfjY/6FqW+HBYCZCvI8sp8lRS0CD2Z8C6E6mLjkU00joDHj/6E+MwL4zcF0hBUtJnr46POVAFWo2kwZzAdBgNVHQ4EFgQU+1WMNfQwi6fInvBDESKBWQHUpCUwHwYDVR0jBBgwFoAU+1WMNfQwi6fInvBDESKBWQHUpCUwDwYDVR0TAQH/BAUwAwEB/zAUBgNVHREEDTALgglsb2NhbGhvc3QwCgYIKoZIzj0EAwIDSAAwRQIhAM5QF7U0pKD2z8gcGxvZqKRVsZM66z0n1+S6TUqiO57SAiBX0LruQ3S6SDKeqfmPTn8SClLSP7HP6OCxlac3G+iMRg==";
+const KEY: &str = "MIGHAgEAMBMGByqGSM49AgEGCCqGSM49AwEHBG0wawIBAQQgDPT+6W0ZJULdzJhNP7wvxxek3yBRoJcyaC8J7qQ50lGhRANCAARbDeMxE3SYMVvfjY/6FqW+HBYCZCvI8sp8lRS0CD2Z8C6E6mLjkU00joDHj/6E+MwL4zcF0hBUtJnr46POVAFW";
+
+pub(super) fn server_config(alpn: &[u8]) -> rustls::ServerConfig {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let cert = CertificateDer::from(base64::decode(CERT).unwrap());
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(base64::decode(KEY).unwrap()));
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .unwrap();
+    config.alpn_protocols = vec![alpn.to_vec()];
+    config
+}
+
+/// A HTTP/3 server answering with the method, path and body of the request.
+fn h3_server() -> SocketAddr {
+    let crypto = QuicServerConfig::try_from(server_config(b"h3")).unwrap();
+    let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let endpoint = {
+        let _runtime = runtime.enter();
+        quinn::Endpoint::server(config, ([127, 0, 0, 1], 0).into()).unwrap()
+    };
+    let addr = endpoint.local_addr().unwrap();
+    std::thread::spawn(move || {
+        runtime.block_on(async move {
+            while let Some(incoming) = endpoint.accept().await {
+                tokio::spawn(async move {
+                    let quic = h3_quinn::Connection::new(incoming.await.unwrap());
+                    let mut conn = h3::server::Connection::<_, Bytes>::new(quic).await.unwrap();
+                    while let Ok(Some(resolver)) = conn.accept().await {
+                        tokio::spawn(async move {
+                            let (req, mut stream) = resolver.resolve_request().await.unwrap();
+                            let mut body = vec![];
+                            while let Some(mut data) = stream.recv_data().await.unwrap() {
+                                body.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+                            }
+                            let text = format!(
+                                "{} {} {}",
+                                req.method(),
+                                req.uri().path(),
+                                String::from_utf8_lossy(&body)
+                            );
+                            let resp = http::Response::builder()
+                                .status(200)
+                                .header("content-type", "text/plain")
+                                .body(())
+                                .unwrap();
+                            stream.send_response(resp).await.unwrap();
+                            stream.send_data(Bytes::from(text)).await.unwrap();
+                            stream.finish().await.unwrap();
+                        });
+                    }
+                });
+            }
+        });
+    });
+    addr
+}
+
+/// A HTTPS server over TCP advertising HTTP/3 on the given port.
+fn tcp_server(h3_port: u16) -> u16 {
+    let config = Arc::new(server_config(b"http/1.1"));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for sock in listener.incoming() {
+            let conn = rustls::ServerConnection::new(config.clone()).unwrap();
+            let mut tls = rustls::StreamOwned::new(conn, sock.unwrap());
+            let mut head = vec![];
+            let mut byte = [0_u8];
+            while !head.ends_with(b"\r\n\r\n") {
+                if tls.read(&mut byte).unwrap_or(0) == 0 {
+                    break;
+                }
+                head.push(byte[0]);
+            }
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\nAlt-Svc: h3=\":{}\"; ma=60\r\n\
+                 Content-Length: 3\r\nConnection: close\r\n\r\ntcp",
+                h3_port
+            );
+            let _ = tls.write_all(resp.as_bytes());
+            tls.conn.send_close_notify();
+            let _ = tls.flush();
+        }
+    });
+    port
+}
+
+#[test]
+fn http3_only() {
+    let h3 = h3_server();
+    let agent = agent().accept_invalid_certs_for_host("localhost").build();
+    let url = format!("https://localhost:{}/echo", h3.port());
+    for body in &["one", "two"] {
+        let resp = agent
+            .post(&url)
+            .set_preferred_ip_version(IpVersion::V4)
+            .http3_only(true)
+            .send_string(body);
+        assert!(
+            resp.synthetic_error().is_none(),
+            "{:?}",
+            resp.synthetic_error()
+        );
+        assert_eq!(resp.http_version(), "HTTP/3");
+        assert_eq!(resp.status_text(), "OK");
+        assert_eq!(resp.header("Content-Type"), Some("text/plain"));
+        assert_eq!(resp.into_string().unwrap(), format!("POST /echo {}", body));
+    }
+}
+
+#[test]
+fn http3_by_alt_svc() {
+    let h3 = h3_server();
+    let port = tcp_server(h3.port());
+    let agent = agent()
+        .http3(true)
+        .accept_invalid_certs_for_host("localhost")
+        .build();
+    let url = format!("https://localhost:{}/page", port);
+    let get = || {
+        agent
+            .get(&url)
+            .set_preferred_ip_version(IpVersion::V4)
+            .call()
+    };
+
+    let resp = get();
+    assert_eq!(resp.http_version(), "HTTP/1.1");
+    assert_eq!(resp.into_string().unwrap(), "tcp");
+    let resp = get();
+    assert_eq!(resp.http_version(), "HTTP/3");
+    assert_eq!(resp.into_string().unwrap(), "GET /page ");
+}
+
+#[test]
+fn http3_falls_back_to_tcp() {
+    // nothing answers QUIC there.
+    let unused = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = tcp_server(unused.local_addr().unwrap().port());
+    drop(unused);
+    let agent = agent()
+        .http3(true)
+        .accept_invalid_certs_for_host("localhost")
+        .build();
+    let url = format!("https://localhost:{}/", port);
+    for _ in 0..2 {
+        let resp = agent
+            .get(&url)
+            .set_preferred_ip_version(IpVersion::V4)
+            .timeout_connect(500)
+            .call();
+        assert_eq!(resp.http_version(), "HTTP/1.1");
+        assert_eq!(resp.into_string().unwrap(), "tcp");
+    }
+}
@@ -10,16 +10,21 @@ mod agent_test;
 mod auth;
 mod body_read;
 mod body_send;
+#[cfg(feature = "http3")]
+mod http3;
+mod proxy;
 mod query_string;
 mod range;
 mod redirect;
 mod simple;
+#[cfg(feature = "tls")]
+mod tls;
 
 type RequestHandler = dyn Fn(&Unit) -> Result<Stream, Error> + Send + 'static;
 
 lazy_static! {
     pub(crate) static ref TEST_HANDLERS: Arc<Mutex<HashMap<String, Box<RequestHandler>>>> =
-        { Arc::new(Mutex::new(HashMap::new())) };
+        Arc::new(Mutex::new(HashMap::new()));
 }
 
 pub(crate) fn set_handler<H>(path: &str, handler: H)
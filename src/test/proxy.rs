@@ -0,0 +1,222 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use super::super::*;
+
+/// A SOCKS5 proxy for one connection that answers the request itself. Gives
+/// back the connect request it got, and the connection kept open.
+fn socks5_server(auth: bool) -> (String, thread::JoinHandle<(Vec<u8>, TcpStream)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let (mut sock, _) = listener.accept().unwrap();
+        let mut greeting = [0_u8; 3];
+        sock.read_exact(&mut greeting[..2]).unwrap();
+        let mut methods = vec![0_u8; greeting[1] as usize];
+        sock.read_exact(&mut methods).unwrap();
+        if auth {
+            assert!(methods.contains(&2));
+            sock.write_all(&[5, 2]).unwrap();
+            let mut buf = [0_u8; 2];
+            sock.read_exact(&mut buf).unwrap();
+            let mut user = vec![0_u8; buf[1] as usize];
+            sock.read_exact(&mut user).unwrap();
+            sock.read_exact(&mut buf[..1]).unwrap();
+            let mut pass = vec![0_u8; buf[0] as usize];
+            sock.read_exact(&mut pass).unwrap();
+            assert_eq!((&user[..], &pass[..]), (&b"martin"[..], &b"secret"[..]));
+            sock.write_all(&[1, 0]).unwrap();
+        } else {
+            sock.write_all(&[5, 0]).unwrap();
+        }
+
+        let mut connect = [0_u8; 512];
+        let n = sock.read(&mut connect).unwrap();
+        sock.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80]).unwrap();
+
+        let mut request = [0_u8; 1024];
+        assert!(sock.read(&mut request).unwrap() > 0);
+        sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            .unwrap();
+        (connect[..n].to_vec(), sock)
+    });
+    (format!("{}:{}", addr.ip(), addr.port()), handle)
+}
+
+/// An http proxy for one connection that answers the tunneled request itself.
+/// Gives back the head of the `CONNECT` request it got.
+#[cfg(feature = "pac")]
+fn connect_server() -> (String, thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let (mut sock, _) = listener.accept().unwrap();
+        let mut head = vec![];
+        let mut byte = [0_u8];
+        while !head.ends_with(b"\r\n\r\n") {
+            sock.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        sock.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+            .unwrap();
+
+        let mut request = [0_u8; 1024];
+        assert!(sock.read(&mut request).unwrap() > 0);
+        sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\ntunnel")
+            .unwrap();
+        String::from_utf8(head).unwrap()
+    });
+    (format!("{}:{}", addr.ip(), addr.port()), handle)
+}
+
+#[test]
+fn socks5h_sends_host_name() {
+    let (addr, server) = socks5_server(false);
+    let proxy = Proxy::new(&format!("socks5h://{}", addr)).unwrap();
+    let agent = agent().set_proxy(proxy).build();
+
+    // the host only resolves behind the proxy.
+    let resp = agent.get("http://backend.invalid:8080/").call();
+    assert_eq!(resp.into_string().unwrap(), "ok");
+
+    let mut expected = vec![5, 1, 0, 3, 15];
+    expected.extend_from_slice(b"backend.invalid");
+    expected.extend_from_slice(&8080_u16.to_be_bytes());
+    assert_eq!(server.join().unwrap().0, expected);
+}
+
+#[test]
+fn socks5_sends_address() {
+    let (addr, server) = socks5_server(true);
+    let proxy = Proxy::new(&format!("socks5://martin:secret@{}", addr)).unwrap();
+    let agent = agent().set_proxy(proxy).build();
+
+    let resp = agent.get("http://127.0.0.2:8080/").call();
+    assert_eq!(resp.into_string().unwrap(), "ok");
+
+    assert_eq!(
+        server.join().unwrap().0,
+        vec![5, 1, 0, 1, 127, 0, 0, 2, 31, 144]
+    );
+}
+
+#[test]
+fn proxy_failover_and_pooling() {
+    let refused = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let (addr_a, server_a) = socks5_server(false);
+    let dead = Proxy::new(&format!("socks5h://{}", refused)).unwrap();
+    let proxy_a = Proxy::new(&format!("socks5h://{}", addr_a)).unwrap();
+    let pooled = agent().set_proxies(vec![dead, proxy_a]).build();
+
+    // the first proxy refuses, the second one is used.
+    let resp = pooled.get("http://backend.invalid:8080/").call();
+    assert_eq!(resp.into_string().unwrap(), "ok");
+    let (_, _tunnel_a) = server_a.join().unwrap();
+    assert_eq!(pooled.state().unwrap().pool.len(), 1);
+
+    // the pooled tunnel through proxy a is not one through proxy b.
+    let (addr_b, server_b) = socks5_server(false);
+    let proxy_b = Proxy::new(&format!("socks5h://{}", addr_b)).unwrap();
+    let mut agent_b = pooled.clone();
+    agent_b.set_proxy(proxy_b);
+    let resp = agent_b.get("http://backend.invalid:8080/").call();
+    assert_eq!(resp.into_string().unwrap(), "ok");
+    assert_eq!(server_b.join().unwrap().0[3], 3);
+    assert_eq!(pooled.state().unwrap().pool.len(), 2);
+
+    // all failing gives the error of the last.
+    let dead = Proxy::new(&format!("socks5h://{}", refused)).unwrap();
+    let resp = agent()
+        .set_proxies(vec![dead.clone(), dead])
+        .build()
+        .get("http://backend.invalid:8080/")
+        .call();
+    assert!(matches!(
+        resp.synthetic_error(),
+        Some(Error::ConnectionRefused(_))
+    ));
+}
+
+#[test]
+fn bad_proxy() {
+    assert!(matches!(
+        Proxy::new("http://localhost:3128"),
+        Err(Error::BadProxy(_))
+    ));
+    assert!(Proxy::new("socks5h://localhost").unwrap().remote_dns());
+}
+
+#[test]
+#[cfg(feature = "pac")]
+fn proxy_pac() {
+    let (addr, server) = socks5_server(false);
+    let pac = format!(
+        r#"function FindProxyForURL(url, host) {{
+            if (isInNet(host, "127.0.0.0", "255.0.0.0")) return "DIRECT";
+            if (shExpMatch(host, "*.invalid") && dnsDomainIs(host, ".invalid"))
+                return "PROXY web.invalid:3128; SOCKS5 {}";
+            return "DIRECT";
+        }}"#,
+        addr
+    );
+    let path = std::env::temp_dir().join(format!("ureq-proxy-pac-{}.pac", std::process::id()));
+    std::fs::write(&path, pac).unwrap();
+    let agent = agent().proxy_pac(path.to_str().unwrap()).build();
+
+    // SOCKS5 from the PAC file resolves the host at the proxy.
+    let resp = agent.get("http://backend.invalid:8080/").call();
+    assert_eq!(resp.into_string().unwrap(), "ok");
+    assert_eq!(server.join().unwrap().0[3], 3);
+
+    // DIRECT connects as usual.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let resp = agent.get(&format!("http://127.0.0.1:{}/", port)).call();
+    assert!(matches!(
+        resp.synthetic_error(),
+        Some(Error::ConnectionRefused(_))
+    ));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "pac")]
+fn proxy_pac_http_connect() {
+    let (addr, server) = connect_server();
+    // the script is compiled once, its globals live between calls.
+    let pac = format!(
+        r#"var calls = 0;
+        function FindProxyForURL(url, host) {{
+            calls += 1;
+            return calls == 1 ? "PROXY {}" : "HTTPS secure.invalid:443";
+        }}"#,
+        addr
+    );
+    let path =
+        std::env::temp_dir().join(format!("ureq-proxy-pac-connect-{}.pac", std::process::id()));
+    std::fs::write(&path, pac).unwrap();
+    let agent = agent().proxy_pac(path.to_str().unwrap()).build();
+
+    // PROXY tunnels with CONNECT, the proxy resolves the host.
+    let resp = agent.get("http://backend.invalid:8080/").call();
+    assert_eq!(resp.into_string().unwrap(), "tunnel");
+    let head = server.join().unwrap();
+    assert!(head.starts_with("CONNECT backend.invalid:8080 HTTP/1.1\r\n"));
+
+    // HTTPS entries are skipped, leaving nothing to connect with.
+    let resp = agent.get("http://other.invalid/").call();
+    assert!(matches!(
+        resp.synthetic_error(),
+        Some(Error::ProxyFailed(_))
+    ));
+
+    std::fs::remove_file(&path).unwrap();
+}
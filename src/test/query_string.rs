@@ -50,3 +50,126 @@ fn query_in_path_and_req() {
     let s = String::from_utf8_lossy(&vec);
     assert!(s.contains("GET /query_in_path_and_req?foo=bar&baz=1%202%203 HTTP/1.1"))
 }
+
+#[test]
+#[cfg(feature = "json")]
+fn query_from_struct() {
+    test::set_handler("/query_from_struct", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = get("test://host/query_from_struct")
+        .query("first", "1")
+        .query_struct(&serde_json::json!({
+            "name": "yo lo",
+            "ids": [1, 2],
+            "missing": null,
+            "flag": true,
+        }))
+        .call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("GET /query_from_struct?first=1&flag=true&ids=1&ids=2&name=yo%20lo HTTP/1.1"))
+}
+
+#[test]
+fn query_encoding_strict() {
+    test::set_handler("/query_encoding_strict", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = get("test://host/query_encoding_strict")
+        .query_encoding(QueryEncoding::Strict)
+        .query("filter", "a,b c+d~")
+        .call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("GET /query_encoding_strict?filter=a%2Cb%20c%2Bd~ HTTP/1.1"))
+}
+
+#[test]
+fn query_encoding_pre_encoded() {
+    test::set_handler("/query_encoding_pre_encoded", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = get("test://host/query_encoding_pre_encoded")
+        .query_encoding(QueryEncoding::PreEncoded)
+        .query("q", "a+b")
+        .query_str("?ids=1%2C2&x=%20")
+        .call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("GET /query_encoding_pre_encoded?q=a+b&ids=1%2C2&x=%20 HTTP/1.1"))
+}
+
+#[test]
+fn remove_query() {
+    test::set_handler("/remove_query", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let mut req = get("test://host/remove_query?debug=url");
+    req.query("debug", "true")
+        .query_str("flag&x=1")
+        .query("debug", "false");
+    assert_eq!(
+        req.query_pairs(),
+        vec![
+            ("debug", "true"),
+            ("flag", ""),
+            ("x", "1"),
+            ("debug", "false")
+        ]
+    );
+    let resp = req.remove_query("debug").call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("GET /remove_query?debug=url&flag&x=1 HTTP/1.1"))
+}
+
+#[test]
+fn sort_query() {
+    test::set_handler("/sort_query", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = get("test://host/sort_query?z=0")
+        .query("b", "2")
+        .query_str("c&a=1")
+        .query("a", "0")
+        .sort_query()
+        .call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("GET /sort_query?z=0&a=1&a=0&b=2&c HTTP/1.1"))
+}
+
+#[test]
+fn query_array_styles() {
+    test::set_handler("/query_array_styles", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let ids = vec!["1".to_string(), "2 3".to_string()];
+    let resp = get("test://host/query_array_styles")
+        .query_array("id", &ids)
+        .query_array_style(ArrayStyle::Brackets)
+        .query_array("tag", &["a", "b"])
+        .query_array_style(ArrayStyle::Comma)
+        .query_array("f", &["x", "y"])
+        .query_array::<&str>("none", &[])
+        .call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("GET /query_array_styles?id=1&id=2%203&tag[]=a&tag[]=b&f=x,y HTTP/1.1"))
+}
+
+#[test]
+fn query_replace() {
+    test::set_handler("/query_replace", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = get("test://host/query_replace?page=0")
+        .query_array("page", &["1", "2"])
+        .query("size", "10")
+        .query_replace("page", "3")
+        .call();
+    let vec = resp.to_write_vec();
+    let s = String::from_utf8_lossy(&vec);
+    assert!(s.contains("GET /query_replace?page=0&size=10&page=3 HTTP/1.1"))
+}
@@ -1,5 +1,7 @@
 use std::io::Read;
 
+use crate::test;
+
 use super::super::*;
 
 #[test]
@@ -37,12 +39,10 @@ fn agent_pool() {
     assert_eq!(len, 1000);
 
     {
-        let mut lock = agent.state().lock().unwrap();
-        let state = lock.as_mut().unwrap();
-        let pool = state.pool();
+        let pool = &agent.state().unwrap().pool;
         assert_eq!(pool.len(), 1);
-        let f = format!("{:?}", pool.get("ureq.s3.eu-central-1.amazonaws.com", 443));
-        assert_eq!(f, "Some(Stream[https])"); // not a great way of testing.
+        let f = pool.get("ureq.s3.eu-central-1.amazonaws.com", 443);
+        assert_eq!(f.as_deref(), Some("Stream[https]")); // not a great way of testing.
     }
 
     // req 2 should be done with a reused connection
@@ -56,3 +56,71 @@ fn agent_pool() {
     let len = reader.read_to_end(&mut buf).unwrap();
     assert_eq!(len, 2000);
 }
+
+#[test]
+fn byte_ranges() {
+    test::set_handler("/byte_ranges", |_unit| {
+        let body = "preamble\r\n\
+            --SEP\r\n\
+            Content-Type: text/plain\r\n\
+            Content-Range: bytes 0-9/*\r\n\
+            \r\n\
+            one\r\n--SE\r\n\
+            --SEP\r\n\
+            Content-Range: bytes 20-22/30\r\n\
+            \r\n\
+            two\r\n\
+            --SEP\r\n\
+            Content-Range: bytes 27-29/30\r\n\
+            \r\n\
+            six\r\n\
+            --SEP--\r\n\
+            epilogue";
+        test::make_response(
+            206,
+            "Partial Content",
+            vec!["Content-Type: multipart/byteranges; boundary=\"SEP\""],
+            body.as_bytes().to_vec(),
+        )
+    });
+    let resp = get("test://host/byte_ranges").call();
+    let mut ranges = resp.into_byte_ranges().unwrap();
+
+    let mut part = ranges.next_part().unwrap().unwrap();
+    assert_eq!(part.header("content-type"), Some("text/plain"));
+    assert_eq!(part.range(), Some((0, 9)));
+    assert_eq!(part.total_len(), None);
+    let mut body = String::new();
+    part.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "one\r\n--SE");
+
+    // the body of this one is skipped.
+    let part = ranges.next_part().unwrap().unwrap();
+    assert_eq!(part.range(), Some((20, 22)));
+    assert_eq!(part.total_len(), Some(30));
+
+    let mut part = ranges.next_part().unwrap().unwrap();
+    let mut body = String::new();
+    part.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "six");
+    assert!(ranges.next_part().unwrap().is_none());
+    assert!(ranges.next_part().unwrap().is_none());
+
+    // a single range is the whole body.
+    test::set_handler("/byte_ranges_single", |_unit| {
+        test::make_response(
+            206,
+            "Partial Content",
+            vec!["Content-Range: bytes 5-7/10"],
+            b"abc".to_vec(),
+        )
+    });
+    let resp = get("test://host/byte_ranges_single").call();
+    let mut ranges = resp.into_byte_ranges().unwrap();
+    let mut part = ranges.next_part().unwrap().unwrap();
+    assert_eq!(part.range(), Some((5, 7)));
+    let mut body = String::new();
+    part.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "abc");
+    assert!(ranges.next_part().unwrap().is_none());
+}
@@ -1,4 +1,5 @@
 use crate::test;
+use std::io::Read;
 
 use super::super::*;
 
@@ -91,3 +92,122 @@ fn redirect_post() {
     assert!(resp.has("x-foo"));
     assert_eq!(resp.header("x-foo").unwrap(), "bar");
 }
+
+#[test]
+fn redirect_307_resends_body() {
+    test::set_handler("/redirect_307_1", |_| {
+        test::make_response(307, "Go here", vec!["Location: /redirect_307_2"], vec![])
+    });
+    test::set_handler("/redirect_307_2", |unit| {
+        assert_eq!(unit.method, "POST");
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    let resp = post("test://host/redirect_307_1").send_string("hello");
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.get_url(), "test://host/redirect_307_2");
+    let s = String::from_utf8_lossy(&resp.to_write_vec()).to_string();
+    assert!(s.starts_with("POST /redirect_307_2 HTTP/1.1\r\n"));
+    assert!(s.ends_with("\r\n\r\nhello"));
+}
+
+#[test]
+fn redirect_308_reader_not_followed() {
+    test::set_handler("/redirect_308_reader", |_| {
+        test::make_response(308, "Go here", vec!["Location: /elsewhere"], vec![])
+    });
+    let resp = put("test://host/redirect_308_reader").send(std::io::Cursor::new(b"hello"));
+    assert_eq!(resp.status(), 308);
+}
+
+#[test]
+fn redirect_downgrade() {
+    let url = |s: &str| url::Url::parse(s).unwrap();
+    assert!(unit::is_downgrade(
+        &url("https://a.com/"),
+        &url("http://a.com/")
+    ));
+    assert!(!unit::is_downgrade(
+        &url("http://a.com/"),
+        &url("https://a.com/")
+    ));
+    assert!(!unit::is_downgrade(
+        &url("https://a.com/"),
+        &url("https://b.com/")
+    ));
+    assert!(!unit::is_downgrade(
+        &url("test://host/"),
+        &url("http://a.com/")
+    ));
+
+    let err = Error::InsecureRedirect("http://a.com/".into());
+    assert_eq!(err.status(), 500);
+    assert_eq!(err.body_text(), "Insecure Redirect: http://a.com/");
+    assert!(!get("https://a.com/").allow_downgrade);
+    assert!(
+        get("https://a.com/")
+            .allow_downgrade_redirects(true)
+            .allow_downgrade
+    );
+}
+
+#[test]
+fn redirect_byte_counts() {
+    test::set_handler("/redirect_bytes1", |_| {
+        test::make_response(302, "Go here", vec!["Location: /redirect_bytes2"], vec![])
+    });
+    test::set_handler("/redirect_bytes2", |_| {
+        test::make_response(200, "OK", vec!["Content-Length: 5"], b"hello".to_vec())
+    });
+    let mut resp = get("test://host/redirect_bytes1").call();
+    assert_eq!(resp.status(), 200);
+    let heads = "HTTP/1.1 302 Go here\r\nLocation: /redirect_bytes2\r\n\r\n".len()
+        + "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n".len();
+    assert_eq!(resp.bytes_read(), heads as u64);
+    // both requests only differ in the path, which has the same length.
+    assert_eq!(resp.bytes_written(), 2 * resp.to_write_vec().len() as u64);
+
+    let mut body = String::new();
+    resp.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "hello");
+    assert_eq!(resp.bytes_read(), heads as u64 + 5);
+}
+
+#[test]
+fn redirect_policy() {
+    let url = |s: &str| url::Url::parse(s).unwrap();
+    let policy = RedirectPolicy::new()
+        .allow_scheme("HTTPS")
+        .allow_host("API.example.com")
+        .allow_host("*.cdn.example.com")
+        .allow_host("[::1]");
+    let from = url("https://api.example.com/");
+    assert!(policy.allows(&from, &url("https://api.example.com/x")));
+    assert!(policy.allows(&from, &url("https://eu.cdn.example.com/")));
+    assert!(policy.allows(&from, &url("https://[::1]:8443/")));
+    assert!(!policy.allows(&from, &url("http://api.example.com/")));
+    assert!(!policy.allows(&from, &url("https://cdn.example.com/")));
+    assert!(!policy.allows(&from, &url("https://evilcdn.example.com/")));
+    assert!(!policy.allows(&from, &url("https://169.254.169.254/")));
+
+    let same = RedirectPolicy::same_origin();
+    assert!(same.allows(&from, &url("https://api.example.com:443/other")));
+    assert!(!same.allows(&from, &url("https://api.example.com:8443/")));
+    assert!(!same.allows(&from, &url("http://api.example.com/")));
+    assert!(RedirectPolicy::new().allows(&from, &url("http://other.com/")));
+
+    test::set_handler("/redirect_policy", |_| {
+        test::make_response(
+            302,
+            "Go here",
+            vec!["Location: test://metadata/redirect_policy2"],
+            vec![],
+        )
+    });
+    let resp = get("test://host/redirect_policy")
+        .redirect_policy(RedirectPolicy::same_origin())
+        .call();
+    assert!(matches!(
+        resp.synthetic_error(),
+        Some(Error::ForbiddenRedirect(url)) if url == "test://metadata/redirect_policy2"
+    ));
+}
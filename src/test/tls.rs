@@ -0,0 +1,276 @@
+use rustls::{Certificate, RootCertStore, ServerCertVerifier};
+
+use crate::revocation::{self, pem_to_der};
+use crate::tls::{self, TlsSettings, Verifier};
+use crate::{ClientIdentity, Crl, Error, TlsCertificate};
+
+// a test CA, with certificate 1001 revoked and 1002 good, until 2036.
+const CA: &str = "\
+-----BEGIN CERTIFICATE-----\n\
+MIIBgzCCASmgAwIBAgIUV0wqTTJ7hgvijrF3hp9UzxiotdwwCgYIKoZIzj0EAwIw\n\
+FzEVMBMGA1UEAwwMdXJlcS10ZXN0LWNhMB4XDTI2MTAxNzIwNTUzM1oXDTM2MTAx\n\
+NDIwNTUzM1owFzEVMBMGA1UEAwwMdXJlcS10ZXN0LWNhMFkwEwYHKoZIzj0CAQYI\n\
+KoZIzj0DAQcDQgAEHd723to8cuLS8YI8OC+SWswUuSTr28reR8sHcWXAkGxWznKc\n\
+oq/YlH1QrIRtlwT2Cv80J7AQIk3HnLzmNwaVG6NTMFEwHQYDVR0OBBYEFEbhXk7y\n\
+hokqHpAixT+GTLG9GdjiMB8GA1UdIwQYMBaAFEbhXk7yhokqHpAixT+GTLG9Gdji\n\
+MA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIgHKXIygU6iDKtJabq\n\
+DTW9bReqU6QrJxMr1kWoJrdsN58CIQD8xGVyIIq49vEC4A68lUgHuLgEUSjwvXQz\n\
+voAZMVfYDw==\n\
+-----END CERTIFICATE-----\n";
+const LEAF_REVOKED: &str = "\
+-----BEGIN CERTIFICATE-----\n\
+MIIBXTCCAQOgAwIBAgICEAEwCgYIKoZIzj0EAwIwFzEVMBMGA1UEAwwMdXJlcS10\n\
+ZXN0LWNhMB4XDTI2MTAxNzIwNTUzM1oXDTM2MTAxNDIwNTUzM1owFDESMBAGA1UE\n\
+AwwJbG9jYWxob3N0MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAExcbdW5PBxZdE\n\
+Jk725yzr5h1SeLjpOXZGn2IkOFYYmhHmu4e+j+wBKD4joOjATOZq6VKg0BCB65Vr\n\
+x++KgSQ2haNCMEAwHQYDVR0OBBYEFOwAt9HQVS6WMwAp1HZXnJOSFV2mMB8GA1Ud\n\
+IwQYMBaAFEbhXk7yhokqHpAixT+GTLG9GdjiMAoGCCqGSM49BAMCA0gAMEUCIBPo\n\
+1oyEJ4r+soFohrRh/osnr7PQF2n5UmF6rDh8byXGAiEAz/wjw5+orpZl4woJ5OQm\n\
+MEZ1vhdVmuJU0e/KfUONIIk=\n\
+-----END CERTIFICATE-----\n";
+const LEAF_GOOD: &str = "\
+-----BEGIN CERTIFICATE-----\n\
+MIIBXjCCAQOgAwIBAgICEAIwCgYIKoZIzj0EAwIwFzEVMBMGA1UEAwwMdXJlcS10\n\
+ZXN0LWNhMB4XDTI2MTAxNzIwNTUzOFoXDTM2MTAxNDIwNTUzOFowFDESMBAGA1UE\n\
+AwwJbG9jYWxob3N0MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEuX836h/m6MsT\n\
+Q+VkXiOcooFt4niVHAqgEmm50hPP4XdV2yLdGmgu/gZOvE3fefrOUTW9wuu3TRTn\n\
+ZKcGFU9d1qNCMEAwHQYDVR0OBBYEFBpS0fwALigWcovbpzX2MOMTDrrIMB8GA1Ud\n\
+IwQYMBaAFEbhXk7yhokqHpAixT+GTLG9GdjiMAoGCCqGSM49BAMCA0kAMEYCIQCM\n\
+YTfm+4zCEyFpScJWjsxqt1Pn+q3fh+1X+oEmUGnFVQIhANiGlIBCJT4GrnecoOec\n\
+hF3MlxBPpxsGKLdDRt8KajgC\n\
+-----END CERTIFICATE-----\n";
+const CRL: &str = "\
+-----BEGIN X509 CRL-----\n\
+MIHGMG0CAQEwCgYIKoZIzj0EAwIwFzEVMBMGA1UEAwwMdXJlcS10ZXN0LWNhFw0y\n\
+NjEwMTcyMDU1MzNaFw0zNjEwMTQyMDU1MzNaMBUwEwICEAEXDTI2MTAxNzIwNTUz\n\
+M1qgDjAMMAoGA1UdFAQDAgEBMAoGCCqGSM49BAMCA0kAMEYCIQCSViqIjzf/JQdK\n\
+tJOZ4ZqzYcjzB/du9E0+9zVb9ToG5AIhALRZ4hxFFZI4GwDXHfwBqcnJdGnGhu+8\n\
+GNVvdtWviWXD\n\
+-----END X509 CRL-----\n";
+const OCSP_GOOD: &str = "MIIBLgoBAKCCAScwggEjBgkrBgEFBQcwAQEEggEUMIIBEDCBuKEZMBcxFTATBgNVBAMMDHVyZXEtdGVzdC1jYRgPMjAyNjEwMTcyMDU1NDJaMGUwYzA7MAkGBSsOAwIaBQAEFIEBlG4zQ2rO9a0RRGyxnauSqdUgBBRG4V5O8oaJKh6QIsU/hkyxvRnY4gICEAKAABgPMjAyNjEwMTcyMDU1NDJaoBEYDzIwMzYxMDE0MjA1NTQyWqEjMCEwHwYJKwYBBQUHMAECBBIEEDigUvSG+39Pe6NENf8uwFIwCgYIKoZIzj0EAwIDRwAwRAIgWidB2ihfBcQW7iqlh2ajUAfbksbPoRpgBDzkjAFh5t8CIETjDy9W5mGpVSFP845wo1m1nX/sT9LvUMnaT/5did3m";
+const OCSP_REVOKED: &str = "MIIBQQoBAKCCATowggE2BgkrBgEFBQcwAQEEggEnMIIBIzCByaEZMBcxFTATBgNVBAMMDHVyZXEtdGVzdC1jYRgPMjAyNjEwMTcyMDU1NDJaMHYwdDA7MAkGBSsOAwIaBQAEFIEBlG4zQ2rO9a0RRGyxnauSqdUgBBRG4V5O8oaJKh6QIsU/hkyxvRnY4gICEAGhERgPMjAyNjEwMTcyMDU1MzNaGA8yMDI2MTAxNzIwNTU0MlqgERgPMjAzNjEwMTQyMDU1NDJaoSMwITAfBgkrBgEFBQcwAQIEEgQQfBLJfxxFV1bTOAHmiEiiRDAKBggqhkjOPQQDAgNJADBGAiEA/NLZUYrBAAX1YU3KWRMkl/DCWfZovLh2eB6QfjMAKdQCIQCm4k5zjwUOLmYJbzF55GMdimAW9eiwNCSR88Ldw12QeA==";
+
+fn chain(leaf: &str) -> Vec<Certificate> {
+    vec![
+        Certificate(pem_to_der(leaf, "CERTIFICATE").unwrap()),
+        Certificate(pem_to_der(CA, "CERTIFICATE").unwrap()),
+    ]
+}
+
+#[test]
+fn crl_revokes() {
+    let crl = Crl::from_pem(CRL).unwrap();
+    assert!(revocation::check_crls(std::slice::from_ref(&crl), &chain(LEAF_REVOKED)).is_err());
+    assert!(revocation::check_crls(&[crl], &chain(LEAF_GOOD)).is_ok());
+}
+
+#[test]
+fn bad_crl() {
+    assert!(matches!(Crl::from_der(b"nope"), Err(Error::BadCrl(_))));
+    assert!(matches!(Crl::from_pem(CA), Err(Error::BadCrl(_))));
+}
+
+#[test]
+fn ocsp_status() {
+    let good = base64::decode(OCSP_GOOD).unwrap();
+    let revoked = base64::decode(OCSP_REVOKED).unwrap();
+    assert!(revocation::check_ocsp(&good, &chain(LEAF_GOOD)).is_ok());
+    assert!(revocation::check_ocsp(&revoked, &chain(LEAF_REVOKED)).is_err());
+    // a response for another certificate.
+    assert!(revocation::check_ocsp(&good, &chain(LEAF_REVOKED)).is_err());
+    assert!(revocation::check_ocsp(&[], &chain(LEAF_GOOD)).is_err());
+}
+
+#[test]
+fn ocsp_bad_signature() {
+    let mut good = base64::decode(OCSP_GOOD).unwrap();
+    let last = good.len() - 1;
+    good[last] ^= 1;
+    assert!(revocation::check_ocsp(&good, &chain(LEAF_GOOD)).is_err());
+}
+
+#[test]
+fn insecure_host() {
+    let settings = TlsSettings {
+        insecure_hosts: vec!["localhost".to_string()],
+        ..TlsSettings::default()
+    };
+    let verifier = Verifier::new(&settings).unwrap();
+    let roots = RootCertStore::empty();
+    let verify = |host: &str| {
+        let name = webpki::DNSNameRef::try_from_ascii_str(host).unwrap();
+        verifier.verify_server_cert(&roots, &chain(LEAF_GOOD), name, &[])
+    };
+    // the test CA is not trusted, so only the listed host gets through.
+    assert!(verify("LocalHost").is_ok());
+    assert!(verify("example.com").is_err());
+    assert!(Verifier::new(&TlsSettings::default()).is_none());
+}
+
+#[test]
+fn root_store() {
+    assert!(!tls::root_store().is_empty());
+}
+
+#[test]
+fn tls_config_shared_per_agent() {
+    use std::sync::Arc;
+
+    let agent = crate::agent();
+    let copy = agent.clone();
+    let config = &agent.state().unwrap().tls_config.0;
+    assert!(Arc::ptr_eq(config, &copy.state().unwrap().tls_config.0));
+
+    let other = crate::agent();
+    assert!(!Arc::ptr_eq(config, &other.state().unwrap().tls_config.0));
+
+    // requests without a built agent share one config too.
+    let default = tls::TlsConfig::default_config();
+    assert!(Arc::ptr_eq(&default.0, &tls::TlsConfig::default_config().0));
+}
+
+#[test]
+fn tls_overrides() {
+    let pem = format!("{}{}", LEAF_GOOD, CA);
+    let certs = TlsCertificate::from_pem(&pem).unwrap();
+    assert_eq!(certs.0, chain(LEAF_GOOD));
+    assert!(matches!(
+        TlsCertificate::from_pem("nope"),
+        Err(Error::BadCertificate(_))
+    ));
+    assert!(matches!(
+        ClientIdentity::from_pem(LEAF_GOOD, CA),
+        Err(Error::BadCertificate(_))
+    ));
+
+    let ca = TlsCertificate::from_pem(CA).unwrap();
+    let mut req = crate::get("https://internal.example/");
+    req.add_root_certificate(&ca)
+        .tls_server_name("backend.example");
+    let overrides = req.tls_overrides.clone().unwrap();
+    assert_eq!(overrides.server_name.as_deref(), Some("backend.example"));
+
+    let settings = TlsSettings::default();
+    let base = tls::TlsConfig::new(&settings).0.root_store.len();
+    let config = tls::TlsConfig::with_overrides(&settings, &overrides);
+    assert_eq!(config.0.root_store.len(), base + 1);
+
+    // connections with settings of their own don't go into the pool.
+    let url = "https://internal.example/".parse().unwrap();
+    let body = crate::body::Payload::Empty.into_read();
+    let unit = crate::unit::Unit::new(&req, &url, true, &body);
+    assert!(!unit.is_poolable());
+}
+
+#[test]
+fn custom_tls_config() {
+    use std::sync::Arc;
+
+    let mut custom = rustls::ClientConfig::new();
+    custom.set_protocols(&[b"custom".to_vec()]);
+    let custom = Arc::new(custom);
+    let agent = crate::agent()
+        .tls_session_cache(0)
+        .tls_config(custom.clone())
+        .build();
+    let state = agent.state().unwrap();
+    assert!(Arc::ptr_eq(&state.tls_config.0, &custom));
+
+    // request roots go into a copy, keeping the rest of the config.
+    let mut req = agent.get("https://internal.example/");
+    req.add_root_certificate(&TlsCertificate::from_pem(CA).unwrap());
+    let overrides = req.tls_overrides.clone().unwrap();
+    let config = tls::TlsConfig::with_overrides(&state.tls_settings, &overrides);
+    assert_eq!(config.0.alpn_protocols, vec![b"custom".to_vec()]);
+    assert_eq!(config.0.root_store.len(), 1);
+}
+
+#[test]
+fn custom_certificate_verifier() {
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    struct Allow;
+
+    impl crate::CertificateVerifier for Allow {
+        fn verify(&self, host: &str, chain: &[&[u8]], _now: SystemTime, verified: bool) -> bool {
+            assert!(!verified);
+            assert_eq!(chain.len(), 2);
+            host == "allowed.test"
+        }
+    }
+
+    let settings = TlsSettings {
+        verifier: Some(Arc::new(Allow)),
+        ..TlsSettings::default()
+    };
+    let verifier = Verifier::new(&settings).unwrap();
+    let roots = RootCertStore::empty();
+    let verify = |host: &str| {
+        let name = webpki::DNSNameRef::try_from_ascii_str(host).unwrap();
+        verifier.verify_server_cert(&roots, &chain(LEAF_GOOD), name, &[])
+    };
+    // the test CA is not trusted, the verifier decides.
+    assert!(verify("allowed.test").is_ok());
+    assert!(verify("other.test").is_err());
+}
+
+#[test]
+#[cfg(feature = "http3")]
+fn key_update_is_not_stale() {
+    use quinn::rustls::{ServerConnection, StreamOwned};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // a server of the newer rustls of quinn, which can send a KeyUpdate to an
+    // idle connection.
+    let config = Arc::new(super::http3::server_config(b"http/1.1"));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let count = connections.clone();
+    std::thread::spawn(move || {
+        for sock in listener.incoming() {
+            count.fetch_add(1, Ordering::SeqCst);
+            let conn = ServerConnection::new(config.clone()).unwrap();
+            let mut tls = StreamOwned::new(conn, sock.unwrap());
+            std::thread::spawn(move || loop {
+                let mut head = vec![];
+                let mut byte = [0_u8];
+                while !head.ends_with(b"\r\n\r\n") {
+                    if tls.read(&mut byte).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    head.push(byte[0]);
+                }
+                tls.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .unwrap();
+                tls.flush().unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+                tls.conn.refresh_traffic_keys().unwrap();
+                while tls.conn.wants_write() {
+                    tls.conn.write_tls(&mut tls.sock).unwrap();
+                }
+            });
+        }
+    });
+
+    let agent = crate::agent()
+        .pool_health_check(true)
+        .accept_invalid_certs_for_host("localhost")
+        .build();
+    let url = format!("https://localhost:{}/", port);
+    for _ in 0..2 {
+        let resp = agent
+            .get(&url)
+            .set_preferred_ip_version(crate::IpVersion::V4)
+            .call();
+        assert_eq!(resp.into_string().unwrap(), "ok");
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    // a TLS handshake message on the idle connection doesn't make it stale.
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
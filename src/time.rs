@@ -0,0 +1,24 @@
+//! Clocks that also work in the browser, where those of `std::time` panic.
+
+use std::time::SystemTime;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub(crate) use web_time::Instant;
+
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+pub(crate) use std::time::Instant;
+
+/// The current time, `SystemTime::now()` everywhere but in the browser.
+pub(crate) fn now() -> SystemTime {
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    {
+        let since = web_time::SystemTime::now()
+            .duration_since(web_time::UNIX_EPOCH)
+            .unwrap_or_default();
+        std::time::UNIX_EPOCH + since
+    }
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    {
+        SystemTime::now()
+    }
+}
@@ -1,7 +1,8 @@
-use std::io::{Result as IoResult, Write};
-use std::sync::{Arc, Mutex};
+use std::io::{self, Cursor, Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
 
-use base64;
+use chunked_transfer::Decoder as ChunkDecoder;
 #[cfg(feature = "cookie")]
 use cookie::{Cookie, CookieJar};
 use qstring::QString;
@@ -9,27 +10,134 @@ use url::Url;
 
 use crate::agent::AgentState;
 use crate::body::{self, Payload, SizedReader};
+use crate::concurrency::Permit;
+use crate::decode;
+use crate::events::EventListener;
+use crate::faults::{FaultConfig, FaultStream};
 use crate::header;
-use crate::stream::{self, connect_https, connect_test, Stream};
-use crate::{Error, Header, Request, Response, IpVersion};
+use crate::hsts;
+use crate::metrics::{ByteCounts, ExchangeStats};
+#[cfg(feature = "ntlm")]
+use crate::ntlm::{self, NtlmCredentials};
+#[cfg(feature = "pac")]
+use crate::pac::Pac;
+use crate::pool;
+use crate::proxy::Proxy;
+use crate::ratelimit;
+use crate::retry;
+#[cfg(feature = "signatures")]
+use crate::signature::{self, Signing};
+#[cfg(all(feature = "sockopt", target_os = "linux"))]
+use crate::sockopt::SocketOptions;
+use crate::stream::{self, connect_https, connect_test, Stream, WireHook};
+use crate::time::Instant;
+#[cfg(feature = "tls")]
+use crate::tls::TlsOverrides;
+use crate::{Error, Header, IpVersion, QueryEncoding, Request, Response};
 
+#[cfg(feature = "cookie")]
 use crate::pool::DEFAULT_HOST;
 
+/// Sent as `User-Agent` unless the request or agent sets one.
+pub(crate) const DEFAULT_USER_AGENT: &str = concat!("ureq/", env!("CARGO_PKG_VERSION"));
+
 /// It's a "unit of work". Maybe a bad name for it?
 ///
 /// *Internal API*
 #[derive(Debug)]
 pub(crate) struct Unit {
-    pub agent: Arc<Mutex<Option<AgentState>>>,
+    pub agent: Arc<Option<AgentState>>,
     pub url: Url,
+    /// Host of the url first requested, before any redirect.
+    pub first_host: String,
     pub is_chunked: bool,
+    pub chunk_size: usize,
     pub query_string: String,
     pub headers: Vec<Header>,
+    /// Header names written first.
+    pub header_order: Vec<String>,
+    /// Send `*` as the request target, for `OPTIONS *`.
+    pub asterisk_form: bool,
+    /// Read the body with its `Content-Encoding` as received.
+    pub no_decompress: bool,
+    /// Parse the response head leniently.
+    pub lenient: bool,
+    /// Print the exchange to stderr.
+    pub verbose: bool,
+    /// The body was not sent in full, for an early error response, so the
+    /// connection can't be reused.
+    pub body_cut_short: bool,
+    /// Max body bytes read by `Response::into_json()`.
+    #[cfg(feature = "json")]
+    pub json_limit: Option<u64>,
+    pub timeout_resolve: u64,
     pub timeout_connect: u64,
     pub timeout_read: u64,
     pub timeout_write: u64,
     pub method: String,
     pub preferred_ip_version: IpVersion,
+    pub wire_hook: Option<WireHook>,
+    pub faults: Option<FaultConfig>,
+    /// SOCKS5 proxies to connect through, the next one when one fails.
+    pub proxies: Vec<Proxy>,
+    /// Proxy auto-config, used when there are no proxies.
+    #[cfg(feature = "pac")]
+    pub pac: Option<Arc<Pac>>,
+    /// Zone id of an IPv6 link-local host.
+    pub zone: Option<String>,
+    /// Connect here instead of resolving the url host.
+    pub connect_addr: Option<SocketAddr>,
+    /// Sent as `Host` instead of the url host, unless the user set `Host`.
+    pub host_header: Option<String>,
+    /// TLS settings of the request over those of the agent.
+    #[cfg(feature = "tls")]
+    pub tls_overrides: Option<TlsOverrides>,
+    /// Bearer token sent as `Authorization`.
+    #[cfg(feature = "oauth2")]
+    pub oauth2_token: Option<String>,
+    /// Whether the request was sent again with a new token after a `401`.
+    #[cfg(feature = "oauth2")]
+    pub oauth2_retried: bool,
+    /// Key and components to sign the request with.
+    #[cfg(feature = "signatures")]
+    pub signing: Option<Signing>,
+    /// Sent unless the user set the same header.
+    pub request_id: Option<Header>,
+    /// Sent with each attempt of a request that isn't idempotent.
+    pub idempotency_key: Option<Header>,
+    /// Told about each step of the request.
+    pub events: Option<Arc<dyn EventListener>>,
+    /// Bytes of the request so far, including its redirects.
+    pub bytes: Arc<ByteCounts>,
+    /// The place of the request under the agent's concurrency limit.
+    pub permit: Option<Arc<Permit>>,
+    /// Whether to answer `Negotiate` challenges.
+    #[cfg(feature = "negotiate")]
+    pub negotiate: bool,
+    #[cfg(all(feature = "sockopt", target_os = "linux"))]
+    pub socket_options: SocketOptions,
+    /// SPNEGO token sent as `Authorization`, once challenged.
+    #[cfg(feature = "negotiate")]
+    pub negotiate_token: Option<String>,
+    /// Credentials to answer `NTLM` challenges with.
+    #[cfg(feature = "ntlm")]
+    pub ntlm: Option<NtlmCredentials>,
+    /// Where the NTLM handshake is at.
+    #[cfg(feature = "ntlm")]
+    pub ntlm_step: Option<ntlm::Step>,
+    /// NTLM message sent as `Authorization`.
+    #[cfg(feature = "ntlm")]
+    pub ntlm_auth: Option<String>,
+    /// Connection of the NTLM handshake, used instead of opening one.
+    #[cfg(feature = "ntlm")]
+    pub kept_stream: Option<Stream>,
+    /// Index of the HAR entry the response body is recorded into.
+    #[cfg(feature = "har")]
+    pub har_entry: Option<usize>,
+    #[cfg(feature = "http3")]
+    pub http3: bool,
+    #[cfg(feature = "http3")]
+    pub http3_only: bool,
 }
 
 impl Unit {
@@ -38,6 +146,9 @@ impl Unit {
     pub(crate) fn new(req: &Request, url: &Url, mix_queries: bool, body: &SizedReader) -> Self {
         //
 
+        let upgraded = hsts::upgrade(&req.agent, url);
+        let url = upgraded.as_ref().unwrap_or(url);
+
         let is_chunked = req
             .header("transfer-encoding")
             // if the user has set an encoding header, obey that.
@@ -45,9 +156,9 @@ impl Unit {
             // otherwise, no chunking.
             .unwrap_or(false);
 
-        let query_string = combine_query(&url, &req.query, mix_queries);
+        let query_string = combine_query(url, &req.query, req.query_encoding, mix_queries);
 
-        let cookie_headers: Vec<_> = extract_cookies(&req.agent, &url);
+        let cookie_headers: Vec<_> = extract_cookies(&req.agent, url);
 
         let extra_headers = {
             let mut extra = vec![];
@@ -62,7 +173,7 @@ impl Unit {
 
             let username = url.username();
             let password = url.password().unwrap_or("");
-            if (username != "" || password != "") && !req.has("authorization") {
+            if (!username.is_empty() || !password.is_empty()) && !req.has("authorization") {
                 let encoded = base64::encode(&format!("{}:{}", username, password));
                 extra.push(Header::new("Authorization", &format!("Basic {}", encoded)));
             }
@@ -79,32 +190,116 @@ impl Unit {
 
         Unit {
             agent: Arc::clone(&req.agent),
-            url: url.clone(),
+            // the credentials are in the Authorization header.
+            url: redacted(url),
+            first_host: url.host_str().unwrap_or("").to_string(),
             is_chunked,
+            chunk_size: req.chunk_size.unwrap_or(body::DEFAULT_CHUNK_SIZE),
             query_string,
             headers,
+            header_order: req.header_order.clone(),
+            asterisk_form: req.asterisk_form,
+            no_decompress: req.no_decompress,
+            lenient: req.lenient_parsing,
+            verbose: req.verbose,
+            body_cut_short: false,
+            #[cfg(feature = "json")]
+            json_limit: req.json_limit,
             preferred_ip_version: req.preferred_ip_version,
+            timeout_resolve: req.timeout_resolve,
             timeout_connect: req.timeout_connect,
             timeout_read: req.timeout_read,
             timeout_write: req.timeout_write,
             method: req.method.clone(),
+            wire_hook: req.wire_hook.clone(),
+            faults: req.faults,
+            proxies: req.proxies.clone(),
+            #[cfg(feature = "pac")]
+            pac: req.pac.clone(),
+            zone: req.zone(),
+            connect_addr: req.connect_addr,
+            host_header: req.host_header.clone(),
+            #[cfg(feature = "tls")]
+            tls_overrides: req.tls_overrides.clone(),
+            #[cfg(feature = "oauth2")]
+            oauth2_token: None,
+            #[cfg(feature = "oauth2")]
+            oauth2_retried: false,
+            #[cfg(feature = "signatures")]
+            signing: req.signing.clone(),
+            request_id: None,
+            idempotency_key: None,
+            events: req.events.clone(),
+            bytes: Arc::new(ByteCounts::new(
+                (*req.agent).as_ref().map(|s| s.counters.clone()),
+            )),
+            permit: None,
+            #[cfg(feature = "negotiate")]
+            negotiate: req.negotiate,
+            #[cfg(all(feature = "sockopt", target_os = "linux"))]
+            socket_options: req.socket_options,
+            #[cfg(feature = "negotiate")]
+            negotiate_token: None,
+            #[cfg(feature = "ntlm")]
+            ntlm: req.ntlm.clone(),
+            #[cfg(feature = "ntlm")]
+            ntlm_step: None,
+            #[cfg(feature = "ntlm")]
+            ntlm_auth: None,
+            #[cfg(feature = "ntlm")]
+            kept_stream: None,
+            #[cfg(feature = "har")]
+            har_entry: None,
+            #[cfg(feature = "http3")]
+            http3: req.http3,
+            #[cfg(feature = "http3")]
+            http3_only: req.http3_only,
+        }
+    }
+
+    /// Whether a connection of the agent pool will do, which is not the case
+    /// for one to an explicit address or with TLS settings of its own.
+    pub fn is_poolable(&self) -> bool {
+        #[cfg(feature = "tls")]
+        {
+            if self.tls_overrides.is_some() {
+                return false;
+            }
         }
+        self.connect_addr.is_none()
     }
 
     pub fn is_head(&self) -> bool {
         self.method.eq_ignore_ascii_case("head")
     }
 
+    /// Whether a body of `Content-Encoding` `encoding` is decoded: unless
+    /// `no_decompress()`, when the user's `Accept-Encoding`, if any, lists it.
+    pub fn decodes(&self, encoding: &str) -> bool {
+        if self.no_decompress {
+            return false;
+        }
+        let accepted = match header::get_header(&self.headers, "accept-encoding") {
+            Some(accepted) => accepted,
+            None => return true,
+        };
+        let encoding = encoding.trim();
+        accepted.split(',').any(|a| {
+            let name = a.split(';').next().unwrap_or("").trim();
+            name == "*" || name.eq_ignore_ascii_case(encoding)
+        })
+    }
+
     #[cfg(test)]
-    pub fn header<'a>(&self, name: &'a str) -> Option<&str> {
+    pub fn header(&self, name: &str) -> Option<&str> {
         header::get_header(&self.headers, name)
     }
     #[cfg(test)]
-    pub fn has<'a>(&self, name: &'a str) -> bool {
+    pub fn has(&self, name: &str) -> bool {
         header::has_header(&self.headers, name)
     }
     #[cfg(test)]
-    pub fn all<'a>(&self, name: &'a str) -> Vec<&str> {
+    pub fn all(&self, name: &str) -> Vec<&str> {
         header::get_all_headers(&self.headers, name)
     }
 }
@@ -120,42 +315,260 @@ pub(crate) fn connect(
 ) -> Result<Response, Error> {
     //
 
-    // open socket
-    let (mut stream, is_recycled) = connect_socket(&unit, use_pooled)?;
+    #[cfg(feature = "har")]
+    let exchange = crate::har::Exchange::start(&unit);
 
-    let send_result = send_prelude(&unit, &mut stream, redir);
+    // the body again for a retry or a 307/308 redirect, if it can be sent again.
+    let again = body.rewind();
 
-    if let Err(err) = send_result {
-        if is_recycled {
-            // we try open a new connection, this time there will be
-            // no connection in the pool. don't use it.
-            return connect(req, unit, false, redirect_count, body, redir);
+    let mut stats = ExchangeStats::default();
+    let mut phase_start = Instant::now();
+
+    #[cfg(feature = "oauth2")]
+    let unit = {
+        let mut unit = unit;
+        crate::oauth2::authorize(&mut unit)?;
+        unit
+    };
+
+    // open socket, unless a handshake keeps using the one it started on.
+    #[cfg(feature = "ntlm")]
+    let (unit, kept) = {
+        let mut unit = unit;
+        let kept = unit.kept_stream.take();
+        (unit, kept)
+    };
+    #[cfg(not(feature = "ntlm"))]
+    let kept = None;
+    let (mut stream, is_recycled) = match kept {
+        Some(stream) => {
+            count_connection(&unit, true);
+            (stream, true)
+        }
+        None => connect_socket(&unit, use_pooled)?,
+    };
+
+    if !is_recycled {
+        stats.connect = Some(phase_start.elapsed());
+    }
+    if unit.verbose {
+        let host = unit.url.host_str().unwrap_or_default();
+        let port = unit.url.port_or_known_default().unwrap_or(0);
+        let verb = if is_recycled {
+            "Re-using connection"
         } else {
-            // not a pooled connection, propagate the error.
-            return Err(err.into());
+            "Connected"
+        };
+        eprintln!("* {} to {} port {}", verb, host, port);
+    }
+    phase_start = Instant::now();
+
+    #[cfg(feature = "tracing")]
+    {
+        if is_recycled {
+            tracing::debug!("reusing pooled connection");
         }
     }
 
+    #[cfg(feature = "har")]
+    let body = match exchange.as_ref() {
+        Some(exchange) => exchange.capture_body(body),
+        None => body,
+    };
+
+    let send_result = send_prelude(&unit, &mut stream, redir);
+
+    let prelude_len = match send_result {
+        Ok(len) => len,
+        Err(err) => {
+            if is_recycled && retry::allow(&unit.agent) {
+                // we try open a new connection, this time there will be
+                // no connection in the pool. don't use it.
+                return connect(req, unit, false, redirect_count, body, redir);
+            } else {
+                // not a pooled connection, propagate the error.
+                return Err(Error::from_write(err));
+            }
+        }
+    };
+
+    // a server may answer before the body is sent in full. an error response
+    // stops sending, anything else is kept while the rest goes out.
+    let mut early: Option<Response> = None;
+    let mut interim_len = 0;
+    let mut stop = |stream: &mut Stream| {
+        if early.is_some() || !stream.has_response() {
+            return false;
+        }
+        let resp = read_response(&unit, stream);
+        verbose_response(&unit, &resp);
+        if resp.status() / 100 == 1 && resp.status() != 101 {
+            if let (103, Some(hook)) = (resp.status(), &req.early_hints) {
+                (hook.0)(&resp.headers);
+            }
+            interim_len += resp.stats.bytes_received;
+            return false;
+        }
+        let is_error = resp.status() >= 400;
+        early = Some(resp);
+        is_error
+    };
+
     // send the body (which can be empty now depending on redirects)
-    let body_bytes_sent = body::send_body(body, unit.is_chunked, &mut stream)?;
+    let sent = body::send_body(
+        body,
+        unit.is_chunked,
+        unit.chunk_size,
+        &mut stream,
+        &mut stop,
+    );
+    let body_bytes_sent = match sent {
+        Ok(sent) => sent,
+        Err(err) => {
+            if is_recycled && is_closed(&err) && retry::allow(&unit.agent) {
+                if let Some(body) = again {
+                    // the server closed the pooled connection, send it all again.
+                    return connect(req, unit, false, redirect_count, body, redir);
+                }
+            }
+            return Err(Error::from_write(err));
+        }
+    };
+
+    stats.send = phase_start.elapsed();
+    stats.bytes_sent = prelude_len as u64 + body_bytes_sent;
+    if let Some(events) = &unit.events {
+        events.on_request_sent(
+            &unit.method,
+            unit.url.as_str(),
+            stats.bytes_sent,
+            stats.send,
+        );
+    }
+    phase_start = Instant::now();
 
     // start reading the response to process cookies and redirects.
-    let mut resp = Response::from_read(&mut stream);
+    let mut unit = unit;
+    let mut resp = match early {
+        Some(resp) => {
+            unit.body_cut_short = resp.status() >= 400;
+            #[cfg(feature = "tracing")]
+            tracing::debug!(status = resp.status(), "early response");
+            resp
+        }
+        None => {
+            let resp = read_response(&unit, &mut stream);
+            verbose_response(&unit, &resp);
+            resp
+        }
+    };
+
+    // skip interim responses, but 101 switches protocol and is the final one.
+    while resp.status() / 100 == 1 && resp.status() != 101 {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(status = resp.status(), "interim response");
+        if let (103, Some(hook)) = (resp.status(), &req.early_hints) {
+            (hook.0)(&resp.headers);
+        }
+        interim_len += resp.stats.bytes_received;
+        resp = read_response(&unit, &mut stream);
+        verbose_response(&unit, &resp);
+    }
+
+    stats.wait = phase_start.elapsed();
+    stats.bytes_received = interim_len + resp.stats.bytes_received;
+    resp.stats = stats;
+    unit.bytes.add(stats.bytes_sent, stats.bytes_received);
+    if let Some(events) = &unit.events {
+        events.on_response_headers(unit.url.as_str(), &resp, stats.wait);
+    }
 
     if let Some(err) = resp.synthetic_error() {
-        if err.is_bad_status_read() && body_bytes_sent == 0 && is_recycled {
-            // We try open a new connection, this happens if the remote server
+        let idempotent = is_idempotent(&unit.method);
+        if err.is_bad_status_read()
+            && is_recycled
+            && (again.is_some() || (idempotent && body_bytes_sent == 0))
+            && retry::allow(&unit.agent)
+        {
+            // We try once on a new connection, this happens if the remote server
             // hangs a pooled connection and we only discover when trying to
-            // read from it. It's however only possible if we didn't send any
-            // body bytes. This is because we currently don't want to buffer
-            // any body to be able to replay it.
-            let empty = Payload::Empty.into_read();
-            return connect(req, unit, false, redirect_count, empty, redir);
+            // read from it, before any response bytes. It's however only done
+            // if the body can be sent again, or if an idempotent request didn't
+            // send any body bytes.
+            #[cfg(feature = "tracing")]
+            tracing::debug!("pooled connection was closed, retrying");
+            let body = again.unwrap_or_else(|| Payload::Empty.into_read());
+            return connect(req, unit, false, redirect_count, body, redir);
         }
     }
 
+    #[cfg(feature = "har")]
+    let unit = {
+        let mut unit = unit;
+        unit.har_entry = exchange.and_then(|e| e.finish(&unit, redir, &resp));
+        unit
+    };
+
     // squirrel away cookies
     save_cookies(&unit, &resp);
+    hsts::save(
+        &unit.agent,
+        &unit.url,
+        resp.header("strict-transport-security"),
+    );
+    #[cfg(feature = "http3")]
+    crate::http3::save_alt_svc(&unit, &resp);
+
+    // answer a Negotiate challenge once, if the body can be sent again.
+    #[cfg(feature = "negotiate")]
+    {
+        if unit.negotiate && unit.negotiate_token.is_none() && crate::negotiate::is_challenge(&resp)
+        {
+            let host = unit.url.host_str().unwrap_or(crate::pool::DEFAULT_HOST);
+            if let Some(token) = crate::negotiate::token(host) {
+                if let Some(body) = again {
+                    let mut unit = unit;
+                    unit.negotiate_token = Some(token);
+                    return connect(req, unit, use_pooled, redirect_count, body, redir);
+                }
+            }
+        }
+    }
+
+    // a 401 with a token of the agent is sent again once, with a new token.
+    #[cfg(feature = "oauth2")]
+    {
+        if crate::oauth2::should_retry(&unit, resp.status()) {
+            if let Some(body) = again {
+                let mut unit = unit;
+                if let Some(token) = unit.oauth2_token.take() {
+                    crate::oauth2::expired(&unit.agent, &token);
+                }
+                unit.oauth2_retried = true;
+                return connect(req, unit, use_pooled, redirect_count, body, redir);
+            }
+        }
+    }
+
+    // NTLM authenticates the connection, so the handshake goes on over it.
+    #[cfg(feature = "ntlm")]
+    {
+        let answer = unit
+            .ntlm
+            .as_ref()
+            .and_then(|creds| crate::ntlm::answer(creds, unit.ntlm_step, &resp));
+        if let Some((auth, step)) = answer {
+            if let Some(body) = again {
+                let (drained, reusable) = drain_body(&unit, &resp, &mut stream)?;
+                unit.bytes.add(0, drained.len() as u64);
+                let mut unit = unit;
+                unit.ntlm_auth = Some(auth);
+                unit.ntlm_step = Some(step);
+                unit.kept_stream = Some(stream).filter(|_| reusable);
+                return connect(req, unit, use_pooled, redirect_count, body, redir);
+            }
+        }
+    }
 
     // handle redirects
     if resp.redirect() && req.redirects > 0 {
@@ -170,14 +583,25 @@ pub(crate) fn connect(
             let new_url = unit
                 .url
                 .join(location)
-                .map_err(|_| Error::BadUrl(format!("Bad redirection: {}", location)))?;
+                .map(|url| redacted(&url))
+                .map_err(|_| Error::BadUrl("Bad redirection".to_string()))?;
+
+            let new_url = hsts::upgrade(&unit.agent, &new_url).unwrap_or(new_url);
+            if is_downgrade(&unit.url, &new_url) && !req.allow_downgrade {
+                return Err(Error::InsecureRedirect(new_url.to_string()));
+            }
+            if !req.redirect_policy.allows(&unit.url, &new_url) {
+                return Err(Error::ForbiddenRedirect(new_url.to_string()));
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(status = resp.status(), location = %new_url, "redirect");
 
             // perform the redirect differently depending on 3xx code.
             match resp.status() {
-                301 | 302 | 303 => {
+                301..=303 => {
                     let empty = Payload::Empty.into_read();
-                    // recreate the unit to get a new hostname and cookies for the new host.
-                    let mut new_unit = Unit::new(req, &new_url, false, &empty);
+                    let mut new_unit = redirect_unit(req, &unit, resp.status(), &new_url, &empty)?;
                     // this is to follow how curl does it. POST, PUT etc change
                     // to GET on a redirect.
                     new_unit.method = match &unit.method[..] {
@@ -186,9 +610,19 @@ pub(crate) fn connect(
                     };
                     return connect(req, new_unit, use_pooled, redirect_count + 1, empty, true);
                 }
+                // the method and body stay, so a body read from a reader can't
+                // be followed and the redirect is the response.
+                307 | 308 => {
+                    if let Some(body) = again {
+                        let mut new_unit =
+                            redirect_unit(req, &unit, resp.status(), &new_url, &body)?;
+                        new_unit.method = unit.method;
+                        // the same request again, unlike the GET of a 303.
+                        new_unit.idempotency_key = unit.idempotency_key;
+                        return connect(req, new_unit, use_pooled, redirect_count + 1, body, true);
+                    }
+                }
                 _ => (),
-                // reinstate this with expect-100
-                // 307 | 308 | _ => connect(unit, method, use_pooled, redirects - 1, body),
             };
         }
     }
@@ -201,26 +635,93 @@ pub(crate) fn connect(
     Ok(resp)
 }
 
+/// The head of a response, or a synthetic one for the error reading it.
+fn read_response(unit: &Unit, stream: &mut Stream) -> Response {
+    Response::read_head(stream, unit.lenient).unwrap_or_else(|e| e.into())
+}
+
+/// Whether the method may be sent twice with the same effect as once.
+pub(crate) fn is_idempotent(method: &str) -> bool {
+    matches!(
+        method.to_ascii_uppercase().as_str(),
+        "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS" | "TRACE"
+    )
+}
+
+/// Whether a write failed because the server closed the connection.
+fn is_closed(err: &IoError) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+    )
+}
+
+/// The url without the `user:pass@` part, which is sent as a Basic
+/// `Authorization` header and mustn't show up anywhere else.
+pub(crate) fn redacted(url: &Url) -> Url {
+    let mut url = url.clone();
+    // only fails for urls that can't have credentials anyway.
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url
+}
+
+/// Whether a redirect from `from` to `to` drops TLS.
+pub(crate) fn is_downgrade(from: &Url, to: &Url) -> bool {
+    from.scheme() == "https" && to.scheme() == "http"
+}
+
+/// The unit for following a redirect to `url`. It is recreated to get the new
+/// hostname and cookies for the new host.
+fn redirect_unit(
+    req: &Request,
+    unit: &Unit,
+    status: u16,
+    url: &Url,
+    body: &SizedReader,
+) -> Result<Unit, Error> {
+    if let Some(events) = &unit.events {
+        events.on_redirect(status, unit.url.as_str(), url.as_str());
+    }
+    let mut new_unit = Unit::new(req, url, false, body);
+    new_unit.request_id = unit.request_id.clone();
+    new_unit.first_host = unit.first_host.clone();
+    new_unit.bytes = unit.bytes.clone();
+    new_unit.permit = unit.permit.clone();
+    if url.host_str() != unit.url.host_str() {
+        // the overrides were meant for the original host.
+        new_unit.host_header = None;
+        new_unit.connect_addr = None;
+        new_unit.zone = None;
+        #[cfg(feature = "tls")]
+        {
+            new_unit.tls_overrides = None;
+        }
+    }
+    ratelimit::acquire(&new_unit)?;
+    retry::record_request(&new_unit.agent);
+    Ok(new_unit)
+}
+
 #[cfg(feature = "cookie")]
-fn extract_cookies(state: &std::sync::Mutex<Option<AgentState>>, url: &Url) -> Vec<Header> {
-    let state = state.lock().unwrap();
+fn extract_cookies(state: &Option<AgentState>, url: &Url) -> Vec<Header> {
     let is_secure = url.scheme().eq_ignore_ascii_case("https");
     let hostname = url.host_str().unwrap_or(DEFAULT_HOST).to_string();
 
     match state.as_ref().map(|state| &state.jar) {
         None => vec![],
-        Some(jar) => match_cookies(jar, &hostname, url.path(), is_secure),
+        Some(jar) => match_cookies(&jar.read().unwrap(), &hostname, url.path(), is_secure),
     }
 }
 
 #[cfg(not(feature = "cookie"))]
-fn extract_cookies(_state: &std::sync::Mutex<Option<AgentState>>, url: &Url) -> Vec<Header> {
+fn extract_cookies(_state: &Option<AgentState>, _url: &Url) -> Vec<Header> {
     vec![]
 }
 
 // TODO check so cookies can't be set for tld:s
 #[cfg(feature = "cookie")]
-fn match_cookies<'a>(jar: &'a CookieJar, domain: &str, path: &str, is_secure: bool) -> Vec<Header> {
+fn match_cookies(jar: &CookieJar, domain: &str, path: &str, is_secure: bool) -> Vec<Header> {
     jar.iter()
         .filter(|c| {
             // if there is a domain, it must be matched.
@@ -250,7 +751,13 @@ fn match_cookies<'a>(jar: &'a CookieJar, domain: &str, path: &str, is_secure: bo
 }
 
 /// Combine the query of the url and the query options set on the request object.
-pub(crate) fn combine_query(url: &Url, query: &QString, mix_queries: bool) -> String {
+pub(crate) fn combine_query(
+    url: &Url,
+    query: &QString,
+    encoding: QueryEncoding,
+    mix_queries: bool,
+) -> String {
+    let query = encode_query(query, encoding);
     match (url.query(), !query.is_empty() && mix_queries) {
         (Some(urlq), true) => format!("?{}&{}", urlq, query),
         (Some(urlq), false) => format!("?{}", urlq),
@@ -259,67 +766,429 @@ pub(crate) fn combine_query(url: &Url, query: &QString, mix_queries: bool) -> St
     }
 }
 
+/// Render the query options set on the request object using the given encoding.
+fn encode_query(query: &QString, encoding: QueryEncoding) -> String {
+    let encode = |s: &str| match encoding {
+        QueryEncoding::Strict => encode_unreserved(s, false),
+        QueryEncoding::Form => encode_unreserved(s, true),
+        _ => s.to_string(),
+    };
+    match encoding {
+        QueryEncoding::Standard => query.to_string(),
+        _ => query
+            .to_pairs()
+            .iter()
+            .map(|(param, value)| format!("{}={}", encode(param), encode(value)))
+            .collect::<Vec<_>>()
+            .join("&"),
+    }
+}
+
+/// A host, optionally with a port, as it is resolved and sent: international
+/// names in punycode like the url crate makes them in urls, so that `Host`,
+/// SNI and certificate checks agree. Invalid names are kept as given.
+pub(crate) fn ascii_host(host: &str) -> String {
+    if host.is_ascii() {
+        return host.to_string();
+    }
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => (name, Some(port)),
+        _ => (host, None),
+    };
+    match (url::Host::parse(name), port) {
+        (Ok(url::Host::Domain(domain)), Some(port)) => format!("{}:{}", domain, port),
+        (Ok(url::Host::Domain(domain)), None) => domain,
+        _ => host.to_string(),
+    }
+}
+
+/// Percent-encode all but the RFC 3986 unreserved characters.
+pub(crate) fn encode_unreserved(s: &str, space_as_plus: bool) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            b' ' if space_as_plus => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
 /// Connect the socket, either by using the pool or grab a new one.
 fn connect_socket(unit: &Unit, use_pooled: bool) -> Result<(Stream, bool), Error> {
-    if use_pooled {
-        let state = &mut unit.agent.lock().unwrap();
-        if let Some(agent) = state.as_mut() {
-            if let Some(stream) = agent.pool.try_get_connection(&unit.url) {
+    if use_pooled && unit.is_poolable() {
+        if let Some(agent) = (*unit.agent).as_ref() {
+            if let Some(stream) = agent.pool.try_get_connection(unit) {
+                count_connection(unit, true);
                 return Ok((stream, true));
             }
         }
     }
-    let stream = match unit.url.scheme() {
-        "http" => stream::connect_http(&unit),
-        "https" => connect_https(&unit),
-        "test" => connect_test(&unit),
-        _ => Err(Error::UnknownScheme(unit.url.scheme().to_string())),
+    #[cfg(feature = "http3")]
+    let http3 = crate::http3::connect(unit)?;
+    #[cfg(not(feature = "http3"))]
+    let http3: Option<Stream> = None;
+    let stream = if let Some(stream) = http3 {
+        stream
+    } else {
+        match unit.url.scheme() {
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            "http" | "https" => Ok(Stream::Xhr(crate::wasm::connect(unit))),
+            #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+            "http" => stream::connect_http(unit),
+            #[cfg(all(feature = "security-framework", target_vendor = "apple"))]
+            "https" => crate::apple_tls::connect(unit),
+            #[cfg(not(any(
+                all(feature = "wasm", target_arch = "wasm32"),
+                all(feature = "security-framework", target_vendor = "apple")
+            )))]
+            "https" => connect_https(unit),
+            "test" => connect_test(unit),
+            _ => Err(Error::UnknownScheme(unit.url.scheme().to_string())),
+        }?
+    };
+    let stream = match &unit.wire_hook {
+        Some(hook) => Stream::Wire(Box::new(stream), hook.clone()),
+        None => stream,
+    };
+    let stream = match unit.faults {
+        Some(config) => Stream::Fault(Box::new(FaultStream::new(stream, config))),
+        None => stream,
     };
-    Ok((stream?, false))
+    count_connection(unit, false);
+    Ok((stream, false))
 }
 
-/// Send request line + headers (all up until the body).
-#[allow(clippy::write_with_newline)]
-fn send_prelude(unit: &Unit, stream: &mut Stream, redir: bool) -> IoResult<()> {
-    //
-
-    // build into a buffer and send in one go.
-    let mut prelude: Vec<u8> = vec![];
+fn count_connection(unit: &Unit, reused: bool) {
+    if let Some(agent) = (*unit.agent).as_ref() {
+        agent.counters.connection(reused);
+    }
+}
 
-    // request line
-    write!(
-        prelude,
-        "{} {}{} HTTP/1.1\r\n",
-        unit.method,
-        unit.url.path(),
-        &unit.query_string
-    )?;
+/// The headers that go out with the request line, including defaults for
+/// those the user didn't set.
+pub(crate) fn prelude_headers(unit: &Unit, redir: bool) -> Vec<Header> {
+    let mut headers = vec![];
 
     // host header if not set by user.
     if !header::has_header(&unit.headers, "host") {
-        write!(prelude, "Host: {}\r\n", unit.url.host().unwrap())?;
+        let host = match &unit.host_header {
+            Some(host) => host.clone(),
+            None => unit.url.host().unwrap().to_string(),
+        };
+        headers.push(Header::new("Host", &host));
     }
     if !header::has_header(&unit.headers, "user-agent") {
-        write!(prelude, "User-Agent: ureq\r\n")?;
+        headers.push(Header::new("User-Agent", DEFAULT_USER_AGENT));
     }
     if !header::has_header(&unit.headers, "accept") {
-        write!(prelude, "Accept: */*\r\n")?;
+        headers.push(Header::new("Accept", "*/*"));
+    }
+    if !unit.no_decompress && !header::has_header(&unit.headers, "accept-encoding") {
+        if let Some(encodings) = decode::accept_encoding() {
+            headers.push(Header::new("Accept-Encoding", &encodings));
+        }
+    }
+    if let Some(request_id) = &unit.request_id {
+        if !header::has_header(&unit.headers, request_id.name()) {
+            headers.push(request_id.clone());
+        }
+    }
+    if let Some(key) = &unit.idempotency_key {
+        headers.push(key.clone());
     }
 
     // other headers
+    let handshake = handshake_auth(unit);
     for header in &unit.headers {
-        if !redir || !header.is_name("Authorization") {
-            write!(prelude, "{}: {}\r\n", header.name(), header.value())?;
+        if !((redir || handshake.is_some()) && header.is_name("Authorization")) {
+            headers.push(header.clone());
         }
     }
+    if let Some(auth) = handshake {
+        headers.push(Header::new("Authorization", &auth));
+    }
 
-    // finish
-    write!(prelude, "\r\n")?;
+    header::order_headers(&mut headers, &unit.header_order);
+    headers
+}
+
+/// The `Authorization` of a Negotiate or NTLM handshake, sent instead of the
+/// one of the user.
+#[allow(unused_variables)]
+fn handshake_auth(unit: &Unit) -> Option<String> {
+    #[cfg(feature = "oauth2")]
+    {
+        if let Some(token) = &unit.oauth2_token {
+            return Some(format!("Bearer {}", token));
+        }
+    }
+    #[cfg(feature = "negotiate")]
+    {
+        if let Some(token) = &unit.negotiate_token {
+            return Some(format!("Negotiate {}", token));
+        }
+    }
+    #[cfg(feature = "ntlm")]
+    {
+        if unit.ntlm_auth.is_some() {
+            return unit.ntlm_auth.clone();
+        }
+    }
+    None
+}
+
+/// Send request line + headers (all up until the body).
+fn send_prelude(unit: &Unit, stream: &mut Stream, redir: bool) -> IoResult<usize> {
+    // build into a buffer and send in one go.
+    let prelude = prelude(unit, redir)?;
+
+    if unit.verbose {
+        eprint!("{}", verbose_lines("> ", &prelude));
+    }
 
     // write all to the wire
     stream.write_all(&prelude[..])?;
 
-    Ok(())
+    Ok(prelude.len())
+}
+
+/// Print the head of a response for `Request::verbose()`.
+fn verbose_response(unit: &Unit, resp: &Response) {
+    if !unit.verbose {
+        return;
+    }
+    match resp.synthetic_error() {
+        Some(err) => eprintln!("* {}", err),
+        None => eprint!("{}", verbose_lines("< ", resp.raw_head())),
+    }
+}
+
+/// Each line of a head after the prefix, like `curl -v` prints them, with the
+/// values of credential headers masked.
+pub(crate) fn verbose_lines(prefix: &str, head: &[u8]) -> String {
+    String::from_utf8_lossy(head)
+        .lines()
+        .map(|line| match line.split_once(':') {
+            Some((name, _)) if is_secret_header(name) => format!("{}{}: ***\n", prefix, name),
+            _ => format!("{}{}\n", prefix, line),
+        })
+        .collect()
+}
+
+/// Whether the value of the header is a credential.
+pub(crate) fn is_secret_header(name: &str) -> bool {
+    ["authorization", "proxy-authorization", "cookie"]
+        .iter()
+        .any(|n| n.eq_ignore_ascii_case(name.trim()))
+}
+
+/// The request line and headers.
+#[allow(clippy::write_with_newline)]
+fn prelude(unit: &Unit, redir: bool) -> IoResult<Vec<u8>> {
+    let mut prelude: Vec<u8> = vec![];
+
+    // request line
+    if unit.asterisk_form {
+        write!(prelude, "{} * HTTP/1.1\r\n", unit.method)?;
+    } else {
+        write!(
+            prelude,
+            "{} {}{} HTTP/1.1\r\n",
+            unit.method,
+            unit.url.path(),
+            &unit.query_string
+        )?;
+    }
+
+    #[allow(unused_mut)]
+    let mut headers = prelude_headers(unit, redir);
+    #[cfg(feature = "signatures")]
+    {
+        if let Some(signing) = &unit.signing {
+            signature::sign(unit, signing, &mut headers)?;
+        }
+    }
+    for header in headers {
+        write!(prelude, "{}: {}\r\n", header.name(), header.value())?;
+    }
+
+    // finish
+    write!(prelude, "\r\n")?;
+
+    Ok(prelude)
+}
+
+/// Send requests on one connection before reading any response, see
+/// [`Agent::pipeline()`](../struct.Agent.html#method.pipeline). Requests that
+/// don't get a response that way, such as after the server closed the connection,
+/// are made again one by one.
+pub(crate) fn pipeline(requests: &mut [Request]) -> Vec<Response> {
+    let empty = Payload::Empty.into_read();
+    let mut responses: Vec<Option<Response>> = requests.iter().map(|_| None).collect();
+    let mut units = vec![];
+    let mut origin = None;
+    for (i, req) in requests.iter().enumerate() {
+        let unit = req.to_url().and_then(|url| {
+            let this = (
+                url.scheme().to_string(),
+                url.host_str().map(|h| h.to_string()),
+                url.port_or_known_default(),
+            );
+            if *origin.get_or_insert_with(|| this.clone()) != this {
+                return Err(Error::BadUrl(format!(
+                    "Not the host of the pipeline: {}",
+                    url
+                )));
+            }
+            let unit = Unit::new(req, &url, true, &empty);
+            ratelimit::acquire(&unit)?;
+            retry::record_request(&unit.agent);
+            Ok(unit)
+        });
+        match unit {
+            Ok(unit) => units.push((i, unit)),
+            Err(e) => responses[i] = Some(e.into()),
+        }
+    }
+
+    // a request whose head can't be made fails on its own, without taking a
+    // place in the batch that a response would be read for.
+    let mut batch = vec![];
+    units.retain(|(i, unit)| match prelude(unit, false) {
+        Ok(prelude) => {
+            unit.bytes.add(prelude.len() as u64, 0);
+            batch.extend(prelude);
+            true
+        }
+        Err(e) => {
+            responses[*i] = Some(Error::from(e).into());
+            false
+        }
+    });
+
+    let mut stream = units
+        .first()
+        .and_then(|(_, unit)| connect_socket(unit, true).ok())
+        .map(|(stream, _)| stream);
+    if let Some(s) = stream.as_mut() {
+        if s.write_all(&batch).is_err() {
+            stream = None;
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(requests = units.len(), "pipelined");
+
+    let mut again = vec![];
+    let count = units.len();
+    for (n, (i, unit)) in units.into_iter().enumerate() {
+        let read = match stream.as_mut() {
+            Some(s) => read_pipelined(&unit, s),
+            None => {
+                again.push(i);
+                continue;
+            }
+        };
+        match read {
+            Ok((mut resp, body, reusable)) => {
+                unit.bytes.add(0, resp.stats.bytes_received);
+                save_cookies(&unit, &resp);
+                if !reusable {
+                    stream = None;
+                } else if n + 1 == count {
+                    if let Some(s) = stream.take() {
+                        pool::recycle(&unit, s);
+                    }
+                }
+                let url = unit.url.to_string();
+                let body = Stream::Cursor(Cursor::new(body));
+                crate::response::set_stream(&mut resp, url, Some(unit), body);
+                responses[i] = Some(resp);
+            }
+            Err(_) => {
+                stream = None;
+                again.push(i);
+            }
+        }
+    }
+
+    for i in again {
+        responses[i] = Some(requests[i].call());
+    }
+    responses
+        .into_iter()
+        .map(|resp| resp.unwrap_or_else(|| Error::BadStatus.into()))
+        .collect()
+}
+
+/// Read a pipelined response with its body as it was sent, which leaves the
+/// connection at the next response. Tells whether there can be a next one.
+fn read_pipelined(unit: &Unit, stream: &mut Stream) -> Result<(Response, Vec<u8>, bool), Error> {
+    let mut resp = Response::read_head(&mut *stream, unit.lenient)?;
+    while resp.status() / 100 == 1 && resp.status() != 101 {
+        resp = Response::read_head(&mut *stream, unit.lenient)?;
+    }
+
+    let (kept, reusable) = drain_body(unit, &resp, stream)?;
+    Ok((resp, kept, reusable))
+}
+
+/// Read the body of `resp` off the stream, returning the bytes and whether
+/// the connection can take another request.
+fn drain_body(unit: &Unit, resp: &Response, stream: &mut Stream) -> Result<(Vec<u8>, bool), Error> {
+    let is_http10 = resp.http_version().eq_ignore_ascii_case("HTTP/1.0");
+    let is_close = resp
+        .header_list("connection")
+        .iter()
+        .any(|c| c.eq_ignore_ascii_case("close"));
+    let is_chunked = resp
+        .header("transfer-encoding")
+        .map(|enc| !enc.is_empty())
+        .unwrap_or(false);
+    let length = resp
+        .header("content-length")
+        .and_then(|l| l.trim().parse::<u64>().ok());
+    let no_body = unit.is_head() || resp.status() == 204 || resp.status() == 304;
+
+    let mut body = KeepRead {
+        reader: stream,
+        kept: vec![],
+    };
+    let ends = if no_body {
+        true
+    } else if is_chunked && !is_http10 {
+        io::copy(&mut ChunkDecoder::new(&mut body), &mut io::sink())?;
+        true
+    } else if let (Some(len), false) = (length, is_http10) {
+        if io::copy(&mut (&mut body).take(len), &mut io::sink())? < len {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "Response body cut short").into());
+        }
+        true
+    } else {
+        // the body goes on until the connection closes.
+        io::copy(&mut body, &mut io::sink())?;
+        false
+    };
+    let reusable = ends && !is_close && !is_http10;
+    Ok((body.kept, reusable))
+}
+
+/// Keeps a copy of everything read.
+struct KeepRead<'a> {
+    reader: &'a mut Stream,
+    kept: Vec<u8>,
+}
+
+impl Read for KeepRead<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let amount = self.reader.read(buf)?;
+        self.kept.extend_from_slice(&buf[..amount]);
+        Ok(amount)
+    }
 }
 
 #[cfg(not(feature = "cookie"))]
@@ -336,20 +1205,23 @@ fn save_cookies(unit: &Unit, resp: &Response) {
     }
 
     // only lock if we know there is something to process
-    let state = &mut unit.agent.lock().unwrap();
-    if let Some(add_jar) = state.as_mut().map(|state| &mut state.jar) {
+    if let Some(state) = (*unit.agent).as_ref() {
+        let host = unit.url.host_str().unwrap_or(DEFAULT_HOST);
+        let is_https = unit.url.scheme() == "https";
+        let mut add_jar = state.jar.write().unwrap();
         for raw_cookie in cookies.iter() {
             let to_parse = if raw_cookie.to_lowercase().contains("domain=") {
                 raw_cookie.to_string()
             } else {
-                let host = &unit.url.host_str().unwrap_or(DEFAULT_HOST).to_string();
                 format!("{}; Domain={}", raw_cookie, host)
             };
             match Cookie::parse_encoded(&to_parse[..]) {
                 Err(_) => (), // ignore unparseable cookies
                 Ok(cookie) => {
-                    let cookie = cookie.into_owned();
-                    add_jar.add(cookie)
+                    let policy = &state.cookie_policy;
+                    if policy.accepts(&cookie, &add_jar, host, &unit.first_host, is_https) {
+                        add_jar.add(cookie.into_owned())
+                    }
                 }
             }
         }
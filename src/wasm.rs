@@ -0,0 +1,153 @@
+use std::io::{Cursor, Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+
+use web_sys::XmlHttpRequest;
+
+use crate::unit::Unit;
+
+/// Headers the browser sets itself, and refuses from scripts.
+const FORBIDDEN: &[&str] = &[
+    "accept-charset",
+    "accept-encoding",
+    "connection",
+    "content-length",
+    "cookie",
+    "date",
+    "expect",
+    "host",
+    "keep-alive",
+    "referer",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "via",
+];
+
+/// A request made with the browser's `XMLHttpRequest` instead of a socket.
+///
+/// The request is written to it as it would be to a connection. Once its
+/// response is read, the request is sent and the response given as if it
+/// had come from a server, with any `Content-Encoding` already undone. The
+/// browser follows redirects and keeps cookies itself.
+///
+/// The request is synchronous, which browsers only allow for binary bodies in
+/// web workers. The `fetch` API would need an async caller.
+///
+/// *Internal API*
+pub(crate) struct XhrStream {
+    /// Scheme, host and port the request target is for.
+    origin: String,
+    chunked: bool,
+    written: Vec<u8>,
+    response: Option<Cursor<Vec<u8>>>,
+}
+
+pub(crate) fn connect(unit: &Unit) -> XhrStream {
+    XhrStream {
+        origin: unit.url.origin().ascii_serialization(),
+        chunked: unit.is_chunked,
+        written: vec![],
+        response: None,
+    }
+}
+
+impl XhrStream {
+    fn send(&self) -> IoResult<Vec<u8>> {
+        let split = find(&self.written, b"\r\n\r\n").ok_or_else(|| {
+            IoError::new(ErrorKind::InvalidInput, "Response read before the request")
+        })?;
+        let head = String::from_utf8_lossy(&self.written[..split]);
+        let mut body = self.written[split + 4..].to_vec();
+        if self.chunked {
+            let mut decoded = vec![];
+            chunked_transfer::Decoder::new(&body[..]).read_to_end(&mut decoded)?;
+            body = decoded;
+        }
+
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap_or("").split(' ');
+        let method = request_line.next().unwrap_or("GET");
+        let target = request_line.next().unwrap_or("/");
+        let url = format!("{}{}", self.origin, target);
+
+        let xhr = XmlHttpRequest::new().map_err(js_error)?;
+        // synchronous, as is the rest of the library.
+        xhr.open_with_async(method, &url, false).map_err(js_error)?;
+        // each char of the text is then a byte of the body.
+        xhr.override_mime_type("text/plain; charset=x-user-defined")
+            .map_err(js_error)?;
+        for line in lines {
+            if let Some((name, value)) = line.split_once(':') {
+                let name = name.trim();
+                if !FORBIDDEN.iter().any(|f| f.eq_ignore_ascii_case(name)) {
+                    xhr.set_request_header(name, value.trim())
+                        .map_err(js_error)?;
+                }
+            }
+        }
+        if body.is_empty() {
+            xhr.send()
+        } else {
+            xhr.send_with_opt_u8_array(Some(&body))
+        }
+        .map_err(js_error)?;
+
+        let status = xhr.status().map_err(js_error)?;
+        if status == 0 {
+            return Err(IoError::new(
+                ErrorKind::ConnectionRefused,
+                format!("Request to {} failed or was blocked by CORS", url),
+            ));
+        }
+        let text = xhr.response_text().map_err(js_error)?.unwrap_or_default();
+        let body: Vec<u8> = text.chars().map(|c| c as u32 as u8).collect();
+
+        let mut response = format!(
+            "HTTP/1.1 {} {}\r\n",
+            status,
+            xhr.status_text().map_err(js_error)?
+        );
+        let headers = xhr.get_all_response_headers().map_err(js_error)?;
+        for line in headers.split("\r\n") {
+            let name = line.split(':').next().unwrap_or("").trim();
+            let framing = ["content-length", "content-encoding", "transfer-encoding"]
+                .iter()
+                .any(|f| f.eq_ignore_ascii_case(name));
+            if !name.is_empty() && !framing {
+                response.push_str(line);
+                response.push_str("\r\n");
+            }
+        }
+        response.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+        let mut response = response.into_bytes();
+        response.extend(body);
+        Ok(response)
+    }
+}
+
+impl Read for XhrStream {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.response.is_none() {
+            self.response = Some(Cursor::new(self.send()?));
+        }
+        self.response.as_mut().unwrap().read(buf)
+    }
+}
+
+impl Write for XhrStream {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn js_error(err: web_sys::wasm_bindgen::JsValue) -> IoError {
+    IoError::other(format!("{:?}", err))
+}
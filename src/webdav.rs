@@ -0,0 +1,43 @@
+use crate::request::Request;
+
+/// The `Depth` header of WebDAV requests, see
+/// [`Request::depth()`](struct.Request.html#method.depth).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Depth {
+    /// Only the resource itself.
+    Zero,
+    /// The resource and its direct members.
+    One,
+    /// The resource and all its members, recursively.
+    Infinity,
+}
+
+impl Depth {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Depth::Zero => "0",
+            Depth::One => "1",
+            Depth::Infinity => "infinity",
+        }
+    }
+}
+
+/// Set the `Destination` of a `COPY` or `MOVE`, which must be absolute, so a
+/// relative destination is resolved against the request url.
+pub(crate) fn set_destination(req: &mut Request, destination: &str) {
+    let absolute = match req.to_url().ok().and_then(|url| url.join(destination).ok()) {
+        Some(url) => url.to_string(),
+        None => destination.to_string(),
+    };
+    req.set("Destination", &absolute);
+}
+
+/// Set the `Lock-Token` of an `UNLOCK`, which is a coded url in angle brackets.
+pub(crate) fn set_lock_token(req: &mut Request, token: &str) {
+    let token = token.trim();
+    if token.starts_with('<') {
+        req.set("Lock-Token", token);
+    } else {
+        req.set("Lock-Token", &format!("<{}>", token));
+    }
+}